@@ -0,0 +1,28 @@
+// Tests with @nontrivial types are C++, and those types derive from this, so they have
+// a user-provided copy constructor and destructor (which don't do anything, members
+// are still copied as usual). That makes them non-trivial for the purposes of calls,
+// so they're passed by an invisible reference, no matter how small they are.
+//
+// It's a template so that every type gets its own (empty) base, which keeps every
+// layout the same as the C one would be.
+template <typename T>
+struct abi_cafe_nontrivial {
+    abi_cafe_nontrivial() {}
+    abi_cafe_nontrivial(const abi_cafe_nontrivial&) {}
+    ~abi_cafe_nontrivial() {}
+};
+
+// thiscall tests are C++ too, because the functions are member functions of a class
+// that derives from the type `this` points to (see CcToolchain::generate_thiscall_class).
+// This names them what the other side calls them, with the prefix C symbols get.
+#define ABI_CAFE_STR2(x) #x
+#define ABI_CAFE_STR(x) ABI_CAFE_STR2(x)
+#define ABI_CAFE_SYMBOL(name) __asm__(ABI_CAFE_STR(__USER_LABEL_PREFIX__) name)
+
+// Everything else is C, so include the headers it might want before we're inside
+// extern "C" (C++ versions of them can have templates in them)
+#include <fenv.h>
+#include <stddef.h>
+#include <stdlib.h>
+
+extern "C" {
@@ -0,0 +1,24 @@
+// What the callee calls between reporting its inputs and its outputs when testing
+// nested calls. Each level does a bit of integer and float work before calling the
+// next one, so everything the callee still needs has to survive calls that really
+// do clobber the caller-saved registers.
+static volatile uint64_t ABI_CAFE_NESTED_SINK;
+
+static ABI_CAFE_NOINLINE void abi_cafe_nested_call(uint32_t depth) {
+    volatile uint64_t ints[8];
+    volatile double floats[8];
+    for (uint32_t i = 0; i < 8; i++) {
+        ints[i] = (uint64_t)depth * 31 + i;
+        floats[i] = (double)(depth + i) * 0.5;
+    }
+    uint64_t int_sum = 0;
+    double float_sum = 0.0;
+    for (uint32_t i = 0; i < 8; i++) {
+        int_sum += ints[i] * ints[(i + 1) % 8];
+        float_sum += floats[i] * floats[(i + 3) % 8];
+    }
+    if (depth > 1) {
+        abi_cafe_nested_call(depth - 1);
+    }
+    ABI_CAFE_NESTED_SINK += int_sum + (uint64_t)float_sum;
+}
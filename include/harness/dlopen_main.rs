@@ -0,0 +1,110 @@
+//! This is the harness main for `--dlopen`, where the caller and callee are shared libraries.
+//!
+//! Unlike [`harness_main.rs`][] nothing is statically linked into this binary: it `dlopen`s
+//! the callee (making its symbols global), then the caller, and runs the caller's `do_test`.
+//! So every call from the caller to the callee goes through the PLT/GOT and gets lazily
+//! bound, and the callee's symbols are subject to interposition like any other dylib's.
+//!
+//! The paths of the two libraries are baked in with `ABI_CAFE_CALLEE_LIB` and
+//! `ABI_CAFE_CALLER_LIB` at compile time. Both sides report their values through the
+//! `CALLER_VALS`/`WRITE_VAL`/... globals exported by this binary, exactly like they
+//! would with `harness_main.rs`, so this only supports `WriteImpl::HarnessCallback`.
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+
+/// Tests write back the raw bytes of their values to a WriteBuffer.
+pub struct WriteBuffer {
+    pub identity: &'static str,
+}
+
+impl WriteBuffer {
+    fn new(identity: &'static str) -> Self {
+        WriteBuffer { identity }
+    }
+}
+
+// The signatures of the interface from our perspective.
+// From the test's perspective the WriteBuffers are totally opaque.
+pub type SetFuncCallback = unsafe extern "C" fn(&mut WriteBuffer, u32) -> ();
+pub type WriteValCallback = unsafe extern "C" fn(&mut WriteBuffer, u32, *const u8, u32) -> ();
+
+pub unsafe extern "C" fn set_func(test: &mut WriteBuffer, func: u32) {
+    let ident = &test.identity;
+    println!(r#"{{ "info": "func", "id": "{ident}", "func": {func} }}"#);
+}
+
+pub unsafe extern "C" fn write_val(
+    test: &mut WriteBuffer,
+    val_idx: u32,
+    input: *const u8,
+    size: u32,
+) {
+    let data = std::slice::from_raw_parts(input, size as usize);
+    let ident = &test.identity;
+    println!(r#"{{ "info": "val", "id": "{ident}", "val": {val_idx}, "bytes": {data:?} }}"#);
+}
+
+#[no_mangle]
+pub static mut CALLER_VALS: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut CALLEE_VALS: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut CALLEE_PADDING: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut SET_FUNC: Option<SetFuncCallback> = None;
+#[no_mangle]
+pub static mut WRITE_VAL: Option<WriteValCallback> = None;
+
+const RTLD_LAZY: c_int = 0x1;
+const RTLD_GLOBAL: c_int = 0x100;
+
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+}
+
+unsafe fn load(path: &CStr, flags: c_int) -> *mut c_void {
+    let handle = dlopen(path.as_ptr(), flags);
+    if handle.is_null() {
+        panic!("couldn't dlopen {path:?}: {:?}", CStr::from_ptr(dlerror()));
+    }
+    handle
+}
+
+pub fn main() {
+    unsafe {
+        let mut caller_vals = WriteBuffer::new("caller");
+        let mut callee_vals = WriteBuffer::new("callee");
+        let mut callee_padding = WriteBuffer::new("callee-padding");
+        CALLER_VALS = &mut caller_vals as *mut _ as *mut _;
+        CALLEE_VALS = &mut callee_vals as *mut _ as *mut _;
+        CALLEE_PADDING = &mut callee_padding as *mut _ as *mut _;
+        SET_FUNC = Some(set_func);
+        WRITE_VAL = Some(write_val);
+
+        // The callee has to be global so the caller's undefined symbols resolve to it
+        let callee_lib = concat!(env!("ABI_CAFE_CALLEE_LIB"), "\0");
+        let caller_lib = concat!(env!("ABI_CAFE_CALLER_LIB"), "\0");
+        load(
+            CStr::from_bytes_with_nul_unchecked(callee_lib.as_bytes()),
+            RTLD_LAZY | RTLD_GLOBAL,
+        );
+        let caller = load(
+            CStr::from_bytes_with_nul_unchecked(caller_lib.as_bytes()),
+            RTLD_LAZY,
+        );
+        let do_test = dlsym(caller, b"do_test\0".as_ptr() as *const c_char);
+        if do_test.is_null() {
+            panic!(
+                "caller doesn't define do_test: {:?}",
+                CStr::from_ptr(dlerror())
+            );
+        }
+        let do_test: unsafe extern "C" fn() = std::mem::transmute(do_test);
+
+        do_test();
+        println!(r#"{{ "info": "done" }}"#);
+    }
+}
@@ -23,6 +23,8 @@ pub static mut CALLER_VALS: WriteBuffer = WriteBuffer(core::ptr::null_mut());
 #[no_mangle]
 pub static mut CALLEE_VALS: WriteBuffer = WriteBuffer(core::ptr::null_mut());
 #[no_mangle]
+pub static mut CALLEE_PADDING: WriteBuffer = WriteBuffer(core::ptr::null_mut());
+#[no_mangle]
 pub static mut SET_FUNC: Option<SetFuncCallback> = None;
 #[no_mangle]
 pub static mut WRITE_VAL: Option<WriteValCallback> = None;
@@ -37,10 +39,12 @@ pub extern fn test_start(
     write_val_callback: WriteValCallback,
     caller_vals: WriteBuffer,
     callee_vals: WriteBuffer,
+    callee_padding: WriteBuffer,
 ) {
     unsafe {
         CALLER_VALS = caller_vals;
         CALLEE_VALS = callee_vals;
+        CALLEE_PADDING = callee_padding;
         SET_FUNC = Some(set_func_callback);
         WRITE_VAL = Some(write_val_callback);
 
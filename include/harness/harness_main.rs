@@ -27,8 +27,13 @@ impl WriteBuffer {
 // From the test's perspective the WriteBuffers are totally opaque.
 pub type SetFuncCallback = unsafe extern "C" fn(&mut WriteBuffer, u32) -> ();
 pub type WriteValCallback = unsafe extern "C" fn(&mut WriteBuffer, u32, *const u8, u32) -> ();
-pub type TestInit =
-    unsafe extern "C" fn(SetFuncCallback, WriteValCallback, &mut WriteBuffer, &mut WriteBuffer) -> ();
+pub type TestInit = unsafe extern "C" fn(
+    SetFuncCallback,
+    WriteValCallback,
+    &mut WriteBuffer,
+    &mut WriteBuffer,
+    &mut WriteBuffer,
+) -> ();
 
 pub unsafe extern "C" fn set_func(test: &mut WriteBuffer, func: u32) {
     let ident = &test.identity;
@@ -52,6 +57,8 @@ pub static mut CALLER_VALS: *mut () = core::ptr::null_mut();
 #[no_mangle]
 pub static mut CALLEE_VALS: *mut () = core::ptr::null_mut();
 #[no_mangle]
+pub static mut CALLEE_PADDING: *mut () = core::ptr::null_mut();
+#[no_mangle]
 pub static mut SET_FUNC: Option<SetFuncCallback> = None;
 #[no_mangle]
 pub static mut WRITE_VAL: Option<WriteValCallback> = None;
@@ -64,8 +71,10 @@ pub fn main() {
     unsafe {
         let mut caller_vals = WriteBuffer::new("caller");
         let mut callee_vals = WriteBuffer::new("callee");
+        let mut callee_padding = WriteBuffer::new("callee-padding");
         CALLER_VALS = &mut caller_vals as *mut _ as *mut _;
         CALLEE_VALS = &mut callee_vals as *mut _ as *mut _;
+        CALLEE_PADDING = &mut callee_padding as *mut _ as *mut _;
         SET_FUNC = Some(set_func);
         WRITE_VAL = Some(write_val);
 
@@ -0,0 +1,32 @@
+// What Rust sides switch the rounding mode and round their floats with, for
+// --rounding-mode. Rust assumes the default floating point environment, so doing
+// either in Rust would be UB. With these a Rust side only moves floats around while
+// another mode is set.
+#include <fenv.h>
+#include <stdlib.h>
+
+// In the order of abi-cafe's RoundingMode::helper_id
+static const int ABI_CAFE_ROUNDING_MODES[] = {
+    FE_TONEAREST,
+    FE_UPWARD,
+    FE_DOWNWARD,
+    FE_TOWARDZERO,
+};
+
+void abi_cafe_set_rounding_mode(int mode) {
+    if (fesetround(ABI_CAFE_ROUNDING_MODES[mode]) != 0) {
+        abort();
+    }
+}
+
+// 1/3 isn't exact, so this rounds differently in every rounding mode
+// (and the volatiles keep the compiler from assuming the default one)
+float abi_cafe_round_f32(float x) {
+    volatile float one = 1, three = 3;
+    return x + one / three;
+}
+
+double abi_cafe_round_f64(double x) {
+    volatile double one = 1, three = 3;
+    return x + one / three;
+}
@@ -10,6 +10,7 @@ type WriteValCallback = unsafe extern fn(WriteBuffer, u32, *const u8, u32) -> ()
 extern {
     pub static mut CALLER_VALS: WriteBuffer;
     pub static mut CALLEE_VALS: WriteBuffer;
+    pub static mut CALLEE_PADDING: WriteBuffer;
     pub static mut SET_FUNC: Option<SetFuncCallback>;
     pub static mut WRITE_VAL: Option<WriteValCallback>;
 }
@@ -22,6 +23,10 @@ unsafe fn write_val<T>(vals: WriteBuffer, val_idx: u32, val: &T) {
         core::mem::size_of_val(val) as u32
     );
 }
+unsafe fn write_cstr(vals: WriteBuffer, val_idx: u32, val: *const core::ffi::c_char) {
+    let bytes = core::ffi::CStr::from_ptr(val).to_bytes();
+    WRITE_VAL.unwrap()(vals, val_idx, bytes.as_ptr(), bytes.len() as u32);
+}
 unsafe fn set_func(vals: WriteBuffer, func_idx: u32) {
     SET_FUNC.unwrap()(vals, func_idx);
 }
@@ -0,0 +1,28 @@
+// What the callee calls between reporting its inputs and its outputs when testing
+// nested calls. Each level does a bit of integer and float work before calling the
+// next one, so everything the callee still needs has to survive calls that really
+// do clobber the caller-saved registers.
+static ABI_CAFE_NESTED_SINK: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[inline(never)]
+fn abi_cafe_nested_call(depth: u32) {
+    let mut ints = [0u64; 8];
+    let mut floats = [0f64; 8];
+    for i in 0..8 {
+        ints[i] = core::hint::black_box(depth as u64 * 31 + i as u64);
+        floats[i] = core::hint::black_box((depth + i as u32) as f64 * 0.5);
+    }
+    let mut int_sum = 0u64;
+    let mut float_sum = 0f64;
+    for i in 0..8 {
+        int_sum = int_sum.wrapping_add(ints[i].wrapping_mul(ints[(i + 1) % 8]));
+        float_sum += floats[i] * floats[(i + 3) % 8];
+    }
+    if depth > 1 {
+        abi_cafe_nested_call(depth - 1);
+    }
+    ABI_CAFE_NESTED_SINK.fetch_add(
+        int_sum.wrapping_add(float_sum as u64),
+        std::sync::atomic::Ordering::Relaxed,
+    );
+}
@@ -0,0 +1,8 @@
+// What the sides switch the rounding mode and round with when testing --rounding-mode.
+// Rust assumes the default floating point environment, so that's done by the C helpers
+// in rounding_mode.c (which every test binary is linked with).
+extern "C" {
+    fn abi_cafe_set_rounding_mode(mode: core::ffi::c_int);
+    fn abi_cafe_round_f32(x: f32) -> f32;
+    fn abi_cafe_round_f64(x: f64) -> f64;
+}
@@ -0,0 +1,176 @@
+//! Resolving `import` nodes, which let several programs share type definitions.
+//!
+//! ```kdl
+//! import "shared/points.types.kdl"
+//!
+//! fn "print" {
+//!     inputs { _ "Point"; }
+//! }
+//! ```
+//!
+//! Imports are resolved before parsing by splicing the (recursively expanded) contents of
+//! the imported file in place of the `import` node, so the rest of the compiler never sees
+//! them. Paths are relative to the file doing the importing. Imported files may only
+//! define types, importing the same file twice is a no-op, and import cycles are an error.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use kdl::{KdlDocument, KdlNode};
+use miette::NamedSource;
+
+use crate::parse::KdlScriptParseError;
+use crate::{Compiler, Result};
+
+/// The top-level items an imported file is allowed to contain
+const IMPORTABLE_ITEMS: &[&str] = &[
+    "struct", "union", "enum", "tagged", "alias", "pun", "import",
+];
+
+/// Loads the contents of an imported file, given its path
+pub type ImportLoader = dyn Fn(&str) -> std::io::Result<String> + Send + Sync;
+
+struct ImportState<'a> {
+    loader: &'a ImportLoader,
+    /// The files we're in the middle of importing (to detect cycles)
+    stack: Vec<String>,
+    /// The files we've already spliced in somewhere
+    imported: HashSet<String>,
+}
+
+impl Compiler {
+    /// Replace every `import` in this program with the definitions it imports
+    ///
+    /// This is done automatically by [`Compiler::compile_string`][], but is exposed so that
+    /// you can get your hands on the self-contained version of a program.
+    pub fn expand_imports(&self, input_name: &str, input_string: String) -> Result<String> {
+        let default_loader = |path: &str| std::fs::read_to_string(path);
+        let mut state = ImportState {
+            loader: self.import_loader.as_deref().unwrap_or(&default_loader),
+            stack: vec![normalize_path(input_name)],
+            imported: HashSet::new(),
+        };
+        state.expand(input_name, input_string, false)
+    }
+}
+
+impl ImportState<'_> {
+    fn expand(&mut self, input_name: &str, input: String, is_import: bool) -> Result<String> {
+        let doc: KdlDocument = input.parse()?;
+        let has_imports = doc
+            .nodes()
+            .iter()
+            .any(|node| node.name().value() == "import");
+        if !is_import && !has_imports {
+            return Ok(input);
+        }
+        let src = Arc::new(NamedSource::new(input_name, input.clone()));
+        let error = |message: String, span, help: Option<&str>| KdlScriptParseError {
+            message,
+            src: src.clone(),
+            span,
+            help: help.map(ToOwned::to_owned),
+        };
+
+        let mut output = String::new();
+        let mut cursor = 0;
+        for node in doc.nodes() {
+            let name = node.name().value();
+            if is_import && !name.starts_with('@') && !IMPORTABLE_ITEMS.contains(&name) {
+                return Err(error(
+                    format!("imported files can only define types, not '{name}'"),
+                    *node.name().span(),
+                    Some("move this to the file that imports this one"),
+                ))?;
+            }
+            if name != "import" {
+                continue;
+            }
+
+            let Some(path) = import_path(node) else {
+                return Err(error(
+                    "import takes exactly one argument: the path of the file to import".to_owned(),
+                    *node.span(),
+                    Some(r#"import "some/file.types.kdl""#),
+                ))?;
+            };
+            let path = resolve_path(input_name, path);
+            let span = node.span();
+            output.push_str(&input[cursor..span.offset()]);
+            cursor = span.offset() + span.len();
+            // The node's span doesn't include its terminator
+            let rest = &input[cursor..];
+            if let Some(after) = rest.trim_start_matches([' ', '\t']).strip_prefix(';') {
+                cursor = input.len() - after.len();
+            }
+
+            if let Some(cycle_start) = self.stack.iter().position(|file| *file == path) {
+                let mut cycle = self.stack[cycle_start..].to_vec();
+                cycle.push(path);
+                return Err(error(
+                    format!("import cycle: {}", cycle.join(" -> ")),
+                    *span,
+                    Some("move the types these files share into a file of their own"),
+                ))?;
+            }
+            if !self.imported.insert(path.clone()) {
+                // Already spliced in, doing it again would just duplicate the definitions
+                continue;
+            }
+            let imported_input = (self.loader)(&path).map_err(|e| {
+                error(
+                    format!("couldn't import {path}: {e}"),
+                    *span,
+                    (e.kind() == std::io::ErrorKind::NotFound)
+                        .then_some("import paths are relative to the file doing the importing"),
+                )
+            })?;
+            self.stack.push(path.clone());
+            let expanded = self.expand(&path, imported_input, true)?;
+            self.stack.pop();
+            output.push_str(&expanded);
+            output.push('\n');
+        }
+        output.push_str(&input[cursor..]);
+        Ok(output)
+    }
+}
+
+/// Get the path out of an `import "path"` node
+fn import_path(node: &KdlNode) -> Option<&str> {
+    let [entry] = node.entries() else {
+        return None;
+    };
+    if entry.name().is_some() || node.children().is_some() {
+        return None;
+    }
+    entry.value().as_string()
+}
+
+/// Get the path of an import, relative to the file importing it
+fn resolve_path(importer: &str, path: &str) -> String {
+    let importer = importer.replace('\\', "/");
+    match importer.rsplit_once('/') {
+        Some((dir, _)) => normalize_path(&format!("{dir}/{path}")),
+        None => normalize_path(path),
+    }
+}
+
+/// Remove `.` and `..` from a path, so each file has only one name
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split('/') {
+        match part {
+            "." => {}
+            ".." if parts
+                .last()
+                .is_some_and(|last| !last.is_empty() && *last != "..") =>
+            {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
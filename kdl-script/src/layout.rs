@@ -0,0 +1,598 @@
+//! Computing the memory layout (size/align/field offsets) of types.
+//!
+//! This implements the standard C layout algorithm (the one `repr(C)` also uses),
+//! parameterized over the handful of target details that affect it (see [`LayoutTarget`][]).
+//! Types that don't have a specified layout (`repr(Rust)`) can't be computed and produce
+//! [`LayoutError::Unspecified`][].
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::parse::{Attr, AttrAligned, AttrPacked, AttrRepr, LangRepr, Repr};
+use crate::types::*;
+use crate::{KdlScriptError, PunEnv};
+
+/// The details of a target that affect the layout of types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LayoutTarget {
+    /// Size (and align) of pointers
+    pub pointer_size: u64,
+    /// Alignment of 8-byte primitives (`u64`, `f64`), this is 4 on i386 SysV!
+    pub align_8: u64,
+    /// Alignment of 16-byte and larger primitives (`u128`, `f128`)
+    pub align_16: u64,
+    /// Size (and align) of the chunks a `_BitInt` wider than 64 bits is made of
+    ///
+    /// This is 8 on x86_64 but 16 on aarch64, where `_BitInt(65)` is laid out like
+    /// an `__int128` instead of two `uint64_t`s.
+    pub bitint_chunk: u64,
+}
+
+impl LayoutTarget {
+    /// A typical 64-bit target (x86_64, aarch64, riscv64...)
+    pub const LP64: Self = LayoutTarget {
+        pointer_size: 8,
+        align_8: 8,
+        align_16: 16,
+        bitint_chunk: 8,
+    };
+
+    /// The size and align of a `_BitInt(bits)`
+    pub fn bitint_layout(&self, bits: u16) -> (u64, u64) {
+        match bits {
+            0..=8 => (1, 1),
+            9..=16 => (2, 2),
+            17..=32 => (4, 4),
+            33..=64 => (8, self.align_8),
+            _ => {
+                let chunk = self.bitint_chunk;
+                (u64::from(bits).div_ceil(chunk * 8) * chunk, chunk)
+            }
+        }
+    }
+}
+
+/// The computed layout of a type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TyLayout {
+    pub size: u64,
+    pub align: u64,
+    /// The fields of the type, in declaration order
+    ///
+    /// Tagged unions list their tag first, followed by every variant's fields
+    /// (named `Variant.field`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldLayout>,
+}
+
+/// The computed layout of a field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldLayout {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    pub align: u64,
+    /// How many bytes of padding come after this field (before the next field or the end)
+    pub padding_after: u64,
+}
+
+/// A primitive somewhere inside a type, and where it is (see [`TypedProgram::leaves_of`][])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafLayout {
+    /// The path to the primitive (`x`, `[1].y`, `Some.0`...)
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    pub align: u64,
+    /// The type of the primitive, None for the tag of a tagged union
+    pub ty: Option<TyIdx>,
+}
+
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("{0} doesn't have a specified layout (repr(Rust))")]
+    Unspecified(String),
+    #[error("{0} has an unsupported layout: {1}")]
+    Unsupported(String, String),
+    #[error(transparent)]
+    Pun(Box<KdlScriptError>),
+}
+
+/// The parts of the attrs on a type that affect layout
+struct LayoutAttrs {
+    lang: Option<LangRepr>,
+    tag: Option<PrimitiveTy>,
+    transparent: bool,
+    packed: bool,
+    align: Option<u64>,
+}
+
+impl TypedProgram {
+    /// Compute the layout of a type
+    ///
+    /// `default_repr` is the repr to use for types that don't specify one,
+    /// and `env` is used to resolve any puns.
+    pub fn layout_of(
+        &self,
+        ty: TyIdx,
+        env: &PunEnv,
+        default_repr: LangRepr,
+        target: &LayoutTarget,
+    ) -> Result<TyLayout, LayoutError> {
+        let cx = LayoutCx {
+            program: self,
+            env,
+            default_repr,
+            target,
+        };
+        cx.layout_of(ty)
+    }
+
+    /// Get the offsets of every byte of padding in a type, including the padding inside its fields
+    ///
+    /// Tagged unions count as fully initialized, since which of their bytes are padding
+    /// depends on the variant.
+    pub fn padding_of(
+        &self,
+        ty: TyIdx,
+        env: &PunEnv,
+        default_repr: LangRepr,
+        target: &LayoutTarget,
+    ) -> Result<Vec<u64>, LayoutError> {
+        let cx = LayoutCx {
+            program: self,
+            env,
+            default_repr,
+            target,
+        };
+        let layout = cx.layout_of(ty)?;
+        let mut used = vec![false; layout.size as usize];
+        cx.mark_used(ty, 0, &mut used)?;
+        Ok((0..layout.size).filter(|&i| !used[i as usize]).collect())
+    }
+
+    /// Get every primitive (or ref, or enum) inside a type, and where it is
+    ///
+    /// Unions and tagged unions list the leaves of every field (and variant), even though
+    /// they overlap. A primitive type is its own only leaf, with an empty name.
+    pub fn leaves_of(
+        &self,
+        ty: TyIdx,
+        env: &PunEnv,
+        default_repr: LangRepr,
+        target: &LayoutTarget,
+    ) -> Result<Vec<LeafLayout>, LayoutError> {
+        let cx = LayoutCx {
+            program: self,
+            env,
+            default_repr,
+            target,
+        };
+        let mut leaves = vec![];
+        cx.leaves(ty, 0, String::new(), &mut leaves)?;
+        Ok(leaves)
+    }
+}
+
+struct LayoutCx<'a> {
+    program: &'a TypedProgram,
+    env: &'a PunEnv,
+    default_repr: LangRepr,
+    target: &'a LayoutTarget,
+}
+
+impl LayoutCx<'_> {
+    fn layout_of(&self, ty: TyIdx) -> Result<TyLayout, LayoutError> {
+        let layout = match self.program.realize_ty(ty) {
+            Ty::Primitive(prim) => self.primitive(*prim),
+            Ty::Empty => TyLayout {
+                size: 0,
+                align: 1,
+                fields: vec![],
+            },
+            Ty::Ref(_) => self.primitive(PrimitiveTy::Ptr),
+            Ty::Array(array_ty) => {
+                let elem = self.layout_of(array_ty.elem_ty)?;
+                TyLayout {
+                    size: elem.size * array_ty.len,
+                    align: elem.align,
+                    fields: vec![],
+                }
+            }
+            Ty::Alias(alias_ty) => self.layout_of(alias_ty.real)?,
+            Ty::Pun(pun_ty) => {
+                let real = self
+                    .program
+                    .resolve_pun(pun_ty, self.env)
+                    .map_err(|e| LayoutError::Pun(Box::new(e)))?;
+                self.layout_of(real)?
+            }
+            Ty::Struct(struct_ty) => {
+                let attrs = self.attrs(ty, &struct_ty.attrs)?;
+                let fields = self.field_tys(&struct_ty.fields, "")?;
+                if attrs.transparent {
+                    let [(_, field)] = &fields[..] else {
+                        return Err(LayoutError::Unsupported(
+                            self.program.format_ty(ty),
+                            "repr(transparent) needs exactly one field".to_owned(),
+                        ));
+                    };
+                    let mut layout = field.clone();
+                    layout.fields = self.struct_fields(fields, 0, false).0;
+                    finish_padding(&mut layout.fields, layout.size);
+                    layout
+                } else {
+                    self.aggregate(self.struct_fields(fields, 0, attrs.packed), &attrs)
+                }
+            }
+            Ty::Union(union_ty) => {
+                let attrs = self.attrs(ty, &union_ty.attrs)?;
+                let fields = self.field_tys(&union_ty.fields, "")?;
+                self.aggregate(self.union_fields(fields, attrs.packed), &attrs)
+            }
+            Ty::Enum(enum_ty) => {
+                let attrs = self.attrs(ty, &enum_ty.attrs)?;
+                // C enums are ints unless told otherwise
+                self.primitive(attrs.tag.unwrap_or(PrimitiveTy::I32))
+            }
+            Ty::Tagged(tagged_ty) => {
+                let attrs = self.attrs(ty, &tagged_ty.attrs)?;
+                let tag_ty = attrs.tag.unwrap_or(PrimitiveTy::I32);
+                let tag = ("tag".to_owned(), self.primitive(tag_ty));
+                let mut variants = vec![];
+                for variant in &tagged_ty.variants {
+                    let prefix = format!("{}.", variant.name);
+                    let fields = variant.fields.as_deref().unwrap_or_default();
+                    variants.push(self.field_tys(fields, &prefix)?);
+                }
+                if attrs.lang == Some(LangRepr::C) || attrs.tag.is_none() {
+                    // repr(C): struct { tag, union { struct { fields }... } }
+                    let mut payload_align = 1;
+                    let mut payload_size = 0;
+                    let mut payload_fields = vec![];
+                    for fields in variants {
+                        let (fields, size, align) = self.struct_fields(fields, 0, attrs.packed);
+                        payload_align = payload_align.max(align);
+                        payload_size = payload_size.max(size);
+                        payload_fields.push(fields);
+                    }
+                    let (mut fields, size, align) = self.struct_fields(
+                        vec![
+                            tag,
+                            (
+                                String::new(),
+                                TyLayout {
+                                    size: align_to(payload_size, payload_align),
+                                    align: payload_align,
+                                    fields: vec![],
+                                },
+                            ),
+                        ],
+                        0,
+                        attrs.packed,
+                    );
+                    // Replace the fake payload field with the real ones
+                    let payload_offset = fields.pop().map_or(0, |payload| payload.offset);
+                    for variant_fields in payload_fields {
+                        fields.extend(variant_fields.into_iter().map(|mut field| {
+                            field.offset += payload_offset;
+                            field
+                        }));
+                    }
+                    self.aggregate((fields, size, align), &attrs)
+                } else {
+                    // repr(int): union { struct { tag, fields }... }
+                    let mut all_fields = vec![];
+                    let mut size = 0;
+                    let mut align = 1;
+                    for mut fields in variants {
+                        fields.insert(0, tag.clone());
+                        let (fields, variant_size, variant_align) =
+                            self.struct_fields(fields, 0, attrs.packed);
+                        size = size.max(variant_size);
+                        align = align.max(variant_align);
+                        // Every variant repeats the tag, only report it once
+                        let skip = if all_fields.is_empty() { 0 } else { 1 };
+                        all_fields.extend(fields.into_iter().skip(skip));
+                    }
+                    if all_fields.is_empty() {
+                        (all_fields, size, align) = self.struct_fields(vec![tag], 0, false);
+                    }
+                    self.aggregate((all_fields, size, align), &attrs)
+                }
+            }
+        };
+        Ok(layout)
+    }
+
+    /// Mark the bytes of a value of this type (at this offset) that aren't padding
+    fn mark_used(&self, ty: TyIdx, offset: u64, used: &mut [bool]) -> Result<(), LayoutError> {
+        match self.program.realize_ty(ty) {
+            Ty::Empty => {}
+            Ty::Alias(alias_ty) => self.mark_used(alias_ty.real, offset, used)?,
+            Ty::Pun(pun_ty) => {
+                let real = self
+                    .program
+                    .resolve_pun(pun_ty, self.env)
+                    .map_err(|e| LayoutError::Pun(Box::new(e)))?;
+                self.mark_used(real, offset, used)?
+            }
+            Ty::Array(array_ty) => {
+                let elem_size = self.layout_of(array_ty.elem_ty)?.size;
+                for i in 0..array_ty.len {
+                    self.mark_used(array_ty.elem_ty, offset + i * elem_size, used)?;
+                }
+            }
+            Ty::Struct(struct_ty) => {
+                let layout = self.layout_of(ty)?;
+                for (field, field_layout) in struct_ty.fields.iter().zip(&layout.fields) {
+                    self.mark_used(field.ty, offset + field_layout.offset, used)?;
+                }
+            }
+            Ty::Union(union_ty) => {
+                // Whatever bytes any field covers aren't padding
+                for field in &union_ty.fields {
+                    self.mark_used(field.ty, offset, used)?;
+                }
+            }
+            Ty::Primitive(_) | Ty::Ref(_) | Ty::Enum(_) | Ty::Tagged(_) => {
+                let size = self.layout_of(ty)?.size;
+                used[offset as usize..(offset + size) as usize].fill(true);
+            }
+        }
+        Ok(())
+    }
+
+    fn leaves(
+        &self,
+        ty: TyIdx,
+        offset: u64,
+        name: String,
+        leaves: &mut Vec<LeafLayout>,
+    ) -> Result<(), LayoutError> {
+        let layout = self.layout_of(ty)?;
+        match self.program.realize_ty(ty) {
+            Ty::Primitive(_) | Ty::Ref(_) | Ty::Enum(_) => leaves.push(LeafLayout {
+                name,
+                offset,
+                size: layout.size,
+                align: layout.align,
+                ty: Some(ty),
+            }),
+            Ty::Empty => {}
+            Ty::Alias(alias_ty) => self.leaves(alias_ty.real, offset, name, leaves)?,
+            Ty::Pun(pun_ty) => {
+                let real = self
+                    .program
+                    .resolve_pun(pun_ty, self.env)
+                    .map_err(|e| LayoutError::Pun(Box::new(e)))?;
+                self.leaves(real, offset, name, leaves)?
+            }
+            Ty::Array(array_ty) => {
+                let elem_size = self.layout_of(array_ty.elem_ty)?.size;
+                for idx in 0..array_ty.len {
+                    let elem_name = format!("{name}[{idx}]");
+                    self.leaves(
+                        array_ty.elem_ty,
+                        offset + idx * elem_size,
+                        elem_name,
+                        leaves,
+                    )?;
+                }
+            }
+            Ty::Struct(StructTy { fields, .. }) | Ty::Union(UnionTy { fields, .. }) => {
+                // The layout lists the fields in declaration order
+                for (field, field_layout) in fields.iter().zip(&layout.fields) {
+                    let field_name = field_name(&name, &field.ident.to_string());
+                    self.leaves(field.ty, offset + field_layout.offset, field_name, leaves)?;
+                }
+            }
+            Ty::Tagged(tagged_ty) => {
+                // The layout lists the tag, followed by every variant's fields
+                let mut field_layouts = layout.fields.iter();
+                if let Some(tag) = field_layouts.next() {
+                    leaves.push(LeafLayout {
+                        name: field_name(&name, &tag.name),
+                        offset: offset + tag.offset,
+                        size: tag.size,
+                        align: tag.align,
+                        ty: None,
+                    });
+                }
+                let fields = tagged_ty
+                    .variants
+                    .iter()
+                    .flat_map(|variant| variant.fields.as_deref().unwrap_or_default());
+                for (field, field_layout) in fields.zip(field_layouts) {
+                    let field_name = field_name(&name, &field_layout.name);
+                    self.leaves(field.ty, offset + field_layout.offset, field_name, leaves)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn primitive(&self, prim: PrimitiveTy) -> TyLayout {
+        use PrimitiveTy::*;
+        if let BitInt { bits, .. } = prim {
+            let (size, align) = self.target.bitint_layout(bits);
+            return TyLayout {
+                size,
+                align,
+                fields: vec![],
+            };
+        }
+        let size = match prim {
+            I8 | U8 | Bool => 1,
+            I16 | U16 | F16 => 2,
+            I32 | U32 | F32 => 4,
+            I64 | U64 | F64 => 8,
+            I128 | U128 | F128 => 16,
+            I256 | U256 => 32,
+            Ptr | CStr => self.target.pointer_size,
+            F32x4 | I64x2 => 16,
+            F32x8 | I64x4 => 32,
+            BitInt { .. } => unreachable!(),
+        };
+        let align = match prim {
+            Ptr | CStr => self.target.pointer_size,
+            // Vectors are always aligned to their size
+            F32x4 | I64x2 | F32x8 | I64x4 => size,
+            _ if size >= 16 => self.target.align_16,
+            _ if size == 8 => self.target.align_8,
+            _ => size,
+        };
+        TyLayout {
+            size,
+            align,
+            fields: vec![],
+        }
+    }
+
+    fn attrs(&self, ty: TyIdx, attrs: &[Attr]) -> Result<LayoutAttrs, LayoutError> {
+        let mut result = LayoutAttrs {
+            lang: None,
+            tag: None,
+            transparent: false,
+            packed: false,
+            align: None,
+        };
+        let mut has_repr = false;
+        for attr in attrs {
+            match attr {
+                Attr::Packed(AttrPacked {}) => result.packed = true,
+                Attr::Align(AttrAligned { align }) => result.align = Some(align.val as u64),
+                Attr::Repr(AttrRepr { reprs }) => {
+                    has_repr = true;
+                    for repr in reprs {
+                        match repr {
+                            Repr::Lang(lang) => result.lang = Some(*lang),
+                            Repr::Primitive(prim) => result.tag = Some(*prim),
+                            Repr::Transparent => result.transparent = true,
+                        }
+                    }
+                }
+                Attr::Passthrough(_) => {}
+                // Only changes how the type is passed, not its layout
+                Attr::NonTrivial(_) => {}
+            }
+        }
+        // An explicit repr attribute disables the default lang repr
+        if !has_repr {
+            result.lang = Some(self.default_repr);
+        }
+        let is_c_like = result.lang == Some(LangRepr::C) || result.tag.is_some();
+        if !is_c_like && !result.transparent {
+            return Err(LayoutError::Unspecified(self.program.format_ty(ty)));
+        }
+        Ok(result)
+    }
+
+    fn field_tys(
+        &self,
+        fields: &[FieldTy],
+        prefix: &str,
+    ) -> Result<Vec<(String, TyLayout)>, LayoutError> {
+        fields
+            .iter()
+            .map(|field| {
+                Ok((
+                    format!("{prefix}{}", field.ident),
+                    self.layout_of(field.ty)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Lay out fields one after the other, returning the fields and the unpadded size/align
+    fn struct_fields(
+        &self,
+        fields: Vec<(String, TyLayout)>,
+        start: u64,
+        packed: bool,
+    ) -> (Vec<FieldLayout>, u64, u64) {
+        let mut offset = start;
+        let mut align = 1;
+        let mut result: Vec<FieldLayout> = vec![];
+        for (name, field) in fields {
+            let field_align = if packed { 1 } else { field.align };
+            let field_offset = align_to(offset, field_align);
+            if let Some(prev) = result.last_mut() {
+                prev.padding_after = field_offset - offset;
+            }
+            align = align.max(field_align);
+            offset = field_offset + field.size;
+            result.push(FieldLayout {
+                name,
+                offset: field_offset,
+                size: field.size,
+                align: field_align,
+                padding_after: TO_END,
+            });
+        }
+        (result, offset, align)
+    }
+
+    /// Overlap all the fields at offset 0
+    fn union_fields(
+        &self,
+        fields: Vec<(String, TyLayout)>,
+        packed: bool,
+    ) -> (Vec<FieldLayout>, u64, u64) {
+        let mut size = 0;
+        let mut align = 1;
+        let mut result = vec![];
+        for (name, field) in fields {
+            let field_align = if packed { 1 } else { field.align };
+            size = size.max(field.size);
+            align = align.max(field_align);
+            result.push(FieldLayout {
+                name,
+                offset: 0,
+                size: field.size,
+                align: field_align,
+                padding_after: TO_END,
+            });
+        }
+        (result, size, align)
+    }
+
+    /// Apply the type's alignment and trailing padding
+    fn aggregate(
+        &self,
+        (mut fields, size, align): (Vec<FieldLayout>, u64, u64),
+        attrs: &LayoutAttrs,
+    ) -> TyLayout {
+        let align = attrs.align.map_or(align, |forced| forced.max(align));
+        let padded_size = align_to(size, align);
+        finish_padding(&mut fields, padded_size);
+        TyLayout {
+            size: padded_size,
+            align,
+            fields,
+        }
+    }
+}
+
+/// Placeholder for `padding_after` on fields that are followed by the end of the type
+const TO_END: u64 = u64::MAX;
+
+fn finish_padding(fields: &mut [FieldLayout], size: u64) {
+    for field in fields {
+        if field.padding_after == TO_END {
+            field.padding_after = size - (field.offset + field.size);
+        }
+    }
+}
+
+fn field_name(parent: &str, field: &str) -> String {
+    if parent.is_empty() {
+        field.to_owned()
+    } else {
+        format!("{parent}.{field}")
+    }
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
@@ -6,13 +6,19 @@ use kdl::KdlDocument;
 use miette::{Diagnostic, NamedSource};
 use thiserror::Error;
 
+pub use import::ImportLoader;
+pub use layout::{FieldLayout, LayoutError, LayoutTarget, LeafLayout, TyLayout};
 pub use parse::{KdlScriptParseError, ParsedProgram, PunEnv};
+pub use sysv::{Eightbyte, SysVClass, SysVClassification};
 pub use types::{Definition, DefinitionGraph, KdlScriptTypeError, TypedProgram};
 
 #[cfg(feature = "eval")]
 pub mod eval;
+pub mod import;
+pub mod layout;
 pub mod parse;
 pub mod spanned;
+pub mod sysv;
 #[cfg(test)]
 mod tests;
 pub mod types;
@@ -55,6 +61,10 @@ pub struct Compiler {
     pub source: Option<Arc<NamedSource>>,
     pub parsed: Option<Arc<ParsedProgram>>,
     pub typed: Option<Arc<TypedProgram>>,
+    /// How to load the files a program `import`s (if None, they're read from disk)
+    ///
+    /// This is given the path of the import, already resolved relative to the importer.
+    pub import_loader: Option<Box<ImportLoader>>,
 }
 
 pub type Result<T> = std::result::Result<T, KdlScriptError>;
@@ -71,9 +81,19 @@ impl Compiler {
             source: None,
             parsed: None,
             typed: None,
+            import_loader: None,
         }
     }
 
+    /// Use this to load the files a program `import`s, instead of reading them from disk
+    pub fn with_import_loader(
+        mut self,
+        loader: impl Fn(&str) -> std::io::Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.import_loader = Some(Box::new(loader));
+        self
+    }
+
     pub fn compile_path(
         &mut self,
         src_path: impl AsRef<Path>,
@@ -92,7 +112,7 @@ impl Compiler {
         input_name: &str,
         input_string: String,
     ) -> std::result::Result<Arc<TypedProgram>, KdlScriptError> {
-        let input_string = Arc::new(input_string);
+        let input_string = Arc::new(self.expand_imports(input_name, input_string)?);
 
         let src = Arc::new(miette::NamedSource::new(input_name, input_string.clone()));
         self.source = Some(src.clone());
@@ -161,6 +161,8 @@ pub enum Tydent {
     Array(Box<Spanned<Tydent>>, u64),
     /// A by-reference type
     Ref(Box<Spanned<Tydent>>),
+    /// A by-reference type that can also be null (`Option<&T>`)
+    OptionRef(Box<Spanned<Tydent>>),
     /// The empty tuple -- `()`
     Empty,
 }
@@ -173,6 +175,7 @@ pub enum Tydent {
 /// * packed(N)
 /// * align(N)
 /// * passthrough attrs to underlying language
+/// * nontrivial (a C++ copy constructor and destructor)
 ///
 /// Probably this should be broken up so that you can't "pack"
 /// a function or other such nonsense...
@@ -186,6 +189,8 @@ pub enum Attr {
     Repr(AttrRepr),
     /// Pass this attribute through to the target language
     Passthrough(AttrPassthrough),
+    /// The type has a user-provided copy constructor and destructor (in C++)
+    NonTrivial(AttrNonTrivial),
 }
 
 /// An attribute declaring this type should be packed (remove padding/align).
@@ -249,6 +254,46 @@ impl std::str::FromStr for LangRepr {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AttrPassthrough(pub Spanned<String>);
 
+/// An attribute declaring this struct isn't "trivial for the purposes of calls":
+/// in C++ it has a user-provided copy constructor and destructor, which means it
+/// has to be passed by an invisible reference, no matter how small it is.
+///
+/// @nontrivial
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AttrNonTrivial {}
+
+/// An attribute on a function input, which changes what the callee is allowed
+/// to assume about it (but not its layout or its value).
+///
+/// These only apply to references, and go right before the input:
+///
+/// ```kdl
+/// inputs {
+///     @noalias
+///     @dereferenceable
+///     x "&MyStruct"
+/// }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ArgAttr {
+    /// `@noalias`: nothing else accesses the referent during the call (C's `restrict`)
+    NoAlias,
+    /// `@nonnull`: the reference is never null
+    NonNull,
+    /// `@dereferenceable`: the whole referent can be read, even if the callee doesn't
+    Dereferenceable,
+}
+impl std::fmt::Display for ArgAttr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ArgAttr::NoAlias => "@noalias",
+            ArgAttr::NonNull => "@nonnull",
+            ArgAttr::Dereferenceable => "@dereferenceable",
+        };
+        s.fmt(f)
+    }
+}
+
 /// A struct decl.
 ///
 /// Field names may be positional by naming them underscore (`_`).
@@ -380,6 +425,8 @@ pub struct AliasDecl {
 pub struct TypedVar {
     pub name: Option<Ident>,
     pub ty: Spanned<Tydent>,
+    /// Only function inputs can have these
+    pub attrs: Vec<Spanned<ArgAttr>>,
 }
 
 /// A function declaration
@@ -501,7 +548,7 @@ impl Parser<'_> {
         trace!("struct decl");
         let name = self.one_string(node, "type name")?;
         let name = self.ident(name)?;
-        let fields = self.typed_var_children(node)?;
+        let fields = self.typed_var_children(node, false)?;
 
         Ok(StructDecl {
             name,
@@ -515,7 +562,7 @@ impl Parser<'_> {
         trace!("union decl");
         let name = self.one_string(node, "type name")?;
         let name = self.ident(name)?;
-        let fields = self.typed_var_children(node)?;
+        let fields = self.typed_var_children(node, false)?;
 
         Ok(UnionDecl {
             name,
@@ -719,7 +766,7 @@ impl Parser<'_> {
                         })?;
                     }
                     self.no_args(stmt)?;
-                    inputs = self.typed_var_children(stmt)?;
+                    inputs = self.typed_var_children(stmt, true)?;
                     input_span = Some(*stmt.name().span());
                     continue;
                 }
@@ -742,7 +789,7 @@ impl Parser<'_> {
                         })?;
                     }
                     self.no_args(stmt)?;
-                    outputs = self.typed_var_children(stmt)?;
+                    outputs = self.typed_var_children(stmt, false)?;
                     output_span = Some(*stmt.name().span());
                     continue;
                 }
@@ -807,6 +854,11 @@ impl Parser<'_> {
                 self.no_children(attr)?;
                 Attr::Packed(AttrPacked {})
             }
+            "@nontrivial" => {
+                trace!("nontrivial attr");
+                self.no_children(attr)?;
+                Attr::NonTrivial(AttrNonTrivial {})
+            }
             "@align" => {
                 trace!("align attr");
                 let Some(e) = attr.entries().first() else {
@@ -974,18 +1026,61 @@ impl Parser<'_> {
     }
 
     /// This node's children should be TypedVars
-    fn typed_var_children(&mut self, node: &KdlNode) -> Result<Vec<TypedVar>> {
-        node.children()
-            .into_iter()
-            .flat_map(|d| d.nodes())
-            .map(|var| {
-                let name = self.var_name_decl(var)?;
-                let ty_str = self.one_string(var, "type")?;
-                let ty = self.tydent(&ty_str)?;
-                self.no_children(var)?;
-                Ok(TypedVar { name, ty })
-            })
-            .collect()
+    ///
+    /// If `arg_attrs` is set (for function inputs), each var can be preceded by [`ArgAttr`][]s.
+    fn typed_var_children(&mut self, node: &KdlNode, arg_attrs: bool) -> Result<Vec<TypedVar>> {
+        let mut vars = vec![];
+        let mut cur_attrs = vec![];
+        for var in node.children().into_iter().flat_map(|d| d.nodes()) {
+            if var.name().value().starts_with('@') {
+                cur_attrs.push(self.arg_attr(var, arg_attrs)?);
+                continue;
+            }
+            let name = self.var_name_decl(var)?;
+            let ty_str = self.one_string(var, "type")?;
+            let ty = self.tydent(&ty_str)?;
+            self.no_children(var)?;
+            let attrs = std::mem::take(&mut cur_attrs);
+            vars.push(TypedVar { name, ty, attrs });
+        }
+        if let Some(attr) = cur_attrs.first() {
+            return Err(KdlScriptParseError {
+                message: format!("{} isn't followed by an input to apply to", **attr),
+                src: self.src.clone(),
+                span: Spanned::span(attr),
+                help: None,
+            })?;
+        }
+        Ok(vars)
+    }
+
+    /// Parse an `@attribute` node that precedes a function input
+    fn arg_attr(&mut self, attr: &KdlNode, allowed: bool) -> Result<Spanned<ArgAttr>> {
+        let span = *attr.name().span();
+        if !allowed {
+            return Err(KdlScriptParseError {
+                message: "only function inputs can have attributes".to_owned(),
+                src: self.src.clone(),
+                span,
+                help: None,
+            })?;
+        }
+        let arg_attr = match attr.name().value() {
+            "@noalias" => ArgAttr::NoAlias,
+            "@nonnull" => ArgAttr::NonNull,
+            "@dereferenceable" => ArgAttr::Dereferenceable,
+            x => {
+                return Err(KdlScriptParseError {
+                    message: format!("I don't know what a '{x}' input attribute is"),
+                    src: self.src.clone(),
+                    span,
+                    help: Some("try @noalias, @nonnull, or @dereferenceable".to_owned()),
+                })?;
+            }
+        };
+        self.no_args(attr)?;
+        self.no_children(attr)?;
+        Ok(Spanned::new(arg_attr, span))
     }
 
     /// This node's children should be enum variants
@@ -1028,7 +1123,7 @@ impl Parser<'_> {
                 let name = Spanned::new(name.value().to_owned(), *name.span());
                 let name = self.ident(name)?;
                 let fields = if var.children().is_some() {
-                    Some(self.typed_var_children(var)?)
+                    Some(self.typed_var_children(var, false)?)
                 } else {
                     None
                 };
@@ -1120,7 +1215,7 @@ fn inherit_spans(tydent: &mut Spanned<Tydent>, input: &Spanned<String>) {
         Tydent::Array(elem_tydent, _) => {
             inherit_spans(elem_tydent, input);
         }
-        Tydent::Ref(pointee_tydent) => {
+        Tydent::Ref(pointee_tydent) | Tydent::OptionRef(pointee_tydent) => {
             inherit_spans(pointee_tydent, input);
         }
         Tydent::Empty => {
@@ -1135,7 +1230,13 @@ type NomResult<I, O> = IResult<I, O, VerboseError<I>>;
 
 /// Matches the syntax for tydent ("identifier, but for types") incl structural types like arrays/references.
 fn tydent(input: &str) -> NomResult<&str, Spanned<Tydent>> {
-    alt((tydent_ref, tydent_array, tydent_empty_tuple, tydent_named))(input)
+    alt((
+        tydent_ref,
+        tydent_array,
+        tydent_empty_tuple,
+        tydent_option_ref,
+        tydent_named,
+    ))(input)
 }
 
 /// Matches a reference type (&T)
@@ -1147,6 +1248,29 @@ fn tydent_ref(input: &str) -> NomResult<&str, Spanned<Tydent>> {
     Ok((input, Spanned::from(Tydent::Ref(Box::new(pointee_ty)))))
 }
 
+/// Matches a nullable reference type (Option<&T>)
+fn tydent_option_ref(input: &str) -> NomResult<&str, Spanned<Tydent>> {
+    let (input, ref_ty) = preceded(
+        pair(tag("Option"), many0(unicode_space)),
+        delimited(
+            tag("<"),
+            context(
+                "a reference type (&T)",
+                cut(delimited(
+                    many0(unicode_space),
+                    tydent_ref,
+                    many0(unicode_space),
+                )),
+            ),
+            cut(tag(">")),
+        ),
+    )(input)?;
+    let Tydent::Ref(pointee_ty) = Spanned::into_inner(ref_ty) else {
+        unreachable!("tydent_ref didn't parse a reference?")
+    };
+    Ok((input, Spanned::from(Tydent::OptionRef(pointee_ty))))
+}
+
 /// Matches an array type ([T; N])
 fn tydent_array(input: &str) -> NomResult<&str, Spanned<Tydent>> {
     let (input, (elem_ty, array_len)) = delimited(
@@ -1499,15 +1623,18 @@ mod runnable {
                         TypedVar {
                             name: Some(Ident::from(String::from("lhs"))),
                             ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
+                            attrs: vec![],
                         },
                         TypedVar {
                             name: Some(Ident::from(String::from("rhs"))),
                             ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
+                            attrs: vec![],
                         },
                     ],
                     outputs: vec![TypedVar {
                         name: Some(Ident::from(String::from("out"))),
                         ty: Spanned::from(Tydent::Name(Ident::from(String::from("i64")))),
+                        attrs: vec![],
                     }],
                     attrs: vec![],
 
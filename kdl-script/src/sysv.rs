@@ -0,0 +1,182 @@
+//! Classifying types the way the x86_64 SysV ABI does when passing them by value.
+//!
+//! Every aggregate of at most 16 bytes is split into "eightbytes", and each one is
+//! assigned a class (INTEGER, SSE, ...) from the fields that overlap it. The class
+//! decides whether that eightbyte is passed in a general purpose register, a vector
+//! register, or the whole thing goes on the stack (MEMORY). This is the part of the
+//! ABI that compilers most often disagree on, so we compute it ourselves to have
+//! something concrete to compare their behaviour against.
+//!
+//! This is built on top of [`crate::layout`][], and so only works for types with
+//! a specified layout. `long double` isn't a thing in kdl-script, so the X87 class never
+//! comes up, and this assumes the baseline target features (so `__m256` is MEMORY).
+
+use serde::Serialize;
+
+use crate::layout::{LayoutError, LayoutTarget};
+use crate::parse::LangRepr;
+use crate::types::*;
+use crate::PunEnv;
+
+/// The class of an eightbyte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SysVClass {
+    /// Padding, or a zero-sized type (passed as nothing)
+    #[serde(rename = "NO_CLASS")]
+    NoClass,
+    /// Passed in a general purpose register
+    #[serde(rename = "INTEGER")]
+    Integer,
+    /// Passed in (the low half of) a vector register
+    #[serde(rename = "SSE")]
+    Sse,
+    /// The upper half of the vector register of the previous SSE eightbyte
+    #[serde(rename = "SSEUP")]
+    SseUp,
+    /// Passed on the stack
+    #[serde(rename = "MEMORY")]
+    Memory,
+}
+
+impl std::fmt::Display for SysVClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SysVClass::NoClass => "NO_CLASS",
+            SysVClass::Integer => "INTEGER",
+            SysVClass::Sse => "SSE",
+            SysVClass::SseUp => "SSEUP",
+            SysVClass::Memory => "MEMORY",
+        };
+        s.fmt(f)
+    }
+}
+
+impl SysVClass {
+    /// Combine the classes of two fields that overlap the same eightbyte
+    fn merge(self, other: Self) -> Self {
+        use SysVClass::*;
+        match (self, other) {
+            (lhs, rhs) if lhs == rhs => lhs,
+            (NoClass, other) | (other, NoClass) => other,
+            (Memory, _) | (_, Memory) => Memory,
+            (Integer, _) | (_, Integer) => Integer,
+            _ => Sse,
+        }
+    }
+}
+
+/// How a type is classified
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SysVClassification {
+    /// Every eightbyte of the type, in order (empty for zero-sized types)
+    ///
+    /// If any of them is MEMORY, they all are.
+    pub eightbytes: Vec<Eightbyte>,
+}
+
+impl SysVClassification {
+    /// Whether the type is passed on the stack
+    pub fn is_memory(&self) -> bool {
+        self.eightbytes
+            .iter()
+            .any(|eightbyte| eightbyte.class == SysVClass::Memory)
+    }
+}
+
+/// One eightbyte of a type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Eightbyte {
+    pub class: SysVClass,
+    /// The (primitive) fields that overlap this eightbyte, i.e. `points[1].x`
+    pub fields: Vec<String>,
+}
+
+impl TypedProgram {
+    /// Classify a type the way x86_64 SysV does when passing it by value
+    ///
+    /// `default_repr` and `env` work just like they do for [`TypedProgram::layout_of`][].
+    pub fn sysv_classify(
+        &self,
+        ty: TyIdx,
+        env: &PunEnv,
+        default_repr: LangRepr,
+        target: &LayoutTarget,
+    ) -> Result<SysVClassification, LayoutError> {
+        let layout = self.layout_of(ty, env, default_repr, target)?;
+        let num_eightbytes = layout.size.div_ceil(8) as usize;
+        let mut eightbytes = vec![
+            Eightbyte {
+                class: SysVClass::NoClass,
+                fields: vec![],
+            };
+            num_eightbytes
+        ];
+        let leaves = self.leaves_of(ty, env, default_repr, target)?;
+
+        let mut is_memory = layout.size > 16;
+        for leaf in leaves {
+            // Misaligned (packed) fields can't be loaded into registers
+            if leaf.offset % leaf.align != 0 {
+                is_memory = true;
+            }
+            let leaf_class = leaf_class(self, leaf.ty);
+            let first = (leaf.offset / 8) as usize;
+            let last = ((leaf.offset + leaf.size).div_ceil(8) as usize).max(first + 1);
+            for (idx, eightbyte) in eightbytes[first..last.min(num_eightbytes)]
+                .iter_mut()
+                .enumerate()
+            {
+                let class = match leaf_class {
+                    // Only the first eightbyte of a 16-byte float gets the SSE register
+                    SysVClass::Sse if idx > 0 => SysVClass::SseUp,
+                    class => class,
+                };
+                eightbyte.class = eightbyte.class.merge(class);
+                eightbyte.fields.push(leaf.name.clone());
+            }
+        }
+
+        // The post-merger cleanup
+        for idx in 0..eightbytes.len() {
+            let prev = idx.checked_sub(1).map(|prev| eightbytes[prev].class);
+            let eightbyte = &mut eightbytes[idx];
+            if eightbyte.class == SysVClass::Memory {
+                is_memory = true;
+            }
+            if eightbyte.class == SysVClass::SseUp
+                && !matches!(prev, Some(SysVClass::Sse | SysVClass::SseUp))
+            {
+                eightbyte.class = SysVClass::Sse;
+            }
+        }
+        if is_memory {
+            for eightbyte in &mut eightbytes {
+                eightbyte.class = SysVClass::Memory;
+            }
+        }
+        Ok(SysVClassification { eightbytes })
+    }
+}
+
+/// The class of a primitive (or the tag of a tagged union, if `ty` is None)
+fn leaf_class(program: &TypedProgram, ty: Option<TyIdx>) -> SysVClass {
+    let Some(ty) = ty else {
+        return SysVClass::Integer;
+    };
+    match program.realize_ty(ty) {
+        Ty::Primitive(prim) => match prim {
+            PrimitiveTy::F16 | PrimitiveTy::F32 | PrimitiveTy::F64 | PrimitiveTy::F128 => {
+                SysVClass::Sse
+            }
+            // 128-bit vectors are SSE + SSEUP like f128, and the 256-bit ones are
+            // too big for anything but MEMORY (without AVX, which we assume)
+            PrimitiveTy::F32x4 | PrimitiveTy::I64x2 => SysVClass::Sse,
+            PrimitiveTy::F32x8 | PrimitiveTy::I64x4 => SysVClass::Memory,
+            // These are _BitInt(256), which is always passed in memory
+            PrimitiveTy::I256 | PrimitiveTy::U256 => SysVClass::Memory,
+            PrimitiveTy::BitInt { bits, .. } if *bits > 128 => SysVClass::Memory,
+            _ => SysVClass::Integer,
+        },
+        _ => SysVClass::Integer,
+    }
+}
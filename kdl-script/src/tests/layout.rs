@@ -0,0 +1,264 @@
+use crate::parse::LangRepr;
+use crate::types::TyIdx;
+use crate::{LayoutTarget, PunEnv, TyLayout, TypedProgram};
+
+fn arg_ty(program: &str, ty_name: &str) -> (std::sync::Arc<TypedProgram>, TyIdx) {
+    let mut compiler = crate::Compiler::new();
+    let program = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let func = program.all_funcs().next().unwrap();
+    let ty = program
+        .realize_func(func)
+        .inputs
+        .iter()
+        .find(|arg| program.format_ty(arg.ty) == ty_name)
+        .unwrap()
+        .ty;
+    (program, ty)
+}
+
+fn c_env() -> PunEnv {
+    PunEnv {
+        lang: "c".to_owned(),
+    }
+}
+
+fn layout_of(program: &str, ty_name: &str, target: &LayoutTarget) -> TyLayout {
+    let (program, ty) = arg_ty(program, ty_name);
+    program
+        .layout_of(ty, &c_env(), LangRepr::C, target)
+        .unwrap()
+}
+
+fn offsets(layout: &TyLayout) -> Vec<(u64, u64)> {
+    layout
+        .fields
+        .iter()
+        .map(|field| (field.offset, field.padding_after))
+        .collect()
+}
+
+#[test]
+fn layout_struct_padding() {
+    let program = r##"
+        struct "Padded" {
+            a "u8"
+            b "u32"
+            c "u16"
+        }
+        fn "func" {
+            inputs { _ "Padded"; }
+        }
+    "##;
+    let layout = layout_of(program, "Padded", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (12, 4));
+    assert_eq!(offsets(&layout), vec![(0, 3), (4, 0), (8, 2)]);
+}
+
+#[test]
+fn layout_struct_i386() {
+    let program = r##"
+        struct "Mixed" {
+            a "u8"
+            b "f64"
+        }
+        fn "func" {
+            inputs { _ "Mixed"; }
+        }
+    "##;
+    let i386 = LayoutTarget {
+        pointer_size: 4,
+        align_8: 4,
+        align_16: 4,
+        bitint_chunk: 4,
+    };
+    let layout = layout_of(program, "Mixed", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (16, 8));
+    let layout = layout_of(program, "Mixed", &i386);
+    assert_eq!((layout.size, layout.align), (12, 4));
+    assert_eq!(offsets(&layout), vec![(0, 3), (4, 0)]);
+}
+
+#[test]
+fn layout_packed_and_aligned() {
+    let program = r##"
+        @packed
+        struct "Packed" {
+            a "u8"
+            b "u32"
+        }
+        @align 16
+        struct "Aligned" {
+            a "u8"
+        }
+        fn "func" {
+            inputs { _ "Packed"; _ "Aligned"; }
+        }
+    "##;
+    let layout = layout_of(program, "Packed", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (5, 1));
+    assert_eq!(offsets(&layout), vec![(0, 0), (1, 0)]);
+    let layout = layout_of(program, "Aligned", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (16, 16));
+    assert_eq!(offsets(&layout), vec![(0, 15)]);
+}
+
+#[test]
+fn layout_union_and_tagged() {
+    let program = r##"
+        union "Either" {
+            a "u8"
+            b "u64"
+        }
+        tagged "MaybeU64" {
+            None
+            Some { _ "u64"; }
+        }
+        fn "func" {
+            inputs { _ "Either"; _ "MaybeU64"; }
+        }
+    "##;
+    let layout = layout_of(program, "Either", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (8, 8));
+    assert_eq!(offsets(&layout), vec![(0, 7), (0, 0)]);
+    let layout = layout_of(program, "MaybeU64", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (16, 8));
+    assert_eq!(offsets(&layout), vec![(0, 4), (8, 0)]);
+}
+
+#[test]
+fn layout_repr_rust_unspecified() {
+    let program = r##"
+        @repr "rust"
+        struct "Opaque" {
+            a "u8"
+        }
+        fn "func" {
+            inputs { _ "Opaque"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let env = PunEnv {
+        lang: "c".to_owned(),
+    };
+    let func = program.all_funcs().next().unwrap();
+    let ty = program.realize_func(func).inputs[0].ty;
+    assert!(program
+        .layout_of(ty, &env, LangRepr::C, &LayoutTarget::LP64)
+        .is_err());
+}
+
+fn padding_of(program: &str, ty_name: &str) -> Vec<u64> {
+    let mut compiler = crate::Compiler::new();
+    let program = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let env = PunEnv {
+        lang: "c".to_owned(),
+    };
+    let func = program.all_funcs().next().unwrap();
+    let ty = program
+        .realize_func(func)
+        .inputs
+        .iter()
+        .find(|arg| program.format_ty(arg.ty) == ty_name)
+        .unwrap()
+        .ty;
+    program
+        .padding_of(ty, &env, LangRepr::C, &LayoutTarget::LP64)
+        .unwrap()
+}
+
+#[test]
+fn layout_padding_bytes() {
+    let program = r##"
+        struct "Padded" {
+            a "u8"
+            b "u16"
+        }
+        struct "Outer" {
+            inner "Padded"
+            arr "[Padded; 2]"
+            c "u64"
+        }
+        union "Either" {
+            a "u8"
+            b "u16"
+        }
+        @align 4
+        union "AlignedEither" {
+            a "u8"
+            b "u16"
+        }
+        fn "func" {
+            inputs { _ "Outer"; _ "Either"; _ "AlignedEither"; }
+        }
+    "##;
+    assert_eq!(padding_of(program, "Outer"), vec![1, 5, 9, 12, 13, 14, 15]);
+    assert_eq!(padding_of(program, "Either"), Vec::<u64>::new());
+    assert_eq!(padding_of(program, "AlignedEither"), vec![2, 3]);
+}
+
+#[test]
+fn layout_bitints() {
+    let program = r##"
+        struct "BitInts" {
+            a "ubitint1"
+            b "bitint33"
+            c "ubitint65"
+            d "bitint129"
+        }
+        fn "func" {
+            inputs { _ "BitInts"; }
+        }
+    "##;
+    let aarch64 = LayoutTarget {
+        bitint_chunk: 16,
+        ..LayoutTarget::LP64
+    };
+    let layout = layout_of(program, "BitInts", &LayoutTarget::LP64);
+    assert_eq!((layout.size, layout.align), (56, 8));
+    assert_eq!(offsets(&layout), vec![(0, 7), (8, 0), (16, 0), (32, 0)]);
+    let layout = layout_of(program, "BitInts", &aarch64);
+    assert_eq!((layout.size, layout.align), (64, 16));
+    assert_eq!(offsets(&layout), vec![(0, 7), (8, 0), (16, 0), (32, 0)]);
+}
+
+#[test]
+fn layout_leaves() {
+    let program = r##"
+        struct "Inner" {
+            x "u8"
+            y "u32"
+        }
+        struct "Outer" {
+            a "u16"
+            inner "[Inner; 2]"
+            b "&u8"
+        }
+        fn "func" {
+            inputs { _ "Outer"; }
+        }
+    "##;
+    let (program, ty) = arg_ty(program, "Outer");
+    let leaves = program
+        .leaves_of(ty, &c_env(), LangRepr::C, &LayoutTarget::LP64)
+        .unwrap()
+        .into_iter()
+        .map(|leaf| (leaf.name, leaf.offset, leaf.size))
+        .collect::<Vec<_>>();
+    let expected = [
+        ("a", 0, 2),
+        ("inner[0].x", 4, 1),
+        ("inner[0].y", 8, 4),
+        ("inner[1].x", 12, 1),
+        ("inner[1].y", 16, 4),
+        ("b", 24, 8),
+    ]
+    .map(|(name, offset, size)| (name.to_owned(), offset, size));
+    assert_eq!(leaves, expected);
+}
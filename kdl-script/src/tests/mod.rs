@@ -1,3 +1,5 @@
+mod layout;
 mod parse_fail;
+mod sysv;
 mod type_fail;
 mod type_pass;
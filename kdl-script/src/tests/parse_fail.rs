@@ -1127,3 +1127,51 @@ fn aliasless_alias() {
 }
 
  */
+
+#[test]
+#[should_panic = "only function inputs can have attributes"]
+fn arg_attr_on_output() {
+    let program = r##"
+        fn "bad" {
+            outputs {
+                @nonnull
+                x "&u32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "I don't know what a '@bogus' input attribute is"]
+fn unknown_arg_attr() {
+    let program = r##"
+        fn "bad" {
+            inputs {
+                @bogus
+                x "&u32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "@noalias isn't followed by an input to apply to"]
+fn dangling_arg_attr() {
+    let program = r##"
+        fn "bad" {
+            inputs {
+                x "&u32"
+                @noalias
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
@@ -0,0 +1,161 @@
+use crate::parse::LangRepr;
+use crate::{LayoutTarget, PunEnv, SysVClass};
+
+/// The class of each eightbyte, and the fields in it
+fn classify(program: &str, ty_name: &str) -> Vec<(SysVClass, String)> {
+    let mut compiler = crate::Compiler::new();
+    let program = compiler
+        .compile_string("test.kdl", program.to_owned())
+        .unwrap();
+    let env = PunEnv {
+        lang: "c".to_owned(),
+    };
+    let func = program.all_funcs().next().unwrap();
+    let ty = program
+        .realize_func(func)
+        .inputs
+        .iter()
+        .find(|arg| program.format_ty(arg.ty) == ty_name)
+        .unwrap()
+        .ty;
+    program
+        .sysv_classify(ty, &env, LangRepr::C, &LayoutTarget::LP64)
+        .unwrap()
+        .eightbytes
+        .into_iter()
+        .map(|eightbyte| (eightbyte.class, eightbyte.fields.join(", ")))
+        .collect()
+}
+
+#[test]
+fn sysv_mixed_struct() {
+    let program = r##"
+        struct "Mixed" {
+            a "f32"
+            b "f32"
+            c "u32"
+            d "f32"
+        }
+        fn "func" {
+            inputs { _ "Mixed"; }
+        }
+    "##;
+    assert_eq!(
+        classify(program, "Mixed"),
+        vec![
+            (SysVClass::Sse, "a, b".to_owned()),
+            (SysVClass::Integer, "c, d".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn sysv_nested_arrays() {
+    let program = r##"
+        struct "Point" {
+            x "f64"
+        }
+        struct "Points" {
+            points "[Point; 2]"
+        }
+        fn "func" {
+            inputs { _ "Points"; }
+        }
+    "##;
+    assert_eq!(
+        classify(program, "Points"),
+        vec![
+            (SysVClass::Sse, "points[0].x".to_owned()),
+            (SysVClass::Sse, "points[1].x".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn sysv_union_merges() {
+    let program = r##"
+        union "IntOrFloat" {
+            i "u32"
+            f "f64"
+        }
+        fn "func" {
+            inputs { _ "IntOrFloat"; }
+        }
+    "##;
+    assert_eq!(
+        classify(program, "IntOrFloat"),
+        vec![(SysVClass::Integer, "i, f".to_owned())]
+    );
+}
+
+#[test]
+fn sysv_f128() {
+    let program = r##"
+        struct "Quad" {
+            x "f128"
+        }
+        fn "func" {
+            inputs { _ "Quad"; }
+        }
+    "##;
+    assert_eq!(
+        classify(program, "Quad"),
+        vec![
+            (SysVClass::Sse, "x".to_owned()),
+            (SysVClass::SseUp, "x".to_owned())
+        ]
+    );
+}
+
+#[test]
+fn sysv_vectors() {
+    let program = r##"
+        struct "M128" {
+            x "f32x4"
+        }
+        struct "M256" {
+            x "i64x4"
+        }
+        fn "func" {
+            inputs { _ "M128"; _ "M256"; }
+        }
+    "##;
+    assert_eq!(
+        classify(program, "M128"),
+        vec![
+            (SysVClass::Sse, "x".to_owned()),
+            (SysVClass::SseUp, "x".to_owned())
+        ]
+    );
+    // Without AVX, which is what this assumes
+    assert!(classify(program, "M256")
+        .iter()
+        .all(|(class, _)| *class == SysVClass::Memory));
+}
+
+#[test]
+fn sysv_memory() {
+    let program = r##"
+        struct "Big" {
+            a "u64"
+            b "u64"
+            c "u64"
+        }
+        @packed
+        struct "Packed" {
+            a "u8"
+            b "u32"
+        }
+        fn "func" {
+            inputs { _ "Big"; _ "Packed"; }
+        }
+    "##;
+    let classes = |ty| {
+        classify(program, ty)
+            .into_iter()
+            .map(|(class, _)| class)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(classes("Big"), vec![SysVClass::Memory; 3]);
+    assert_eq!(classes("Packed"), vec![SysVClass::Memory]);
+}
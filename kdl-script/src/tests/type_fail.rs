@@ -11,6 +11,32 @@ fn no_primitive() {
     res.map_err(miette::Report::new).unwrap();
 }
 
+#[test]
+#[should_panic = "undefined type name"]
+fn no_signed_bitint1() {
+    let program = r##"
+        struct "wrong" {
+            x "bitint1"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "undefined type name"]
+fn no_padded_bitint() {
+    let program = r##"
+        struct "wrong" {
+            x "bitint033"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
 #[test]
 #[should_panic = "undefined type name"]
 fn no_arg_type() {
@@ -23,3 +49,107 @@ fn no_arg_type() {
     let res = compiler.compile_string("test.kdl", program.to_owned());
     res.map_err(miette::Report::new).unwrap();
 }
+
+#[test]
+#[should_panic = "alias A refers back to itself"]
+fn alias_cycle() {
+    let program = r##"
+        alias "A" "B"
+        alias "B" "A"
+        fn "bad" {
+            inputs { x "A"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "alias A refers back to itself"]
+fn alias_cycle_through_ref() {
+    let program = r##"
+        alias "A" "&A"
+        fn "bad" {
+            inputs { x "A"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "import cycle: a.types.kdl -> b.types.kdl -> a.types.kdl"]
+fn import_cycle() {
+    let program = r##"
+        import "a.types.kdl"
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|path| match path {
+        "a.types.kdl" => Ok(r#"import "b.types.kdl""#.to_owned()),
+        "b.types.kdl" => Ok(r#"import "a.types.kdl""#.to_owned()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    });
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "imported files can only define types"]
+fn import_funcs() {
+    let program = r##"
+        import "funcs.kdl"
+    "##;
+    let mut compiler = crate::Compiler::new()
+        .with_import_loader(|_| Ok(r#"fn "sneaky" { inputs { x "u32"; }; }"#.to_owned()));
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "only references can be @noalias"]
+fn arg_attr_not_ref() {
+    let program = r##"
+        fn "bad" {
+            inputs {
+                @noalias
+                x "u32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "nullable references can't be outputs"]
+fn option_ref_output() {
+    let program = r##"
+        struct "Node" {
+            next "Option<&Node>"
+        }
+        fn "bad" {
+            outputs { _ "Option<&Node>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "only references can be @nonnull"]
+fn arg_attr_option_ref() {
+    let program = r##"
+        fn "bad" {
+            inputs {
+                @nonnull
+                x "Option<&u32>"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
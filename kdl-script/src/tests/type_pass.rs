@@ -28,6 +28,69 @@ fn prim_struct() -> Result<(), miette::Report> {
     Ok(())
 }
 
+#[test]
+fn bitint_struct() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "BitInts" {
+            _0 "ubitint1"
+            _1 "bitint2"
+            _2 "bitint33"
+            _3 "ubitint65"
+            _4 "[bitint129; 2]"
+            _5 "&ubitint7"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn cstr_struct() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Strings" {
+            _0 "cstr"
+            _1 "[cstr; 2]"
+            _2 "&cstr"
+        }
+        fn "strcmp" {
+            inputs { a "cstr"; b "cstr"; }
+            outputs { _ "i32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn option_ref_struct() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Node" {
+            val "u32"
+            next "Option<&Node>"
+        }
+        struct "Tree" {
+            left "Option< &Tree >"
+            right "Option<&Tree>"
+            vals "[Option<&u8>; 2]"
+        }
+        fn "walk" {
+            inputs { head "Option<&Node>"; tree "&Tree"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler.compile_string("test.kdl", program.to_owned())?;
+    let inputs = program
+        .all_funcs()
+        .map(|func| program.realize_func(func))
+        .flat_map(|func| &func.inputs)
+        .map(|arg| program.format_ty(arg.ty))
+        .collect::<Vec<_>>();
+    assert_eq!(inputs, ["Option<&Node>", "&Tree"]);
+    Ok(())
+}
+
 #[test]
 fn c_enum_simple() -> Result<(), miette::Report> {
     let program = r##"
@@ -279,3 +342,90 @@ fn example_puns() -> Result<(), miette::Report> {
     compiler.compile_path("examples/puns.kdl")?;
     Ok(())
 }
+
+#[test]
+fn shared_imports() -> Result<(), miette::Report> {
+    // Both the test and the file it imports import points, but it only gets defined once
+    let program = r##"
+        import "shared/points.types.kdl"
+        import "shared/lines.types.kdl"
+
+        fn "print" {
+            inputs { _ "Point"; _ "Line"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|path| match path {
+        "tests/shared/points.types.kdl" => Ok(r#"
+            struct "Point" {
+                x "f32"
+                y "f32"
+            }
+        "#
+        .to_owned()),
+        "tests/shared/lines.types.kdl" => Ok(r#"
+            import "./points.types.kdl"; struct "Line" { start "Point"; end "Point"; }
+        "#
+        .to_owned()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    });
+    compiler.compile_string("tests/test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn arg_attrs() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+            y "f32"
+        }
+        alias "PointRef" "&Point"
+
+        fn "attrs" {
+            inputs {
+                @noalias
+                @nonnull
+                dst "&Point"
+                @dereferenceable
+                src "PointRef"
+                len "u32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn nontrivial_structs() -> Result<(), miette::Report> {
+    let program = r##"
+        @nontrivial
+        struct "Handle" {
+            id "u32"
+        }
+        struct "Holder" {
+            handle "Handle"
+        }
+        struct "Pointer" {
+            handle "&Handle"
+        }
+        struct "Plain" {
+            id "u32"
+        }
+        fn "take" {
+            inputs { a "Handle"; b "Holder"; c "Pointer"; d "Plain"; e "[Holder; 2]"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler.compile_string("test.kdl", program.to_owned())?;
+    assert!(program.has_nontrivial_tys());
+    let nontrivial = program
+        .all_funcs()
+        .map(|func| program.realize_func(func))
+        .flat_map(|func| &func.inputs)
+        .map(|arg| program.ty_is_nontrivial(arg.ty))
+        .collect::<Vec<_>>();
+    assert_eq!(nontrivial, [true, true, false, false, true]);
+    Ok(())
+}
@@ -164,6 +164,8 @@ pub struct Arg {
     pub name: Ident,
     /// The type of the arg
     pub ty: TyIdx,
+    /// Any [`ArgAttr`][]s on the arg (only inputs that are references have these)
+    pub attrs: Vec<ArgAttr>,
 }
 
 /// A primitive
@@ -205,6 +207,24 @@ pub enum PrimitiveTy {
     Bool,
     /// An opaque pointer (like `void*`)
     Ptr,
+    /// A pointer to a null-terminated string (like `const char*`)
+    ///
+    /// Unlike [`PrimitiveTy::Ptr`][] this points at real memory: the value is the
+    /// bytes of the string (not including the terminator), which can be empty.
+    CStr,
+    /// `bitint33` / `_BitInt(33)`, or `ubitint33` / `unsigned _BitInt(33)`
+    ///
+    /// Any width up to 65535 works (signed ones need at least 2 bits), but the ones
+    /// that don't line up with a normal integer are the interesting ones.
+    BitInt { bits: u16, signed: bool },
+    /// `f32x4` / `__m128` (x86 only)
+    F32x4,
+    /// `i64x2` / `__m128i` (x86 only)
+    I64x2,
+    /// `f32x8` / `__m256` (x86 only, and how it's passed depends on whether AVX is enabled)
+    F32x8,
+    /// `i64x4` / `__m256i` (x86 only, and how it's passed depends on whether AVX is enabled)
+    I64x4,
 }
 
 pub const PRIMITIVES: &[(&str, PrimitiveTy)] = &[
@@ -226,8 +246,58 @@ pub const PRIMITIVES: &[(&str, PrimitiveTy)] = &[
     ("f128", PrimitiveTy::F128),
     ("bool", PrimitiveTy::Bool),
     ("ptr", PrimitiveTy::Ptr),
+    ("cstr", PrimitiveTy::CStr),
+    ("f32x4", PrimitiveTy::F32x4),
+    ("i64x2", PrimitiveTy::I64x2),
+    ("f32x8", PrimitiveTy::F32x8),
+    ("i64x4", PrimitiveTy::I64x4),
 ];
 
+impl PrimitiveTy {
+    /// Get the primitive with this name, including the infinitely many `bitintN`s
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(&(_, prim)) = PRIMITIVES.iter().find(|(prim_name, _)| *prim_name == name) {
+            return Some(prim);
+        }
+        let (signed, bits) = if let Some(bits) = name.strip_prefix("ubitint") {
+            (false, bits)
+        } else {
+            (true, name.strip_prefix("bitint")?)
+        };
+        // No leading zeros or signs, so every width has exactly one name
+        if bits.starts_with(|c: char| !c.is_ascii_digit() || c == '0') {
+            return None;
+        }
+        let bits = bits.parse::<u16>().ok()?;
+        if signed && bits < 2 {
+            return None;
+        }
+        Some(PrimitiveTy::BitInt { bits, signed })
+    }
+
+    /// If this is a SIMD vector, how many bytes it is
+    pub fn vector_size(&self) -> Option<u64> {
+        match self {
+            PrimitiveTy::F32x4 | PrimitiveTy::I64x2 => Some(16),
+            PrimitiveTy::F32x8 | PrimitiveTy::I64x4 => Some(32),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PrimitiveTy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrimitiveTy::BitInt { bits, signed: true } => write!(f, "bitint{bits}"),
+            PrimitiveTy::BitInt {
+                bits,
+                signed: false,
+            } => write!(f, "ubitint{bits}"),
+            _ => write!(f, "{}", format!("{:?}", self).to_lowercase()),
+        }
+    }
+}
+
 /// The Ty of a nominal struct.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct StructTy {
@@ -328,6 +398,11 @@ pub struct ArrayTy {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RefTy {
     pub pointee_ty: TyIdx,
+    /// Whether this is an `Option<&T>`, which can also be null (a `T*` in C)
+    ///
+    /// This is how a type can point to itself (like the `next` of a linked list node),
+    /// since the list has to end somewhere. Nullable references can't be out-params.
+    pub nullable: bool,
 }
 
 /// The Ty of a Pun.
@@ -424,6 +499,10 @@ pub(crate) struct TyCtx {
 #[derive(Debug, Clone)]
 struct TypeFact {
     contains_ref: bool,
+    /// The type is (or contains) a `@nontrivial` struct
+    nontrivial: bool,
+    /// The size of the biggest vector the type is (or contains, not behind a reference)
+    max_vector: Option<u64>,
 }
 
 /// Information about types for a specific scope.
@@ -454,6 +533,9 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
         tys: HashMap::new(),
     });
 
+    // Aliases are transparent, so a cycle of them has no actual type at the bottom
+    check_alias_cycles(&tcx.src, parsed)?;
+
     // Add all the user defined types
     for (ty_name, _ty_decl) in &parsed.tys {
         let _ty_idx = tcx.push_nominal_decl_incomplete(ty_name.clone());
@@ -473,7 +555,20 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
                 .map(|(idx, var)| -> Result<Arg> {
                     let name = ident_var(var.name.clone(), "arg", idx, &var.ty);
                     let ty = tcx.memoize_ty(&var.ty)?;
-                    Ok(Arg { name, ty })
+                    if let Some(attr) = var.attrs.first() {
+                        if !tcx.is_always_ref(ty) {
+                            Err(KdlScriptTypeError {
+                                message: format!("only references can be {}", **attr),
+                                src: tcx.src.clone(),
+                                span: Spanned::span(attr),
+                                help: Some(
+                                    "make this input a reference like \"&MyStruct\"".to_owned(),
+                                ),
+                            })?;
+                        }
+                    }
+                    let attrs = var.attrs.iter().map(|attr| **attr).collect();
+                    Ok(Arg { name, ty, attrs })
                 })
                 .collect::<Result<Vec<_>>>()?;
             let outputs = func_decl
@@ -483,7 +578,21 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
                 .map(|(idx, var)| {
                     let name = ident_var(var.name.clone(), "out", idx, &var.ty);
                     let ty = tcx.memoize_ty(&var.ty)?;
-                    Ok(Arg { name, ty })
+                    if let Ty::Ref(RefTy { nullable: true, .. }) = tcx.realize_ty(ty) {
+                        Err(KdlScriptTypeError {
+                            message: "nullable references can't be outputs".to_owned(),
+                            src: tcx.src.clone(),
+                            span: Spanned::span(&var.ty),
+                            help: Some(
+                                "use a reference like \"&MyStruct\" for an out-param".to_owned(),
+                            ),
+                        })?;
+                    }
+                    Ok(Arg {
+                        name,
+                        ty,
+                        attrs: vec![],
+                    })
                 })
                 .collect::<Result<Vec<_>>>()?;
 
@@ -510,6 +619,65 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
     })
 }
 
+/// Reject aliases that refer back to themselves without going through a nominal type.
+///
+/// Even a reference doesn't help here: `alias "A" "&A"` is as meaningless as C's `typedef A* A`.
+fn check_alias_cycles(src: &Arc<NamedSource>, parsed: &ParsedProgram) -> Result<()> {
+    for (ty_name, ty_decl) in &parsed.tys {
+        let TyDecl::Alias(decl) = ty_decl else {
+            continue;
+        };
+        let mut path = vec![ty_name.to_string()];
+        if alias_leads_to(parsed, ty_name, &decl.alias, &mut path) {
+            Err(KdlScriptTypeError {
+                message: format!("alias {ty_name} refers back to itself"),
+                src: src.clone(),
+                span: Spanned::span(&decl.name),
+                help: Some(format!(
+                    "{} (make one of these a struct to break the cycle)",
+                    path.join(" -> ")
+                )),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether this type refers to the alias `target` through a chain of aliases
+/// (recording the chain in `path`)
+fn alias_leads_to(
+    parsed: &ParsedProgram,
+    target: &Ident,
+    ty: &Tydent,
+    path: &mut Vec<String>,
+) -> bool {
+    match ty {
+        Tydent::Empty => false,
+        Tydent::Array(elem_ty, _) | Tydent::Ref(elem_ty) | Tydent::OptionRef(elem_ty) => {
+            alias_leads_to(parsed, target, elem_ty, path)
+        }
+        Tydent::Name(name) => {
+            if name == target {
+                path.push(name.to_string());
+                return true;
+            }
+            // Some other cycle we're not part of, it will be reported on its own
+            if path.iter().any(|link| name == link) {
+                return false;
+            }
+            let Some(TyDecl::Alias(decl)) = parsed.tys.get(name) else {
+                return false;
+            };
+            path.push(name.to_string());
+            if alias_leads_to(parsed, target, &decl.alias, path) {
+                return true;
+            }
+            path.pop();
+            false
+        }
+    }
+}
+
 impl TyCtx {
     /// Add the builtin types to the TyCtx
     fn add_builtins(&mut self) {
@@ -712,7 +880,17 @@ impl TyCtx {
             Tydent::Empty => self.memoize_inner(Ty::Empty),
             Tydent::Ref(pointee_ty_ref) => {
                 let pointee_ty = self.memoize_ty(pointee_ty_ref)?;
-                self.memoize_inner(Ty::Ref(RefTy { pointee_ty }))
+                self.memoize_inner(Ty::Ref(RefTy {
+                    pointee_ty,
+                    nullable: false,
+                }))
+            }
+            Tydent::OptionRef(pointee_ty_ref) => {
+                let pointee_ty = self.memoize_ty(pointee_ty_ref)?;
+                self.memoize_inner(Ty::Ref(RefTy {
+                    pointee_ty,
+                    nullable: true,
+                }))
             }
             Tydent::Array(elem_ty_ref, len) => {
                 let elem_ty = self.memoize_ty(elem_ty_ref)?;
@@ -722,6 +900,9 @@ impl TyCtx {
                 // Nominal types take a separate path because they're scoped
                 if let Some(ty_idx) = self.resolve_nominal_ty(name) {
                     ty_idx
+                } else if let Some(prim) = PrimitiveTy::from_name(name) {
+                    // There are too many bitints to put in the scope up front
+                    self.memoize_inner(Ty::Primitive(prim))
                 } else {
                     return Err(KdlScriptTypeError {
                         message: format!("use of undefined type name: {name}"),
@@ -758,6 +939,19 @@ impl TyCtx {
             .expect("Internal Compiler Error: invalid TyIdx")
     }
 
+    /// Whether this type is a (non-null) reference, no matter which aliases or puns it goes through
+    fn is_always_ref(&self, ty: TyIdx) -> bool {
+        match self.realize_ty(ty) {
+            Ty::Ref(ref_ty) => !ref_ty.nullable,
+            Ty::Alias(alias) => self.is_always_ref(alias.real),
+            Ty::Pun(pun) => pun
+                .blocks
+                .iter()
+                .all(|block| self.is_always_ref(block.real)),
+            _ => false,
+        }
+    }
+
     /// Resolve a [`PunTy`][] based on the current [`PunEnv`][].
     pub fn resolve_pun(&self, pun: &PunTy, env: &PunEnv) -> Result<TyIdx> {
         for block in &pun.blocks {
@@ -787,6 +981,8 @@ impl TyCtx {
         ) -> Result<Option<TypeFact>> {
             let mut facts = TypeFact {
                 contains_ref: false,
+                nontrivial: false,
+                max_vector: None,
             };
             let mut missing_info = vec![];
             for child_ty in child_tys {
@@ -794,8 +990,14 @@ impl TyCtx {
                     missing_info.push(child_ty);
                     continue;
                 };
-                let TypeFact { contains_ref } = child_fact;
+                let TypeFact {
+                    contains_ref,
+                    nontrivial,
+                    max_vector,
+                } = child_fact;
                 facts.contains_ref |= contains_ref;
+                facts.nontrivial |= nontrivial;
+                facts.max_vector = facts.max_vector.max(*max_vector);
             }
 
             // If everything resolved, great, we're done
@@ -825,16 +1027,27 @@ impl TyCtx {
 
         while let Some(ty_idx) = to_compute.pop() {
             let facts = match self.realize_ty(ty_idx) {
-                Ty::Primitive(_) => Some(TypeFact {
+                Ty::Primitive(prim) => Some(TypeFact {
                     contains_ref: false,
+                    nontrivial: false,
+                    max_vector: prim.vector_size(),
                 }),
                 Ty::Empty => Some(TypeFact {
                     contains_ref: false,
+                    nontrivial: false,
+                    max_vector: None,
                 }),
                 Ty::Enum(_) => Some(TypeFact {
                     contains_ref: false,
+                    nontrivial: false,
+                    max_vector: None,
+                }),
+                // The pointee is behind a reference, so it doesn't matter if it's nontrivial
+                Ty::Ref(_) => Some(TypeFact {
+                    contains_ref: true,
+                    nontrivial: false,
+                    max_vector: None,
                 }),
-                Ty::Ref(_) => Some(TypeFact { contains_ref: true }),
 
                 Ty::Alias(ty) => {
                     let child_tys = vec![ty.real];
@@ -857,6 +1070,7 @@ impl TyCtx {
                     )?
                 }
                 Ty::Struct(ty) => {
+                    let is_nontrivial = ty.attrs.iter().any(|a| matches!(a, Attr::NonTrivial(_)));
                     let child_tys = ty.fields.iter().map(|f| f.ty).collect();
                     aggregate_facts(
                         self,
@@ -865,6 +1079,10 @@ impl TyCtx {
                         ty_idx,
                         child_tys,
                     )?
+                    .map(|facts| TypeFact {
+                        nontrivial: facts.nontrivial || is_nontrivial,
+                        ..facts
+                    })
                 }
                 Ty::Union(ty) => {
                     let child_tys = ty.fields.iter().map(|f| f.ty).collect();
@@ -935,7 +1153,7 @@ impl TyCtx {
     /// Stringify a type.
     pub fn format_ty(&self, ty: TyIdx) -> String {
         match self.realize_ty(ty) {
-            Ty::Primitive(prim) => format!("{:?}", prim).to_lowercase(),
+            Ty::Primitive(prim) => prim.to_string(),
             Ty::Empty => "()".to_string(),
             Ty::Struct(decl) => format!("{}", decl.name),
             Ty::Enum(decl) => format!("{}", decl.name),
@@ -949,7 +1167,11 @@ impl TyCtx {
             }
             Ty::Ref(ref_ty) => {
                 let inner = self.format_ty(ref_ty.pointee_ty);
-                format!("&{}", inner)
+                if ref_ty.nullable {
+                    format!("Option<&{}>", inner)
+                } else {
+                    format!("&{}", inner)
+                }
             }
         }
     }
@@ -988,6 +1210,23 @@ impl TypedProgram {
         self.tcx.ty_facts[&ty].contains_ref
     }
 
+    /// Whether this type is (or contains) a `@nontrivial` struct, which only C++ can declare
+    pub fn ty_is_nontrivial(&self, ty: TyIdx) -> bool {
+        self.tcx.ty_facts[&ty].nontrivial
+    }
+
+    /// The size of the biggest SIMD vector this type is (or contains, not behind a reference)
+    ///
+    /// 256-bit ones are passed differently depending on whether AVX is enabled.
+    pub fn ty_max_vector_size(&self, ty: TyIdx) -> Option<u64> {
+        self.tcx.ty_facts[&ty].max_vector
+    }
+
+    /// Whether any type in the program is [nontrivial][`TypedProgram::ty_is_nontrivial`]
+    pub fn has_nontrivial_tys(&self) -> bool {
+        self.tcx.ty_facts.values().any(|facts| facts.nontrivial)
+    }
+
     pub fn all_funcs(&self) -> impl Iterator<Item = FuncIdx> {
         0..self.builtin_funcs_start
     }
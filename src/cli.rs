@@ -1,7 +1,13 @@
+use crate::error::CliParseError;
+use crate::harness::report::SkipReason;
 use crate::harness::test::*;
 use crate::harness::vals::*;
+use crate::log::MapLogger;
 use crate::toolchains::*;
-use crate::{files::Paths, Config, OutputFormat};
+use crate::{
+    files::Paths, ArmFloatAbi, ChangedFiles, Config, FuzzConfig, Goldens, GroupBy, Instrument,
+    LayoutFormat, OutputFormat, RiscvFloatAbi, Shard, TestOrder,
+};
 
 use camino::Utf8PathBuf;
 use clap::Parser;
@@ -19,15 +25,27 @@ static DEFAULT_CONVENTIONS: &[CallingConvention] = &[
     CallingConvention::Stdcall,
     CallingConvention::Fastcall,
     CallingConvention::Vectorcall,
+    CallingConvention::Thiscall,
+    CallingConvention::Aapcs,
+    CallingConvention::AapcsVfp,
     // Rust!
     CallingConvention::Rust,
 ];
 
+/// Documents [`crate::ExitCode`][] in `--help`
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  every test passed (or was skipped)
+  1  some tests failed
+  2  the arguments were invalid
+  3  a toolchain, rules file, or output couldn't be set up
+  4  some test files couldn't be read or parsed";
+
 /// Pair your toolchains at the ABI Cafe!
 ///
 /// When run, we will generate, build, run, and check the crossproduct of:
 ///
-/// --tests --conventions --reprs --pairs --gen-vals --write-vals --select-vals
+/// --tests --conventions --reprs --pairs --gen-vals --write-vals --select-vals --linkers --calls --lto --target-features
 ///
 /// Most of these combinations will end up marked as "skipped", because e.g.
 /// the cc codegen backend will refuse to try to generate repr(Rust) structs,
@@ -36,6 +54,7 @@ static DEFAULT_CONVENTIONS: &[CallingConvention] = &[
 /// Some of the combinations will end up marked as "busted" or "random" because
 /// they're known to be gibberish or broken, and that's ok! We're here to find those things!
 #[derive(Parser)]
+#[clap(after_help = EXIT_CODES_HELP)]
 struct Cli {
     /// which test files to run (SimpleStruct, MetersU32, ...)
     ///
@@ -53,9 +72,30 @@ struct Cli {
     #[clap(default_values_t = DEFAULT_REPRS.to_owned())]
     reprs: Vec<LangRepr>,
 
+    /// extra pairs of conventions to try where the caller and callee disagree, with the syntax "caller_conv:callee_conv"
+    ///
+    /// e.g. "c:rust" makes the caller call every function as extern "C" while the callee
+    /// defines them as extern "Rust", like a bad function pointer cast would. These tests
+    /// are expected to fail, to confirm that abi-cafe notices the corruption.
+    ///
+    /// default: (none, both sides always use the same convention)
+    #[clap(long, value_delimiter(','))]
+    mixed_conventions: Vec<String>,
+
+    /// extra pairs of reprs to try where the caller and callee disagree, with the syntax "caller_repr:callee_repr"
+    ///
+    /// e.g. "rust:c" makes the caller lay out types with repr(Rust) while the callee
+    /// uses repr(C). These are genuinely incompatible for most types, so this is
+    /// for confirming that abi-cafe actually notices when the two sides disagree.
+    ///
+    /// default: (none, both sides always use the same repr)
+    #[clap(long, value_delimiter(','))]
+    mixed_reprs: Vec<String>,
+
     /// which toolchains should be available for pairing (cc, rustc, gcc, ...)
+    ///
+    /// default: cc,rustc
     #[clap(long, short = 'l', alias = "impls", value_delimiter(','))]
-    #[clap(default_values_t = DEFAULT_TOOLCHAINS.iter().map(|s| s.to_string()).collect::<Vec<_>>())]
     toolchains: Vec<String>,
 
     /// which toolchain pairings to run for each test (cc_calls_rustc, rustc_calls_rustc, ..)
@@ -75,6 +115,31 @@ struct Cli {
     #[clap(default_values_t = vec![ValueGeneratorKind::Graffiti])]
     gen_vals: Vec<ValueGeneratorKind>,
 
+    /// generate random values with this seed (shorthand for `--gen-vals randomN`)
+    ///
+    /// With --fuzz, this is the seed of the first iteration (and each iteration
+    /// after it counts up from there).
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// keep rerunning the tests with fresh random values until one fails
+    ///
+    /// Every iteration uses a new seed, and the seed of the iteration that failed
+    /// is printed, so you can reproduce it with --seed. This is for hunting
+    /// intermittent bugs, so you'll probably want to select just a few tests.
+    #[clap(long)]
+    fuzz: bool,
+
+    /// with --fuzz, stop after this many iterations
+    ///
+    /// default: (keep going until something fails)
+    #[clap(long)]
+    fuzz_iterations: Option<u64>,
+
+    /// with --fuzz, don't stop at the first failure (every failing seed is printed at the end)
+    #[clap(long)]
+    fuzz_keep_going: bool,
+
     /// which value wrting/reporting styles to generate for each test (harness, print, assert, noop)
     ///
     /// "harness" uses callbacks to report the values back to the abi cafe test harness
@@ -88,12 +153,16 @@ struct Cli {
     #[clap(default_values_t = vec![WriteImpl::HarnessCallback])]
     write_vals: Vec<WriteImpl>,
 
-    /// UNIMPLEMENTED: which of the values in a test to write (see --write-vals)
+    /// only test these functions, and only these values of them
+    ///
+    /// "func" tests only the function with that name (in every test that has one)
+    /// "func:argN" only writes/checks the Nth argument of it
+    /// "func:argN:valM" only writes/checks the Mth value (field) of that argument
+    /// "func:vary:N+M" checks every argument, but fixes all except the Nth and Mth to zero
     ///
-    /// This is an internal feature of abi-cafe, and used in minimization (see --minimize-vals),
-    /// but is not currently exposed as a thing you can actually ask for, pending a syntax.
+    /// default: (every value of every function)
     #[clap(long, short, value_delimiter(','))]
-    select_vals: Vec<String>,
+    select_vals: Vec<FuncSelection>,
 
     /// when a test fails, and we regenerate a minimized value,
     /// replace the --write-vals selection with this one (presumably cleaner/prettier)
@@ -101,6 +170,311 @@ struct Cli {
     #[clap(default_value_t = WriteImpl::Print)]
     minimize_vals: WriteImpl,
 
+    /// treat reference inputs as in/out parameters
+    ///
+    /// The callee checks it got the caller's values through the reference as usual,
+    /// but then writes new values through it, which the caller checks after the call.
+    /// This covers the read-modify-write path that passing by value never touches.
+    /// (References to types that contain references are left alone.)
+    #[clap(long)]
+    inout_refs: bool,
+
+    /// pass a by-value input and a reference to it in the same call
+    ///
+    /// When a function takes a struct by value and a reference to the same type, the
+    /// caller passes `x` and `&x` (the same variable). The callee writes new values through
+    /// the reference before it reports the by-value input, which must still be the caller's
+    /// original: if the write shows up there, the toolchain passed the caller's variable
+    /// instead of a copy. The caller checks it got the write afterwards.
+    #[clap(long)]
+    alias_refs: bool,
+
+    /// make the callee unwind out of every function instead of returning
+    ///
+    /// The callee reports its inputs and then panics, and the caller must catch it. Only
+    /// `--conventions=c_unwind` (and rust) allow this, with any other convention the
+    /// process must abort instead. Only Rust callers and callees are supported.
+    #[clap(long)]
+    unwind: bool,
+
+    /// make Rust declare the fields of repr(Rust) structs in a (deterministically) shuffled order
+    ///
+    /// repr(Rust) lets the compiler reorder fields however it likes, so this checks
+    /// that a pair of Rust toolchains (i.e. two rustc versions, or codegen backends)
+    /// still agree on the layout when the declaration order isn't the obvious one.
+    /// Both sides shuffle the same way, and the shuffle changes with --gen-vals/--seed.
+    #[clap(long)]
+    shuffle_rust_fields: bool,
+
+    /// build the caller and callee as shared libraries, and `dlopen` them at runtime
+    ///
+    /// By default both sides are statically linked into the test binary, which means
+    /// the calls never go through the PLT/GOT, lazy binding, or symbol interposition.
+    /// This tests the dynamic linking path instead. Only works on ELF platforms,
+    /// and only with --write-vals=harness.
+    #[clap(long)]
+    dlopen: bool,
+
+    /// linkers to link each test with (default, bfd, gold, lld, mold)
+    ///
+    /// The linker can affect symbol resolution and section layout, so a test that
+    /// passes with one linker and fails with another points at a relocation or
+    /// visibility bug. Linkers that aren't installed are skipped.
+    #[clap(long, value_delimiter(','))]
+    #[clap(default_values_t = vec![Linker::Default])]
+    linkers: Vec<Linker>,
+
+    /// how the caller should call the callee's functions (direct, indirect, cf_protection)
+    ///
+    /// "indirect" calls every function through a function pointer the compiler can't
+    /// see through, instead of by name. Indirect calls can be lowered differently, and
+    /// they're what CFI (-fcf-protection, -fsanitize=cfi) and pointer authentication check.
+    /// "cf_protection" calls through pointers too, with both sides built with x86's
+    /// -fcf-protection=full (-Zcf-protection=full for rustc, which needs nightly).
+    #[clap(long, value_delimiter(','))]
+    #[clap(default_values_t = vec![CallPath::Direct])]
+    calls: Vec<CallPath>,
+
+    /// link-time optimization modes to build each test with (off, thin, fat)
+    ///
+    /// LTO can inline the callee into the caller, which hides (or "fixes") ABI
+    /// mismatches because there's no real call left. A test that only passes with
+    /// LTO is flagged. Pairs that can't be compiled into one LTO unit are skipped.
+    #[clap(long, value_delimiter(','))]
+    #[clap(default_values_t = vec![Lto::Off])]
+    lto: Vec<Lto>,
+
+    /// sets of extra target features to build each test with (like "+avx2+fma", or "default")
+    ///
+    /// Both sides get the same features (rustc's -Ctarget-feature, or gcc/clang's
+    /// -m<feature>/-mno-<feature>). Features can change how vectors are passed (AVX
+    /// puts 256-bit vectors in registers). Only x86 features whose rustc and C names
+    /// abi-cafe knows are accepted (sse..sse4.2, avx, avx2, fma, avx512f, bmi1, ...).
+    /// Toolchains that can't take target features (msvc, manifest toolchains) are skipped.
+    #[clap(long, value_delimiter(','))]
+    #[clap(default_values_t = vec![TargetFeatures::default()])]
+    target_features: Vec<TargetFeatures>,
+
+    /// extra pairs of target features to try where the caller and callee were built differently, with the syntax "caller_features:callee_features"
+    ///
+    /// e.g. "+avx2:default" builds the caller with AVX2 and the callee without, like
+    /// a program built for a newer CPU calling into a library that wasn't. Tests that
+    /// pass 256-bit vectors by value are expected to fail when only one side has AVX.
+    ///
+    /// default: (none, both sides always have the same features)
+    #[clap(long, value_delimiter(','))]
+    mixed_target_features: Vec<String>,
+
+    /// watch the test binaries with a tool that catches bad memory accesses (valgrind, msan)
+    ///
+    /// Reading padding, uninitialized bytes, or past the end of an argument is an ABI bug
+    /// that comparing values usually can't see. Anything the tool reports fails the test,
+    /// with the tool's report attached. "valgrind" runs the binaries under memcheck (and
+    /// needs valgrind installed), "msan" compiles everything with `-fsanitize=memory`
+    /// (which needs clang and a nightly rustc, so other toolchains are skipped).
+    #[clap(long)]
+    instrument: Option<Instrument>,
+
+    /// the RISC-V float ABI to test (ilp32, ilp32f, ilp32d, lp64, lp64f, lp64d)
+    ///
+    /// Every C compiler is told to use it with `-mabi` (and a `-march` that has the
+    /// float registers it needs, unless CFLAGS has one), so a cross compiler that defaults
+    /// to something else can't quietly test the wrong ABI. rustc gets the matching
+    /// `-Ctarget-feature`, but it can't change its float ABI (and objects
+    /// with different float ABIs won't link), so this has to be the float ABI of the
+    /// target: pick one with a target that uses it, like riscv64gc-unknown-linux-gnu for
+    /// lp64d. By default we use whatever the target uses.
+    #[clap(long)]
+    riscv_float_abi: Option<RiscvFloatAbi>,
+
+    /// the 32-bit ARM float ABI to test (soft, softfp, hard)
+    ///
+    /// Every C compiler is told to use it with `-mfloat-abi`. rustc's float ABI is fixed by
+    /// its target, so this has to link with it: hard for the eabihf targets, soft or softfp
+    /// (which pass floats the same way) for the others. Either way the
+    /// aapcs and aapcs_vfp conventions test both ways of passing floats.
+    /// By default we use whatever the target uses.
+    #[clap(long)]
+    arm_float_abi: Option<ArmFloatAbi>,
+
+    /// include the kdl-script source of each failing test in the json report
+    ///
+    /// This makes a report self-contained, so it can be archived or shared with
+    /// people who don't have the test files (or procgen'd tests) on hand.
+    #[clap(long)]
+    embed_source: bool,
+
+    /// record every setting of this run in the json report
+    ///
+    /// This is everything the flags resolved to (conventions, pairs, reprs, values,
+    /// writers...), so a saved report says exactly how to produce it again.
+    /// Paths are made relative to the current dir (or the home dir) where possible.
+    #[clap(long)]
+    embed_config: bool,
+
+    /// write a Cargo project that reproduces each minimized failure with a Rust side
+    ///
+    /// These go in target/reproducers/, and `cargo test` in one builds the C side (if any)
+    /// with build.rs and fails if the two sides disagree about the value, without any of
+    /// abi-cafe involved. That's the format for filing the failure as a rustc bug.
+    /// Both sides get the compilers and flags the failing test used.
+    #[clap(long)]
+    cargo_reproducers: bool,
+
+    /// record how big each test's callee functions came out (in bytes of machine code)
+    ///
+    /// This probes the compiled callee with `nm`, so comparing e.g. conv_c and
+    /// conv_fastcall (see `--group-by convention`) shows what a convention costs.
+    #[clap(long)]
+    codegen_size: bool,
+
+    /// compile one throwaway test side with each toolchain before any tests start
+    ///
+    /// The first compile with each toolchain is slowed down by cold caches, which skews
+    /// the timings in the json report. This gets it out of the way first, so the timings
+    /// of the actual tests are comparable. The warmup isn't part of the report.
+    #[clap(long)]
+    warmup: bool,
+
+    /// also emit the LLVM IR of both sides of each test (for LLVM-based toolchains)
+    ///
+    /// The IR is written next to the compiled sides as `<lib>.ll`, and failing tests
+    /// point at it, so you can inspect the lowered call sequence without rerunning anything.
+    /// Toolchains that don't use LLVM (gcc, msvc, custom codegen backends) are skipped.
+    #[clap(long)]
+    emit_llvm_ir: bool,
+
+    /// make every callee go through a chain of this many tail calls before doing its job
+    ///
+    /// Each link of the chain has the same signature and calling convention as the
+    /// function being tested, and is marked as never-inline. Every call must really be
+    /// a tail call (`become` in Rust, which needs nightly, and clang's musttail in C,
+    /// which only applies to functions that return something unless the test is C++),
+    /// and the toolchains that can't promise that are unsupported. This catches
+    /// conventions that get corrupted by tail calls, which a callee that's a leaf
+    /// function never could.
+    ///
+    /// default: 0 (the callee is the function that gets called)
+    #[clap(long, default_value_t = 0)]
+    tail_call_chain: usize,
+
+    /// make every callee call a chain of this many never-inlined functions
+    ///
+    /// The calls happen between reporting the inputs and making the outputs, and every
+    /// level does some integer and float work, so caller-saved registers really get
+    /// clobbered. A callee that never calls anything (like with --write-vals=noop) never
+    /// has to save anything either, so this is what makes conventions like
+    /// preserve_most/preserve_all disagreeing about which registers are preserved show up.
+    ///
+    /// default: 0 (no extra calls)
+    #[clap(long, default_value_t = 0)]
+    nested_calls: usize,
+
+    /// make callers fill the padding of every input with this byte (like 0xaa), so callees can report whether it survived
+    ///
+    /// After working out the values of its inputs, the caller writes the byte into every
+    /// padding byte of them (including padding inside nested structs and arrays). The callee
+    /// then reports the raw bytes of those inputs back, and the report says whether the
+    /// padding arrived untouched ("preserved"), zeroed, or full of something else ("garbage").
+    /// None of these are failures, since every ABI is free to do whatever it wants here.
+    ///
+    /// Only types with a specified layout are checked, and only with --write-vals=harness.
+    #[clap(long)]
+    pad_byte: Option<String>,
+
+    /// make both sides put every i128/u128 through a division before reporting it
+    ///
+    /// Dividing by an opaque 1 leaves the value alone, but lowers to a call into the
+    /// runtime library (__divti3/__udivti3 from compiler-rt, libgcc, or compiler-builtins),
+    /// so the value also has to survive being passed to and returned from those helpers.
+    /// Only applies with --write-vals=harness.
+    #[clap(long)]
+    through_helpers: bool,
+
+    /// run every test in this rounding mode (nearest, upward, downward, towardzero)
+    ///
+    /// The caller switches the FPU to the mode (with fesetround) before its first call, and
+    /// both sides add an opaque 1/3 to every f32/f64 before reporting it, which rounds
+    /// differently in every mode. The callee never sets the mode itself, so both sides only
+    /// agree if it sees the caller's mode, and if the caller's mode (the x87 control word,
+    /// MXCSR, FPCR...) survives the call. This makes the harness blind to tiny errors in
+    /// big floats, so use it alongside normal runs. Only applies with --write-vals=harness.
+    #[clap(long)]
+    rounding_mode: Option<RoundingMode>,
+
+    /// deliberately break every test in the given way (argcount, align), to check that abi-cafe notices
+    ///
+    /// "argcount" makes the caller omit the last input of every function
+    ///
+    /// "align" makes the caller declare every struct with 64-byte alignment, so the two
+    /// sides disagree about the size of the struct, where its fields are when it's
+    /// inside something else, and how it's passed (which can also crash the test)
+    ///
+    /// Tests with a fault injected are expected to fail, so they will be reported
+    /// as failures only if the fault *wasn't* caught.
+    #[clap(long)]
+    inject_fault: Option<FaultInjection>,
+
+    /// treat skipped tests as errors (optionally only for the given reasons: rules, convention, repr, unsupported)
+    ///
+    /// "rules" are tests skipped by test rules
+    /// "convention" are tests where a toolchain doesn't support the calling convention
+    /// "repr" are tests where a toolchain doesn't support the type repr
+    /// "unsupported" are tests where a toolchain doesn't support something else
+    ///
+    /// default: (skips are fine), or all reasons if the flag is passed without any
+    #[clap(long, value_delimiter(','), num_args = 0..)]
+    error_on_skip: Option<Vec<SkipReason>>,
+
+    /// treat busted tests that only fail between two different toolchains as failures
+    ///
+    /// A test is "interop-only" if it fails for a pair of toolchains, but passes when
+    /// each of them is paired with itself. That's a genuine disagreement between the two
+    /// (rather than one of them being broken on its own), which is usually the most
+    /// interesting kind of failure, even when it's already known and marked busted.
+    #[clap(long)]
+    error_on_interop_only: bool,
+
+    /// treat toolchains that didn't run any tests as failures
+    ///
+    /// Every toolchain named by --toolchains or --pairs that didn't end up in a single
+    /// test that actually ran (because a filter excluded it, it isn't available, or it
+    /// doesn't support any of the conventions...) is listed in the report either way.
+    #[clap(long)]
+    error_on_idle_toolchains: bool,
+
+    /// how many values of one register class the interleaved_* tests should pass
+    ///
+    /// Each count produces functions that pass that many ints (or floats) with
+    /// the other class interleaved between them, so sweeping this goes from
+    /// "fits in registers" to "heavily spilled onto the stack".
+    #[clap(long, value_delimiter(','))]
+    #[clap(default_values_t = vec![1, 2, 4, 6, 8, 10, 12, 16])]
+    interleave_counts: Vec<usize>,
+
+    /// how many arguments the stack_args test should pass on the stack (after filling the registers)
+    ///
+    /// Each count produces functions that pass that many ints, bytes, mixed sizes,
+    /// and small structs after enough arguments to use up every register, so big
+    /// counts reach deep into the caller's stack (and past the red zone).
+    #[clap(long, value_delimiter(','))]
+    #[clap(default_values_t = vec![1, 4, 16, 64])]
+    stack_args: Vec<usize>,
+
+    /// C headers to generate tests from, one test per header (named after the file)
+    ///
+    /// Every function the header declares becomes a function of the test (along with the
+    /// types it uses), so an existing C API can be tested without writing any kdl-script.
+    /// Anything that can't be expressed in kdl-script (variadic functions, bitfields,
+    /// long double...) is skipped with a warning. This needs libclang, which is loaded at
+    /// runtime (set LIBCLANG_PATH if it can't be found).
+    #[clap(long, value_delimiter(','))]
+    import_headers: Vec<Utf8PathBuf>,
+
+    /// extra args for libclang when parsing --import-headers, like "-Ipath/to/include -DFOO"
+    #[clap(long, allow_hyphen_values = true, value_delimiter(' '))]
+    header_clang_args: Vec<String>,
+
     /// UNIMPLEMENTED: sugar for selecting all the test combo settings at once using
     /// the test key syntax. i.e. "mytest::conv_rust::repr_rust::rustc_calls_cc::random3"
     ///
@@ -108,10 +482,138 @@ struct Cli {
     #[clap(long, short, value_delimiter(','))]
     key: Vec<String>,
 
-    /// final report output format (human, json)
-    #[clap(long, default_value_t = OutputFormat::Human)]
+    /// final report output format (human, json, rustc-json, compact, heatmap)
+    ///
+    /// "compact" prints one line per failed or busted test, `id<TAB>conclusion<TAB>reason`,
+    /// and nothing else, for piping into grep/awk or diffing two runs.
+    ///
+    /// "heatmap" prints a grid of toolchain pairs by calling conventions, with how many
+    /// tests in each cell passed, failed, and were busted.
+    #[clap(long, default_value_t = OutputFormat::Human, global = true)]
     output_format: OutputFormat,
 
+    /// write the final report to this file (creating it) instead of stdout
+    ///
+    /// Logs and progress still go to the terminal, so this is a nice way to
+    /// save a json report as an artifact while still watching the run.
+    #[clap(long, global = true)]
+    output: Option<Utf8PathBuf>,
+
+    /// also append the results to this SQLite database (creating it if needed)
+    ///
+    /// Every test gets a row in the `results` table (with a timestamp, the target, the
+    /// toolchains, convention, repr, and conclusion), so keeping one database across
+    /// runs lets you query how results changed over time.
+    #[clap(long)]
+    sqlite: Option<Utf8PathBuf>,
+
+    /// with --sqlite, the revision to file the results under (like the commit of the compiler being tested)
+    #[clap(long)]
+    sqlite_revision: Option<String>,
+
+    /// keep a work queue of the run in this file, and skip any test it says already finished
+    ///
+    /// The first run with a given file lists every test in it, and records the result
+    /// of each test as soon as it's done. If that run gets interrupted, running again with
+    /// the same flags and the same file only runs the tests that hadn't finished, and the
+    /// report still covers every test, with the earlier ones read back from the file.
+    #[clap(long)]
+    resume: Option<Utf8PathBuf>,
+
+    /// instead of running any tests, print the layout (size/align/field offsets/padding)
+    /// we compute for every type in the selected tests, in this format (human, json)
+    ///
+    /// These are the layouts for the current target, assuming repr(C). Diffing the json
+    /// across abi-cafe versions or targets is a good way to catch silent layout changes.
+    #[clap(long)]
+    emit_layouts: Option<LayoutFormat>,
+
+    /// with --emit-layouts, also print how x86_64 SysV classifies each type (INTEGER/SSE/MEMORY)
+    ///
+    /// This is the classification of each eightbyte abi-cafe computes itself, so you can
+    /// compare it to what the compilers actually do with the type.
+    #[clap(long)]
+    sysv_classes: bool,
+
+    /// with --emit-layouts, compute every layout this many times and fail if they ever differ
+    ///
+    /// Each time after the first, every test is compiled again from its source, so this
+    /// catches layouts (and type orders) that depend on hashing or iteration order anywhere
+    /// in kdl-script, not just in the layout computation itself. It's a check on abi-cafe,
+    /// no compilers are involved.
+    #[clap(long)]
+    layout_iterations: Option<usize>,
+
+    /// instead of running any tests, check that the compilers agree with the layouts abi-cafe computes
+    ///
+    /// Every toolchain in the selected pairs builds a tiny program that prints the real
+    /// sizeof/alignof/offsetof of each type, which gets compared to the repr(C) layout
+    /// from --emit-layouts. Mismatches are layout bugs, independent of any calling convention.
+    #[clap(long)]
+    check_layouts: bool,
+
+    /// with --check-layouts, also check the layouts the debug info (DWARF) describes
+    ///
+    /// The layout probes are built with debug info, and the DW_AT_byte_size of each type
+    /// and DW_AT_data_member_location of each field get compared to our layouts too.
+    /// Debuggers trust these, so a mismatch means the debug info lies about the layout.
+    /// This needs the DWARF to be in the probe binary itself, so it's mostly for ELF targets.
+    #[clap(long)]
+    check_dwarf: bool,
+
+    /// instead of running any tests, print how many tests the other flags select
+    ///
+    /// This breaks the total down by axis (tests x conventions x pairs x reprs x values
+    /// x writers x selections), so you can see which flag to trim before launching 50k tests.
+    #[clap(long)]
+    count: bool,
+
+    /// instead of running any tests, print everything this build of abi-cafe supports as json
+    ///
+    /// This lists every convention, repr, value generator, writer (and the other axes),
+    /// every toolchain with whether its compiler is installed, and every test, so tools
+    /// that drive abi-cafe can build a valid set of flags without hardcoding any of them.
+    #[clap(long)]
+    capabilities: bool,
+
+    /// instead of running any tests, merge the json reports of runs on different targets
+    ///
+    /// The reports (from --output-format=json) are lined up by test, and printed as a grid
+    /// of tests by targets. With --output-format=json the result is one report keyed by
+    /// target, whose possible_rules cover every target.
+    #[clap(long, value_delimiter(','))]
+    merge_reports: Vec<Utf8PathBuf>,
+
+    /// only run one slice of the selected tests, like 2/4 for the second of four
+    ///
+    /// The tests are striped across the shards, so running every shard (say, on different
+    /// CI machines) runs every test exactly once. Every pair of the same test stays in the
+    /// same shard, so interop-only failures are still found. The reports of the shards can
+    /// be put back together with --merge-reports.
+    #[clap(long)]
+    shard: Option<Shard>,
+
+    /// group the human report's results by this part of the test key (test, convention, pair, repr)
+    ///
+    /// default: (no grouping, results are only sorted by outcome)
+    #[clap(long)]
+    group_by: Option<GroupBy>,
+
+    /// what order to start running tests in (priority, declared)
+    ///
+    /// "priority" runs the combinations most likely to fail first (cross-toolchain pairings,
+    /// unusual conventions, mixed reprs, big structs...) so on a long run the interesting
+    /// failures show up early instead of after hours of passing tests. This only changes
+    /// the order tests run in, the final report is always in declared order.
+    #[clap(long, default_value_t = TestOrder::Priority)]
+    order: TestOrder,
+
+    /// how many tests may be building or running at once
+    ///
+    /// Minimizing failures after the run is limited by this too.
+    #[clap(long, short, default_value_t = 128)]
+    jobs: usize,
+
     /// add a rustc_codegen_backend, with the syntax "toolchain_name:path/to/backend"
     ///
     /// toolchain_name here is an arbitrary id that will be used to uniquely identify
@@ -119,16 +621,91 @@ struct Cli {
     #[clap(long, value_delimiter(','))]
     add_rustc_codegen_backend: Vec<String>,
 
+    /// add a C toolchain that uses a specific compiler, with the syntax "toolchain_name=path/to/cc"
+    ///
+    /// toolchain_name here is an arbitrary id that will be used to uniquely identify
+    /// the compiler as a toolchain, for the purposes of --toolchains and --pairs.
+    /// This lets you test several versions of the same compiler in one run
+    /// (e.g. "clang-17=/usr/bin/clang-17,clang-18=/usr/bin/clang-18").
+    ///
+    /// Toolchains added this way are automatically enabled.
+    #[clap(long, alias = "toolchain", value_delimiter(','))]
+    add_toolchain: Vec<String>,
+
+    /// set an environment variable when running test binaries, with the syntax "KEY=VALUE"
+    ///
+    /// This is useful for callees that need things like LD_LIBRARY_PATH to load.
+    /// Can be passed multiple times, and the variables are recorded in the report.
+    #[clap(long)]
+    env: Vec<String>,
+
+    /// run test binaries with the given command, i.e. `<runner> path/to/test_bin`
+    ///
+    /// This is useful for targets that can't run binaries on the host,
+    /// e.g. "qemu-aarch64 -L /usr/aarch64-linux-gnu" or a script that copies
+    /// the binary to a device and runs it over ssh. The runner's stdout and
+    /// exit code are treated exactly like the test binary's would be.
+    /// It's split into args like a shell would, so quote args with spaces in them.
+    #[clap(long)]
+    runner: Option<String>,
+
+    /// keep running, and re-run the tests whenever a file in `--add-tests` (or `--rules`) changes
+    ///
+    /// Rapid saves are debounced into one re-run, and after each run we print
+    /// which tests changed their result since the previous one. This is for
+    /// making authoring tests an interactive loop, so it requires `--add-tests`.
+    #[clap(long)]
+    watch: bool,
+
+    /// if a toolchain we're testing isn't installed, warn and skip its pairings
+    ///
+    /// By default we check that every toolchain's compiler exists before running
+    /// anything, and refuse to run if any are missing (instead of failing every test).
+    #[clap(long)]
+    skip_missing_toolchains: bool,
+
     /// spider the given directory for .kdl and .procgen.kdl test files at runtime,
     /// and add them to the test suite.
     ///
     /// The structure of the subdirectories doesn't matter, you can organize them
     /// however you want, just know we'll spider into all of them to look for test files!
     ///
+    /// This can be passed several times to add several directories (i.e. a private test
+    /// pack alongside your own tests), but every test needs a unique name.
+    ///
     /// Note that there are already builtin tests (disabled with `--disable-builtin-tests`),
     /// and it would be nice for tests to be upstreamed so everyone can benefit!
+    #[clap(long, alias = "tests-dir")]
+    add_tests: Vec<Utf8PathBuf>,
+
+    /// only run the tests affected by the files changed since the given git revision
+    ///
+    /// A test is affected if its file (or a .types.kdl file it imports) changed.
+    /// Any other changed file outside the test directories could be a rules file,
+    /// a toolchain manifest, or the compiler itself, so that runs every test.
+    /// Uncommitted and untracked files count as changed.
+    #[clap(long)]
+    since: Option<String>,
+
+    /// same as `--since`, but with an explicit list of the changed files
+    ///
+    /// Useful when only changes to some directories should count, i.e.
+    /// `--changed-files "$(git diff --name-only origin/main -- tests/ compiler/ | paste -sd,)"`
+    #[clap(long, value_delimiter(','))]
+    changed_files: Option<Vec<Utf8PathBuf>>,
+
+    /// compare the source generated for every test to a golden copy in this directory
+    ///
+    /// Any difference (or missing golden file) fails the test at the generate stage,
+    /// with the first lines that differ. This catches changes to abi-cafe's generators
+    /// that silently change the code they emit. The goldens are laid out just like
+    /// `target/generated_impls`, so they can be checked into a repo next to the tests.
+    #[clap(long)]
+    goldens: Option<Utf8PathBuf>,
+
+    /// write the source generated for every test to `--goldens` instead of comparing it
     #[clap(long)]
-    add_tests: Option<Utf8PathBuf>,
+    update_goldens: bool,
 
     /// Add the test expectations at the given path
     ///
@@ -139,6 +716,28 @@ struct Cli {
     #[clap(long)]
     rules: Option<Utf8PathBuf>,
 
+    /// only run the toolchain pairs allowed by the given file
+    ///
+    /// (If not specified we'll look for a file called abi-cafe-pairs.toml in the working dir)
+    ///
+    /// The file has `[[allow]]` and `[[deny]]` entries with a `caller`, a `callee`
+    /// (either can be "*"), and an optional `reason`. If there are any allow entries,
+    /// only pairs matching one are run. Pairs matching a deny entry are never run.
+    /// Excluded pairs (and why) are recorded in the report.
+    #[clap(long)]
+    pair_rules: Option<Utf8PathBuf>,
+
+    /// register a toolchain for every .toml manifest in the given directory
+    ///
+    /// (If not specified we'll look for a directory called abi-cafe-toolchains in the working dir)
+    ///
+    /// A manifest describes how to compile the output of one of the builtin codegen
+    /// backends (and how it should spell conventions and types), so you can test
+    /// compilers abi-cafe doesn't know about. The toolchains can then be used with
+    /// `--toolchains` and `--pairs` like any other.
+    #[clap(long)]
+    toolchain_manifests: Option<Utf8PathBuf>,
+
     /// Enable debuginfo in compiled binaries.
     ///
     /// i.e. this passes -g to various compilers.
@@ -160,49 +759,386 @@ struct Cli {
     /// deprecated, does nothing (we always procgen now)
     #[clap(long, hide = true)]
     procgen_tests: bool,
+
+    #[clap(subcommand)]
+    command: Option<Subcommand>,
+}
+
+/// Things to do instead of running the tests
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// compare two json reports (from --output-format=json), without running any tests
+    ///
+    /// Every test whose conclusion changed is listed under what it became (newly failing,
+    /// newly passing, newly busted, newly skipped) or as removed, along with how many
+    /// tests didn't change. With --output-format=json the buckets are printed as json.
+    /// The exit status is 1 if anything is newly failing.
+    Compare {
+        /// the report from before the change
+        old: Utf8PathBuf,
+        /// the report from after the change
+        new: Utf8PathBuf,
+    },
+    /// only run the test with this id (like simple::conv_c::repr_c::rustc_calls_cc), and print everything about it
+    ///
+    /// Instead of the usual report this prints the test's options and rules, the signatures
+    /// of its functions, the sources generated for both sides, every command that was run
+    /// to build and run it, and for every subtest the expected and observed values (and
+    /// the minimized reproducer, if it failed). Logging is as verbose as it gets (unless
+    /// RUST_LOG says otherwise).
+    ///
+    /// Only the combinations the other flags select exist, so pass the same flags as the
+    /// run that reported the test (before `explain`).
+    Explain {
+        /// the id of the test, as printed in the report
+        id: String,
+    },
+}
+
+/// Parse the process' CLI args into a Config, and set up logging
+///
+/// The returned logger is the one that was installed globally. Invalid args are
+/// an error (and the logger isn't set up), except for the ones clap rejects itself,
+/// which exit the process.
+pub fn make_app() -> Result<(Config, MapLogger), CliParseError> {
+    let cfg = config_from_cli(Cli::parse())?;
+
+    // Explaining a test is when all the details are wanted
+    let default_filter = if cfg.explain.is_some() {
+        "debug"
+    } else {
+        "info"
+    };
+    let filter_layer = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(default_filter))
+        .expect("failed to initialize logger");
+
+    let logger = MapLogger::new();
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(logger.clone())
+        .init();
+
+    Ok((cfg, logger))
 }
 
-pub fn make_app() -> Config {
+/// Parse the given CLI args into a Config
+///
+/// This is for embedding abi-cafe, so unlike [`make_app`][] it doesn't
+/// touch the global logger. The first arg is the binary name, just like
+/// with `std::env::args()`.
+///
+/// Combinations of args that don't make sense are an error, but args clap itself
+/// rejects (like unknown flags) exit the process.
+pub fn config_from_args<I, T>(args: I) -> Result<Config, CliParseError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    config_from_cli(Cli::parse_from(args))
+}
+
+/// An error for a combination of args that doesn't make sense
+fn usage_error<T>(message: impl Into<String>) -> Result<T, CliParseError> {
+    Err(CliParseError::Other(message.into()))
+}
+
+/// Split an arg like `caller:callee` in two, or complain with `message`
+fn split_arg<'a>(
+    arg: &'a str,
+    separator: &str,
+    message: &str,
+) -> Result<(&'a str, &'a str), CliParseError> {
+    arg.split_once(separator)
+        .ok_or_else(|| CliParseError::Other(format!("{message} (got '{arg}')")))
+}
+
+fn config_from_cli(cli: Cli) -> Result<Config, CliParseError> {
     let Cli {
         tests,
         conventions,
+        mixed_conventions,
         reprs,
+        mixed_reprs,
         toolchains,
         pairs,
         gen_vals,
+        seed,
+        fuzz,
+        fuzz_iterations,
+        fuzz_keep_going,
         write_vals,
         minimize_vals,
+        inout_refs,
+        alias_refs,
+        unwind,
+        shuffle_rust_fields,
+        dlopen,
+        linkers,
+        calls,
+        lto,
+        target_features,
+        mixed_target_features,
+        instrument,
+        riscv_float_abi,
+        arm_float_abi,
+        embed_source,
+        embed_config,
+        cargo_reproducers,
+        codegen_size,
+        warmup,
+        emit_llvm_ir,
+        tail_call_chain,
+        nested_calls,
+        pad_byte,
+        through_helpers,
+        rounding_mode,
+        inject_fault,
+        error_on_skip,
+        error_on_interop_only,
+        error_on_idle_toolchains,
+        interleave_counts,
+        stack_args,
+        import_headers,
+        header_clang_args,
         output_format,
+        output,
+        sqlite,
+        sqlite_revision,
+        resume,
+        emit_layouts,
+        sysv_classes,
+        layout_iterations,
+        check_layouts,
+        check_dwarf,
+        count,
+        capabilities,
+        merge_reports,
+        shard,
+        group_by,
+        order,
+        jobs,
         add_rustc_codegen_backend,
+        add_toolchain,
+        env,
+        runner,
+        watch,
+        skip_missing_toolchains,
         add_tests,
+        since,
+        changed_files,
+        goldens,
+        update_goldens,
         rules,
+        pair_rules,
+        toolchain_manifests,
         disable_builtin_tests,
         disable_builtin_rules,
         debug,
+        select_vals,
         // unimplemented
-        select_vals: _,
         key: _,
         // deprecated
         procgen_tests: _,
-    } = Cli::parse();
+        command,
+    } = cli;
+
+    let custom_c_toolchains: Vec<(String, Utf8PathBuf)> = add_toolchain
+        .iter()
+        .map(|pair| {
+            let (a, b) = split_arg(
+                pair,
+                "=",
+                "invalid --add-toolchain syntax, must be 'impl_name=path/to/compiler'",
+            )?;
+            Ok((String::from(a), Utf8PathBuf::from(b)))
+        })
+        .collect::<Result<_, CliParseError>>()?;
+
+    if let Some(runner) = &runner {
+        crate::harness::split_command_line(runner)
+            .map_err(|e| CliParseError::Other(format!("invalid --runner: {e}")))?;
+    }
+
+    let run_env: Vec<(String, String)> = env
+        .iter()
+        .map(|pair| {
+            let (a, b) = split_arg(pair, "=", "invalid --env syntax, must be 'KEY=VALUE'")?;
+            Ok((String::from(a), String::from(b)))
+        })
+        .collect::<Result<_, CliParseError>>()?;
 
     let run_tests = tests;
-    let run_toolchains = toolchains;
+    // Only the toolchains the user actually asked for get checked for idleness
+    let mut named_toolchains = toolchains.clone();
+    let mut run_toolchains = if toolchains.is_empty() {
+        DEFAULT_TOOLCHAINS.iter().map(|s| s.to_string()).collect()
+    } else {
+        toolchains
+    };
+    for (name, _path) in &custom_c_toolchains {
+        if !run_toolchains.contains(name) {
+            run_toolchains.push(name.clone());
+        }
+        if !named_toolchains.contains(name) {
+            named_toolchains.push(name.clone());
+        }
+    }
     let run_conventions = conventions;
+    let run_mixed_conventions: Vec<(CallingConvention, CallingConvention)> = mixed_conventions
+        .iter()
+        .map(|pair| {
+            let (caller, callee) = split_arg(
+                pair,
+                ":",
+                "invalid --mixed-conventions syntax, must be 'caller_conv:callee_conv'",
+            )?;
+            let parse = |conv: &str| {
+                conv.parse().map_err(|e| {
+                    CliParseError::Other(format!("unknown convention in --mixed-conventions: {e}"))
+                })
+            };
+            Ok((parse(caller)?, parse(callee)?))
+        })
+        .collect::<Result<_, CliParseError>>()?;
     let run_reprs = reprs;
-    let run_values = gen_vals;
+    let run_mixed_reprs: Vec<(LangRepr, LangRepr)> = mixed_reprs
+        .iter()
+        .map(|pair| {
+            let (caller, callee) = split_arg(
+                pair,
+                ":",
+                "invalid --mixed-reprs syntax, must be 'caller_repr:callee_repr'",
+            )?;
+            let parse = |repr: &str| {
+                repr.parse().map_err(|e| {
+                    CliParseError::Other(format!("unknown repr in --mixed-reprs: {e}"))
+                })
+            };
+            Ok((parse(caller)?, parse(callee)?))
+        })
+        .collect::<Result<_, CliParseError>>()?;
+    let run_mixed_target_features: Vec<(TargetFeatures, TargetFeatures)> = mixed_target_features
+        .iter()
+        .map(|pair| {
+            let (caller, callee) = split_arg(
+                pair,
+                ":",
+                "invalid --mixed-target-features syntax, must be 'caller_features:callee_features'",
+            )?;
+            let parse = |features: &str| {
+                features.parse().map_err(|e| {
+                    CliParseError::Other(format!(
+                        "invalid target features in --mixed-target-features: {e}"
+                    ))
+                })
+            };
+            Ok((parse(caller)?, parse(callee)?))
+        })
+        .collect::<Result<_, CliParseError>>()?;
+    let pad_byte = pad_byte
+        .map(|byte| {
+            let parsed = match byte.strip_prefix("0x") {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => byte.parse(),
+            };
+            match parsed {
+                Ok(0) => {
+                    usage_error("--pad-byte 0x00 can't tell preserved padding from zeroed padding")
+                }
+                Ok(byte) => Ok(byte),
+                Err(_) => usage_error("invalid --pad-byte, must be a byte like 0xaa"),
+            }
+        })
+        .transpose()?;
+    let gen_vals_is_default = gen_vals == [ValueGeneratorKind::Graffiti];
+    let run_values = if let Some(seed) = seed {
+        if !gen_vals_is_default {
+            return usage_error("--seed and --gen-vals can't be used together");
+        }
+        vec![ValueGeneratorKind::Random { seed }]
+    } else {
+        gen_vals
+    };
+    if jobs == 0 {
+        return usage_error("--jobs must be at least 1");
+    }
+    let (compare_reports, explain) = match command {
+        Some(Subcommand::Compare { old, new }) => (Some((old, new)), None),
+        Some(Subcommand::Explain { id }) => (None, Some(id)),
+        None => (None, None),
+    };
+    if explain.is_some() && (shard.is_some() || watch || fuzz) {
+        return usage_error(
+            "explain runs one test, so it can't be used with --shard, --watch, or --fuzz",
+        );
+    }
+    if sysv_classes && emit_layouts.is_none() {
+        return usage_error("--sysv-classes only makes sense with --emit-layouts");
+    }
+    if sqlite_revision.is_some() && sqlite.is_none() {
+        return usage_error("--sqlite-revision only makes sense with --sqlite");
+    }
+    if resume.is_some() && (watch || fuzz || explain.is_some()) {
+        return usage_error("--resume can't be used with --watch, --fuzz, or explain (which rerun tests on purpose)");
+    }
+    if layout_iterations.is_some() && emit_layouts.is_none() {
+        return usage_error("--layout-iterations only makes sense with --emit-layouts");
+    }
+    if layout_iterations.is_some_and(|iterations| iterations < 2) {
+        return usage_error(
+            "--layout-iterations must be at least 2 (or there's nothing to compare)",
+        );
+    }
+    if (fuzz_iterations.is_some() || fuzz_keep_going) && !fuzz {
+        return usage_error("--fuzz-iterations and --fuzz-keep-going only make sense with --fuzz");
+    }
+    if fuzz && !gen_vals_is_default {
+        return usage_error("--fuzz picks its own values, so it can't be used with --gen-vals");
+    }
+    if fuzz && watch {
+        return usage_error("--fuzz and --watch can't be used together");
+    }
+    let fuzz = fuzz.then_some(FuzzConfig {
+        first_seed: seed,
+        max_iterations: fuzz_iterations,
+        keep_going: fuzz_keep_going,
+    });
     let run_writers = write_vals;
     let run_selections = vec![FunctionSelector::All];
     let minimizing_write_impl = minimize_vals;
+    let fault_injection = inject_fault;
+    let mut interleave_counts = interleave_counts;
+    if interleave_counts.contains(&0) {
+        return usage_error("--interleave-counts must all be at least 1");
+    }
+    interleave_counts.sort();
+    interleave_counts.dedup();
+    let mut stack_args = stack_args;
+    if stack_args.contains(&0) {
+        return usage_error("--stack-args must all be at least 1");
+    }
+    stack_args.sort();
+    stack_args.dedup();
 
     let mut run_pairs: Vec<_> = pairs
         .iter()
         .map(|pair| {
-            pair.split_once("_calls_")
-                .expect("invalid 'pair' syntax, must be 'impl_calls_impl'")
+            let (a, b) = split_arg(
+                pair,
+                "_calls_",
+                "invalid --pairs syntax, must be 'impl_calls_impl'",
+            )?;
+            Ok((String::from(a), String::from(b)))
         })
-        .map(|(a, b)| (String::from(a), String::from(b)))
-        .collect();
+        .collect::<Result<_, CliParseError>>()?;
+    for (caller, callee) in &run_pairs {
+        for name in [caller, callee] {
+            if !named_toolchains.contains(name) {
+                named_toolchains.push(name.clone());
+            }
+        }
+    }
 
     // If no pairs specified, add default ones
     if run_pairs.is_empty() {
@@ -222,11 +1158,14 @@ pub fn make_app() -> Config {
     let rustc_codegen_backends: Vec<(String, String)> = add_rustc_codegen_backend
         .iter()
         .map(|pair| {
-            pair.split_once(':')
-                .expect("invalid syntax, must be 'impl_name:path/to/backend'")
+            let (a, b) = split_arg(
+                pair,
+                ":",
+                "invalid --add-rustc-codegen-backend syntax, must be 'impl_name:path/to/backend'",
+            )?;
+            Ok((String::from(a), String::from(b)))
         })
-        .map(|(a, b)| (String::from(a), String::from(b)))
-        .collect();
+        .collect::<Result<_, CliParseError>>()?;
 
     for (name, _path) in &rustc_codegen_backends {
         if !run_pairs.iter().any(|(a, b)| a == name || b == name) {
@@ -238,24 +1177,43 @@ Hint: Try using `--pairs {name}_calls_rustc` or `--pairs rustc_calls_{name}`.
         }
     }
 
-    let filter_layer = EnvFilter::try_from_default_env()
-        .or_else(|_| EnvFilter::try_new("info"))
-        .expect("failed to initialize logger");
-
-    let logger = crate::log::MapLogger::new();
-    tracing_subscriber::registry()
-        .with(filter_layer)
-        .with(logger.clone())
-        .init();
-
     let target_dir: Utf8PathBuf = "target".into();
     let out_dir = target_dir.join("temp");
     let generated_src_dir = target_dir.join("generated_impls");
-    let runtime_test_input_dir = add_tests;
+    let runtime_test_input_dirs = add_tests;
+    let changed_files = match (since, changed_files) {
+        (Some(_), Some(_)) => {
+            return usage_error("--since and --changed-files can't be used together")
+        }
+        (Some(rev), None) => Some(ChangedFiles::Since(rev)),
+        (None, Some(files)) => Some(ChangedFiles::List(
+            // An empty list (nothing changed) comes through as one empty path
+            files
+                .into_iter()
+                .filter(|file| !file.as_str().is_empty())
+                .collect(),
+        )),
+        (None, None) => None,
+    };
+    if check_dwarf && !check_layouts {
+        return usage_error("--check-dwarf only does anything with --check-layouts");
+    }
+    if update_goldens && goldens.is_none() {
+        return usage_error("--update-goldens needs --goldens to know where to write them");
+    }
+    let goldens = goldens.map(|dir| Goldens {
+        dir,
+        update: update_goldens,
+    });
+    if watch && runtime_test_input_dirs.is_empty() {
+        return usage_error(
+            "--watch requires --add-tests (the builtin tests are baked into the binary)",
+        );
+    }
     let runtime_rules_file = if let Some(rules) = rules {
         // If they specify rules, require them to exist
         if !rules.exists() {
-            panic!("could not find --rules {rules}");
+            return usage_error(format!("could not find --rules {rules}"));
         }
         Some(rules)
     } else {
@@ -268,28 +1226,162 @@ Hint: Try using `--pairs {name}_calls_rustc` or `--pairs rustc_calls_{name}`.
         }
     };
 
+    let runtime_pair_rules_file = if let Some(pair_rules) = pair_rules {
+        if !pair_rules.exists() {
+            return usage_error(format!("could not find --pair-rules {pair_rules}"));
+        }
+        Some(pair_rules)
+    } else {
+        let default_pair_rules: Utf8PathBuf = "abi-cafe-pairs.toml".into();
+        if default_pair_rules.exists() {
+            Some(default_pair_rules)
+        } else {
+            None
+        }
+    };
+
+    let runtime_toolchain_manifest_dir = if let Some(dir) = toolchain_manifests {
+        if !dir.is_dir() {
+            return usage_error(format!("could not find --toolchain-manifests {dir}"));
+        }
+        Some(dir)
+    } else {
+        let default_dir: Utf8PathBuf = "abi-cafe-toolchains".into();
+        if default_dir.is_dir() {
+            Some(default_dir)
+        } else {
+            None
+        }
+    };
+
     let paths = Paths {
         target_dir,
         out_dir,
         generated_src_dir,
-        runtime_test_input_dir,
+        runtime_test_input_dirs,
         runtime_rules_file,
+        runtime_pair_rules_file,
+        runtime_toolchain_manifest_dir,
     };
-    Config {
+    Ok(Config {
         output_format,
+        output_file: output,
+        sqlite,
+        sqlite_revision,
+        resume,
+        group_by,
+        order,
+        jobs,
         run_conventions,
+        run_mixed_conventions,
         run_reprs,
+        run_mixed_reprs,
         run_toolchains,
+        named_toolchains,
         run_tests,
         run_pairs,
         rustc_codegen_backends,
+        custom_c_toolchains,
+        run_env,
+        runner,
         run_values,
         run_writers,
         run_selections,
+        select_vals,
+        interleave_counts,
+        stack_args,
+        import_headers,
+        header_clang_args,
         minimizing_write_impl,
+        inout_refs,
+        alias_refs,
+        unwind,
+        shuffle_rust_fields,
+        dlopen,
+        run_linkers: linkers,
+        run_call_paths: calls,
+        run_ltos: lto,
+        run_target_features: target_features,
+        run_mixed_target_features,
+        instrument,
+        riscv_float_abi,
+        arm_float_abi,
+        embed_source,
+        embed_config,
+        cargo_reproducers,
+        codegen_size,
+        warmup,
+        emit_llvm_ir,
+        tail_call_chain,
+        nested_calls,
+        pad_byte,
+        through_helpers,
+        rounding_mode,
+        fault_injection,
+        error_on_skip,
+        error_on_interop_only,
+        error_on_idle_toolchains,
         disable_builtin_tests,
         disable_builtin_rules,
         paths,
+        watch,
+        fuzz,
+        skip_missing_toolchains,
+        emit_layouts,
+        sysv_classes,
+        layout_iterations,
+        check_layouts,
+        check_dwarf,
+        count,
+        capabilities,
+        merge_reports,
+        compare_reports,
+        shard,
+        explain,
+        changed_files,
+        goldens,
         debug,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli_error(args: &[&str]) -> String {
+        match config_from_args(std::iter::once("abi-cafe").chain(args.iter().copied())) {
+            Err(CliParseError::Other(message)) => message,
+            Ok(_) => panic!("{args:?} should be invalid"),
+        }
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert!(config_from_args(["abi-cafe", "--pairs", "cc_calls_rustc"]).is_ok());
+        assert_eq!(cli_error(&["--jobs", "0"]), "--jobs must be at least 1");
+        assert_eq!(
+            cli_error(&["--pairs", "cc_rustc"]),
+            "invalid --pairs syntax, must be 'impl_calls_impl' (got 'cc_rustc')"
+        );
+        assert!(cli_error(&["--mixed-conventions", "c:bogus"])
+            .starts_with("unknown convention in --mixed-conventions: "));
+        assert_eq!(
+            cli_error(&["--pad-byte", "0x00"]),
+            "--pad-byte 0x00 can't tell preserved padding from zeroed padding"
+        );
+        assert_eq!(
+            cli_error(&["--pad-byte", "0xfff"]),
+            "invalid --pad-byte, must be a byte like 0xaa"
+        );
+        assert_eq!(
+            cli_error(&["--rules", "does/not/exist.toml"]),
+            "could not find --rules does/not/exist.toml"
+        );
+        let cfg = config_from_args(["abi-cafe", "--tests", "simple", "explain", "simple::x"]);
+        assert_eq!(cfg.unwrap().explain.as_deref(), Some("simple::x"));
+        assert_eq!(
+            cli_error(&["--watch", "explain", "simple::x"]),
+            "explain runs one test, so it can't be used with --shard, --watch, or --fuzz"
+        );
     }
 }
@@ -1,3 +1,4 @@
+use kdl_script::SysVClassification;
 use miette::Diagnostic;
 
 use crate::{harness::test::TestId, TestBuffer};
@@ -8,8 +9,89 @@ pub enum CliParseError {
     Other(String),
 }
 
+/// An error that stopped [`crate::run`][] from running the tests at all
+#[derive(Debug, thiserror::Error, Diagnostic)]
+pub enum HarnessError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Generate(#[from] GenerateError),
+    /// The details of each failure were already logged
+    #[error("some tests failed to load")]
+    TestsFailedToLoad,
+    #[error("couldn't read the report {path}: {details}")]
+    ReadReport { path: String, details: String },
+    #[error("couldn't record the results in {path}: {details}")]
+    Sqlite { path: String, details: String },
+    #[error("couldn't use the queue file {path}: {details}")]
+    ResumeQueue { path: String, details: String },
+    #[error("some of the toolchains being tested aren't available (pass --skip-missing-toolchains to skip them instead)")]
+    MissingToolchains {
+        #[related]
+        missing: Vec<ToolchainMissingError>,
+    },
+    #[error("can't test the {abi} float ABI on {target}, which uses {target_abi}")]
+    #[diagnostic(help(
+        "rustc's float ABI is fixed by its target, so use a target with that float ABI"
+    ))]
+    RiscvFloatAbi {
+        abi: crate::RiscvFloatAbi,
+        target: String,
+        /// What the target uses instead (or that it isn't RISC-V)
+        target_abi: String,
+    },
+    #[error("can't test the {abi} float ABI on {target}, which uses {target_abi}")]
+    #[diagnostic(help(
+        "rustc's float ABI is fixed by its target, so use a target that passes floats the same way (an eabihf one like armv7-unknown-linux-gnueabihf for hard, an eabi one like armv7-unknown-linux-gnueabi for soft and softfp)"
+    ))]
+    ArmFloatAbi {
+        abi: crate::ArmFloatAbi,
+        target: String,
+        /// What the target uses instead (or that it isn't 32-bit ARM)
+        target_abi: String,
+    },
+    #[error("there's no test {id} to explain")]
+    #[diagnostic(help(
+        "test ids look like simple::conv_c::repr_c::rustc_calls_cc, and only the combinations the other flags select exist (so pass the same flags as the run that reported it)"
+    ))]
+    ExplainNotFound { id: String },
+}
+
+impl HarnessError {
+    /// The exit code the CLI should report this error with
+    pub fn exit_code(&self) -> crate::ExitCode {
+        match self {
+            HarnessError::TestsFailedToLoad
+            | HarnessError::Generate(
+                GenerateError::DuplicateTest { .. } | GenerateError::ReadTest { .. },
+            ) => crate::ExitCode::TestsFailedToLoad,
+            HarnessError::Generate(_)
+            | HarnessError::ReadReport { .. }
+            | HarnessError::Sqlite { .. }
+            | HarnessError::ResumeQueue { .. }
+            | HarnessError::MissingToolchains { .. }
+            | HarnessError::RiscvFloatAbi { .. }
+            | HarnessError::ArmFloatAbi { .. } => crate::ExitCode::Setup,
+            HarnessError::ExplainNotFound { .. } => crate::ExitCode::Usage,
+        }
+    }
+}
+
+/// A toolchain we were asked to test can't actually be used
+#[derive(Debug, Clone, thiserror::Error, Diagnostic)]
+#[error("toolchain {toolchain} needs `{binary}`, but it couldn't be found or run")]
+#[diagnostic(help("{install_hint}"))]
+pub struct ToolchainMissingError {
+    pub toolchain: String,
+    pub binary: String,
+    pub install_hint: String,
+}
+
 #[derive(Debug, thiserror::Error, Diagnostic)]
 pub enum UnsupportedError {
+    #[error("unsupported convention {0}")]
+    Convention(String),
+    #[error("unsupported repr {0}")]
+    Repr(String),
     #[error("unsupported: {0}")]
     Other(String),
 }
@@ -28,6 +110,8 @@ pub enum GenerateError {
     KdlScriptError(#[from] kdl_script::KdlScriptError),
     #[error(transparent)]
     TomlError(#[from] toml::de::Error),
+    #[error("invalid toolchain manifest for {toolchain}: {details}")]
+    InvalidToolchainManifest { toolchain: String, details: String },
     /// Used to signal we just skipped it
     #[error("<skipped>")]
     Skipped,
@@ -43,6 +127,24 @@ pub enum GenerateError {
         block2: String,
         block2_val_count: usize,
     },
+    #[error("there are two tests named {test}:\n  {first}\n  {second}")]
+    #[diagnostic(help("test names come from their file names, so rename one of the files"))]
+    DuplicateTest {
+        test: TestId,
+        first: String,
+        second: String,
+    },
+    #[error("couldn't import C header {path}: {details}")]
+    ImportHeader { path: String, details: String },
+    #[error("couldn't get the files changed since {rev} from git: {details}")]
+    GitDiff { rev: String, details: String },
+    #[error("{src} doesn't match its golden file {golden}\n{details}")]
+    #[diagnostic(help("if the change is intended, rerun with --update-goldens"))]
+    GoldenMismatch {
+        src: String,
+        golden: String,
+        details: String,
+    },
     #[error("failed to read and parse test {test}")]
     ReadTest {
         test: TestId,
@@ -68,6 +170,34 @@ pub enum BuildError {
     RustCompile(std::process::Output),
     #[error("c compile error\n{0}")]
     CCompile(#[from] cc::Error),
+    #[error("{toolchain} compile error \n{} \n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr))]
+    ManifestCompile {
+        toolchain: String,
+        output: std::process::Output,
+    },
+    #[error("{toolchain} couldn't compile the layout probe \n{} \n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr))]
+    ProbeCompile {
+        toolchain: String,
+        output: std::process::Output,
+    },
+    #[error("ar couldn't archive {lib_path} \n{} \n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr))]
+    Archive {
+        lib_path: String,
+        output: std::process::Output,
+    },
+    #[error("{toolchain} couldn't emit llvm ir \n{} \n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr))]
+    IrCompile {
+        toolchain: String,
+        output: std::process::Output,
+    },
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -80,10 +210,13 @@ pub enum CheckFailure {
         caller: {}
         callee: {}
       the value was {val_path}: {val_ty_name}
-      whose arg was {arg_name}: {arg_ty_name}",
+      whose arg was {arg_name}: {arg_ty_name}
+      {write_order}{byte_swapped}{}{}",
         fmt_bytes(expected),
         fmt_bytes(caller),
-        fmt_bytes(callee)
+        fmt_bytes(callee),
+        fmt_sysv(sysv_classes),
+        fmt_sysv_mismatches(sysv_classes, arg_fields)
     )]
     ValMismatch {
         func_idx: usize,
@@ -97,6 +230,15 @@ pub enum CheckFailure {
         expected: Vec<u8>,
         caller: Vec<u8>,
         callee: Vec<u8>,
+        write_order: WriteOrder,
+        /// Which sides saw the expected value with its bytes reversed
+        byte_swapped: ByteSwapped,
+        /// Every value of the arg, for hex dumps (empty if the arg is just this value)
+        arg_fields: Vec<FieldBytes>,
+        /// The size of the arg, if we know where every value in it is
+        arg_size: Option<u64>,
+        /// How x86_64 SysV classifies the arg (if that's how it was passed)
+        sysv_classes: Option<SysVClassification>,
     },
     #[error(
         "    func {func_name}'s value had unexpected variant
@@ -105,7 +247,9 @@ pub enum CheckFailure {
         caller: {caller}
         callee: {callee}
       the value was {val_path}: {val_ty_name}
-      whose arg was {arg_name}: {arg_ty_name}"
+      whose arg was {arg_name}: {arg_ty_name}
+      {write_order}{}",
+        fmt_sysv(sysv_classes)
     )]
     TagMismatch {
         func_idx: usize,
@@ -119,9 +263,97 @@ pub enum CheckFailure {
         expected: String,
         caller: String,
         callee: String,
+        write_order: WriteOrder,
+        /// How x86_64 SysV classifies the arg (if that's how it was passed)
+        sysv_classes: Option<SysVClassification>,
     },
 }
 
+/// The bytes of one of the values in the arg of a [`CheckFailure::ValMismatch`][]
+#[derive(Debug, Clone)]
+pub struct FieldBytes {
+    /// The path to the value inside the arg (i.e. `.y` or `[3].x`)
+    pub field: String,
+    /// Where the value is in the arg (or what it points to), if we know its layout
+    pub offset: Option<u64>,
+    pub expected: Vec<u8>,
+    pub caller: Vec<u8>,
+    pub callee: Vec<u8>,
+}
+
+impl FieldBytes {
+    /// Whether the caller or callee disagreed with the expected value
+    pub fn mismatched(&self) -> bool {
+        self.caller != self.expected || self.callee != self.expected
+    }
+}
+
+/// Which sides of a [`CheckFailure::ValMismatch`][] saw exactly the expected bytes, but reversed
+///
+/// That's what reading a scalar in the wrong byte order looks like, which is an
+/// easy mistake to make (and miss) on big-endian targets like s390x and powerpc64.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByteSwapped {
+    pub caller: bool,
+    pub callee: bool,
+}
+
+impl ByteSwapped {
+    pub fn new(expected: &[u8], caller: &[u8], callee: &[u8]) -> Self {
+        // Values that are their own reverse (like a single byte) can't tell us anything
+        let swapped = |observed: &[u8]| {
+            observed.len() == expected.len()
+                && observed != expected
+                && observed.iter().eq(expected.iter().rev())
+        };
+        Self {
+            caller: swapped(caller),
+            callee: swapped(callee),
+        }
+    }
+}
+
+impl std::fmt::Display for ByteSwapped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sides = match (self.caller, self.callee) {
+            (false, false) => return Ok(()),
+            (true, false) => "the caller's bytes are",
+            (false, true) => "the callee's bytes are",
+            (true, true) => "both sides' bytes are",
+        };
+        write!(
+            f,
+            "\n      {sides} the expected bytes reversed (is something using the wrong byte order?)"
+        )
+    }
+}
+
+/// When a value was written by each side, relative to the other writes in its function
+///
+/// This distinguishes "the value was wrong" from "the values were written in the wrong order".
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOrder {
+    pub expected: usize,
+    pub caller: Option<usize>,
+    pub callee: Option<usize>,
+}
+
+impl std::fmt::Display for WriteOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn fmt_idx(idx: Option<usize>) -> String {
+            idx.map(|idx| format!("#{idx}"))
+                .unwrap_or_else(|| "never".to_owned())
+        }
+        write!(
+            f,
+            "it should be write #{} of the func (caller wrote it {}, callee wrote it {})",
+            self.expected,
+            fmt_idx(self.caller),
+            fmt_idx(self.callee)
+        )
+    }
+}
+
 #[derive(Debug, thiserror::Error, Diagnostic)]
 pub enum LinkError {
     #[error("io error\n{0}")]
@@ -130,6 +362,15 @@ pub enum LinkError {
         String::from_utf8_lossy(&.0.stdout),
         String::from_utf8_lossy(&.0.stderr))]
     RustLink(std::process::Output),
+    #[error("couldn't link {lib} into a shared library \n{} \n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr))]
+    SharedLib {
+        lib: String,
+        output: std::process::Output,
+    },
+    #[error("couldn't find a linker for shared libraries\n{0}")]
+    Cc(#[from] cc::Error),
 }
 
 #[derive(Debug, thiserror::Error, Diagnostic)]
@@ -146,19 +387,30 @@ pub enum RunError {
     #[error("test impl called write_val on func {func} val {val} twice")]
     DoubleWrite { func: usize, val: usize },
     #[error(
-        "test impl exited with bad status (crashed?): {status}
+        "test impl exited with bad status (crashed?): {exit}
     caller last reported: fn {caller_func} value {caller_val_idx}
-    callee last reported: fn {callee_func} value {callee_val_idx}
-    "
+    callee last reported: fn {callee_func} value {callee_val_idx}{}
+    ",
+        fmt_crash_site(*in_call, caller_func)
     )]
     BadExit {
-        status: std::process::ExitStatus,
+        exit: crate::harness::report::TestExit,
         caller_func_idx: usize,
         caller_val_idx: usize,
         caller_func: String,
         callee_func_idx: usize,
         callee_val_idx: usize,
         callee_func: String,
+        /// The caller had started calling a function the callee never got to
+        in_call: bool,
+    },
+    #[error(
+        "{tool} found a problem while running the test{}",
+        fmt_tool_report(report)
+    )]
+    Instrumented {
+        tool: crate::Instrument,
+        report: String,
     },
     #[error("test impl sent invalid messages to harness (executed some kind of UB?)")]
     InvalidMessages {
@@ -167,6 +419,66 @@ pub enum RunError {
     },
 }
 
+fn fmt_tool_report(report: &str) -> String {
+    report.lines().map(|line| format!("\n    {line}")).collect()
+}
+
+fn fmt_crash_site(in_call: bool, caller_func: &str) -> String {
+    if in_call {
+        format!("\n    it crashed calling fn {caller_func}, before the callee started (are the args or the stack set up wrong?)")
+    } else {
+        String::new()
+    }
+}
+
+fn fmt_sysv(classes: &Option<SysVClassification>) -> String {
+    classes
+        .as_ref()
+        .map(|classes| {
+            format!(
+                "\n      x86_64 SysV classifies the arg as: {}",
+                crate::harness::layout::fmt_sysv_classes(classes)
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Which eightbytes of the arg were wrong, and on which side
+///
+/// If only the SSE half (or only the INTEGER half) of an arg came out wrong,
+/// the two sides disagreed about which register that half goes in.
+fn fmt_sysv_mismatches(classes: &Option<SysVClassification>, arg_fields: &[FieldBytes]) -> String {
+    let Some(classes) = classes else {
+        return String::new();
+    };
+    if classes.is_memory() || arg_fields.is_empty() {
+        return String::new();
+    }
+    let mut out = String::new();
+    for (idx, eightbyte) in classes.eightbytes.iter().enumerate() {
+        let fields = arg_fields
+            .iter()
+            .filter(|field| {
+                let name = field.field.strip_prefix('.').unwrap_or(&field.field);
+                eightbyte.fields.iter().any(|leaf| leaf == name)
+            })
+            .collect::<Vec<_>>();
+        let caller = fields.iter().any(|field| field.caller != field.expected);
+        let callee = fields.iter().any(|field| field.callee != field.expected);
+        let sides = match (caller, callee) {
+            (false, false) => "matched",
+            (true, false) => "was wrong for the caller",
+            (false, true) => "was wrong for the callee",
+            (true, true) => "was wrong for both sides",
+        };
+        out.push_str(&format!(
+            "\n      eightbyte {idx} ({}) {sides}",
+            eightbyte.class
+        ));
+    }
+    out
+}
+
 fn fmt_bytes(bytes: &[u8]) -> String {
     bytes
         .iter()
@@ -7,13 +7,15 @@ use crate::GenerateError;
 
 const INCLUDES: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/include");
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Paths {
     pub target_dir: Utf8PathBuf,
     pub out_dir: Utf8PathBuf,
     pub generated_src_dir: Utf8PathBuf,
-    pub runtime_test_input_dir: Option<Utf8PathBuf>,
+    pub runtime_test_input_dirs: Vec<Utf8PathBuf>,
     pub runtime_rules_file: Option<Utf8PathBuf>,
+    pub runtime_pair_rules_file: Option<Utf8PathBuf>,
+    pub runtime_toolchain_manifest_dir: Option<Utf8PathBuf>,
 }
 impl Paths {
     pub fn harness_dylib_main_file(&self) -> Utf8PathBuf {
@@ -25,6 +27,17 @@ impl Paths {
     pub fn freestanding_bin_main_file(&self) -> Utf8PathBuf {
         self.out_dir.join("main.rs")
     }
+    pub fn dlopen_bin_main_file(&self) -> Utf8PathBuf {
+        self.out_dir.join("dlopen_main.rs")
+    }
+    /// The C helpers of `--rounding-mode`
+    pub fn rounding_mode_file(&self) -> Utf8PathBuf {
+        self.out_dir.join("rounding_mode.c")
+    }
+    /// Where `--cargo-reproducers` go
+    pub fn reproducers_dir(&self) -> Utf8PathBuf {
+        self.target_dir.join("reproducers")
+    }
 
     /// Delete and recreate the build dir
     pub fn init_dirs(&self) -> Result<(), GenerateError> {
@@ -57,6 +70,22 @@ impl Paths {
             file.write_all(harness_file_contents.as_bytes())
                 .expect("failed to initialize main.rs");
         }
+        {
+            let harness_file_contents = get_file("harness/dlopen_main.rs");
+            let harness_file_path = self.dlopen_bin_main_file();
+            let mut file = std::fs::File::create_new(harness_file_path)
+                .expect("failed to create dlopen_main.rs");
+            file.write_all(harness_file_contents.as_bytes())
+                .expect("failed to initialize dlopen_main.rs");
+        }
+        {
+            let harness_file_contents = get_file("harness/rounding_mode.c");
+            let harness_file_path = self.rounding_mode_file();
+            let mut file = std::fs::File::create_new(harness_file_path)
+                .expect("failed to create rounding_mode.c");
+            file.write_all(harness_file_contents.as_bytes())
+                .expect("failed to initialize rounding_mode.c");
+        }
         Ok(())
     }
 }
@@ -70,10 +99,15 @@ pub fn clear_and_create_dir(path: impl AsRef<Utf8Path>) {
 
 pub fn get_file(path: impl AsRef<Utf8Path>) -> String {
     let path = path.as_ref();
-    let Some(file) = INCLUDES.get_file(path) else {
+    let Some(file) = try_get_file(path) else {
         unreachable!("embedded file didn't exist: {path}");
     };
-    load_file(file)
+    file
+}
+
+/// Like [`get_file`][] but for paths that came from the user (like kdl-script imports)
+pub fn try_get_file(path: impl AsRef<Utf8Path>) -> Option<String> {
+    INCLUDES.get_file(path.as_ref()).map(load_file)
 }
 
 pub fn load_file(file: &File) -> String {
@@ -1,8 +1,8 @@
 use std::process::Command;
 use std::sync::Arc;
 
-use camino::Utf8Path;
-use tracing::info;
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::{debug, info, warn};
 
 use crate::error::*;
 use crate::harness::report::*;
@@ -22,12 +22,60 @@ impl TestHarness {
         let callee_lib = self
             .build_static_lib(key, CallSide::Callee, &src.callee_src)
             .await?;
+        let rounding_lib = if key.options.rounding_mode.is_some() {
+            Some(self.build_rounding_lib().await?)
+        } else {
+            None
+        };
         Ok(BuildOutput {
             caller_lib,
             callee_lib,
+            rounding_lib,
         })
     }
 
+    /// Build the C helpers Rust sides switch rounding modes and round with
+    /// (once, every test with `--rounding-mode` links the same ones)
+    async fn build_rounding_lib(&self) -> Result<String, BuildError> {
+        let lib_name = "abi_cafe_rounding".to_owned();
+        let once = self
+            .built_static_libs
+            .lock()
+            .unwrap()
+            .entry(lib_name.clone())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+        let lib_name = once
+            .get_or_try_init(|| async {
+                let _token = self
+                    .concurrency_limiter
+                    .acquire()
+                    .await
+                    .expect("failed to acquire concurrency limit semaphore");
+                info!("compiling   {lib_name}");
+                let src_path = self.paths.rounding_mode_file();
+                let mut build = cc::Build::new();
+                build
+                    .cargo_metadata(false)
+                    .cargo_debug(false)
+                    .cargo_warnings(false)
+                    .cargo_output(false)
+                    .target(&self.toolchains.platform_info.target)
+                    .opt_level(0)
+                    .file(&src_path)
+                    .out_dir(&self.paths.out_dir);
+                // cc adds the output and source itself
+                let compiler = build.try_get_compiler()?;
+                self.commands
+                    .record_line(format!("{:?} -c {src_path}", compiler.to_command()));
+                build.try_compile(&lib_name)?;
+                Ok::<_, BuildError>(lib_name.clone())
+            })
+            .await?
+            .clone();
+        Ok(lib_name)
+    }
+
     async fn build_static_lib(
         &self,
         key: &TestKey,
@@ -53,13 +101,148 @@ impl TestHarness {
                     .await
                     .expect("failed to acquire concurrency limit semaphore");
                 info!("compiling   {lib_name}");
-                build_static_lib(&self.paths, src_path, toolchain, call_side, &lib_name).await
+                build_static_lib(
+                    &self.paths,
+                    src_path,
+                    toolchain,
+                    call_side,
+                    &lib_name,
+                    &key.options.codegen_for(call_side),
+                    &self.commands,
+                )
+                .await
             })
             .await?
             .clone();
         Ok(real_lib_name)
     }
 
+    /// Compile something with every toolchain these tests use, and throw it away (`--warmup`)
+    ///
+    /// The first compile with a toolchain pays for cold caches (loading the compiler and
+    /// its libraries off disk), which would otherwise end up in the timings of whichever
+    /// tests happen to build first. So we compile a copy of one of the toolchain's real
+    /// test sides, under a name the actual run never uses, so none of it is reused.
+    pub async fn warm_up(&self, test_keys: &[TestKey]) {
+        let mut warmed = std::collections::BTreeSet::new();
+        for key in test_keys {
+            if self.get_test_rules(key).run < TestRunMode::Build {
+                continue;
+            }
+            for call_side in [CallSide::Caller, CallSide::Callee] {
+                let toolchain_id = key.toolchain_id(call_side);
+                if warmed.contains(toolchain_id) {
+                    continue;
+                }
+                // If this test can't be generated for it, some other test can
+                match self.warm_up_side(key, call_side).await {
+                    Ok(()) => {
+                        warmed.insert(toolchain_id.to_owned());
+                    }
+                    Err(e) => debug!("couldn't warm up {toolchain_id} with {}: {e}", key.test),
+                }
+            }
+        }
+    }
+
+    async fn warm_up_side(&self, key: &TestKey, call_side: CallSide) -> Result<(), GenerateError> {
+        let test = self
+            .test_with_vals(
+                &key.test,
+                key.options.val_generator,
+                key.options.functions.varied_args(),
+            )
+            .await?;
+        let toolchain_id = key.toolchain_id(call_side).to_owned();
+        let test = self.test_with_toolchain(test, toolchain_id.clone()).await?;
+        let toolchain = self.toolchain_by_test_key(key, call_side);
+        let lib_name = format!("warmup_{toolchain_id}");
+        let src_path = self.paths.generated_src_dir.join("warmup").join(format!(
+            "{lib_name}.{}",
+            toolchain.src_ext(&test.types, &key.options.for_call_side(call_side))
+        ));
+        super::generate::generate_src(
+            &src_path,
+            None,
+            toolchain.clone(),
+            test,
+            call_side,
+            key.options.clone(),
+            self.layout_target(),
+        )
+        .await?;
+
+        info!("warming up  {toolchain_id}");
+        // Whether it builds doesn't matter, the compiler ran either way
+        // (and nobody needs to see its commands)
+        let built = build_static_lib(
+            &self.paths,
+            &src_path,
+            toolchain,
+            call_side,
+            &lib_name,
+            &key.options.codegen_for(call_side),
+            &CommandLog::default(),
+        )
+        .await;
+        if let Err(e) = built {
+            debug!("warmup build with {toolchain_id} failed: {e}");
+        }
+        Ok(())
+    }
+
+    /// Emit the LLVM IR of both sides next to their static libs (best-effort)
+    pub async fn emit_test_llvm_ir(&self, key: &TestKey, src: &GenerateOutput) -> LlvmIr {
+        LlvmIr {
+            caller: self
+                .emit_llvm_ir(key, CallSide::Caller, &src.caller_src)
+                .await,
+            callee: self
+                .emit_llvm_ir(key, CallSide::Callee, &src.callee_src)
+                .await,
+        }
+    }
+
+    async fn emit_llvm_ir(
+        &self,
+        key: &TestKey,
+        call_side: CallSide,
+        src_path: &Utf8Path,
+    ) -> Option<Utf8PathBuf> {
+        let toolchain = self.toolchain_by_test_key(key, call_side);
+        let lib_name = self.static_lib_name(key, call_side);
+        // Sides are shared between tests just like their static libs, so only emit them once
+        let once = self
+            .emitted_llvm_ir
+            .lock()
+            .unwrap()
+            .entry(lib_name.clone())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+        once.get_or_init(|| async {
+            let _token = self
+                .concurrency_limiter
+                .acquire()
+                .await
+                .expect("failed to acquire concurrency limit semaphore");
+            let ir_path = self.paths.out_dir.join(format!("{lib_name}.ll"));
+            info!("emitting ir {lib_name}");
+            match toolchain.emit_llvm_ir(src_path, &ir_path, &key.options.codegen_for(call_side)) {
+                Ok(()) => Some(ir_path),
+                Err(BuildError::Io(e)) if e.kind() == std::io::ErrorKind::Unsupported => {
+                    debug!("not emitting llvm ir for {lib_name}: {e}");
+                    None
+                }
+                Err(e) => {
+                    warn!("couldn't emit llvm ir for {lib_name}: {e}");
+                    None
+                }
+            }
+        })
+        .await
+        .clone()
+    }
+
     #[allow(dead_code)]
     pub async fn link_dylib(
         &self,
@@ -73,7 +256,14 @@ impl TestHarness {
             .expect("failed to acquire concurrency limit semaphore");
         let dynamic_lib_name = self.dynamic_lib_name(key);
         info!("linking     {dynamic_lib_name}");
-        build_harness_dylib(&self.toolchains, &self.paths, build, &dynamic_lib_name)
+        build_harness_dylib(
+            &self.toolchains,
+            &self.paths,
+            build,
+            &dynamic_lib_name,
+            key.options.linker,
+            &self.commands,
+        )
     }
 
     pub async fn link_bin(
@@ -81,6 +271,9 @@ impl TestHarness {
         key: &TestKey,
         build: &BuildOutput,
     ) -> Result<LinkOutput, LinkError> {
+        if key.options.dlopen {
+            return self.link_dlopen_bin(key, build).await;
+        }
         let _token = self
             .concurrency_limiter
             .acquire()
@@ -93,7 +286,168 @@ impl TestHarness {
         } else {
             self.paths.freestanding_bin_main_file()
         };
-        build_harness_main(&self.toolchains, &self.paths, build, &bin_name, &bin_main)
+        build_harness_main(
+            &self.toolchains,
+            &self.paths,
+            build,
+            &bin_name,
+            &bin_main,
+            key.options.linker,
+            self.lto_unit(key).map(|unit| (key.options.lto, unit)),
+            &self.commands,
+        )
+    }
+
+    /// The kind of LTO unit both sides of a test are optimized in (None without `--lto`)
+    ///
+    /// [`TestHarness::generate_test`][] already checked that both sides agree on it.
+    pub fn lto_unit(&self, key: &TestKey) -> Option<LtoUnit> {
+        if key.options.lto == Lto::Off {
+            return None;
+        }
+        self.toolchain_by_test_key(key, CallSide::Caller).lto_unit()
+    }
+
+    /// Link the two sides into shared libraries, and a binary that `dlopen`s them (`--dlopen`)
+    async fn link_dlopen_bin(
+        &self,
+        key: &TestKey,
+        build: &BuildOutput,
+    ) -> Result<LinkOutput, LinkError> {
+        let linker = key.options.linker;
+        let rounding_lib = build.rounding_lib.as_deref();
+        let caller_lib = self
+            .link_shared_lib(&build.caller_lib, rounding_lib, linker)
+            .await?;
+        let callee_lib = self
+            .link_shared_lib(&build.callee_lib, rounding_lib, linker)
+            .await?;
+        let _token = self
+            .concurrency_limiter
+            .acquire()
+            .await
+            .expect("failed to acquire concurrency limit semaphore");
+        let bin_name = self.bin_name(key);
+        info!("linking     {bin_name}");
+        build_dlopen_main(
+            &self.toolchains,
+            &self.paths,
+            &caller_lib,
+            &callee_lib,
+            &bin_name,
+            linker,
+            &self.commands,
+        )
+    }
+
+    async fn link_shared_lib(
+        &self,
+        static_lib: &str,
+        rounding_lib: Option<&str>,
+        linker: Linker,
+    ) -> Result<Utf8PathBuf, LinkError> {
+        // Sides are shared between tests just like their static libs, so only link them once
+        // (their names already include the linker)
+        let once = self
+            .built_shared_libs
+            .lock()
+            .unwrap()
+            .entry(static_lib.to_owned())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+        let shared_lib = once
+            .get_or_try_init(|| async {
+                let _token = self
+                    .concurrency_limiter
+                    .acquire()
+                    .await
+                    .expect("failed to acquire concurrency limit semaphore");
+                info!("linking     lib{static_lib}.so");
+                build_shared_lib(
+                    &self.paths,
+                    static_lib,
+                    rounding_lib,
+                    linker,
+                    &self.commands,
+                )
+            })
+            .await?
+            .clone();
+        Ok(shared_lib)
+    }
+
+    /// Find out how big the callee's functions are (best-effort, None if we can't tell)
+    pub async fn measure_codegen_size(
+        &self,
+        key: &TestKey,
+        build: &BuildOutput,
+    ) -> Option<CodegenSize> {
+        let lib_file = if cfg!(target_os = "windows") {
+            format!("{}.lib", build.callee_lib)
+        } else {
+            format!("lib{}.a", build.callee_lib)
+        };
+        let lib_path = self.paths.out_dir.join(lib_file);
+        let output = match Command::new("nm")
+            .arg("--print-size")
+            .arg("--defined-only")
+            .arg(&lib_path)
+            .output()
+        {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                warn!(
+                    "couldn't measure codegen size of {lib_path}: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("couldn't measure codegen size of {lib_path} (is nm installed?): {e}");
+                return None;
+            }
+        };
+        // Lines look like `<addr> <size> <kind> <symbol>`, we only want code
+        let symbol_sizes = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let [_addr, size, kind, symbol] = *line.split_whitespace().collect::<Vec<_>>()
+                else {
+                    return None;
+                };
+                if !kind.eq_ignore_ascii_case("t") {
+                    return None;
+                }
+                let size = u64::from_str_radix(size, 16).ok()?;
+                // Apple platforms prefix every symbol with an underscore
+                let symbol = if cfg!(target_vendor = "apple") {
+                    symbol.strip_prefix('_').unwrap_or(symbol)
+                } else {
+                    symbol
+                };
+                Some((symbol.to_owned(), size))
+            })
+            .collect::<Vec<_>>();
+
+        let test = self.test(&key.test);
+        let mut funcs = SortedMap::new();
+        for func in key.options.functions.active_funcs(&test.types) {
+            let func_name = test.types.realize_func(func).name.as_str();
+            let func_size = symbol_sizes
+                .iter()
+                .filter(|(symbol, _)| {
+                    symbol == func_name
+                        || symbol
+                            .strip_prefix(func_name)
+                            .and_then(|rest| rest.strip_prefix("_tail"))
+                            .is_some_and(|link| link.parse::<usize>().is_ok())
+                })
+                .map(|(_, size)| size)
+                .sum();
+            funcs.insert(func_name.to_string(), func_size);
+        }
+        let total = funcs.values().sum();
+        Some(CodegenSize { funcs, total })
     }
 
     fn static_lib_name(&self, key: &TestKey, call_side: CallSide) -> String {
@@ -121,10 +475,24 @@ async fn build_static_lib(
     toolchain: Arc<dyn Toolchain + Send + Sync>,
     call_side: CallSide,
     static_lib_name: &str,
+    codegen: &CodegenOptions,
+    commands: &CommandLog,
 ) -> Result<String, BuildError> {
     let lib_name = match call_side {
-        CallSide::Callee => toolchain.compile_callee(src_path, &paths.out_dir, static_lib_name)?,
-        CallSide::Caller => toolchain.compile_caller(src_path, &paths.out_dir, static_lib_name)?,
+        CallSide::Callee => toolchain.compile_callee(
+            src_path,
+            &paths.out_dir,
+            static_lib_name,
+            codegen,
+            commands,
+        )?,
+        CallSide::Caller => toolchain.compile_caller(
+            src_path,
+            &paths.out_dir,
+            static_lib_name,
+            codegen,
+            commands,
+        )?,
     };
 
     Ok(lib_name)
@@ -136,6 +504,8 @@ fn build_harness_dylib(
     paths: &Paths,
     build: &BuildOutput,
     dynamic_lib_name: &str,
+    linker: Linker,
+    commands: &CommandLog,
 ) -> Result<LinkOutput, LinkError> {
     let target = &toolchains.platform_info.target;
     let rustc = &toolchains.rustc_command;
@@ -150,6 +520,7 @@ fn build_harness_dylib(
         .arg(&build.caller_lib)
         .arg("-l")
         .arg(&build.callee_lib)
+        .args(build.rounding_lib.iter().flat_map(|lib| ["-l", lib]))
         .arg("--crate-type")
         .arg("cdylib")
         .arg("--target")
@@ -163,8 +534,104 @@ fn build_harness_dylib(
     if toolchains.debug {
         cmd.arg("-g");
     }
+    if toolchains.instrument == Some(Instrument::Msan) {
+        cmd.arg("-Zsanitizer=memory");
+    }
+    cmd.args(linker_args(&toolchains.platform_info.target, linker));
 
-    debug!("running: {:?}", cmd);
+    commands.record(&cmd);
+    let out = cmd.output()?;
+
+    if !out.status.success() {
+        Err(LinkError::RustLink(out))
+    } else {
+        Ok(LinkOutput { test_bin: output })
+    }
+}
+
+/// Turn one side's static lib into a shared library (with the system C compiler)
+fn build_shared_lib(
+    paths: &Paths,
+    static_lib: &str,
+    rounding_lib: Option<&str>,
+    linker: Linker,
+    commands: &CommandLog,
+) -> Result<Utf8PathBuf, LinkError> {
+    let input = paths.out_dir.join(format!("lib{static_lib}.a"));
+    let output = paths.out_dir.join(format!("lib{static_lib}.so"));
+    let mut cmd = cc::Build::new()
+        .cargo_metadata(false)
+        .cargo_warnings(false)
+        .cargo_output(false)
+        .try_get_compiler()?
+        .to_command();
+    // Everything in the static lib has to end up in there, nothing references it yet
+    cmd.arg("-shared")
+        .arg("-o")
+        .arg(&output)
+        .arg("-Wl,--whole-archive")
+        .arg(&input)
+        .arg("-Wl,--no-whole-archive");
+    // A Rust side's rounding helpers have to be in its own lib, nothing else links them
+    // (and fesetround is in libm)
+    if let Some(rounding_lib) = rounding_lib {
+        cmd.arg(paths.out_dir.join(format!("lib{rounding_lib}.a")))
+            .arg("-lm");
+    }
+    if linker != Linker::Default {
+        cmd.arg(format!("-fuse-ld={linker}"));
+    }
+
+    commands.record(&cmd);
+    let out = cmd.output()?;
+
+    if !out.status.success() {
+        Err(LinkError::SharedLib {
+            lib: static_lib.to_owned(),
+            output: out,
+        })
+    } else {
+        Ok(output)
+    }
+}
+
+/// Build the test harness that `dlopen`s the two sides of the FFI boundary.
+fn build_dlopen_main(
+    toolchains: &Toolchains,
+    paths: &Paths,
+    caller_lib: &Utf8Path,
+    callee_lib: &Utf8Path,
+    bin_name: &str,
+    linker: Linker,
+    commands: &CommandLog,
+) -> Result<LinkOutput, LinkError> {
+    let target = &toolchains.platform_info.target;
+    let rustc = &toolchains.rustc_command;
+
+    let output = paths.out_dir.join(bin_name);
+    let mut cmd = Command::new(rustc);
+    // The paths get baked into the binary, so they have to work from anywhere
+    cmd.env("ABI_CAFE_CALLER_LIB", caller_lib.canonicalize_utf8()?)
+        .env("ABI_CAFE_CALLEE_LIB", callee_lib.canonicalize_utf8()?)
+        .arg("-v")
+        .arg("--crate-type")
+        .arg("bin")
+        .arg("--target")
+        .arg(target)
+        // The two sides find the harness callbacks in the binary's dynamic symbols
+        .arg("-Clink-arg=-rdynamic")
+        .arg("-o")
+        .arg(&output)
+        .arg(paths.dlopen_bin_main_file());
+    if toolchains.debug {
+        cmd.arg("-g");
+    }
+    if toolchains.instrument == Some(Instrument::Msan) {
+        cmd.arg("-Zsanitizer=memory");
+    }
+    cmd.args(linker_args(&toolchains.platform_info.target, linker));
+
+    commands.record(&cmd);
     let out = cmd.output()?;
 
     if !out.status.success() {
@@ -175,12 +642,16 @@ fn build_harness_dylib(
 }
 
 /// Compile and link the test harness with the two sides of the FFI boundary.
+#[allow(clippy::too_many_arguments)]
 fn build_harness_main(
     toolchains: &Toolchains,
     paths: &Paths,
     build: &BuildOutput,
     bin_name: &str,
     bin_main: &Utf8Path,
+    linker: Linker,
+    lto: Option<(Lto, LtoUnit)>,
+    commands: &CommandLog,
 ) -> Result<LinkOutput, LinkError> {
     let target = &toolchains.platform_info.target;
     let rustc = &toolchains.rustc_command;
@@ -194,6 +665,7 @@ fn build_harness_main(
         .arg(&build.caller_lib)
         .arg("-l")
         .arg(&build.callee_lib)
+        .args(build.rounding_lib.iter().flat_map(|lib| ["-l", lib]))
         .arg("--crate-type")
         .arg("bin")
         .arg("--target")
@@ -207,8 +679,13 @@ fn build_harness_main(
     if toolchains.debug {
         cmd.arg("-g");
     }
+    if toolchains.instrument == Some(Instrument::Msan) {
+        cmd.arg("-Zsanitizer=memory");
+    }
+    cmd.args(linker_args(&toolchains.platform_info.target, linker));
+    cmd.args(lto_args(lto, linker));
 
-    debug!("running: {:?}", cmd);
+    commands.record(&cmd);
     let out = cmd.output()?;
 
     if !out.status.success() {
@@ -217,3 +694,35 @@ fn build_harness_main(
         Ok(LinkOutput { test_bin: output })
     }
 }
+
+/// The rustc flags to link for `target` with the given linker
+pub fn linker_args(target: &str, linker: Linker) -> Vec<String> {
+    match linker {
+        Linker::Default => vec![],
+        // link.exe doesn't know about -fuse-ld, lld-link has to be the linker itself
+        Linker::Lld if target.ends_with("-msvc") => vec!["-Clinker=lld-link".to_owned()],
+        linker => vec![format!("-Clink-arg=-fuse-ld={linker}")],
+    }
+}
+
+/// The rustc flags to make the linker optimize the two sides together (see [`Lto`][])
+pub fn lto_args(lto: Option<(Lto, LtoUnit)>, linker: Linker) -> Vec<String> {
+    let mut args = vec![];
+    match lto {
+        None => {}
+        Some((_, LtoUnit::Llvm)) => {
+            args.extend(["-Clinker-plugin-lto", "-Copt-level=2"]);
+        }
+        Some((lto, LtoUnit::Gcc)) => {
+            // rustc defaults to lld on some targets, which can't hand GIMPLE back to gcc
+            if linker == Linker::Default {
+                args.push("-Clink-arg=-fuse-ld=bfd");
+            }
+            args.extend(["-Clink-arg=-flto", "-Clink-arg=-O2"]);
+            if lto == Lto::Fat {
+                args.push("-Clink-arg=-flto-partition=one");
+            }
+        }
+    }
+    args.into_iter().map(|arg| arg.to_owned()).collect()
+}
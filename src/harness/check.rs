@@ -1,7 +1,9 @@
 use console::Style;
-use harness::run::{FuncBuffer, ValBuffer};
+use harness::run::{FuncBuffer, TestBuffer, ValBuffer};
+use kdl_script::parse::LangRepr;
 use kdl_script::types::PrimitiveTy;
-use kdl_script::types::Ty;
+use kdl_script::types::{Ty, TyIdx, TypedProgram};
+use kdl_script::{LayoutTarget, PunEnv};
 use tracing::{error, info};
 
 use crate::error::*;
@@ -11,13 +13,19 @@ impl TestHarness {
     pub async fn check_test(
         &self,
         key: &TestKey,
+        rules: &TestRules,
         RunOutput {
             caller_funcs,
             callee_funcs,
+            callee_padding,
         }: &RunOutput,
     ) -> CheckOutput {
         let test = self
-            .test_with_vals(&key.test, key.options.val_generator)
+            .test_with_vals(
+                &key.test,
+                key.options.val_generator,
+                key.options.functions.varied_args(),
+            )
             .await
             .expect("check-test called before test_with_vals!?");
         let options = &key.options;
@@ -29,6 +37,7 @@ impl TestHarness {
         // funcs (subtests) -> vals (args/returns) -> fields -> bytes
 
         let mut results: Vec<SubtestDetails> = Vec::new();
+        let mut inexact_floats = 0;
 
         // `Run` already checks that this length is congruent with all the inputs/outputs Vecs
         let expected_funcs = key.options.functions.active_funcs(&test.types);
@@ -48,11 +57,45 @@ impl TestHarness {
                 }
             }
 
-            for expected_val in expected_vals {
+            for (expected_write_idx, expected_val) in expected_vals.into_iter().enumerate() {
                 let val_idx = expected_val.absolute_val_idx;
                 let caller_val = caller_func.vals.get(val_idx).unwrap_or(&empty_val);
                 let callee_val = callee_func.vals.get(val_idx).unwrap_or(&empty_val);
-                if let Err(e) = self.check_val(&test, expected_val, caller_val, callee_val) {
+                let write_order = WriteOrder {
+                    expected: expected_write_idx,
+                    caller: caller_val.write_idx,
+                    callee: callee_val.write_idx,
+                };
+                if let Err(mut e) = self.check_val(
+                    &test,
+                    expected_val.clone(),
+                    caller_val,
+                    callee_val,
+                    write_order,
+                    rules.float_ulps,
+                    &mut inexact_floats,
+                ) {
+                    if let CheckFailure::ValMismatch {
+                        arg_fields,
+                        arg_size,
+                        ..
+                    } = &mut e
+                    {
+                        (*arg_fields, *arg_size) = arg_bytes(
+                            &test,
+                            &expected_val,
+                            caller_func,
+                            callee_func,
+                            options,
+                            &self.layout_target(),
+                        );
+                    }
+                    if self.uses_sysv_x64(options.convention) {
+                        let (CheckFailure::ValMismatch { sysv_classes, .. }
+                        | CheckFailure::TagMismatch { sysv_classes, .. }) = &mut e;
+                        *sysv_classes =
+                            self.sysv_classes(&test.types, expected_val.arg().ty, options.repr);
+                    }
                     results.push(SubtestDetails {
                         result: Err(e),
                         minimized: None,
@@ -104,6 +147,12 @@ impl TestHarness {
             }
         }
 
+        if inexact_floats > 0 {
+            info!(
+                "{inexact_floats} float values were only within {} ULPs",
+                rules.float_ulps.unwrap_or_default()
+            );
+        }
         if all_passed {
             info!("{}", Style::new().green().apply_to("all tests passed"));
         } else {
@@ -114,7 +163,61 @@ impl TestHarness {
             all_passed,
             subtest_names: names,
             subtest_checks: results,
+            inexact_floats,
+            padding: self.check_padding(key, &test, callee_padding),
+        }
+    }
+
+    /// See what happened to the `--pad-byte` the caller filled the padding of its inputs with
+    ///
+    /// Nothing here is a failure, ABIs are allowed to do anything they want with padding.
+    fn check_padding(
+        &self,
+        key: &TestKey,
+        test: &TestWithVals,
+        callee_padding: &TestBuffer,
+    ) -> Vec<PaddingCheck> {
+        let Some(pad_byte) = key.options.pad_byte else {
+            return vec![];
+        };
+        let env = self.toolchain_by_test_key(key, CallSide::Callee).pun_env();
+        let layout_target = self.layout_target();
+        let mut checks = vec![];
+        for func_idx in key.options.functions.active_funcs(&test.types) {
+            let Some(func) = callee_padding.funcs.get(func_idx) else {
+                continue;
+            };
+            let function = test.types.realize_func(func_idx);
+            for (arg_idx, arg) in function.inputs.iter().enumerate() {
+                let Some(val) = func.vals.get(arg_idx).filter(|val| !val.bytes.is_empty()) else {
+                    continue;
+                };
+                let Ok(padding) =
+                    test.types
+                        .padding_of(arg.ty, &env, key.options.callee_repr, &layout_target)
+                else {
+                    continue;
+                };
+                let bytes = padding
+                    .iter()
+                    .filter_map(|&offset| val.bytes.get(offset as usize).copied())
+                    .collect::<Vec<_>>();
+                let verdict = if bytes.iter().all(|&byte| byte == pad_byte) {
+                    PaddingVerdict::Preserved
+                } else if bytes.iter().all(|&byte| byte == 0) {
+                    PaddingVerdict::Zeroed
+                } else {
+                    PaddingVerdict::Garbage
+                };
+                checks.push(PaddingCheck {
+                    func_name: function.name.to_string(),
+                    arg_name: arg.name.to_string(),
+                    padding_bytes: padding.len(),
+                    verdict,
+                });
+            }
         }
+        checks
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -124,8 +227,12 @@ impl TestHarness {
         expected_val: ValueRef,
         caller_val: &ValBuffer,
         callee_val: &ValBuffer,
+        write_order: WriteOrder,
+        float_ulps: Option<u32>,
+        inexact_floats: &mut usize,
     ) -> Result<(), CheckFailure> {
         let types = &test.types;
+        let big_endian = test.vals.big_endian;
         // Enums and Taggeds are "fake" fields representing the semantic value (tag).
         // In this case showing the bytes doesn't make sense, so show the Variant name
         // (although we get bytes here they're the array index into the variant,
@@ -136,43 +243,88 @@ impl TestHarness {
         // time we're here to print <other variant> and shrug.
         if let Ty::Tagged(tagged_ty) = types.realize_ty(expected_val.ty) {
             let expected_tag = expected_val.generate_idx(tagged_ty.variants.len());
-            let caller_tag = load_tag(caller_val);
-            let callee_tag = load_tag(callee_val);
+            let caller_tag = load_tag(caller_val, big_endian);
+            let callee_tag = load_tag(callee_val, big_endian);
 
             if caller_tag != Some(expected_tag) || callee_tag != Some(expected_tag) {
                 let expected = tagged_variant_name(tagged_ty, Some(expected_tag));
                 let caller = tagged_variant_name(tagged_ty, caller_tag);
                 let callee = tagged_variant_name(tagged_ty, callee_tag);
-                return Err(tag_error(types, &expected_val, expected, caller, callee));
+                return Err(tag_error(
+                    types,
+                    &expected_val,
+                    expected,
+                    caller,
+                    callee,
+                    write_order,
+                ));
             }
         } else if let Ty::Enum(enum_ty) = types.realize_ty(expected_val.ty) {
             let expected_tag = expected_val.generate_idx(enum_ty.variants.len());
-            let caller_tag = load_tag(caller_val);
-            let callee_tag = load_tag(callee_val);
+            let caller_tag = load_tag(caller_val, big_endian);
+            let callee_tag = load_tag(callee_val, big_endian);
 
             if caller_tag != Some(expected_tag) || callee_tag != Some(expected_tag) {
                 let expected = enum_variant_name(enum_ty, Some(expected_tag));
                 let caller = enum_variant_name(enum_ty, caller_tag);
                 let callee = enum_variant_name(enum_ty, callee_tag);
-                return Err(tag_error(types, &expected_val, expected, caller, callee));
+                return Err(tag_error(
+                    types,
+                    &expected_val,
+                    expected,
+                    caller,
+                    callee,
+                    write_order,
+                ));
             }
-        } else if let Ty::Primitive(PrimitiveTy::Bool) = types.realize_ty(expected_val.ty) {
+        } else if let Some(names) = binary_tag_names(types.realize_ty(expected_val.ty)) {
             let expected_tag = expected_val.generate_idx(2);
-            let caller_tag = load_tag(caller_val);
-            let callee_tag = load_tag(callee_val);
+            let caller_tag = load_tag(caller_val, big_endian);
+            let callee_tag = load_tag(callee_val, big_endian);
 
             if caller_tag != Some(expected_tag) || callee_tag != Some(expected_tag) {
-                let expected = bool_variant_name(expected_tag, Some(expected_tag));
-                let caller = bool_variant_name(expected_tag, caller_tag);
-                let callee = bool_variant_name(expected_tag, callee_tag);
-                return Err(tag_error(types, &expected_val, expected, caller, callee));
+                let expected = binary_variant_name(names, expected_tag, Some(expected_tag));
+                let caller = binary_variant_name(names, expected_tag, caller_tag);
+                let callee = binary_variant_name(names, expected_tag, callee_tag);
+                return Err(tag_error(
+                    types,
+                    &expected_val,
+                    expected,
+                    caller,
+                    callee,
+                    write_order,
+                ));
             }
         } else if caller_val.bytes != callee_val.bytes {
+            // Some paths are known to be lossy for floats (x87, flush-to-zero...),
+            // so the rules can allow them to be off by a few ULPs
+            if let (Some(ulps), Ty::Primitive(prim)) =
+                (float_ulps, types.realize_ty(expected_val.ty))
+            {
+                if is_float(*prim)
+                    && within_ulps(&caller_val.bytes, &callee_val.bytes, ulps, big_endian)
+                {
+                    *inexact_floats += 1;
+                    return Ok(());
+                }
+            }
+            // The bits of a _BitInt past its width are unspecified, so ignore them
+            if let Ty::Primitive(PrimitiveTy::BitInt { bits, .. }) =
+                types.realize_ty(expected_val.ty)
+            {
+                if bitint_value(&caller_val.bytes, *bits, big_endian)
+                    == bitint_value(&callee_val.bytes, *bits, big_endian)
+                {
+                    return Ok(());
+                }
+            }
+
             // General case, just get a pile of bytes to span both values
             let func = expected_val.func();
             let arg = expected_val.arg();
-            let mut expected = vec![0; caller_val.bytes.len().max(callee_val.bytes.len())];
-            expected_val.fill_bytes(&mut expected);
+            let len = caller_val.bytes.len().max(callee_val.bytes.len());
+            let expected = expected_bytes(types, &expected_val, len);
+            let byte_swapped = ByteSwapped::new(&expected, &caller_val.bytes, &callee_val.bytes);
             // FIXME: this doesn't do the right thing for enums
             // <https://github.com/Gankra/abi-cafe/issues/34>
             return Err(CheckFailure::ValMismatch {
@@ -187,6 +339,11 @@ impl TestHarness {
                 expected,
                 caller: caller_val.bytes.clone(),
                 callee: callee_val.bytes.clone(),
+                write_order,
+                byte_swapped,
+                arg_fields: vec![],
+                arg_size: None,
+                sysv_classes: None,
             });
         }
 
@@ -194,10 +351,212 @@ impl TestHarness {
     }
 }
 
-fn load_tag(val: &ValBuffer) -> Option<usize> {
+/// Get the bytes of every value in the arg of this value, so the whole arg can be hex dumped
+///
+/// If we know where every value is in the arg (see [`place_fields`][]), this also
+/// returns the size of the arg.
+fn arg_bytes(
+    test: &TestWithVals,
+    val: &ValueRef,
+    caller_func: &FuncBuffer,
+    callee_func: &FuncBuffer,
+    options: &TestOptions,
+    target: &LayoutTarget,
+) -> (Vec<FieldBytes>, Option<u64>) {
+    let types = &test.types;
+    let Some(arg) = test.vals.at_func(val.func_idx).nth(val.arg_idx) else {
+        return (vec![], None);
+    };
+    let mut tags = vec![];
+    let empty_val = ValBuffer::default();
+    let mut fields = arg
+        .filter(|field| field.should_write_val(options))
+        .map(|field| {
+            let caller = caller_func
+                .vals
+                .get(field.absolute_val_idx)
+                .unwrap_or(&empty_val);
+            let callee = callee_func
+                .vals
+                .get(field.absolute_val_idx)
+                .unwrap_or(&empty_val);
+            let len = caller.bytes.len().max(callee.bytes.len());
+            // Tags are written as the u32 index of the variant
+            let num_variants = match types.realize_ty(field.ty) {
+                Ty::Tagged(tagged_ty) => Some(tagged_ty.variants.len()),
+                Ty::Enum(enum_ty) => Some(enum_ty.variants.len()),
+                ty if binary_tag_names(ty).is_some() => Some(2),
+                _ => None,
+            };
+            let expected = if let Some(num_variants) = num_variants {
+                let tag = field.generate_idx(num_variants) as u32;
+                if test.vals.big_endian {
+                    tag.to_be_bytes().to_vec()
+                } else {
+                    tag.to_le_bytes().to_vec()
+                }
+            } else {
+                expected_bytes(types, &field, len)
+            };
+            // The path starts with the name of the arg, which the dump already shows
+            let field_path = field
+                .path
+                .find(['.', '['])
+                .map(|idx| field.path[idx..].to_owned())
+                .unwrap_or_default();
+            tags.push(matches!(types.realize_ty(field.ty), Ty::Tagged(_)));
+            FieldBytes {
+                field: field_path,
+                offset: None,
+                expected,
+                caller: caller.bytes.clone(),
+                callee: callee.bytes.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+    // A dump of one value wouldn't tell you anything the error doesn't
+    if fields.len() <= 1 {
+        return (vec![], None);
+    }
+    let arg_ty = val.arg().ty;
+    let arg_size = place_fields(types, arg_ty, &mut fields, &tags, options.repr, target);
+    (fields, arg_size)
+}
+
+/// Find where each value is in the memory of its arg, and return the size of the arg
+///
+/// For a ref, that's the memory it points to. This gives up (and leaves every offset
+/// None) unless every value is exactly one of the primitives of the arg's layout, so
+/// values behind nested refs, or args without a specified layout, get dumped end to end.
+fn place_fields(
+    types: &TypedProgram,
+    arg_ty: TyIdx,
+    fields: &mut [FieldBytes],
+    tags: &[bool],
+    repr: LangRepr,
+    target: &LayoutTarget,
+) -> Option<u64> {
+    let (ty, prefix) = match types.realize_ty(arg_ty) {
+        Ty::Ref(ref_ty) => (ref_ty.pointee_ty, ".*"),
+        _ => (arg_ty, ""),
+    };
+    let env = PunEnv {
+        lang: "c".to_owned(),
+    };
+    let size = types.layout_of(ty, &env, repr, target).ok()?.size;
+    let leaves = types.leaves_of(ty, &env, repr, target).ok()?;
+    let offsets = fields
+        .iter()
+        .zip(tags)
+        .map(|(field, &is_tag)| {
+            let path = field.field.strip_prefix(prefix)?;
+            let name = path.strip_prefix('.').unwrap_or(path);
+            // The tag of a tagged union is recorded under the path of the union itself
+            let name = match (is_tag, name) {
+                (true, "") => "tag".to_owned(),
+                (true, name) => format!("{name}.tag"),
+                (false, name) => name.to_owned(),
+            };
+            let leaf = leaves.iter().find(|leaf| leaf.name == name)?;
+            (leaf.size == field.expected.len() as u64).then_some(leaf.offset)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    for (field, offset) in fields.iter_mut().zip(offsets) {
+        field.offset = Some(offset);
+    }
+    Some(size)
+}
+
+/// The bytes we expect a (non-tag) value to have
+fn expected_bytes(types: &TypedProgram, val: &ValueRef, len: usize) -> Vec<u8> {
+    match types.realize_ty(val.ty) {
+        Ty::Primitive(PrimitiveTy::BitInt { bits, signed }) => {
+            return val.generate_bitint(*bits, *signed, len as u64);
+        }
+        // Strings are as long as they are, not as long as what the two sides saw
+        Ty::Primitive(PrimitiveTy::CStr) => return val.generate_cstr(),
+        _ => {}
+    }
+    let mut expected = vec![0; len];
+    val.fill_bytes(&mut expected);
+    expected
+}
+
+/// The bytes of a `_BitInt(bits)` with everything past the first `bits` bits zeroed
+fn bitint_value(bytes: &[u8], bits: u16, big_endian: bool) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    if big_endian {
+        bytes.reverse();
+    }
+    extend_bitint(&mut bytes, bits, false);
+    bytes
+}
+
+fn is_float(prim: PrimitiveTy) -> bool {
+    matches!(
+        prim,
+        PrimitiveTy::F16 | PrimitiveTy::F32 | PrimitiveTy::F64 | PrimitiveTy::F128
+    )
+}
+
+/// Whether two floats (of the same size) are at most `ulps` representable values apart
+///
+/// NaNs are never within any ULPs of anything (identical bytes already matched before this).
+fn within_ulps(lhs: &[u8], rhs: &[u8], ulps: u32, big_endian: bool) -> bool {
+    if lhs.len() != rhs.len() || lhs.is_empty() || lhs.len() > 16 {
+        return false;
+    }
+    let bits = lhs.len() as u32 * 8;
+    let load = |bytes: &[u8]| {
+        let mut buf = [0; 16];
+        if big_endian {
+            buf[16 - bytes.len()..].copy_from_slice(bytes);
+            u128::from_be_bytes(buf)
+        } else {
+            buf[..bytes.len()].copy_from_slice(bytes);
+            u128::from_le_bytes(buf)
+        }
+    };
+    let sign_bit = 1u128 << (bits - 1);
+    let exp_bits = match bits {
+        16 => 5,
+        32 => 8,
+        64 => 11,
+        _ => 15,
+    };
+    let mantissa_bits = bits - 1 - exp_bits;
+    let is_nan = |val: u128| {
+        let magnitude = val & !sign_bit;
+        let exp_mask = ((1u128 << exp_bits) - 1) << mantissa_bits;
+        magnitude & exp_mask == exp_mask && magnitude & !exp_mask != 0
+    };
+    // Map the sign-magnitude encoding onto a line where adjacent floats
+    // are adjacent integers (so -0.0 and +0.0 are 1 apart, which is fine)
+    let ordered = |val: u128| -> i128 {
+        let magnitude = (val & !sign_bit) as i128;
+        if val & sign_bit != 0 {
+            -magnitude - 1
+        } else {
+            magnitude
+        }
+    };
+    let (lhs, rhs) = (load(lhs), load(rhs));
+    if is_nan(lhs) || is_nan(rhs) {
+        return false;
+    }
+    ordered(lhs).abs_diff(ordered(rhs)) <= ulps as u128
+}
+
+/// Tags are written as the u32 index of the variant (in the target's byte order)
+fn load_tag(val: &ValBuffer, big_endian: bool) -> Option<usize> {
     let buf = val.bytes.get(..4)?;
     let bytes = <[u8; 4]>::try_from(buf).ok()?;
-    Some(u32::from_ne_bytes(bytes) as usize)
+    let tag = if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    };
+    Some(tag as usize)
 }
 
 fn tagged_variant_name(tagged_ty: &kdl_script::types::TaggedTy, tag: Option<usize>) -> String {
@@ -226,7 +585,18 @@ fn enum_variant_name(enum_ty: &kdl_script::types::EnumTy, tag: Option<usize>) ->
     format!("{enum_name}::{variant_name}")
 }
 
-fn bool_variant_name(expected_tag: usize, tag: Option<usize>) -> String {
+/// The names of the two "variants" of the types whose tag is just a bool
+///
+/// That's bools themselves, and nullable references (whose tag is whether they're null).
+fn binary_tag_names(ty: &Ty) -> Option<[&'static str; 2]> {
+    match ty {
+        Ty::Primitive(PrimitiveTy::Bool) => Some(["false", "true"]),
+        Ty::Ref(ref_ty) if ref_ty.nullable => Some(["null", "non-null"]),
+        _ => None,
+    }
+}
+
+fn binary_variant_name(names: [&str; 2], expected_tag: usize, tag: Option<usize>) -> String {
     let Some(tag) = tag else {
         return "<tag never recorded?>".to_owned();
     };
@@ -236,9 +606,8 @@ fn bool_variant_name(expected_tag: usize, tag: Option<usize>) -> String {
     //
     // So to figure out what variant a bool is supposed to have, we work out
     // what variant the expected_tag has, and then either say "the same or opposite"
-    let bools = ["false", "true"];
-    let expected_bool = bools[expected_tag];
-    let unexpected_bool = bools[1 - expected_tag];
+    let expected_bool = names[expected_tag];
+    let unexpected_bool = names[1 - expected_tag];
 
     let res = if tag == expected_tag {
         expected_bool
@@ -254,6 +623,7 @@ fn tag_error(
     expected: String,
     caller: String,
     callee: String,
+    write_order: WriteOrder,
 ) -> CheckFailure {
     let func = expected_val.func();
     let arg = expected_val.arg();
@@ -269,5 +639,87 @@ fn tag_error(
         expected,
         caller,
         callee,
+        write_order,
+        sysv_classes: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulps() {
+        // (lhs, rhs, ulps, within)
+        let f32s: &[(f32, f32, u32, bool)] = &[
+            (1.0, 1.0, 0, true),
+            (1.0, f32::from_bits(1.0f32.to_bits() + 1), 0, false),
+            (1.0, f32::from_bits(1.0f32.to_bits() + 1), 1, true),
+            (1.0, f32::from_bits(1.0f32.to_bits() + 3), 2, false),
+            (-1.0, f32::from_bits((-1.0f32).to_bits() + 2), 2, true),
+            // Across a power of two the exponent changes, but they're still neighbours
+            (2.0, f32::from_bits(2.0f32.to_bits() - 1), 1, true),
+            // +0 and -0 are one apart, and the smallest subnormals are on either side
+            (0.0, -0.0, 0, false),
+            (0.0, -0.0, 1, true),
+            (f32::from_bits(1), -f32::from_bits(1), 2, false),
+            (f32::from_bits(1), -f32::from_bits(1), 3, true),
+            // Close in bits, but nowhere near in value
+            (1.0, -1.0, 4, false),
+            (f32::MAX, f32::INFINITY, 1, true),
+            (f32::NAN, f32::NAN, u32::MAX, false),
+            (f32::NAN, 1.0, u32::MAX, false),
+            (f32::INFINITY, f32::NAN, u32::MAX, false),
+        ];
+        let f64s: &[(f64, f64, u32, bool)] = &[
+            (1.0, f64::from_bits(1.0f64.to_bits() + 1), 0, false),
+            (1.0, f64::from_bits(1.0f64.to_bits() + 1), 1, true),
+            (-2.0, f64::from_bits((-2.0f64).to_bits() - 1), 1, true),
+            (0.0, -0.0, 1, true),
+            (0.0, -0.0, 0, false),
+            (f64::from_bits(1), -f64::from_bits(1), 2, false),
+            (1.0, -1.0, u32::MAX, false),
+            (f64::NAN, f64::NAN, u32::MAX, false),
+            (-f64::NAN, 0.0, u32::MAX, false),
+        ];
+        // (name, little-endian lhs and rhs, big-endian lhs and rhs, ulps, within)
+        let f32_cases = f32s.iter().map(|&(lhs, rhs, ulps, within)| {
+            let le = [lhs, rhs].map(|val| val.to_le_bytes().to_vec());
+            let be = [lhs, rhs].map(|val| val.to_be_bytes().to_vec());
+            (format!("{lhs:?}f32 vs {rhs:?}f32"), le, be, ulps, within)
+        });
+        let f64_cases = f64s.iter().map(|&(lhs, rhs, ulps, within)| {
+            let le = [lhs, rhs].map(|val| val.to_le_bytes().to_vec());
+            let be = [lhs, rhs].map(|val| val.to_be_bytes().to_vec());
+            (format!("{lhs:?}f64 vs {rhs:?}f64"), le, be, ulps, within)
+        });
+        for (name, [le_lhs, le_rhs], [be_lhs, be_rhs], ulps, within) in f32_cases.chain(f64_cases) {
+            assert_eq!(
+                within_ulps(&le_lhs, &le_rhs, ulps, false),
+                within,
+                "{name} ({ulps} ulps, little-endian)"
+            );
+            assert_eq!(
+                within_ulps(&be_lhs, &be_rhs, ulps, true),
+                within,
+                "{name} ({ulps} ulps, big-endian)"
+            );
+            // Reading the bytes in the wrong order is a different number entirely
+            if ulps == 1 && within && le_lhs != le_rhs {
+                assert!(
+                    !within_ulps(&le_lhs, &le_rhs, ulps, true),
+                    "{name} (swapped)"
+                );
+            }
+        }
+
+        // Floats of different sizes never match
+        assert!(!within_ulps(
+            &1.0f32.to_le_bytes(),
+            &1.0f64.to_le_bytes(),
+            u32::MAX,
+            false
+        ));
+        assert!(!within_ulps(&[], &[], u32::MAX, false));
     }
 }
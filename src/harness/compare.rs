@@ -0,0 +1,270 @@
+//! Comparing two json reports (`abi-cafe compare`)
+//!
+//! Upgrading a compiler (or changing abi-cafe) changes the results of some tests, and
+//! a run's report on its own doesn't say which. This reads an old and a new report back
+//! in, lines them up by test id, and sorts every test that changed by what it became.
+
+use camino::Utf8Path;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::HarnessError;
+use crate::harness::multi_target::ReadReport;
+use crate::harness::report::*;
+use crate::*;
+
+/// What changed between two reports
+#[derive(Debug, Serialize)]
+pub struct ReportComparison {
+    /// See [`REPORT_SCHEMA_VERSION`][]
+    pub schema_version: u32,
+    /// The file the old report was read from
+    pub old: String,
+    /// The file the new report was read from
+    pub new: String,
+    /// Tests that fail now, but didn't before (or didn't exist)
+    pub newly_failing: Vec<ChangedTest>,
+    /// Tests that pass now, but didn't before (or didn't exist)
+    pub newly_passing: Vec<ChangedTest>,
+    /// Tests that are busted now, but weren't before (or didn't exist)
+    pub newly_busted: Vec<ChangedTest>,
+    /// Tests that are skipped now, but weren't before (or didn't exist)
+    pub newly_skipped: Vec<ChangedTest>,
+    /// Tests that are only in the old report
+    pub removed: Vec<ChangedTest>,
+    /// Tests with the same conclusion in both reports
+    pub unchanged: Vec<String>,
+}
+
+/// A test whose conclusion changed
+#[derive(Debug, Serialize)]
+pub struct ChangedTest {
+    pub id: String,
+    /// None if the test is new
+    pub old: Option<TestConclusion>,
+    /// None if the test was removed
+    pub new: Option<TestConclusion>,
+}
+
+/// Compare the report at `old_path` to the one at `new_path`
+pub fn compare_reports(
+    old_path: &Utf8Path,
+    new_path: &Utf8Path,
+) -> Result<ReportComparison, HarnessError> {
+    let old = ReadReport::from_path(old_path)?;
+    let new = ReadReport::from_path(new_path)?;
+    if let (Some(old_target), Some(new_target)) = (old.target(), new.target()) {
+        if old_target != new_target {
+            warn!("comparing a report for {old_target} to one for {new_target}");
+        }
+    }
+
+    let old_tests = old
+        .tests
+        .into_iter()
+        .map(|test| (test.id, test.conclusion))
+        .collect::<SortedMap<_, _>>();
+    let new_tests = new
+        .tests
+        .into_iter()
+        .map(|test| (test.id, test.conclusion))
+        .collect::<SortedMap<_, _>>();
+
+    let mut comparison = ReportComparison {
+        schema_version: REPORT_SCHEMA_VERSION,
+        old: old_path.to_string(),
+        new: new_path.to_string(),
+        newly_failing: vec![],
+        newly_passing: vec![],
+        newly_busted: vec![],
+        newly_skipped: vec![],
+        removed: vec![],
+        unchanged: vec![],
+    };
+    for (id, &conclusion) in &new_tests {
+        let old = old_tests.get(id).copied();
+        if old == Some(conclusion) {
+            comparison.unchanged.push(id.clone());
+            continue;
+        }
+        let bucket = match conclusion {
+            TestConclusion::Failed => &mut comparison.newly_failing,
+            TestConclusion::Passed => &mut comparison.newly_passing,
+            TestConclusion::Busted => &mut comparison.newly_busted,
+            TestConclusion::Skipped => &mut comparison.newly_skipped,
+        };
+        bucket.push(ChangedTest {
+            id: id.clone(),
+            old,
+            new: Some(conclusion),
+        });
+    }
+    for (id, &conclusion) in &old_tests {
+        if !new_tests.contains_key(id) {
+            comparison.removed.push(ChangedTest {
+                id: id.clone(),
+                old: Some(conclusion),
+                new: None,
+            });
+        }
+    }
+    Ok(comparison)
+}
+
+impl ReportComparison {
+    /// Whether anything fails now that didn't before
+    pub fn failed(&self) -> bool {
+        !self.newly_failing.is_empty()
+    }
+
+    pub fn print_human(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        use console::Style;
+        let red = Style::new().red();
+        let green = Style::new().green();
+        let blue = Style::new().blue();
+
+        writeln!(f, "comparing {} to {}", self.old, self.new)?;
+        let buckets = [
+            ("newly failing", &self.newly_failing, &red),
+            ("newly passing", &self.newly_passing, &green),
+            ("newly busted", &self.newly_busted, &blue),
+            ("newly skipped", &self.newly_skipped, &blue),
+            ("removed", &self.removed, &blue),
+        ];
+        for (name, tests, style) in buckets {
+            if tests.is_empty() {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "{} ({}):", style.apply_to(name), tests.len())?;
+            for test in tests {
+                let old = test
+                    .old
+                    .map(|conclusion| conclusion.to_string())
+                    .unwrap_or_else(|| "(new)".to_owned());
+                let new = test
+                    .new
+                    .map(|conclusion| conclusion.to_string())
+                    .unwrap_or_else(|| "(removed)".to_owned());
+                writeln!(f, "  {}: {old} -> {new}", test.id)?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} newly failing, {} newly passing, {} newly busted, {} newly skipped, {} removed, {} unchanged",
+            self.newly_failing.len(),
+            self.newly_passing.len(),
+            self.newly_busted.len(),
+            self.newly_skipped.len(),
+            self.removed.len(),
+            self.unchanged.len(),
+        )?;
+        Ok(())
+    }
+
+    pub fn print_json(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+
+    fn ids(tests: &[ChangedTest]) -> Vec<(&str, Option<TestConclusion>)> {
+        tests
+            .iter()
+            .map(|test| (test.id.as_str(), test.old))
+            .collect()
+    }
+
+    #[test]
+    fn buckets() {
+        use TestConclusion::*;
+        let old = full_report(vec![
+            test_report("same", "cc", "rustc", Passed),
+            test_report("breaks", "cc", "rustc", Passed),
+            test_report("fixed", "cc", "rustc", Failed),
+            test_report("busts", "cc", "rustc", Passed),
+            test_report("skips", "cc", "rustc", Failed),
+            test_report("removed", "cc", "rustc", Passed),
+        ]);
+        let new = full_report(vec![
+            test_report("same", "cc", "rustc", Passed),
+            test_report("breaks", "cc", "rustc", Failed),
+            test_report("fixed", "cc", "rustc", Passed),
+            test_report("busts", "cc", "rustc", Busted),
+            test_report("skips", "cc", "rustc", Skipped),
+            test_report("added", "cc", "rustc", Failed),
+        ]);
+        let [old_path, new_path] = write_reports("compare", [("old.json", old), ("new.json", new)]);
+        let comparison = compare_reports(&old_path, &new_path).unwrap();
+
+        let id = |test: &str| format!("{test}::conv_c::repr_c::cc_calls_rustc");
+        let (breaks, added, fixed) = (id("breaks"), id("added"), id("fixed"));
+        assert_eq!(
+            ids(&comparison.newly_failing),
+            [(added.as_str(), None), (breaks.as_str(), Some(Passed))]
+        );
+        assert_eq!(
+            ids(&comparison.newly_passing),
+            [(fixed.as_str(), Some(Failed))]
+        );
+        assert_eq!(ids(&comparison.newly_busted)[0].0, id("busts"));
+        assert_eq!(ids(&comparison.newly_skipped)[0].0, id("skips"));
+        assert_eq!(comparison.removed.len(), 1);
+        assert_eq!(comparison.removed[0].id, id("removed"));
+        assert_eq!(comparison.removed[0].new, None);
+        assert_eq!(comparison.unchanged, [id("same")]);
+        // Which makes the exit status 1
+        assert!(comparison.failed());
+
+        let mut out = vec![];
+        comparison.print_human(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains(&format!("  {breaks}: passed -> failed\n")),
+            "{out}"
+        );
+        assert!(
+            out.contains(&format!("  {added}: (new) -> failed\n")),
+            "{out}"
+        );
+        assert!(out.ends_with(
+            "2 newly failing, 1 newly passing, 1 newly busted, 1 newly skipped, 1 removed, 1 unchanged\n"
+        ));
+
+        std::fs::remove_dir_all(old_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn nothing_newly_failing() {
+        use TestConclusion::*;
+        let old = full_report(vec![
+            test_report("fixed", "cc", "rustc", Failed),
+            test_report("still_broken", "cc", "rustc", Failed),
+        ]);
+        let new = full_report(vec![
+            test_report("fixed", "cc", "rustc", Passed),
+            test_report("still_broken", "cc", "rustc", Failed),
+        ]);
+        let [old_path, new_path] =
+            write_reports("compare-ok", [("old.json", old), ("new.json", new)]);
+        let comparison = compare_reports(&old_path, &new_path).unwrap();
+        // Failures that were already there don't count
+        assert!(!comparison.failed());
+        assert_eq!(comparison.newly_passing.len(), 1);
+        assert_eq!(comparison.unchanged.len(), 1);
+
+        // And a report that doesn't exist is an error, not an empty comparison
+        let missing = old_path.with_file_name("missing.json");
+        assert!(matches!(
+            compare_reports(&missing, &new_path),
+            Err(HarnessError::ReadReport { .. })
+        ));
+        std::fs::remove_dir_all(old_path.parent().unwrap()).unwrap();
+    }
+}
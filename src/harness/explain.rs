@@ -0,0 +1,193 @@
+//! Everything about one test in one place (`abi-cafe explain`)
+//!
+//! Triaging a failure means digging up its signatures, the sources generated for it,
+//! the commands that built it, and the values that didn't match, which are otherwise
+//! spread across the report, the logs, and `target/`. [`FullReport::print_explain`][]
+//! puts all of that in one dossier for the single test `abi-cafe explain` ran.
+
+use std::io::Write;
+
+use console::Style;
+use kdl_script::types::{Func, TypedProgram};
+
+use crate::harness::report::*;
+use crate::*;
+
+impl FullReport {
+    /// Print everything we know about the test `explain` ran
+    ///
+    /// `commands` are the commands that were run for it, in the order they ran.
+    pub fn print_explain(
+        &self,
+        harness: &TestHarness,
+        commands: &[String],
+        mut f: impl Write,
+    ) -> Result<(), std::io::Error> {
+        let red = Style::new().red();
+        let green = Style::new().green();
+        let blue = Style::new().blue();
+        let bold = Style::new().bold();
+
+        let Some(test) = self.tests.first() else {
+            return Ok(());
+        };
+        let key = &test.key;
+        let results = &test.results;
+        writeln!(f, "{}", bold.apply_to(format!("explaining {}", test.id)))?;
+        writeln!(f)?;
+        writeln!(f, "target:     {}", self.target)?;
+        writeln!(f, "test:       {}", key.test)?;
+        writeln!(f, "caller:     {}", key.caller)?;
+        writeln!(f, "callee:     {}", key.callee)?;
+        let rules = serde_json::to_string(&test.rules).map_err(std::io::Error::other)?;
+        writeln!(f, "rules:      {rules}")?;
+        let conclusion = match test.conclusion {
+            TestConclusion::Passed => green.apply_to(test.conclusion.to_string()),
+            TestConclusion::Failed => red.apply_to(test.conclusion.to_string()),
+            TestConclusion::Skipped | TestConclusion::Busted => {
+                blue.apply_to(test.conclusion.to_string())
+            }
+        };
+        writeln!(f, "conclusion: {conclusion}")?;
+        writeln!(f, "options:")?;
+        let options = serde_json::to_value(&key.options).map_err(std::io::Error::other)?;
+        for (name, val) in options.as_object().into_iter().flatten() {
+            writeln!(f, "  {name}: {val}")?;
+        }
+
+        section(&mut f, "signatures")?;
+        let types = &harness.test(&key.test).types;
+        for func in key.options.functions.active_funcs(types) {
+            writeln!(f, "{}", signature(types, types.realize_func(func)))?;
+        }
+
+        section(&mut f, "steps")?;
+        fn step<T>(
+            f: &mut impl Write,
+            name: &str,
+            result: &Option<Result<T, impl std::fmt::Display>>,
+        ) -> Result<(), std::io::Error> {
+            let red = Style::new().red();
+            match result {
+                None => writeln!(f, "{name:9} (not run)"),
+                Some(Ok(_)) => writeln!(f, "{name:9} {}", Style::new().green().apply_to("ok")),
+                Some(Err(e)) => {
+                    writeln!(f, "{name:9} {}", red.apply_to("failed"))?;
+                    writeln!(f, "{}", red.apply_to(e))
+                }
+            }
+        }
+        step(&mut f, "generate", &results.source)?;
+        step(&mut f, "build", &results.build)?;
+        step(&mut f, "link", &results.link)?;
+        step(&mut f, "run", &results.run)?;
+        if let Some(check) = &results.check {
+            let num_passed = check
+                .subtest_checks
+                .iter()
+                .filter(|subtest| subtest.result.is_ok())
+                .count();
+            writeln!(
+                f,
+                "{:9} {num_passed}/{} passed",
+                "check",
+                check.subtest_checks.len()
+            )?;
+        }
+
+        if let Some(Ok(sources)) = &results.source {
+            section(&mut f, "generated sources")?;
+            write_source(&mut f, "caller", &sources.caller_src)?;
+            write_source(&mut f, "callee", &sources.callee_src)?;
+        }
+
+        section(&mut f, "commands")?;
+        for command in commands {
+            writeln!(f, "{command}")?;
+        }
+        if let Some(runner) = &self.runner {
+            writeln!(f, "(test binaries were run with {runner})")?;
+        }
+
+        let Some(check) = &results.check else {
+            return Ok(());
+        };
+        section(&mut f, "values")?;
+        for (subtest_name, subtest) in check.subtest_names.iter().zip(&check.subtest_checks) {
+            let Err(e) = &subtest.result else {
+                writeln!(f, "{subtest_name} {}", green.apply_to("passed"))?;
+                continue;
+            };
+            writeln!(f, "{subtest_name} {}", red.apply_to("failed!"))?;
+            writeln!(f, "{}", red.apply_to(e))?;
+            write_arg_dump(&mut f, e)?;
+        }
+        for padding in &check.padding {
+            writeln!(
+                f,
+                "{}::{} padding ({} bytes) {}",
+                padding.func_name,
+                padding.arg_name,
+                padding.padding_bytes,
+                format!("{:?}", padding.verdict).to_lowercase()
+            )?;
+        }
+
+        for subtest in &check.subtest_checks {
+            let Some(minimized) = &subtest.minimized else {
+                continue;
+            };
+            section(
+                &mut f,
+                &format!(
+                    "minimized reproducer ({} {} {})",
+                    minimized.func_name, minimized.arg_name, minimized.val_path
+                ),
+            )?;
+            write_source(&mut f, "caller", &minimized.sources.caller_src)?;
+            write_source(&mut f, "callee", &minimized.sources.callee_src)?;
+        }
+        Ok(())
+    }
+}
+
+fn section(f: &mut impl Write, name: &str) -> Result<(), std::io::Error> {
+    writeln!(f)?;
+    writeln!(
+        f,
+        "{}",
+        Style::new().bold().apply_to(format!("== {name} =="))
+    )
+}
+
+/// Print a generated source file, or why we couldn't
+fn write_source(
+    f: &mut impl Write,
+    call_side: &str,
+    path: &camino::Utf8Path,
+) -> Result<(), std::io::Error> {
+    writeln!(
+        f,
+        "{}",
+        Style::new().blue().apply_to(format!("{call_side}: {path}"))
+    )?;
+    match std::fs::read_to_string(path) {
+        Ok(src) => writeln!(f, "{src}"),
+        Err(e) => writeln!(f, "(couldn't read it: {e})"),
+    }
+}
+
+/// A function's signature like `fn name(arg0: u32, arg1: &MyStruct) -> (out0: u8)`
+fn signature(types: &TypedProgram, func: &Func) -> String {
+    let args = |args: &[kdl_script::types::Arg]| {
+        args.iter()
+            .map(|arg| format!("{}: {}", arg.name, types.format_ty(arg.ty)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut signature = format!("fn {}({})", func.name, args(&func.inputs));
+    if !func.outputs.is_empty() {
+        signature.push_str(&format!(" -> ({})", args(&func.outputs)));
+    }
+    signature
+}
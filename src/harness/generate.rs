@@ -1,5 +1,6 @@
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
+use kdl_script::LayoutTarget;
 use std::fs::File;
 use std::io::Write;
 use std::sync::Arc;
@@ -12,6 +13,106 @@ use crate::*;
 
 impl TestHarness {
     pub async fn generate_test(&self, key: &TestKey) -> Result<GenerateOutput, GenerateError> {
+        if key.options.dlopen {
+            if !self.cfg_enabled(r#"cfg(all(unix, not(target_vendor = "apple")))"#) {
+                Err(UnsupportedError::Other(
+                    "--dlopen is only implemented for ELF platforms".to_owned(),
+                ))?;
+            }
+            if key.options.val_writer != WriteImpl::HarnessCallback {
+                Err(UnsupportedError::Other(
+                    "--dlopen only works with --write-vals=harness".to_owned(),
+                ))?;
+            }
+        }
+        let linker = key.options.linker;
+        let is_msvc = self.toolchains.platform_info.target.ends_with("-msvc");
+        if is_msvc && !matches!(linker, Linker::Default | Linker::Lld) {
+            Err(UnsupportedError::Other(format!(
+                "--linkers={linker} doesn't work with msvc"
+            )))?;
+        }
+        if !linker.is_installed(is_msvc) {
+            Err(UnsupportedError::Other(format!(
+                "--linkers={linker} needs {}, which isn't installed",
+                linker.program(is_msvc).unwrap_or_default()
+            )))?;
+        }
+        let lto = key.options.lto;
+        if lto != Lto::Off {
+            if key.options.dlopen {
+                Err(UnsupportedError::Other(
+                    "--lto can't optimize across the shared libraries of --dlopen".to_owned(),
+                ))?;
+            }
+            let caller_unit = self.toolchain_by_test_key(key, CallSide::Caller).lto_unit();
+            let callee_unit = self.toolchain_by_test_key(key, CallSide::Callee).lto_unit();
+            match (caller_unit, callee_unit) {
+                (Some(caller_unit), Some(callee_unit)) if caller_unit == callee_unit => {
+                    if caller_unit == LtoUnit::Gcc && linker == Linker::Lld {
+                        Err(UnsupportedError::Other(
+                            "--lto with gcc needs a linker that can run gcc's lto plugin, which lld can't"
+                                .to_owned(),
+                        ))?;
+                    }
+                }
+                _ => Err(UnsupportedError::Other(format!(
+                    "{} and {} can't be compiled into one unit for --lto={lto}",
+                    key.caller, key.callee
+                )))?,
+            }
+            for call_side in [CallSide::Caller, CallSide::Callee] {
+                let toolchain = self.toolchain_by_test_key(key, call_side);
+                if lto == Lto::Fat && toolchain.lang() == "rust" {
+                    Err(UnsupportedError::Other(format!(
+                        "{} only emits ThinLTO bitcode (-Clto=fat would bundle std into both sides)",
+                        key.toolchain_id(call_side)
+                    )))?;
+                }
+            }
+        }
+        for call_side in [CallSide::Caller, CallSide::Callee] {
+            let features = key.options.target_features_for(call_side);
+            if !features.is_default()
+                && !self
+                    .toolchain_by_test_key(key, call_side)
+                    .supports_target_features()
+            {
+                Err(UnsupportedError::Other(format!(
+                    "{} can't compile with --target-features={features}",
+                    key.toolchain_id(call_side)
+                )))?;
+            }
+        }
+        if key.options.call_path == CallPath::CfProtection {
+            if !self.has_cfg("target_arch=\"x86_64\"") && !self.has_cfg("target_arch=\"x86\"") {
+                Err(UnsupportedError::Other(
+                    "--calls=cf_protection is x86's control-flow protection".to_owned(),
+                ))?;
+            }
+            for call_side in [CallSide::Caller, CallSide::Callee] {
+                if !self
+                    .toolchain_by_test_key(key, call_side)
+                    .supports_cf_protection()
+                {
+                    Err(UnsupportedError::Other(format!(
+                        "{} can't compile with --calls=cf_protection",
+                        key.toolchain_id(call_side)
+                    )))?;
+                }
+            }
+        }
+        if self.toolchains.instrument == Some(Instrument::Msan) {
+            // Anything that isn't instrumented makes msan report nonsense
+            for call_side in [CallSide::Caller, CallSide::Callee] {
+                if !self.toolchain_by_test_key(key, call_side).supports_msan() {
+                    Err(UnsupportedError::Other(format!(
+                        "{} can't compile with --instrument=msan (only clang and nightly rustc have MemorySanitizer)",
+                        key.toolchain_id(call_side)
+                    )))?;
+                }
+            }
+        }
         // FIXME: these two could be done concurrently
         let caller_src = self.generate_src(key, CallSide::Caller).await?;
         let callee_src = self.generate_src(key, CallSide::Callee).await?;
@@ -28,10 +129,24 @@ impl TestHarness {
         call_side: CallSide,
     ) -> Result<Utf8PathBuf, GenerateError> {
         let test = self
-            .test_with_vals(&key.test, key.options.val_generator)
+            .test_with_vals(
+                &key.test,
+                key.options.val_generator,
+                key.options.functions.varied_args(),
+            )
             .await?;
         let toolchain_id = key.toolchain_id(call_side).to_owned();
-        let test_with_toolchain = self.test_with_toolchain(test, toolchain_id).await?;
+        let test_with_toolchain = if let Some(replay) = self.replay(key) {
+            // Not memoized, this tree only exists for this (minimized) test
+            let test = Arc::new(TestWithVals {
+                inner: test.inner.clone(),
+                vals: Arc::new(test.vals.with_replay(&replay)),
+            });
+            let toolchain = self.toolchain_by_test_key(key, call_side);
+            test.with_toolchain(&*toolchain).await?
+        } else {
+            self.test_with_toolchain(test, toolchain_id).await?
+        };
         let src_path = self.src_path(key, call_side);
 
         // Briefly lock this map to insert/acquire a OnceCell and then release the lock
@@ -50,10 +165,12 @@ impl TestHarness {
                 let options = key.options.clone();
                 generate_src(
                     &src_path,
+                    self.golden_path(&src_path),
                     toolchain,
                     test_with_toolchain,
                     call_side,
                     options,
+                    self.layout_target(),
                 )
             })
             .await?;
@@ -65,20 +182,35 @@ impl TestHarness {
         let toolchain = self.toolchain_by_test_key(key, call_side);
         let mut output = self.base_id(key, Some(call_side), "_");
         output.push('.');
-        output.push_str(toolchain.src_ext());
+        output.push_str(toolchain.src_ext(
+            &self.test(&key.test).types,
+            &key.options.for_call_side(call_side),
+        ));
         self.paths.generated_src_dir.join(toolchain_id).join(output)
     }
+
+    /// Where the golden copy of this generated source goes (with `--goldens`),
+    /// and whether to update it instead of comparing to it
+    fn golden_path(&self, src_path: &Utf8Path) -> Option<(Utf8PathBuf, bool)> {
+        let goldens = self.goldens.as_ref()?;
+        let rel_path = src_path
+            .strip_prefix(&self.paths.generated_src_dir)
+            .expect("generated source wasn't in the generated source dir!?");
+        Some((goldens.dir.join(rel_path), goldens.update))
+    }
 }
 
-async fn generate_src(
+pub(super) async fn generate_src(
     src_path: &Utf8Path,
+    golden: Option<(Utf8PathBuf, bool)>,
     toolchain: Arc<dyn Toolchain + Send + Sync>,
     test_with_toolchain: Arc<TestWithToolchain>,
     call_side: CallSide,
     options: TestOptions,
+    layout_target: LayoutTarget,
 ) -> Result<(), GenerateError> {
     let mut output_string = String::new();
-    let test = test_with_toolchain.with_options(options)?;
+    let test = test_with_toolchain.with_options(options.for_call_side(call_side), layout_target)?;
     match call_side {
         CallSide::Callee => toolchain.generate_callee(&mut output_string, test)?,
         CallSide::Caller => toolchain.generate_caller(&mut output_string, test)?,
@@ -89,5 +221,119 @@ async fn generate_src(
     let mut output = File::create(src_path)?;
     output.write_all(output_string.as_bytes())?;
 
+    if let Some((golden_path, update)) = golden {
+        sync_golden(src_path, &golden_path, update, &output_string)?;
+    }
     Ok(())
 }
+
+/// Overwrite the golden file with what we generated (if `update`), or check that they match
+fn sync_golden(
+    src_path: &Utf8Path,
+    golden_path: &Utf8Path,
+    update: bool,
+    src: &str,
+) -> Result<(), GenerateError> {
+    if update {
+        std::fs::create_dir_all(golden_path.parent().expect("golden file had no parent!?"))?;
+        std::fs::write(golden_path, src)?;
+        Ok(())
+    } else {
+        check_golden(src_path, golden_path, src)
+    }
+}
+
+/// Check that we generated exactly what the golden file says we should
+fn check_golden(
+    src_path: &Utf8Path,
+    golden_path: &Utf8Path,
+    src: &str,
+) -> Result<(), GenerateError> {
+    let mismatch = |details: String| GenerateError::GoldenMismatch {
+        src: src_path.to_string(),
+        golden: golden_path.to_string(),
+        details,
+    };
+    let golden = match std::fs::read_to_string(golden_path) {
+        Ok(golden) => golden,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(mismatch("(there is no golden file yet)".to_owned()));
+        }
+        Err(e) => return Err(e)?,
+    };
+    if golden == src {
+        return Ok(());
+    }
+
+    // Show the first few lines that changed (a real diff tool can show the rest)
+    const MAX_LINES: usize = 5;
+    let golden_lines = golden.lines().collect::<Vec<_>>();
+    let src_lines = src.lines().collect::<Vec<_>>();
+    let first = golden_lines
+        .iter()
+        .zip(&src_lines)
+        .position(|(golden, src)| golden != src)
+        .unwrap_or(golden_lines.len().min(src_lines.len()));
+    let mut details = format!("first difference at line {}:\n", first + 1);
+    for line in golden_lines.iter().skip(first).take(MAX_LINES) {
+        details.push_str(&format!("- {line}\n"));
+    }
+    for line in src_lines.iter().skip(first).take(MAX_LINES) {
+        details.push_str(&format!("+ {line}\n"));
+    }
+    if golden_lines.len() != src_lines.len() {
+        details.push_str(&format!(
+            "(the golden file has {} lines, we generated {})",
+            golden_lines.len(),
+            src_lines.len()
+        ));
+    }
+    Err(mismatch(details.trim_end().to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details(result: Result<(), GenerateError>) -> String {
+        match result {
+            Err(GenerateError::GoldenMismatch { details, .. }) => details,
+            other => panic!("expected a golden mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn goldens() {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let dir = dir.join(format!("abi-cafe-{}-goldens", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let src_path = dir.join("generated/cc/simple_caller.c");
+        let golden_path = dir.join("goldens/cc/simple_caller.c");
+        let src = "int a;\nint b;\nint c;\n";
+
+        // Nothing to compare to until it's blessed
+        assert_eq!(
+            details(sync_golden(&src_path, &golden_path, false, src)),
+            "(there is no golden file yet)"
+        );
+        sync_golden(&src_path, &golden_path, true, src).unwrap();
+        assert_eq!(std::fs::read_to_string(&golden_path).unwrap(), src);
+        sync_golden(&src_path, &golden_path, false, src).unwrap();
+
+        let changed = "int a;\nlong b;\nint c;\n";
+        assert_eq!(
+            details(check_golden(&src_path, &golden_path, changed)),
+            "first difference at line 2:\n- int b;\n- int c;\n+ long b;\n+ int c;"
+        );
+        assert_eq!(
+            details(check_golden(&src_path, &golden_path, "int a;\nint b;\n")),
+            "first difference at line 3:\n- int c;\n(the golden file has 3 lines, we generated 2)"
+        );
+        // Updating overwrites whatever was there
+        sync_golden(&src_path, &golden_path, true, changed).unwrap();
+        check_golden(&src_path, &golden_path, changed).unwrap();
+        assert!(check_golden(&src_path, &golden_path, src).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
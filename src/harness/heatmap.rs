@@ -0,0 +1,201 @@
+//! A grid of toolchain pairs by calling conventions (`--output-format heatmap`)
+//!
+//! A flat list of results leaves the clustering to you. This counts the results of every
+//! test by `(caller, callee, convention)` and lays them out as a grid, so something like
+//! "clang as the callee breaks under vectorcall" is one red cell (or a red column, if it
+//! breaks with every caller) that you can spot at a glance.
+
+use console::Style;
+
+use crate::harness::report::*;
+use crate::*;
+
+/// The results of a run, counted by toolchain pair and convention
+#[derive(Debug)]
+pub struct Heatmap {
+    /// The rows (`caller_calls_callee`)
+    pub pairs: Vec<String>,
+    /// The columns (the caller's convention, and the callee's if it's different)
+    pub conventions: Vec<String>,
+    /// The counts for every (pair, convention) that had any tests
+    pub cells: SortedMap<(String, String), HeatmapCell>,
+}
+
+/// How the tests in one cell of a [`Heatmap`][] concluded
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeatmapCell {
+    pub passed: usize,
+    pub failed: usize,
+    pub busted: usize,
+    pub skipped: usize,
+}
+
+impl HeatmapCell {
+    /// How many tests actually ran
+    fn run(&self) -> usize {
+        self.passed + self.failed + self.busted
+    }
+
+    /// What to print in the cell (this has to make sense without colors too)
+    fn label(&self) -> String {
+        if self.run() == 0 {
+            return "skip".to_owned();
+        }
+        format!("{}/{}/{}", self.passed, self.failed, self.busted)
+    }
+
+    fn style(&self) -> Style {
+        if self.run() == 0 {
+            Style::new().dim()
+        } else if self.failed > 0 {
+            Style::new().red()
+        } else if self.busted > 0 {
+            Style::new().blue()
+        } else {
+            Style::new().green()
+        }
+    }
+}
+
+impl FullReport {
+    /// Count the results of this run by toolchain pair and convention
+    pub fn heatmap(&self) -> Heatmap {
+        let mut pairs = SortedMap::new();
+        let mut conventions = SortedMap::new();
+        let mut cells = SortedMap::<(String, String), HeatmapCell>::new();
+        for test in &self.tests {
+            let key = &test.key;
+            let pair = GroupBy::Pair.group_name(key);
+            let options = &key.options;
+            let convention = if options.convention == options.callee_convention {
+                options.convention.to_string()
+            } else {
+                format!("{}/{}", options.convention, options.callee_convention)
+            };
+            pairs.insert(pair.clone(), ());
+            // Sort the columns by convention, not by name
+            conventions.insert(
+                (options.convention, options.callee_convention),
+                convention.clone(),
+            );
+
+            let cell = cells.entry((pair, convention)).or_default();
+            match test.conclusion {
+                TestConclusion::Passed => cell.passed += 1,
+                TestConclusion::Failed => cell.failed += 1,
+                TestConclusion::Busted => cell.busted += 1,
+                TestConclusion::Skipped => cell.skipped += 1,
+            }
+        }
+        Heatmap {
+            pairs: pairs.into_keys().collect(),
+            conventions: conventions.into_values().collect(),
+            cells,
+        }
+    }
+
+    pub fn print_heatmap(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        self.heatmap().print_human(f)
+    }
+}
+
+impl Heatmap {
+    pub fn print_human(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        let dim = Style::new().dim();
+        let bold = Style::new().bold();
+
+        let pair_width = self.pairs.iter().map(|pair| pair.len()).max().unwrap_or(0);
+        let widths = self
+            .conventions
+            .iter()
+            .map(|convention| {
+                self.pairs
+                    .iter()
+                    .filter_map(|pair| self.cells.get(&(pair.clone(), convention.clone())))
+                    .map(|cell| cell.label().len())
+                    .fold(convention.len(), usize::max)
+            })
+            .collect::<Vec<_>>();
+
+        // Pad before styling, the escape codes would throw off the widths
+        write!(f, "{:pair_width$}", "")?;
+        for (convention, width) in self.conventions.iter().zip(&widths) {
+            write!(f, "  {}", bold.apply_to(format!("{convention:>width$}")))?;
+        }
+        writeln!(f)?;
+        for pair in &self.pairs {
+            write!(f, "{pair:<pair_width$}")?;
+            for (convention, width) in self.conventions.iter().zip(&widths) {
+                match self.cells.get(&(pair.clone(), convention.clone())) {
+                    Some(cell) => {
+                        let label = format!("{:>width$}", cell.label());
+                        write!(f, "  {}", cell.style().apply_to(label))?;
+                    }
+                    None => write!(f, "  {}", dim.apply_to(format!("{:>width$}", ".")))?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{}",
+            dim.apply_to("passed/failed/busted, skip = all skipped, . = no tests")
+        )?;
+        let has_failures = |cell: &&HeatmapCell| cell.failed > 0 || cell.busted > 0;
+        let bad_cells = self.cells.values().filter(has_failures).count();
+        writeln!(f, "{bad_cells} of {} cells have failures", self.cells.len())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+
+    #[test]
+    fn heatmap_cells() {
+        use TestConclusion::*;
+        let with_conv = |convention, callee_convention| TestOptions {
+            convention,
+            callee_convention,
+            ..TestOptions::default()
+        };
+        let stdcall = with_conv(CallingConvention::Stdcall, CallingConvention::Stdcall);
+        let mixed = with_conv(CallingConvention::C, CallingConvention::Stdcall);
+        let report = full_report(vec![
+            test_report("simple", "cc", "rustc", Passed),
+            test_report("structs", "cc", "rustc", Failed),
+            test_report("unions", "cc", "rustc", Busted),
+            test_report("simple", "rustc", "cc", Passed),
+            test_report("structs", "rustc", "cc", Skipped),
+            test_report_with("simple", "cc", "rustc", stdcall.clone(), Skipped),
+            test_report_with("simple", "rustc", "cc", mixed.clone(), Passed),
+            test_report_with("structs", "rustc", "cc", mixed, Failed),
+        ]);
+        let heatmap = report.heatmap();
+
+        assert_eq!(heatmap.pairs, ["cc_calls_rustc", "rustc_calls_cc"]);
+        // Columns go in convention order, with mixed conventions after the caller's
+        assert_eq!(heatmap.conventions, ["c", "c/stdcall", "stdcall"]);
+        let label = |pair: &str, convention: &str| {
+            heatmap
+                .cells
+                .get(&(pair.to_owned(), convention.to_owned()))
+                .map(|cell| cell.label())
+        };
+        assert_eq!(label("cc_calls_rustc", "c").as_deref(), Some("1/1/1"));
+        assert_eq!(label("rustc_calls_cc", "c").as_deref(), Some("1/0/0"));
+        assert_eq!(label("cc_calls_rustc", "stdcall").as_deref(), Some("skip"));
+        assert_eq!(
+            label("rustc_calls_cc", "c/stdcall").as_deref(),
+            Some("1/1/0")
+        );
+        assert_eq!(label("rustc_calls_cc", "stdcall"), None);
+        assert_eq!(heatmap.cells.len(), 4);
+        let cell = heatmap.cells[&("rustc_calls_cc".to_owned(), "c".to_owned())];
+        assert_eq!((cell.passed, cell.skipped), (1, 1));
+    }
+}
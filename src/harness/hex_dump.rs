@@ -0,0 +1,233 @@
+//! Hex dumps of the values in an arg, for explaining value mismatches
+//!
+//! ```text
+//!          expect                    caller                    callee
+//! 0000  [10 11|.. ..|20 21 22 23] [10 11|.. ..|20 21 22 23] [10 11|.. ..|30 31 32 33] .x, .y
+//! 0008  [30 31|.. ..              [30 31|.. ..              [30 31|.. ..              .z
+//! ```
+//!
+//! When we know the layout of the arg, every value is shown at its offset in the arg
+//! (or in what it points to), and `..` marks padding. Otherwise the values are laid end
+//! to end in the order they're written and the offsets are into the dump, since the
+//! harness never records padding. `[`, `|`, and `]` mark where each value starts and ends,
+//! and the values that start on each row are named in the margin.
+
+use std::fmt::Write;
+
+use console::Style;
+
+use crate::error::FieldBytes;
+use crate::fivemat::Fivemat;
+
+/// How many bytes of each side to show per row
+const ROW_LEN: usize = 8;
+/// How wide the dump of one side of a row is (a separator before each byte, and one after)
+const COLUMN_WIDTH: usize = ROW_LEN * 3 + 1;
+
+/// One byte of the dump
+#[derive(Clone, Copy)]
+enum Cell {
+    /// This byte of this field
+    Byte {
+        field_idx: usize,
+        byte_idx: usize,
+    },
+    Padding,
+}
+
+/// Write a hex dump of these values, with expected/caller/callee side by side
+///
+/// If `arg_size` is known, every field should have an offset, and the bytes between
+/// (and after) them are shown as padding. Bytes that differ from the expected value
+/// are highlighted, as are the names of the values that contain them.
+pub fn write_hex_dump(
+    f: &mut Fivemat,
+    fields: &[FieldBytes],
+    arg_size: Option<u64>,
+) -> std::fmt::Result {
+    let red = Style::new().red();
+    let dim = Style::new().dim();
+    let field_len = |field: &FieldBytes| {
+        field
+            .expected
+            .len()
+            .max(field.caller.len())
+            .max(field.callee.len())
+    };
+    // The offset of every byte of the dump, and what's there
+    let mut order = (0..fields.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&field_idx| fields[field_idx].offset);
+    let mut cells = vec![];
+    let mut end = 0;
+    for field_idx in order {
+        let field = &fields[field_idx];
+        let start = field.offset.unwrap_or(end);
+        cells.extend((end..start).map(|offset| (offset, Cell::Padding)));
+        let len = field_len(field);
+        cells.extend((0..len).map(|byte_idx| {
+            let offset = start + byte_idx as u64;
+            (
+                offset,
+                Cell::Byte {
+                    field_idx,
+                    byte_idx,
+                },
+            )
+        }));
+        end = end.max(start + len as u64);
+    }
+    cells.extend((end..arg_size.unwrap_or(0)).map(|offset| (offset, Cell::Padding)));
+    let ends_field = |cell: Option<&(u64, Cell)>| match cell {
+        Some(&(
+            _,
+            Cell::Byte {
+                field_idx,
+                byte_idx,
+            },
+        )) => byte_idx + 1 == field_len(&fields[field_idx]),
+        _ => false,
+    };
+    let sides: [fn(&FieldBytes) -> &[u8]; 3] = [
+        |field| &field.expected,
+        |field| &field.caller,
+        |field| &field.callee,
+    ];
+
+    writeln!(
+        f,
+        "{:6} {:COLUMN_WIDTH$} {:COLUMN_WIDTH$} callee",
+        "", "expect", "caller"
+    )?;
+    for row in cells.chunks(ROW_LEN) {
+        write!(f, "{:04X}  ", row[0].0)?;
+        for side in sides {
+            for (idx_in_row, &(_, cell)) in row.iter().enumerate() {
+                let prev = idx_in_row.checked_sub(1).map(|idx| &row[idx]);
+                let separator = match cell {
+                    Cell::Byte { byte_idx: 0, .. } if idx_in_row == 0 => '[',
+                    Cell::Byte { byte_idx: 0, .. } => '|',
+                    Cell::Padding if ends_field(prev) => '|',
+                    _ => ' ',
+                };
+                let (field, byte_idx) = match cell {
+                    Cell::Byte {
+                        field_idx,
+                        byte_idx,
+                    } => (&fields[field_idx], byte_idx),
+                    Cell::Padding => {
+                        write!(f, "{separator}{}", dim.apply_to(".."))?;
+                        continue;
+                    }
+                };
+                let byte = side(field).get(byte_idx);
+                let text = byte
+                    .map(|byte| format!("{byte:02X}"))
+                    .unwrap_or_else(|| "--".to_owned());
+                if byte != field.expected.get(byte_idx) {
+                    write!(f, "{separator}{}", red.apply_to(text))?;
+                } else {
+                    write!(f, "{separator}{text}")?;
+                }
+            }
+            let padding = (ROW_LEN - row.len()) * 3;
+            let end = if ends_field(row.last()) { ']' } else { ' ' };
+            write!(f, "{end}{:padding$} ", "")?;
+        }
+        let names = row
+            .iter()
+            .filter_map(|&(_, cell)| match cell {
+                Cell::Byte {
+                    field_idx,
+                    byte_idx: 0,
+                } => Some(&fields[field_idx]),
+                _ => None,
+            })
+            .map(|field| {
+                if field.mismatched() {
+                    red.apply_to(&field.field).to_string()
+                } else {
+                    field.field.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        writeln!(f, "{}", names.join(", "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn field(field: &str, expected: &[u8], caller: &[u8], callee: &[u8]) -> FieldBytes {
+        FieldBytes {
+            field: field.to_owned(),
+            offset: None,
+            expected: expected.to_vec(),
+            caller: caller.to_vec(),
+            callee: callee.to_vec(),
+        }
+    }
+
+    #[test]
+    fn hex_dump_marks_fields() -> std::fmt::Result {
+        console::set_colors_enabled(false);
+        let fields = [
+            field(".x", &[0x10, 0x11], &[0x10, 0x11], &[0x10, 0x11]),
+            field(
+                ".y",
+                &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27],
+                &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27],
+                &[0x30, 0x31],
+            ),
+            field(".z", &[0x30, 0x31], &[0x30, 0x31], &[0x30, 0x31]),
+        ];
+        let mut out = String::new();
+        let mut f = Fivemat::new(&mut out, "  ");
+        f.add_indent(1);
+        write_hex_dump(&mut f, &fields, None)?;
+        assert_eq!(
+            out,
+            "         expect                    caller                    callee
+  0000  [10 11|20 21 22 23 24 25  [10 11|20 21 22 23 24 25  [10 11|30 31 -- -- -- --  .x, .y
+  0008   26 27|30 31]              26 27|30 31]              -- --|30 31]             .z
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hex_dump_shows_padding() -> std::fmt::Result {
+        console::set_colors_enabled(false);
+        // struct { u16 x; u32 y; u16 z; }
+        let at = |offset, field| FieldBytes {
+            offset: Some(offset),
+            ..field
+        };
+        let fields = [
+            at(0, field(".x", &[0x10, 0x11], &[0x10, 0x11], &[0x10, 0x11])),
+            at(
+                4,
+                field(
+                    ".y",
+                    &[0x20, 0x21, 0x22, 0x23],
+                    &[0x20, 0x21, 0x22, 0x23],
+                    &[0x30, 0x31, 0x32, 0x33],
+                ),
+            ),
+            at(8, field(".z", &[0x30, 0x31], &[0x30, 0x31], &[0x30, 0x31])),
+        ];
+        let mut out = String::new();
+        let mut f = Fivemat::new(&mut out, "  ");
+        f.add_indent(1);
+        write_hex_dump(&mut f, &fields, Some(12))?;
+        assert_eq!(
+            out,
+            "         expect                    caller                    callee
+  0000  [10 11|.. ..|20 21 22 23] [10 11|.. ..|20 21 22 23] [10 11|.. ..|30 31 32 33] .x, .y
+  0008  [30 31|.. ..              [30 31|.. ..              [30 31|.. ..              .z
+"
+        );
+        Ok(())
+    }
+}
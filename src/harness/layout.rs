@@ -0,0 +1,444 @@
+//! Dumping the layouts we compute for the types in tests (`--emit-layouts`)
+
+use std::str::FromStr;
+
+use kdl_script::types::{Ty, TyIdx, TypedProgram};
+use kdl_script::{Definition, LayoutTarget, PunEnv, SysVClassification, TyLayout};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::*;
+
+/// The layouts of every type in every test
+#[derive(Debug, Serialize)]
+pub struct LayoutReport {
+    /// The target the layouts were computed for
+    pub target: String,
+    /// The details of the target that fed into the layouts
+    pub layout_target: LayoutTarget,
+    pub tests: Vec<TestLayouts>,
+    /// Layouts that came out different when they were computed again (`--layout-iterations`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nondeterministic: Vec<NondeterministicLayout>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestLayouts {
+    pub test: String,
+    pub types: Vec<TypeLayout>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeLayout {
+    pub name: String,
+    /// The layout, if it's specified (None for repr(Rust) types)
+    pub layout: Option<TyLayout>,
+    /// Why we couldn't compute a layout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How x86_64 SysV would pass this type (only with --sysv-classes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sysv_classes: Option<SysVClassification>,
+}
+
+/// A type whose layout changed when we computed it again
+#[derive(Debug, Serialize)]
+pub struct NondeterministicLayout {
+    pub test: String,
+    /// The type (or, if the types came out in a different order, the first one's name)
+    pub name: String,
+    /// Which computation disagreed with the first one (the first is 0)
+    pub iteration: usize,
+    /// The first result, as json
+    pub first: String,
+    /// The result this time, as json
+    pub again: String,
+}
+
+impl TestHarness {
+    /// The details of the current target that affect layouts
+    pub fn layout_target(&self) -> LayoutTarget {
+        let has_cfg = |cfg: &str| self.has_cfg(cfg);
+        let pointer_size = if has_cfg("target_pointer_width=\"16\"") {
+            2
+        } else if has_cfg("target_pointer_width=\"32\"") {
+            4
+        } else {
+            8
+        };
+        // i386 SysV only aligns 8-byte values (and everything larger) to 4
+        let is_i386_sysv = has_cfg("target_arch=\"x86\"") && !has_cfg("target_os=\"windows\"");
+        let align_8 = if is_i386_sysv { 4 } else { 8 };
+        let align_16 = if is_i386_sysv {
+            4
+        } else if pointer_size == 8 {
+            16
+        } else {
+            8
+        };
+        // _BitInts wider than 64 bits are arrays of the biggest integer the ABI
+        // has registers for (or would have, if 32-bit targets had 64-bit registers)
+        let bitint_chunk = if is_i386_sysv {
+            4
+        } else if has_cfg("target_arch=\"aarch64\"") || has_cfg("target_arch=\"riscv64\"") {
+            16
+        } else {
+            8
+        };
+        LayoutTarget {
+            pointer_size,
+            align_8,
+            align_16,
+            bitint_chunk,
+        }
+    }
+
+    pub(crate) fn has_cfg(&self, cfg: &str) -> bool {
+        let cfgs = &self.toolchains.platform_info.cfgs;
+        cfgs.contains(&cargo_platform::Cfg::from_str(cfg).expect("failed to parse cfg"))
+    }
+
+    /// Whether the target stores the most significant byte of a value first
+    pub fn big_endian(&self) -> bool {
+        self.has_cfg("target_endian=\"big\"")
+    }
+
+    /// The convention this actually is on this target (see [`CallingConvention::resolved`][])
+    pub fn resolved_convention(&self, convention: CallingConvention) -> CallingConvention {
+        convention.resolved(|cfg| self.has_cfg(cfg))
+    }
+
+    /// Whether args passed with this convention get classified the x86_64 SysV way
+    pub fn uses_sysv_x64(&self, convention: CallingConvention) -> bool {
+        let is_sysv_target =
+            self.has_cfg("target_arch=\"x86_64\"") && !self.has_cfg("target_os=\"windows\"");
+        match convention {
+            CallingConvention::Sysv64 => true,
+            CallingConvention::C
+            | CallingConvention::CUnwind
+            | CallingConvention::Cdecl
+            | CallingConvention::System => is_sysv_target,
+            _ => false,
+        }
+    }
+
+    /// How x86_64 SysV classifies an aggregate (None for anything else, or if we can't tell)
+    pub fn sysv_classes(
+        &self,
+        program: &TypedProgram,
+        ty: TyIdx,
+        repr: LangRepr,
+    ) -> Option<SysVClassification> {
+        let is_aggregate = matches!(
+            program.realize_ty(ty),
+            Ty::Struct(_) | Ty::Union(_) | Ty::Tagged(_) | Ty::Array(_)
+        );
+        if !is_aggregate {
+            return None;
+        }
+        let env = PunEnv {
+            lang: "c".to_owned(),
+        };
+        program
+            .sysv_classify(ty, &env, repr, &self.layout_target())
+            .ok()
+    }
+
+    /// The size of the biggest argument any function in this test takes (or returns)
+    ///
+    /// Arguments without a known layout (i.e. repr(Rust)) are ignored.
+    pub fn largest_arg_size(&self, test: &Test) -> u64 {
+        let layout_target = self.layout_target();
+        let env = PunEnv {
+            lang: "c".to_owned(),
+        };
+        let program = &test.types;
+        program
+            .all_funcs()
+            .flat_map(|func| {
+                let func = program.realize_func(func);
+                func.inputs.iter().chain(&func.outputs)
+            })
+            .filter_map(|arg| {
+                program
+                    .layout_of(arg.ty, &env, LangRepr::C, &layout_target)
+                    .ok()
+            })
+            .map(|layout| layout.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Compute the layouts of every nominal type in these tests
+    ///
+    /// If `sysv_classes` is set, this also includes how x86_64 SysV classifies each type.
+    pub fn layout_report(&self, tests: &[Arc<Test>], sysv_classes: bool) -> LayoutReport {
+        let layout_target = self.layout_target();
+        // Layouts are only specified for C-like types, so resolve puns the way C would
+        let env = PunEnv {
+            lang: "c".to_owned(),
+        };
+        let tests = tests
+            .iter()
+            .map(|test| {
+                let program = &test.types;
+                let defs = match program.definition_graph(&env) {
+                    Ok(defs) => defs.definitions(program.all_funcs()),
+                    Err(e) => {
+                        warn!("couldn't compute the types of {}: {e}", test.name);
+                        vec![]
+                    }
+                };
+                let types = defs
+                    .into_iter()
+                    .filter_map(|def| match def {
+                        Definition::DefineTy(ty) if program.realize_ty(ty).is_nominal() => Some(ty),
+                        _ => None,
+                    })
+                    .map(|ty| {
+                        let name = program.format_ty(ty);
+                        let sysv_classes = sysv_classes
+                            .then(|| self.sysv_classes(program, ty, LangRepr::C))
+                            .flatten();
+                        match program.layout_of(ty, &env, LangRepr::C, &layout_target) {
+                            Ok(layout) => TypeLayout {
+                                name,
+                                layout: Some(layout),
+                                error: None,
+                                sysv_classes,
+                            },
+                            Err(e) => TypeLayout {
+                                name,
+                                layout: None,
+                                error: Some(e.to_string()),
+                                sysv_classes,
+                            },
+                        }
+                    })
+                    .collect();
+                TestLayouts {
+                    test: test.name.clone(),
+                    types,
+                }
+            })
+            .collect();
+        LayoutReport {
+            target: self.toolchains.platform_info.target.clone(),
+            layout_target,
+            tests,
+            nondeterministic: vec![],
+        }
+    }
+
+    /// Compute the layouts in `first` again until we've done it `iterations` times,
+    /// and get every one that came out different
+    ///
+    /// Every test gets compiled from its source again each time, so this also catches
+    /// type checking that isn't deterministic.
+    pub fn check_layout_determinism(
+        &self,
+        tests: &[Arc<Test>],
+        first: &LayoutReport,
+        sysv_classes: bool,
+        iterations: usize,
+    ) -> Vec<NondeterministicLayout> {
+        let to_json = |ty: Option<&TypeLayout>| {
+            ty.map(|ty| serde_json::to_string(ty).expect("failed to serialize layout"))
+                .unwrap_or_else(|| "(missing)".to_owned())
+        };
+        let mut nondeterministic = vec![];
+        for iteration in 1..iterations {
+            let recompiled = tests
+                .iter()
+                .filter_map(|test| {
+                    let mut compiler = kdl_script::Compiler::new();
+                    match compiler.compile_string(&test.name, test.source.clone()) {
+                        Ok(types) => Some(Arc::new(Test {
+                            name: test.name.clone(),
+                            source: test.source.clone(),
+                            types,
+                        })),
+                        Err(e) => {
+                            warn!("couldn't compile {} again: {e}", test.name);
+                            None
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            let again = self.layout_report(&recompiled, sysv_classes);
+            for again_test in &again.tests {
+                let Some(first_test) = first.tests.iter().find(|t| t.test == again_test.test)
+                else {
+                    continue;
+                };
+                let len = first_test.types.len().max(again_test.types.len());
+                for idx in 0..len {
+                    let first_ty = to_json(first_test.types.get(idx));
+                    let again_ty = to_json(again_test.types.get(idx));
+                    if first_ty != again_ty {
+                        let name = first_test
+                            .types
+                            .get(idx)
+                            .or(again_test.types.get(idx))
+                            .map(|ty| ty.name.clone())
+                            .unwrap_or_default();
+                        error!(
+                            "layout of {}::{name} changed on iteration {iteration}",
+                            again_test.test
+                        );
+                        nondeterministic.push(NondeterministicLayout {
+                            test: again_test.test.clone(),
+                            name,
+                            iteration,
+                            first: first_ty,
+                            again: again_ty,
+                        });
+                    }
+                }
+            }
+        }
+        nondeterministic
+    }
+}
+
+impl LayoutReport {
+    pub fn print_human(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        writeln!(f, "layouts for {}", self.target)?;
+        for test in &self.tests {
+            if test.types.is_empty() {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "{}", test.test)?;
+            for ty in &test.types {
+                let Some(layout) = &ty.layout else {
+                    writeln!(
+                        f,
+                        "  {}: {}",
+                        ty.name,
+                        ty.error.as_deref().unwrap_or_default()
+                    )?;
+                    continue;
+                };
+                writeln!(
+                    f,
+                    "  {}: size {}, align {}",
+                    ty.name, layout.size, layout.align
+                )?;
+                let max_name_len = layout
+                    .fields
+                    .iter()
+                    .fold(0, |max, field| max.max(field.name.len()));
+                for field in &layout.fields {
+                    let mut line = format!(
+                        "    {:<max_name_len$}  offset {:<4} size {:<4} align {:<3}",
+                        field.name, field.offset, field.size, field.align
+                    );
+                    if field.padding_after != 0 {
+                        line.push_str(&format!(" (+{} padding)", field.padding_after));
+                    }
+                    writeln!(f, "{}", line.trim_end())?;
+                }
+                if let Some(classes) = &ty.sysv_classes {
+                    writeln!(f, "    sysv classes: {}", fmt_sysv_classes(classes))?;
+                }
+            }
+        }
+        if !self.nondeterministic.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "nondeterministic layouts:")?;
+            for layout in &self.nondeterministic {
+                writeln!(
+                    f,
+                    "  {}::{} (iteration {})",
+                    layout.test, layout.name, layout.iteration
+                )?;
+                writeln!(f, "    first: {}", layout.first)?;
+                writeln!(f, "    again: {}", layout.again)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any layouts came out different when they were computed again
+    pub fn failed(&self) -> bool {
+        !self.nondeterministic.is_empty()
+    }
+
+    pub fn print_json(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
+    }
+}
+
+/// Format a SysV classification for humans, i.e. `SSE (x, y), INTEGER (z)`
+pub fn fmt_sysv_classes(classes: &SysVClassification) -> String {
+    if classes.eightbytes.is_empty() {
+        return "(zero-sized, passed as nothing)".to_owned();
+    }
+    if classes.is_memory() {
+        return "MEMORY (passed on the stack)".to_owned();
+    }
+    classes
+        .eightbytes
+        .iter()
+        .map(|eightbyte| {
+            let fields = if eightbyte.fields.is_empty() {
+                "padding".to_owned()
+            } else {
+                eightbyte.fields.join(", ")
+            };
+            format!("{} ({fields})", eightbyte.class)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::harness::testing::*;
+
+    #[test]
+    fn determinism() {
+        let (harness, dir) = test_harness("layout-determinism", &["simple"]);
+        let tests = harness.all_tests();
+        let mut first = harness.layout_report(&tests, false);
+        assert_eq!(first.tests.len(), 1);
+        assert!(!first.tests[0].types.is_empty());
+
+        // The real layouts come out the same every time
+        let checked = harness.check_layout_determinism(&tests, &first, false, 3);
+        assert!(checked.is_empty(), "{checked:?}");
+
+        // Pretend the first computation got a layout wrong, and dropped the last type
+        let types = &mut first.tests[0].types;
+        let wrong = types
+            .iter_mut()
+            .find_map(|ty| Some((ty.name.clone(), ty.layout.as_mut()?)))
+            .unwrap();
+        let wrong_name = wrong.0;
+        wrong.1.size += 1;
+        let dropped = types.pop().unwrap().name;
+        assert_ne!(wrong_name, dropped);
+
+        let checked = harness.check_layout_determinism(&tests, &first, false, 3);
+        let found = checked
+            .iter()
+            .map(|layout| (layout.name.as_str(), layout.iteration))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (&*wrong_name, 1),
+                (&*dropped, 1),
+                (&*wrong_name, 2),
+                (&*dropped, 2)
+            ]
+        );
+        for layout in &checked {
+            assert_eq!(layout.test, "simple");
+            assert_ne!(layout.first, layout.again);
+        }
+        assert_eq!(checked[1].first, "(missing)");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,372 @@
+//! Checking that compilers agree with the layouts we compute (`--check-layouts`)
+//!
+//! For each toolchain we generate a tiny program that defines all the types in a test
+//! and prints their real `sizeof`/`alignof`/`offsetof` (or `size_of`/`align_of`/`offset_of!`).
+//! Those get compared to the `repr(C)` layouts from [`super::layout`][]. A mismatch here
+//! is a layout bug, which has nothing to do with calling conventions, so it's much easier
+//! to understand than the value mismatches it would otherwise show up as.
+//!
+//! With `--check-dwarf` the layouts in the probe's debug info are compared too (see [`dwarf`][]).
+
+use kdl_script::types::Ty;
+use kdl_script::{Definition, LayoutTarget};
+use serde::Serialize;
+use tracing::{debug, info};
+
+use crate::error::*;
+use crate::harness::run::runner_command;
+use crate::*;
+
+mod dwarf;
+
+/// The results of checking the layouts of every test with every toolchain
+#[derive(Debug, Serialize)]
+pub struct LayoutCheckReport {
+    /// The target the layouts were computed for
+    pub target: String,
+    pub toolchains: Vec<ToolchainId>,
+    pub tests: Vec<TestLayoutCheck>,
+}
+
+/// The results of checking one test with one toolchain
+#[derive(Debug, Serialize)]
+pub struct TestLayoutCheck {
+    pub test: String,
+    pub toolchain: ToolchainId,
+    /// How many sizes/aligns/offsets were compared
+    pub num_checked: usize,
+    pub mismatches: Vec<LayoutMismatch>,
+    /// Why we couldn't check this test (unsupported types, compile errors...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A size/align/offset the compiler disagrees with us about
+#[derive(Debug, Serialize)]
+pub struct LayoutMismatch {
+    /// The type (`MyStruct`) or field (`MyStruct.field`) with the wrong layout
+    pub item: String,
+    /// "size", "align", or "offset" (or "dwarf size" and "dwarf offset" for the debug info)
+    pub property: String,
+    /// What abi-cafe computed
+    pub computed: u64,
+    /// What the compiler actually did
+    pub actual: u64,
+}
+
+/// The sizes/aligns/offsets of some types, keyed by (item, property)
+type LayoutFacts = SortedMap<(String, String), u64>;
+
+impl TestHarness {
+    /// Check the layouts of these tests with these toolchains
+    pub fn check_layouts(
+        self: &Arc<Self>,
+        rt: &tokio::runtime::Runtime,
+        tests: &[Arc<Test>],
+        toolchains: Vec<ToolchainId>,
+        check_dwarf: bool,
+    ) -> LayoutCheckReport {
+        let tasks = tests
+            .iter()
+            .flat_map(|test| {
+                toolchains.iter().map(|toolchain_id| {
+                    let harness = self.clone();
+                    let test = test.name.clone();
+                    let toolchain_id = toolchain_id.clone();
+                    rt.spawn(async move {
+                        harness
+                            .check_test_layouts(test, toolchain_id, check_dwarf)
+                            .await
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let tests = tasks
+            .into_iter()
+            .map(|task| rt.block_on(task).expect("failed to join task"))
+            .collect();
+        LayoutCheckReport {
+            target: self.toolchains.platform_info.target.clone(),
+            toolchains,
+            tests,
+        }
+    }
+
+    async fn check_test_layouts(
+        &self,
+        test: TestId,
+        toolchain_id: ToolchainId,
+        check_dwarf: bool,
+    ) -> TestLayoutCheck {
+        let _permit = self
+            .concurrency_limiter
+            .acquire()
+            .await
+            .expect("failed to acquire concurrency limit semaphore");
+        info!("checking layouts of {test} with {toolchain_id}");
+        let mut check = TestLayoutCheck {
+            test: test.clone(),
+            toolchain: toolchain_id.clone(),
+            num_checked: 0,
+            mismatches: vec![],
+            error: None,
+        };
+        let (computed, actual) = match self.probe_layouts(&test, &toolchain_id, check_dwarf).await {
+            Ok(facts) => facts,
+            Err(e) => {
+                check.error = Some(e);
+                return check;
+            }
+        };
+        for ((item, property), &computed) in &computed {
+            // Types the compiler didn't report (or that we can't compute) aren't checked
+            let Some(&actual) = actual.get(&(item.clone(), property.clone())) else {
+                continue;
+            };
+            check.num_checked += 1;
+            if actual != computed {
+                check.mismatches.push(LayoutMismatch {
+                    item: item.clone(),
+                    property: property.clone(),
+                    computed,
+                    actual,
+                });
+            }
+        }
+        check
+    }
+
+    /// Get the layouts we compute for a test, and the ones this toolchain actually produces
+    async fn probe_layouts(
+        &self,
+        test: &TestId,
+        toolchain_id: &ToolchainId,
+        check_dwarf: bool,
+    ) -> Result<(LayoutFacts, LayoutFacts), String> {
+        let toolchain = self.toolchains.toolchains[toolchain_id].clone();
+        let options = TestOptions::default();
+        let test = self
+            .test_with_vals(test, options.val_generator, None)
+            .await
+            .map_err(|e| e.to_string())?;
+        let test = self
+            .test_with_toolchain(test, toolchain_id.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut computed = computed_layouts(&test, &self.layout_target());
+
+        // Generate, build, and run the probe
+        let mut src = String::new();
+        toolchain
+            .generate_layout_probe(
+                &mut src,
+                test.with_options(options.clone(), self.layout_target())
+                    .map_err(|e| e.to_string())?,
+            )
+            .map_err(|e| e.to_string())?;
+        let base_name = format!("{}_{toolchain_id}_layouts", test.name);
+        let src_dir = self.paths.generated_src_dir.join("layouts");
+        let out_dir = self.paths.out_dir.join("layouts");
+        let src_path = src_dir.join(format!(
+            "{base_name}.{}",
+            toolchain.src_ext(&test.types, &options)
+        ));
+        let exe_path = out_dir.join(format!("{base_name}{}", std::env::consts::EXE_SUFFIX));
+        std::fs::create_dir_all(&src_dir).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
+        std::fs::write(&src_path, src).map_err(|e| e.to_string())?;
+        toolchain
+            .compile_layout_probe(&src_path, &exe_path)
+            .map_err(|e: BuildError| e.to_string())?;
+
+        let mut cmd = runner_command(self.runner.as_deref(), &[], &exe_path);
+        debug!("running: {:?}", cmd);
+        let output = cmd
+            .envs(&self.run_env)
+            .output()
+            .map_err(|e| format!("couldn't run {exe_path}: {e}"))?;
+        if !output.status.success() {
+            return Err(format!("{exe_path} failed: {}", output.status));
+        }
+        let mut actual = parse_probe_output(&String::from_utf8_lossy(&output.stdout))
+            .map_err(|line| format!("{exe_path} printed a weird line: {line}"))?;
+
+        if check_dwarf {
+            // The debug info should describe exactly the same sizes and offsets
+            let dwarf = dwarf::dwarf_layouts(&exe_path, &src_path)?;
+            if dwarf.is_empty() && !computed.is_empty() {
+                return Err(format!(
+                    "{exe_path} has no debug info about any types (it may be elsewhere, like a .dSYM or .pdb)"
+                ));
+            }
+            actual.extend(dwarf);
+            let dwarf_computed = computed
+                .iter()
+                .filter_map(|((item, property), &val)| {
+                    let property = match &**property {
+                        "size" => dwarf::DWARF_SIZE,
+                        "offset" => dwarf::DWARF_OFFSET,
+                        _ => return None,
+                    };
+                    Some(((item.clone(), property.to_owned()), val))
+                })
+                .collect::<Vec<_>>();
+            computed.extend(dwarf_computed);
+        }
+        Ok((computed, actual))
+    }
+}
+
+/// The layouts we compute for the nominal types of a test (only the ones we can compute)
+fn computed_layouts(test: &TestWithToolchain, layout_target: &LayoutTarget) -> LayoutFacts {
+    let program = &test.types;
+    let mut facts = LayoutFacts::new();
+    for def in test.defs.definitions(program.all_funcs()) {
+        let Definition::DefineTy(ty) = def else {
+            continue;
+        };
+        let realized = program.realize_ty(ty);
+        if !realized.is_nominal() {
+            continue;
+        }
+        let Ok(layout) = program.layout_of(ty, &test.env, LangRepr::C, layout_target) else {
+            continue;
+        };
+        let name = program.format_ty(ty);
+        facts.insert((name.clone(), "size".to_owned()), layout.size);
+        facts.insert((name.clone(), "align".to_owned()), layout.align);
+        // Only struct fields have interesting (or even well-defined) offsets
+        if let Ty::Struct(_) = realized {
+            for field in &layout.fields {
+                let item = format!("{name}.{}", field.name);
+                facts.insert((item, "offset".to_owned()), field.offset);
+            }
+        }
+    }
+    facts
+}
+
+/// Parse the output of a [`Toolchain::generate_layout_probe`][] program
+///
+/// On failure the offending line is returned.
+fn parse_probe_output(output: &str) -> Result<LayoutFacts, String> {
+    let mut facts = LayoutFacts::new();
+    for line in output.lines() {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        let parse = |val: &str| val.parse::<u64>().map_err(|_| line.to_owned());
+        match parts[..] {
+            ["ty", name, size, align] => {
+                facts.insert((name.to_owned(), "size".to_owned()), parse(size)?);
+                facts.insert((name.to_owned(), "align".to_owned()), parse(align)?);
+            }
+            ["field", ty, field, offset] => {
+                facts.insert(
+                    (format!("{ty}.{field}"), "offset".to_owned()),
+                    parse(offset)?,
+                );
+            }
+            _ => return Err(line.to_owned()),
+        }
+    }
+    Ok(facts)
+}
+
+impl LayoutCheckReport {
+    /// Whether any compiler disagreed with us
+    pub fn failed(&self) -> bool {
+        self.tests.iter().any(|test| !test.mismatches.is_empty())
+    }
+
+    pub fn print_human(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        use console::Style;
+        let red = Style::new().red();
+        let green = Style::new().green();
+        let blue = Style::new().blue();
+
+        writeln!(
+            f,
+            "checking layouts for {} against {}",
+            self.target,
+            self.toolchains.join(", ")
+        )?;
+        let mut num_checked = 0;
+        let mut num_mismatches = 0;
+        let mut num_errors = 0;
+        for test in &self.tests {
+            let name = format!("{}::{}", test.test, test.toolchain);
+            if let Some(error) = &test.error {
+                num_errors += 1;
+                writeln!(f, "  {name}: {}", blue.apply_to("couldn't check"))?;
+                for line in error.lines() {
+                    writeln!(f, "    {line}")?;
+                }
+                continue;
+            }
+            num_checked += test.num_checked;
+            num_mismatches += test.mismatches.len();
+            if test.mismatches.is_empty() {
+                writeln!(f, "  {name}: {}", green.apply_to("ok"))?;
+                continue;
+            }
+            writeln!(f, "  {name}: {}", red.apply_to("mismatched"))?;
+            for mismatch in &test.mismatches {
+                writeln!(
+                    f,
+                    "    {} {}: {} says {}, abi-cafe computed {}",
+                    mismatch.item,
+                    mismatch.property,
+                    test.toolchain,
+                    mismatch.actual,
+                    mismatch.computed
+                )?;
+            }
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{num_checked} layout facts checked, {num_mismatches} mismatched, {num_errors} tests couldn't be checked"
+        )?;
+        Ok(())
+    }
+
+    pub fn print_json(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_probe() {
+        let output = "ty Point 8 4\nfield Point x 0\nfield Point y 4\nty u64 8 8\n";
+        let facts = parse_probe_output(output).unwrap();
+        let fact = |item: &str, property: &str| facts[&(item.to_owned(), property.to_owned())];
+        assert_eq!(facts.len(), 6);
+        assert_eq!((fact("Point", "size"), fact("Point", "align")), (8, 4));
+        assert_eq!(fact("Point.x", "offset"), 0);
+        assert_eq!(fact("Point.y", "offset"), 4);
+        assert_eq!((fact("u64", "size"), fact("u64", "align")), (8, 8));
+        assert!(parse_probe_output("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_probe_errors() {
+        // The error is the line we choked on
+        let bad = [
+            "ty Point 8",
+            "ty Point 8 4 2",
+            "ty Point eight 4",
+            "ty Point 8 -4",
+            "field Point x",
+            "field Point x 0x4",
+            "size Point 8 4",
+            "",
+        ];
+        for line in bad {
+            let output = format!("ty Ok 1 1\n{line}\nfield Ok x 0\n");
+            assert_eq!(parse_probe_output(&output), Err(line.to_owned()));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Reading the layouts the debug info of a layout probe describes (`--check-dwarf`)
+//!
+//! Debuggers don't compute layouts, they believe whatever `DW_AT_byte_size` and
+//! `DW_AT_data_member_location` say. So we pull those out of the probe binary and
+//! compare them to our layouts just like the sizes/offsets the probe printed.
+
+use std::borrow::Cow;
+
+use camino::Utf8Path;
+use gimli::{EndianSlice, RunTimeEndian};
+use object::{Object, ObjectSection};
+
+use super::LayoutFacts;
+
+/// The property of a size that came from the debug info
+pub const DWARF_SIZE: &str = "dwarf size";
+/// The property of an offset that came from the debug info
+pub const DWARF_OFFSET: &str = "dwarf offset";
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// Get the sizes of all the types (and offsets of all the struct fields) defined
+/// in the debug info of this binary, only looking at the parts compiled from `src_path`
+/// (everything else is the standard library or the C runtime).
+pub fn dwarf_layouts(exe_path: &Utf8Path, src_path: &Utf8Path) -> Result<LayoutFacts, String> {
+    let data = std::fs::read(exe_path).map_err(|e| format!("couldn't read {exe_path}: {e}"))?;
+    let file =
+        object::File::parse(&*data).map_err(|e| format!("couldn't parse {exe_path}: {e}"))?;
+    let endian = if file.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+    let load_section = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+        let data = file
+            .section_by_name(id.name())
+            .and_then(|section| section.uncompressed_data().ok())
+            .unwrap_or(Cow::Borrowed(&[]));
+        Ok(data)
+    };
+    let sections = gimli::DwarfSections::load(load_section).map_err(|e| e.to_string())?;
+    let dwarf = sections.borrow(|section| EndianSlice::new(section, endian));
+
+    let src_name = src_path.file_name().unwrap_or(src_path.as_str());
+    let mut facts = LayoutFacts::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next().map_err(|e| e.to_string())? {
+        let unit = dwarf.unit(header).map_err(|e| e.to_string())?;
+        // rustc names its units like `path/to/src.rs/@/cgu_name`
+        let is_probe = unit
+            .name
+            .is_some_and(|name| name.to_string_lossy().contains(src_name));
+        if !is_probe {
+            continue;
+        }
+        let mut tree = unit.entries_tree(None).map_err(|e| e.to_string())?;
+        let root = tree.root().map_err(|e| e.to_string())?;
+        add_facts(&dwarf, &unit, root, &mut facts).map_err(|e| e.to_string())?;
+    }
+    Ok(facts)
+}
+
+/// Record the layouts of every type definition in this part of the tree
+fn add_facts(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    node: gimli::EntriesTreeNode<Reader>,
+    facts: &mut LayoutFacts,
+) -> Result<(), gimli::Error> {
+    let entry = node.entry();
+    let tag = entry.tag();
+    let is_type_def = matches!(
+        tag,
+        gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_enumeration_type
+    ) && entry.attr(gimli::DW_AT_declaration)?.is_none();
+    let ty_name = if is_type_def {
+        attr_string(dwarf, unit, entry, gimli::DW_AT_name)?
+    } else {
+        None
+    };
+    if let Some(ty_name) = &ty_name {
+        if let Some(size) = attr_udata(entry, gimli::DW_AT_byte_size)? {
+            // Types with the same name in other scopes aren't ours, so the first one wins
+            facts
+                .entry((ty_name.clone(), DWARF_SIZE.to_owned()))
+                .or_insert(size);
+        }
+    }
+
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let member = child.entry();
+        if let (Some(ty_name), gimli::DW_TAG_structure_type, gimli::DW_TAG_member) =
+            (&ty_name, tag, member.tag())
+        {
+            let field_name = attr_string(dwarf, unit, member, gimli::DW_AT_name)?;
+            let offset = attr_udata(member, gimli::DW_AT_data_member_location)?;
+            if let (Some(field_name), Some(offset)) = (field_name, offset) {
+                facts
+                    .entry((format!("{ty_name}.{field_name}"), DWARF_OFFSET.to_owned()))
+                    .or_insert(offset);
+            }
+            continue;
+        }
+        add_facts(dwarf, unit, child, facts)?;
+    }
+    Ok(())
+}
+
+fn attr_string(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    attr: gimli::DwAt,
+) -> Result<Option<String>, gimli::Error> {
+    let Some(value) = entry.attr_value(attr)? else {
+        return Ok(None);
+    };
+    let string = dwarf.attr_string(unit, value)?;
+    Ok(Some(string.to_string_lossy().into_owned()))
+}
+
+/// Get an attribute that's a plain number (offsets can also be expressions, which we don't do)
+fn attr_udata(
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    attr: gimli::DwAt,
+) -> Result<Option<u64>, gimli::Error> {
+    Ok(entry
+        .attr_value(attr)?
+        .and_then(|value| value.udata_value()))
+}
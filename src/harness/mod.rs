@@ -9,34 +9,66 @@
 use crate::*;
 use camino::Utf8PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use test::*;
 use tokio::sync::{OnceCell, Semaphore};
 use tracing::warn;
 
 mod build;
 mod check;
+pub mod compare;
+pub mod explain;
 mod generate;
+pub mod heatmap;
+pub mod hex_dump;
+pub mod layout;
+pub mod layout_check;
+pub mod multi_target;
 mod read;
 pub mod report;
+pub mod reproducer;
+pub mod resume;
 mod run;
+pub mod sqlite;
 pub mod test;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod vals;
 
-pub use read::{find_test_rules, find_tests, spawn_read_test};
-pub use run::TestBuffer;
+pub use read::{find_pair_rules, find_test_rules, find_tests, spawn_read_test};
+pub use run::{split_command_line, TestBuffer};
 
 pub type Memoized<K, V> = Mutex<SortedMap<K, Arc<OnceCell<V>>>>;
 
 pub struct TestHarness {
     paths: Paths,
+    /// Extra env vars to set when running test binaries
+    pub run_env: SortedMap<String, String>,
+    /// A command to run test binaries with (instead of running them directly)
+    pub runner: Option<String>,
+    /// Whether to measure the size of the compiled callees
+    codegen_size: bool,
+    /// Whether to also emit the LLVM IR of both sides
+    emit_llvm_ir: bool,
+    /// Golden copies of the generated source to compare against (or update)
+    goldens: Option<Goldens>,
     pub toolchains: Toolchains,
     tests: SortedMap<TestId, Arc<Test>>,
     test_rules: Vec<ExpectFile>,
-    tests_with_vals: Memoized<(TestId, ValueGeneratorKind), Arc<TestWithVals>>,
-    tests_with_toolchain:
-        Memoized<(TestId, ValueGeneratorKind, ToolchainId), Arc<TestWithToolchain>>,
+    tests_with_vals: Memoized<(TestId, ValueGeneratorKind, Option<VariedArgs>), Arc<TestWithVals>>,
+    tests_with_toolchain: Memoized<
+        (TestId, ValueGeneratorKind, Option<VariedArgs>, ToolchainId),
+        Arc<TestWithToolchain>,
+    >,
     generated_sources: Memoized<Utf8PathBuf, ()>,
     built_static_libs: Memoized<String, String>,
+    built_shared_libs: Memoized<String, Utf8PathBuf>,
+    emitted_llvm_ir: Memoized<String, Option<Utf8PathBuf>>,
+    /// The values failures were minimized to, keyed by the test, value generator, and
+    /// selection of the minimized test (see [`TestHarness::record_replay`][])
+    replays: Mutex<SortedMap<(TestId, ValueGeneratorKind, FunctionSelector), ReplayedVal>>,
+    /// Every command we ran to build and run tests (see [`TestHarness::commands`][])
+    commands: CommandLog,
     concurrency_limiter: tokio::sync::Semaphore,
 }
 
@@ -44,11 +76,17 @@ impl TestHarness {
     pub fn new(
         test_rules: Vec<ExpectFile>,
         tests: SortedMap<TestId, Arc<Test>>,
+        toolchain_manifests: Vec<ToolchainManifest>,
         cfg: &Config,
     ) -> Self {
-        let toolchains = toolchains::create_toolchains(cfg);
+        let toolchains = toolchains::create_toolchains(cfg, toolchain_manifests);
         Self {
             paths: cfg.paths.clone(),
+            run_env: cfg.run_env.iter().cloned().collect(),
+            runner: cfg.runner.clone(),
+            codegen_size: cfg.codegen_size,
+            emit_llvm_ir: cfg.emit_llvm_ir,
+            goldens: cfg.goldens.clone(),
             tests,
             test_rules,
             toolchains,
@@ -56,10 +94,19 @@ impl TestHarness {
             tests_with_toolchain: Default::default(),
             generated_sources: Default::default(),
             built_static_libs: Default::default(),
-            concurrency_limiter: Semaphore::new(128),
+            built_shared_libs: Default::default(),
+            emitted_llvm_ir: Default::default(),
+            replays: Default::default(),
+            commands: Default::default(),
+            concurrency_limiter: Semaphore::new(cfg.jobs),
         }
     }
 
+    /// Every command run to build and run tests so far, in the order they ran
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.commands()
+    }
+
     pub fn toolchain_by_test_key(
         &self,
         key: &TestKey,
@@ -79,6 +126,7 @@ impl TestHarness {
         &self,
         test_id: &TestId,
         vals: ValueGeneratorKind,
+        varied_args: Option<VariedArgs>,
     ) -> Result<Arc<TestWithVals>, GenerateError> {
         let test_id = test_id.clone();
         let test = self.test(&test_id);
@@ -86,13 +134,90 @@ impl TestHarness {
             .tests_with_vals
             .lock()
             .unwrap()
-            .entry((test_id, vals))
+            .entry((test_id, vals, varied_args.clone()))
             .or_insert_with(|| Arc::new(OnceCell::new()))
             .clone();
         // Either acquire the cached result, or make it
-        let output = once.get_or_try_init(|| test.with_vals(vals)).await?.clone();
+        let output = once
+            .get_or_try_init(|| test.with_vals(vals, varied_args, self.big_endian()))
+            .await?
+            .clone();
         Ok(output)
     }
+    /// Remember exactly what the value a test failed on was, so that the test it's
+    /// minimized to (`minimized_key`) gets the same bytes, instead of regenerating them
+    ///
+    /// Values are deterministic for a given generator, but this way minimizing doesn't
+    /// rely on that staying true for every generator, selection, and writer, and the
+    /// minimized test is pinned to the bytes the failure actually reported.
+    pub async fn record_replay(
+        &self,
+        failed_key: &TestKey,
+        minimized_key: &TestKey,
+        failure: &CheckFailure,
+    ) -> Result<(), GenerateError> {
+        let replay = match failure {
+            CheckFailure::ValMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                expected,
+                ..
+            } => ReplayedVal {
+                func_idx: *func_idx,
+                arg_idx: *arg_idx,
+                val_idx: *val_idx,
+                val: ValueGenerator::Replay {
+                    bytes: expected.as_slice().into(),
+                },
+            },
+            // Tags are picked from the generator, so replay the generator itself
+            CheckFailure::TagMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                ..
+            } => {
+                let test = self
+                    .test_with_vals(
+                        &failed_key.test,
+                        failed_key.options.val_generator,
+                        failed_key.options.functions.varied_args(),
+                    )
+                    .await?;
+                let val = test.vals.funcs[*func_idx].args[*arg_idx].vals[*val_idx]
+                    .val
+                    .clone();
+                ReplayedVal {
+                    func_idx: *func_idx,
+                    arg_idx: *arg_idx,
+                    val_idx: *val_idx,
+                    val,
+                }
+            }
+        };
+        self.replays.lock().unwrap().insert(
+            (
+                minimized_key.test.clone(),
+                minimized_key.options.val_generator,
+                minimized_key.options.functions.clone(),
+            ),
+            replay,
+        );
+        Ok(())
+    }
+    /// The value recorded by [`TestHarness::record_replay`][] for this test, if any
+    pub fn replay(&self, key: &TestKey) -> Option<ReplayedVal> {
+        self.replays
+            .lock()
+            .unwrap()
+            .get(&(
+                key.test.clone(),
+                key.options.val_generator,
+                key.options.functions.clone(),
+            ))
+            .cloned()
+    }
     pub async fn test_with_toolchain(
         &self,
         test: Arc<TestWithVals>,
@@ -100,13 +225,14 @@ impl TestHarness {
     ) -> Result<Arc<TestWithToolchain>, GenerateError> {
         let test_id = test.name.clone();
         let vals = test.vals.generator_kind;
+        let varied_args = test.vals.varied_args.clone();
         let toolchain = self.toolchains.toolchains[&toolchain_id].clone();
         // Briefly lock this map to insert/acquire a OnceCell and then release the lock
         let once = self
             .tests_with_toolchain
             .lock()
             .unwrap()
-            .entry((test_id, vals, toolchain_id.clone()))
+            .entry((test_id, vals, varied_args, toolchain_id.clone()))
             .or_insert_with(|| Arc::new(OnceCell::new()))
             .clone();
         // Either acquire the cached result, or make it
@@ -116,6 +242,54 @@ impl TestHarness {
             .clone();
         Ok(output)
     }
+    /// How likely this test is to fail (higher runs sooner with `--order priority`)
+    ///
+    /// `arg_size` is the [`TestHarness::largest_arg_size`][] of the test.
+    pub fn test_priority(&self, key: &TestKey, arg_size: u64) -> u32 {
+        let mut priority = 0;
+        // Toolchains almost always agree with themselves, pairing them up is the point
+        if key.caller != key.callee {
+            priority += 4;
+        }
+        // The default conventions are the best trodden
+        if !matches!(
+            key.options.convention,
+            CallingConvention::C | CallingConvention::Rust
+        ) {
+            priority += 4;
+        }
+        if key.options.repr != key.options.callee_repr {
+            priority += 2;
+        }
+        if key.options.convention != key.options.callee_convention {
+            priority += 2;
+        }
+        if let ValueGeneratorKind::Random { .. } = key.options.val_generator {
+            priority += 1;
+        }
+        // Big structs get split across registers or passed in memory
+        priority += match arg_size {
+            0..=8 => 0,
+            9..=16 => 1,
+            17..=64 => 2,
+            _ => 3,
+        };
+        priority
+    }
+
+    /// [`TestHarness::test_priority`][] for all these tests
+    pub fn test_priorities(&self, keys: &[TestKey]) -> Vec<u32> {
+        let mut arg_sizes = SortedMap::new();
+        keys.iter()
+            .map(|key| {
+                let arg_size = *arg_sizes
+                    .entry(key.test.clone())
+                    .or_insert_with(|| self.largest_arg_size(&self.test(&key.test)));
+                self.test_priority(key, arg_size)
+            })
+            .collect()
+    }
+
     pub fn spawn_test(
         self: Arc<Self>,
         rt: &tokio::runtime::Runtime,
@@ -126,6 +300,24 @@ impl TestHarness {
         rt.spawn(async move { harness.do_test(test_key, rules).await })
     }
 
+    /// Only generate the source of this test, once `--jobs` allows it
+    ///
+    /// This is how failures get minimized. The main run only has to wait its turn to
+    /// build and run, but there are so many of these that generating needs a turn too.
+    pub async fn generate_limited(
+        &self,
+        test_key: TestKey,
+        mut test_rules: TestRules,
+    ) -> TestRunResults {
+        test_rules.run = TestRunMode::Generate;
+        let _permit = self
+            .concurrency_limiter
+            .acquire()
+            .await
+            .expect("failed to acquire concurrency limit semaphore");
+        self.do_test(test_key, test_rules).await
+    }
+
     /// Generate, Compile, Link, Load, and Run this test.
     #[tracing::instrument(name = "test", skip_all, fields(id = self.base_id(&test_key, None, "::")))]
     pub async fn do_test(&self, test_key: TestKey, test_rules: TestRules) -> TestRunResults {
@@ -137,7 +329,9 @@ impl TestHarness {
         }
 
         res.ran_to = Generate;
+        let start = Instant::now();
         res.source = Some(self.generate_test(&res.key).await);
+        res.timings.record(Generate, start);
         let source = match res.source.as_ref().unwrap() {
             Ok(v) => v,
             Err(e) => {
@@ -158,7 +352,9 @@ impl TestHarness {
         }
 
         res.ran_to = Build;
+        let start = Instant::now();
         res.build = Some(self.build_test(&res.key, source).await);
+        res.timings.record(Build, start);
         let build = match res.build.as_ref().unwrap() {
             Ok(v) => v,
             Err(e) => {
@@ -166,12 +362,20 @@ impl TestHarness {
                 return res;
             }
         };
+        if self.codegen_size {
+            res.codegen_size = self.measure_codegen_size(&res.key, build).await;
+        }
+        if self.emit_llvm_ir {
+            res.llvm_ir = Some(self.emit_test_llvm_ir(&res.key, source).await);
+        }
         if res.rules.run <= Build {
             return res;
         }
 
         res.ran_to = Link;
+        let start = Instant::now();
         res.link = Some(self.link_bin(&res.key, build).await);
+        res.timings.record(Link, start);
         let link = match res.link.as_ref().unwrap() {
             Ok(v) => v,
             Err(e) => {
@@ -184,7 +388,9 @@ impl TestHarness {
         }
 
         res.ran_to = Run;
+        let start = Instant::now();
         res.run = Some(self.run_bin_test(&res.key, link).await);
+        res.timings.record(Run, start);
         let run = match res.run.as_ref().unwrap() {
             Ok(v) => v,
             Err(e) => {
@@ -197,7 +403,9 @@ impl TestHarness {
         }
 
         res.ran_to = Check;
-        res.check = Some(self.check_test(&res.key, run).await);
+        let start = Instant::now();
+        res.check = Some(self.check_test(&res.key, &res.rules, run).await);
+        res.timings.record(Check, start);
 
         res
     }
@@ -211,10 +419,28 @@ impl TestHarness {
             options:
                 TestOptions {
                     convention,
+                    callee_convention,
                     functions,
                     val_writer,
                     val_generator,
                     repr,
+                    callee_repr,
+                    inout_refs,
+                    alias_refs,
+                    tail_call_chain,
+                    nested_calls,
+                    unwind,
+                    fault,
+                    shuffle_fields,
+                    dlopen,
+                    linker,
+                    call_path,
+                    pad_byte,
+                    through_helpers,
+                    rounding_mode,
+                    lto,
+                    target_features,
+                    callee_target_features,
                 },
             caller,
             callee,
@@ -226,8 +452,16 @@ impl TestHarness {
         output.push_str(test);
         output.push_str(separator);
         output.push_str(&format!("conv_{convention}"));
+        if callee_convention != convention {
+            output.push_str(separator);
+            output.push_str(&format!("callee_conv_{callee_convention}"));
+        }
         output.push_str(separator);
         output.push_str(&format!("repr_{repr}"));
+        if callee_repr != repr {
+            output.push_str(separator);
+            output.push_str(&format!("callee_repr_{callee_repr}"));
+        }
         if let FunctionSelector::One { idx, args } = functions {
             let test = self.tests[test].clone();
             let func = test.types.realize_func(*idx);
@@ -241,6 +475,11 @@ impl TestHarness {
                     output.push_str(&format!("val{idx}"));
                 }
             }
+            if let ArgSelector::Vary { idxs } = args {
+                let idxs = idxs.iter().map(|idx| idx.to_string()).collect::<Vec<_>>();
+                output.push_str(separator);
+                output.push_str(&format!("vary{}", idxs.join("_")));
+            }
         }
         output.push_str(separator);
         match call_side {
@@ -276,6 +515,74 @@ impl TestHarness {
                 output.push_str(&other.to_string())
             }
         }
+        if *inout_refs {
+            output.push_str(separator);
+            output.push_str("inout");
+        }
+        if *alias_refs {
+            output.push_str(separator);
+            output.push_str("aliased");
+        }
+        if *tail_call_chain > 0 {
+            output.push_str(separator);
+            output.push_str(&format!("tail_chain{tail_call_chain}"));
+        }
+        if *nested_calls > 0 {
+            output.push_str(separator);
+            output.push_str(&format!("nested{nested_calls}"));
+        }
+        if *unwind {
+            output.push_str(separator);
+            output.push_str("unwind");
+        }
+        if *shuffle_fields {
+            output.push_str(separator);
+            output.push_str("shuffled");
+        }
+        if *dlopen {
+            output.push_str(separator);
+            output.push_str("dlopen");
+        }
+        if *linker != Linker::Default {
+            output.push_str(separator);
+            output.push_str(&format!("ld_{linker}"));
+        }
+        if *call_path != CallPath::Direct {
+            output.push_str(separator);
+            output.push_str(&call_path.to_string());
+        }
+        if let Some(pad_byte) = pad_byte {
+            output.push_str(separator);
+            output.push_str(&format!("pad{pad_byte:02x}"));
+        }
+        if *through_helpers {
+            output.push_str(separator);
+            output.push_str("helpers");
+        }
+        if let Some(rounding_mode) = rounding_mode {
+            output.push_str(separator);
+            output.push_str(&format!("round_{rounding_mode}"));
+        }
+        if *lto != Lto::Off {
+            output.push_str(separator);
+            output.push_str(&format!("lto_{lto}"));
+        }
+        if !target_features.is_default() {
+            output.push_str(separator);
+            output.push_str(&format!("tf_{}", target_features.id()));
+        }
+        if callee_target_features != target_features {
+            output.push_str(separator);
+            if callee_target_features.is_default() {
+                output.push_str("callee_tf_default");
+            } else {
+                output.push_str(&format!("callee_tf_{}", callee_target_features.id()));
+            }
+        }
+        if let Some(fault) = fault {
+            output.push_str(separator);
+            output.push_str(&format!("fault_{fault}"));
+        }
         output
     }
 
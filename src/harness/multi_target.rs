@@ -0,0 +1,412 @@
+//! Merging the json reports of runs on several targets into one (`--merge-reports`)
+//!
+//! Each run of abi-cafe only tests the target it runs on, so covering x86_64, aarch64,
+//! and riscv64 means three runs and three reports. This reads those reports back in
+//! and lines them up by test id, so every test gets one result per target. The
+//! `possible_rules` of each report are already keyed by target, so merging them
+//! produces a rules file that covers every target at once.
+//!
+//! The reports of the shards of a run (`--shard`) are all for the same target, so
+//! those get put back together into one report for that target first.
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::HarnessError;
+use crate::harness::report::*;
+use crate::*;
+
+/// The results of several runs (each on a different target), lined up by test
+#[derive(Debug, Serialize)]
+pub struct MultiTargetReport {
+    /// See [`REPORT_SCHEMA_VERSION`][]
+    pub schema_version: u32,
+    /// Every target, in the order their reports were given
+    pub targets: Vec<TargetReport>,
+    /// The conclusion of every test on each target it was run on, by test id and then target
+    pub tests: SortedMap<String, SortedMap<String, TestConclusion>>,
+    /// The `possible_rules` of every target, merged
+    pub possible_rules: Option<ExpectFile>,
+}
+
+/// The gist of one target's report
+#[derive(Debug, Serialize)]
+pub struct TargetReport {
+    pub target: String,
+    /// The file the report was read from (or the files, for the shards of a run)
+    pub report: String,
+    /// The shards the report was put back together from (see `--shard`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shards: Vec<Shard>,
+    pub summary: TestSummary,
+    pub toolchain_versions: SortedMap<String, Option<String>>,
+}
+
+/// The parts of a [`FullReport`][] we need, as read back from json
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReadReport {
+    pub schema_version: u32,
+    /// Only missing in reports from before it was recorded
+    pub target: Option<String>,
+    #[serde(default)]
+    pub shard: Option<Shard>,
+    pub summary: TestSummary,
+    pub toolchain_versions: SortedMap<String, Option<String>>,
+    pub possible_rules: Option<ExpectFile>,
+    pub tests: Vec<ReadTestReport>,
+}
+
+/// The parts of a [`TestReport`][] we need, as read back from json
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReadTestReport {
+    pub id: String,
+    pub conclusion: TestConclusion,
+}
+
+impl ReadReport {
+    /// Read a report that `--output-format=json` produced
+    pub(crate) fn from_path(path: &Utf8Path) -> Result<Self, HarnessError> {
+        let read_error = |details: String| HarnessError::ReadReport {
+            path: path.to_string(),
+            details,
+        };
+        let file = std::fs::File::open(path).map_err(|e| read_error(e.to_string()))?;
+        let report: ReadReport = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| read_error(e.to_string()))?;
+        if report.schema_version != REPORT_SCHEMA_VERSION {
+            return Err(read_error(format!(
+                "it has schema_version {}, but this abi-cafe only understands {REPORT_SCHEMA_VERSION}",
+                report.schema_version
+            )));
+        }
+        Ok(report)
+    }
+
+    /// The target this report is for
+    pub(crate) fn target(&self) -> Option<&str> {
+        // Older reports only mention their target in their rules
+        self.target.as_deref().or_else(|| {
+            let rules = self.possible_rules.as_ref()?;
+            rules.target.keys().next().map(|target| target.as_str())
+        })
+    }
+}
+
+/// Merge the reports of runs on different targets
+pub fn merge_reports(paths: &[Utf8PathBuf]) -> Result<MultiTargetReport, HarnessError> {
+    let mut merged = MultiTargetReport {
+        schema_version: REPORT_SCHEMA_VERSION,
+        targets: vec![],
+        tests: SortedMap::new(),
+        possible_rules: None,
+    };
+    for path in paths {
+        let report = ReadReport::from_path(path)?;
+        let Some(target) = report.target().map(|target| target.to_owned()) else {
+            return Err(HarnessError::ReadReport {
+                path: path.to_string(),
+                details: "it doesn't say which target it's for (it's from an older abi-cafe)"
+                    .to_owned(),
+            });
+        };
+        let other_idx = merged
+            .targets
+            .iter()
+            .position(|other| other.target == target);
+        if let Some(other) = other_idx.map(|idx| &merged.targets[idx]) {
+            // Only another shard of the same run may be for the same target
+            let is_other_shard = report.shard.is_some_and(|shard| {
+                other
+                    .shards
+                    .iter()
+                    .all(|other| other.count == shard.count && other.index != shard.index)
+            });
+            if other.shards.is_empty() || !is_other_shard {
+                return Err(HarnessError::ReadReport {
+                    path: path.to_string(),
+                    details: format!("it's for {target}, just like {}", other.report),
+                });
+            }
+        }
+
+        for test in report.tests {
+            let targets = merged.tests.entry(test.id.clone()).or_default();
+            // Shards are disjoint, so each test has exactly one result per target
+            if let Some(old) = targets.insert(target.clone(), test.conclusion) {
+                let details = if old == test.conclusion {
+                    format!("it has {target}'s result for {} again", test.id)
+                } else {
+                    format!(
+                        "it says {} {} on {target}, but it already {old} there",
+                        test.id, test.conclusion
+                    )
+                };
+                return Err(HarnessError::ReadReport {
+                    path: path.to_string(),
+                    details,
+                });
+            }
+        }
+        if let Some(rules) = report.possible_rules {
+            let possible_rules = merged
+                .possible_rules
+                .get_or_insert_with(ExpectFile::default);
+            for (target, rules) in rules.target {
+                possible_rules
+                    .target
+                    .entry(target)
+                    .or_default()
+                    .extend(rules);
+            }
+        }
+        if let Some(idx) = other_idx {
+            let other = &mut merged.targets[idx];
+            other.report = format!("{}, {path}", other.report);
+            other.shards.extend(report.shard);
+            other.summary.add(&report.summary);
+            other.toolchain_versions.extend(report.toolchain_versions);
+        } else {
+            merged.targets.push(TargetReport {
+                target,
+                report: path.to_string(),
+                shards: report.shard.into_iter().collect(),
+                summary: report.summary,
+                toolchain_versions: report.toolchain_versions,
+            });
+        }
+    }
+
+    for target in &merged.targets {
+        if let Some(shard) = target.shards.first() {
+            if target.shards.len() != shard.count {
+                warn!(
+                    "only {} of the {} shards of {} were merged, so some tests are missing",
+                    target.shards.len(),
+                    shard.count,
+                    target.target
+                );
+            }
+        }
+    }
+    Ok(merged)
+}
+
+impl MultiTargetReport {
+    /// Whether any test failed on any target
+    pub fn failed(&self) -> bool {
+        self.tests
+            .values()
+            .flat_map(|targets| targets.values())
+            .any(|conclusion| *conclusion == TestConclusion::Failed)
+    }
+
+    pub fn print_human(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        use console::Style;
+        let red = Style::new().red();
+        let green = Style::new().green();
+        let blue = Style::new().blue();
+
+        // Tests nobody ran would just be a wall of "skipped"
+        let tests = self
+            .tests
+            .iter()
+            .filter(|(_, targets)| {
+                targets
+                    .values()
+                    .any(|conclusion| *conclusion != TestConclusion::Skipped)
+            })
+            .collect::<Vec<_>>();
+        let id_width = tests.iter().map(|(id, _)| id.len()).max().unwrap_or(0);
+        // (The last column doesn't need any padding)
+        let mut widths = self
+            .targets
+            .iter()
+            .map(|target| target.target.len().max("skipped".len()))
+            .collect::<Vec<_>>();
+        if let Some(last) = widths.last_mut() {
+            *last = 0;
+        }
+
+        write!(f, "{:id_width$}", "")?;
+        for (target, width) in self.targets.iter().zip(&widths) {
+            write!(f, "  {:width$}", target.target)?;
+        }
+        writeln!(f)?;
+        for (id, targets) in &tests {
+            write!(f, "{id:id_width$}")?;
+            for (target, &width) in self.targets.iter().zip(&widths) {
+                // Pad before styling, the escape codes would throw off the width
+                let (cell, style) = match targets.get(&target.target) {
+                    Some(TestConclusion::Passed) => ("passed", &green),
+                    Some(TestConclusion::Busted) => ("busted", &blue),
+                    Some(TestConclusion::Failed) => ("failed", &red),
+                    Some(TestConclusion::Skipped) => ("skipped", &blue),
+                    None => ("-", &blue),
+                };
+                write!(f, "  {}", style.apply_to(format!("{cell:width$}")))?;
+            }
+            writeln!(f)?;
+        }
+        let num_hidden = self.tests.len() - tests.len();
+        if num_hidden > 0 {
+            writeln!(
+                f,
+                "({num_hidden} tests skipped on every target aren't shown)"
+            )?;
+        }
+
+        writeln!(f)?;
+        for target in &self.targets {
+            let summary = &target.summary;
+            writeln!(
+                f,
+                "{}: {} test sets run - {} passed, {} busted, {} failed, {} skipped ({})",
+                target.target,
+                summary.num_tests,
+                summary.num_passed,
+                summary.num_busted,
+                summary.num_failed,
+                summary.num_skipped,
+                target.report,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn print_json(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+    use TestConclusion::*;
+
+    fn on_target(target: &str, shard: Option<Shard>, tests: Vec<TestReport>) -> FullReport {
+        let mut report = full_report(tests);
+        report.target = target.to_owned();
+        report.shard = shard;
+        report
+    }
+
+    fn shard(index: usize) -> Option<Shard> {
+        Some(Shard { index, count: 2 })
+    }
+
+    fn read_error(result: Result<MultiTargetReport, HarnessError>) -> (String, String) {
+        match result {
+            Err(HarnessError::ReadReport { path, details }) => (path, details),
+            other => panic!("expected a ReadReport error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn merge_targets_and_shards() {
+        let x64 = "x86_64-unknown-linux-gnu";
+        let arm = "aarch64-unknown-linux-gnu";
+        let paths = write_reports(
+            "merge",
+            [
+                (
+                    "x64-1.json",
+                    on_target(x64, shard(1), vec![test_report("a", "cc", "rustc", Passed)]),
+                ),
+                (
+                    "arm.json",
+                    on_target(
+                        arm,
+                        None,
+                        vec![
+                            test_report("a", "cc", "rustc", Failed),
+                            test_report("b", "cc", "rustc", Passed),
+                        ],
+                    ),
+                ),
+                (
+                    "x64-2.json",
+                    on_target(x64, shard(2), vec![test_report("b", "cc", "rustc", Busted)]),
+                ),
+            ],
+        );
+        let merged = merge_reports(&paths).unwrap();
+
+        let targets = merged
+            .targets
+            .iter()
+            .map(|target| (target.target.as_str(), target.shards.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(targets, [(x64, 2), (arm, 0)]);
+        // The shards' summaries add up
+        assert_eq!(merged.targets[0].summary.num_tests, 2);
+        assert_eq!(merged.targets[0].summary.num_busted, 1);
+        let id = |test: &str| format!("{test}::conv_c::repr_c::cc_calls_rustc");
+        let a = &merged.tests[&id("a")];
+        assert_eq!((a[x64], a[arm]), (Passed, Failed));
+        let b = &merged.tests[&id("b")];
+        assert_eq!((b[x64], b[arm]), (Busted, Passed));
+        assert!(merged.failed());
+        std::fs::remove_dir_all(paths[0].parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        let x64 = "x86_64-unknown-linux-gnu";
+        let passed = || vec![test_report("a", "cc", "rustc", Passed)];
+        let failed = || vec![test_report("a", "cc", "rustc", Failed)];
+        let paths = write_reports(
+            "merge-duplicates",
+            [
+                ("1.json", on_target(x64, shard(1), passed())),
+                ("same.json", on_target(x64, shard(2), passed())),
+                ("conflict.json", on_target(x64, shard(2), failed())),
+                ("unsharded.json", on_target(x64, None, passed())),
+                ("again.json", on_target(x64, shard(1), vec![])),
+            ],
+        );
+        let [first, same, conflict, unsharded, again] = &paths;
+        let merge = |second: &Utf8PathBuf| merge_reports(&[first.clone(), second.clone()]);
+
+        // Two shards with the same test
+        let (path, details) = read_error(merge(same));
+        assert_eq!(path, same.as_str());
+        assert!(details.contains("again"), "{details}");
+        let (path, details) = read_error(merge(conflict));
+        assert_eq!(path, conflict.as_str());
+        assert!(
+            details.ends_with(" failed on x86_64-unknown-linux-gnu, but it already passed there"),
+            "{details}"
+        );
+
+        // Two reports for the same target that aren't different shards of one run
+        let (_, details) = read_error(merge(unsharded));
+        assert!(
+            details.starts_with("it's for x86_64-unknown-linux-gnu, just like"),
+            "{details}"
+        );
+        let (_, details) = read_error(merge(again));
+        assert!(
+            details.starts_with("it's for x86_64-unknown-linux-gnu, just like"),
+            "{details}"
+        );
+        std::fs::remove_dir_all(first.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn merge_other_schema_version() {
+        let mut report = full_report(vec![test_report("a", "cc", "rustc", Passed)]);
+        report.schema_version = REPORT_SCHEMA_VERSION + 1;
+        let paths = write_reports("merge-schema", [("new.json", report)]);
+        let (_, details) = read_error(merge_reports(&paths));
+        assert_eq!(
+            details,
+            format!(
+                "it has schema_version {}, but this abi-cafe only understands {REPORT_SCHEMA_VERSION}",
+                REPORT_SCHEMA_VERSION + 1
+            )
+        );
+        std::fs::remove_dir_all(paths[0].parent().unwrap()).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+mod c_header;
+mod changes;
 mod procgen;
 
 use std::{
@@ -17,8 +19,27 @@ use crate::*;
 pub enum TestFile {
     Kdl(Pathish),
     KdlProcgen(Pathish),
+    Interleaved {
+        int_ty: &'static str,
+        float_ty: &'static str,
+        counts: Vec<usize>,
+    },
+    /// Small structs of mixed ints and floats (see `procgen_mixed_regs_test_string`)
+    MixedRegs,
+    /// Lots of arguments passed on the stack (see `procgen_stack_args_test_string`)
+    StackArgs {
+        counts: Vec<usize>,
+    },
+    /// The functions declared in a C header (see `--import-headers`)
+    CHeader {
+        path: Utf8PathBuf,
+        clang_args: Vec<String>,
+    },
 }
 
+/// The (int, float) pairs we generate interleaved register exhaustion tests for
+static INTERLEAVED_TESTS: &[(&str, &str)] = &[("i64", "f64"), ("i32", "f32")];
+
 #[derive(Debug, Clone)]
 pub enum Pathish {
     Runtime(Utf8PathBuf),
@@ -32,6 +53,23 @@ impl Pathish {
     }
 }
 
+impl TestFile {
+    /// Where this test came from, for telling the user about it
+    fn describe(&self) -> String {
+        match self {
+            TestFile::Kdl(Pathish::Runtime(path))
+            | TestFile::KdlProcgen(Pathish::Runtime(path)) => path.to_string(),
+            TestFile::Kdl(Pathish::Static(path)) | TestFile::KdlProcgen(Pathish::Static(path)) => {
+                format!("{path} (builtin)")
+            }
+            TestFile::Interleaved { .. } => "(builtin interleaved test)".to_owned(),
+            TestFile::MixedRegs => "(builtin mixed registers test)".to_owned(),
+            TestFile::StackArgs { .. } => "(builtin stack arguments test)".to_owned(),
+            TestFile::CHeader { path, .. } => format!("{path} (imported C header)"),
+        }
+    }
+}
+
 pub fn find_test_rules(cfg: &Config) -> Result<Vec<ExpectFile>, GenerateError> {
     let static_rules = find_test_rules_static(cfg.disable_builtin_rules)?;
     let rules = find_test_rules_runtime(cfg.paths.runtime_rules_file.as_deref())?;
@@ -58,13 +96,100 @@ pub fn find_test_rules_runtime(rule_file: Option<&Utf8Path>) -> Result<ExpectFil
     }
 }
 
+pub fn find_pair_rules(cfg: &Config) -> Result<PairRulesFile, GenerateError> {
+    if let Some(pair_rules_file) = &cfg.paths.runtime_pair_rules_file {
+        let data = read_runtime_file_to_string(pair_rules_file)?;
+        let rules = toml::from_str(&data)?;
+        Ok(rules)
+    } else {
+        Ok(PairRulesFile::default())
+    }
+}
+
 pub fn find_tests(cfg: &Config) -> Result<SortedMap<TestId, TestFile>, GenerateError> {
-    let mut tests = find_tests_runtime(cfg.paths.runtime_test_input_dir.as_deref())?;
-    let mut more_tests = find_tests_static(cfg.disable_builtin_tests)?;
-    tests.append(&mut more_tests);
+    let mut tests = SortedMap::new();
+    for dir in &cfg.paths.runtime_test_input_dirs {
+        merge_tests(&mut tests, find_tests_runtime(Some(dir))?)?;
+    }
+    merge_tests(&mut tests, find_tests_static(cfg.disable_builtin_tests)?)?;
+    merge_tests(
+        &mut tests,
+        find_tests_interleaved(cfg.disable_builtin_tests, &cfg.interleave_counts),
+    )?;
+    if !cfg.disable_builtin_tests {
+        insert_test(&mut tests, "mixed_regs".to_owned(), TestFile::MixedRegs)?;
+        if !cfg.stack_args.is_empty() {
+            let counts = cfg.stack_args.clone();
+            insert_test(
+                &mut tests,
+                "stack_args".to_owned(),
+                TestFile::StackArgs { counts },
+            )?;
+        }
+    }
+    for path in &cfg.import_headers {
+        let name = path.file_stem().expect("header had no name!?").to_owned();
+        let test = TestFile::CHeader {
+            path: path.clone(),
+            clang_args: cfg.header_clang_args.clone(),
+        };
+        insert_test(&mut tests, name, test)?;
+    }
+    if let Some(changed) = &cfg.changed_files {
+        changes::retain_affected_tests(cfg, changed, &mut tests)?;
+    }
     Ok(tests)
 }
 
+/// Add some tests to the test suite, refusing to let two tests have the same name
+fn merge_tests(
+    tests: &mut SortedMap<TestId, TestFile>,
+    more_tests: SortedMap<TestId, TestFile>,
+) -> Result<(), GenerateError> {
+    for (name, test) in more_tests {
+        insert_test(tests, name, test)?;
+    }
+    Ok(())
+}
+
+fn insert_test(
+    tests: &mut SortedMap<TestId, TestFile>,
+    name: TestId,
+    test: TestFile,
+) -> Result<(), GenerateError> {
+    if let Some(existing) = tests.get(&name) {
+        return Err(GenerateError::DuplicateTest {
+            test: name,
+            first: existing.describe(),
+            second: test.describe(),
+        });
+    }
+    tests.insert(name, test);
+    Ok(())
+}
+
+/// The interleaved tests are entirely procgen'd, and have no file backing them
+pub fn find_tests_interleaved(
+    disable_builtin_tests: bool,
+    counts: &[usize],
+) -> SortedMap<TestId, TestFile> {
+    let mut tests = SortedMap::new();
+    if disable_builtin_tests || counts.is_empty() {
+        return tests;
+    }
+    for &(int_ty, float_ty) in INTERLEAVED_TESTS {
+        tests.insert(
+            format!("interleaved_{int_ty}_{float_ty}"),
+            TestFile::Interleaved {
+                int_ty,
+                float_ty,
+                counts: counts.to_owned(),
+            },
+        );
+    }
+    tests
+}
+
 pub fn find_tests_runtime(
     start_dir: Option<&Utf8Path>,
 ) -> Result<SortedMap<TestId, TestFile>, GenerateError> {
@@ -85,11 +210,14 @@ pub fn find_tests_runtime(
 
             let path = entry.path();
             let test_file = Utf8PathBuf::from_path_buf(path).expect("non-utf8 test path");
+            if is_shared_types(&test_file) {
+                continue;
+            }
             let Some((name, test)) = classify_test(&test_file, true) else {
                 warn!("test isn't a known test format: {}", test_file);
                 continue;
             };
-            tests.insert(name, test);
+            insert_test(&mut tests, name, test)?;
         }
     }
     Ok(tests)
@@ -116,11 +244,14 @@ pub fn find_tests_static(
                 let path = file.path();
                 let test_file =
                     Utf8PathBuf::from_path_buf(path.to_owned()).expect("non-utf8 test path");
+                if is_shared_types(&test_file) {
+                    continue;
+                }
                 let Some((name, test)) = classify_test(&test_file, false) else {
                     warn!("test isn't a known test format: {}", test_file);
                     continue;
                 };
-                tests.insert(name, test);
+                insert_test(&mut tests, name, test)?;
             }
         }
     }
@@ -146,25 +277,60 @@ async fn read_test(test: TestId, test_file: TestFile) -> Result<Arc<Test>, Gener
 }
 
 async fn read_test_inner(test: &TestId, test_file: TestFile) -> Result<Arc<Test>, GenerateError> {
+    let mut compiler = kdl_script::Compiler::new();
     let (test_file, input) = match test_file {
         TestFile::KdlProcgen(test_file) => {
             let ty_def = read_file_to_string(&test_file)?;
             let input = procgen::procgen_test_for_ty_string(test, Some(&ty_def));
-            (test_file, input)
+            compiler = with_import_loader(compiler, &test_file);
+            (test_file.as_str().to_owned(), input)
         }
         TestFile::Kdl(test_file) => {
             let input = read_file_to_string(&test_file)?;
-            (test_file, input)
+            compiler = with_import_loader(compiler, &test_file);
+            (test_file.as_str().to_owned(), input)
+        }
+        TestFile::Interleaved {
+            int_ty,
+            float_ty,
+            counts,
+        } => {
+            let input = procgen::procgen_interleaved_test_string(int_ty, float_ty, &counts);
+            (format!("{test}.kdl"), input)
+        }
+        TestFile::MixedRegs => {
+            let input = procgen::procgen_mixed_regs_test_string();
+            (format!("{test}.kdl"), input)
+        }
+        TestFile::StackArgs { counts } => {
+            let input = procgen::procgen_stack_args_test_string(&counts);
+            (format!("{test}.kdl"), input)
+        }
+        TestFile::CHeader { path, clang_args } => {
+            let input = c_header::import_header(&path, &clang_args)?;
+            (format!("{test}.kdl"), input)
         }
     };
-    let mut compiler = kdl_script::Compiler::new();
-    let types = compiler.compile_string(test_file.as_str(), input)?;
+    // Resolve imports up front, so the source we keep around is self-contained
+    let input = compiler.expand_imports(&test_file, input)?;
+    let types = compiler.compile_string(&test_file, input.clone())?;
     Ok(Arc::new(Test {
         name: test.to_owned(),
+        source: input,
         types,
     }))
 }
 
+/// Make a test's `import`s come from the same place the test did
+fn with_import_loader(compiler: kdl_script::Compiler, pathish: &Pathish) -> kdl_script::Compiler {
+    match pathish {
+        Pathish::Runtime(_) => compiler,
+        Pathish::Static(_) => compiler.with_import_loader(|path| {
+            crate::files::try_get_file(path).ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }),
+    }
+}
+
 fn read_file_to_string(pathish: &Pathish) -> std::io::Result<String> {
     match pathish {
         Pathish::Runtime(path) => read_runtime_file_to_string(path),
@@ -172,6 +338,11 @@ fn read_file_to_string(pathish: &Pathish) -> std::io::Result<String> {
     }
 }
 
+/// Whether this is a file of type definitions for tests to `import`, rather than a test
+fn is_shared_types(test_file: &Utf8Path) -> bool {
+    test_file.as_str().ends_with(".types.kdl")
+}
+
 #[allow(clippy::manual_map)]
 fn classify_test(test_file: &Utf8Path, is_runtime: bool) -> Option<(String, TestFile)> {
     let file_name = test_file.file_name().expect("test file had no name!?");
@@ -0,0 +1,428 @@
+//! Generating a test from the function declarations in a C header (`--import-headers`)
+//!
+//! The header is parsed with libclang (loaded at runtime, so abi-cafe still works
+//! without it), and every function declared in it (not the headers it includes) becomes
+//! a function of the test, along with the types it uses. The result is a plain kdl-script
+//! test, so it goes through all the same conventions and pairings as any other test.
+//!
+//! Lots of C can't be expressed in kdl-script (bitfields, variadic functions, long double...),
+//! so anything that uses it is skipped, and listed in comments at the top of the test.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use camino::Utf8Path;
+use clang::{Entity, EntityKind, Type, TypeKind};
+
+use crate::error::GenerateError;
+
+/// Only one instance of libclang can be loaded at a time
+static CLANG: Mutex<()> = Mutex::new(());
+
+/// Identifiers that can't be used as names in the generated Rust
+static RESERVED: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+    "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized",
+    "use", "virtual", "where", "while", "yield", "abstract", "become",
+];
+
+/// Read a C header and produce the source of a kdl-script test for it
+pub fn import_header(path: &Utf8Path, clang_args: &[String]) -> Result<String, GenerateError> {
+    let import_error = |details: String| GenerateError::ImportHeader {
+        path: path.to_string(),
+        details,
+    };
+    let _lock = CLANG.lock().unwrap_or_else(|e| e.into_inner());
+    let clang =
+        clang::Clang::new().map_err(|e| import_error(format!("couldn't load libclang: {e}")))?;
+    let index = clang::Index::new(&clang, false, false);
+    let tu = index
+        .parser(path.as_std_path())
+        .arguments(clang_args)
+        .parse()
+        .map_err(|e| import_error(e.to_string()))?;
+    let errors = tu
+        .get_diagnostics()
+        .into_iter()
+        .filter(|diagnostic| diagnostic.get_severity() >= clang::diagnostic::Severity::Error)
+        .map(|diagnostic| diagnostic.to_string())
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(import_error(errors.join("\n")));
+    }
+
+    let mut importer = Importer::default();
+    let decls = tu.get_entity().get_children();
+    // typedefs are the only name anonymous structs (and enums) have
+    for decl in &decls {
+        if decl.get_kind() != EntityKind::TypedefDecl {
+            continue;
+        }
+        let (Some(name), Some(ty)) = (decl.get_name(), decl.get_typedef_underlying_type()) else {
+            continue;
+        };
+        if let Some(def) = ty.get_canonical_type().get_declaration() {
+            if def.is_anonymous() {
+                importer.typedef_names.entry(def).or_insert(name);
+            }
+        }
+    }
+    let mut seen_funcs = HashSet::new();
+    for decl in decls {
+        if decl.get_kind() != EntityKind::FunctionDecl || !decl.is_in_main_file() {
+            continue;
+        }
+        let Some(name) = decl.get_name() else {
+            continue;
+        };
+        // Functions can be declared over and over
+        if !seen_funcs.insert(name.clone()) {
+            continue;
+        }
+        match importer.func(&decl) {
+            Ok(func) => importer.funcs.push(func),
+            Err(reason) => importer.skipped.push(format!("fn {name}: {reason}")),
+        }
+    }
+    if importer.funcs.is_empty() {
+        let mut details = "it doesn't declare any functions we can test".to_owned();
+        for skipped in &importer.skipped {
+            details.push_str(&format!("\n  skipped {skipped}"));
+        }
+        return Err(import_error(details));
+    }
+
+    let mut output = format!("// Imported from {path}\n");
+    if !importer.skipped.is_empty() {
+        output.push_str("//\n// These couldn't be imported:\n");
+        for skipped in &importer.skipped {
+            tracing::warn!("couldn't import {skipped} (from {path})");
+            output.push_str(&format!("//   {skipped}\n"));
+        }
+    }
+    for def in importer.types.iter().chain(&importer.funcs) {
+        output.push('\n');
+        output.push_str(def);
+    }
+    Ok(output)
+}
+
+#[derive(Default)]
+struct Importer<'tu> {
+    /// The names of anonymous types, taken from the typedefs of them
+    typedef_names: HashMap<Entity<'tu>, String>,
+    /// Types that were defined (or are being defined right now)
+    defined: HashSet<String>,
+    /// Types that are being defined right now (pointers to these become opaque)
+    defining: HashSet<String>,
+    /// Types that couldn't be defined, and why
+    failed: HashMap<String, String>,
+    types: Vec<String>,
+    funcs: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl<'tu> Importer<'tu> {
+    fn func(&mut self, decl: &Entity<'tu>) -> Result<String, String> {
+        let name = ident(&decl.get_name().unwrap_or_default());
+        let ty = decl.get_type().ok_or("it has no type")?;
+        if ty.get_kind() == TypeKind::FunctionNoPrototype {
+            return Err("it has no prototype".to_owned());
+        }
+        if decl.is_variadic() {
+            return Err("it's variadic".to_owned());
+        }
+        let mut inputs = vec![];
+        for (idx, arg) in decl.get_arguments().unwrap_or_default().iter().enumerate() {
+            let arg_name = arg.get_name().map(|name| ident(&name));
+            let arg_ty = arg.get_type().ok_or("an arg has no type")?;
+            let arg_ty = self
+                .ty(arg_ty)
+                .map_err(|reason| format!("arg {idx} is {reason}"))?;
+            inputs.push(format!(
+                "        {} \"{arg_ty}\"\n",
+                arg_name.as_deref().unwrap_or("_")
+            ));
+        }
+        let mut outputs = vec![];
+        let result_ty = decl.get_result_type().ok_or("it has no return type")?;
+        if result_ty.get_canonical_type().get_kind() != TypeKind::Void {
+            let result_ty = self
+                .ty(result_ty)
+                .map_err(|reason| format!("it returns {reason}"))?;
+            outputs.push(format!("        _ \"{result_ty}\"\n"));
+        }
+
+        let mut output = format!("fn \"{name}\" {{\n");
+        for (block, args) in [("inputs", inputs), ("outputs", outputs)] {
+            if args.is_empty() {
+                continue;
+            }
+            output.push_str(&format!("    {block} {{\n"));
+            output.extend(args);
+            output.push_str("    }\n");
+        }
+        output.push_str("}\n");
+        Ok(output)
+    }
+
+    /// The kdl-script name of a type, defining it if needed
+    ///
+    /// On failure this says what the type is and why it can't be imported.
+    fn ty(&mut self, ty: Type<'tu>) -> Result<String, String> {
+        let ty = ty.get_canonical_type();
+        let display = ty.get_display_name();
+        let unsupported = |why: &str| Err(format!("{display}, {why}"));
+        let name = match ty.get_kind() {
+            TypeKind::Bool => "bool".to_owned(),
+            TypeKind::CharS
+            | TypeKind::CharU
+            | TypeKind::SChar
+            | TypeKind::UChar
+            | TypeKind::WChar
+            | TypeKind::Char16
+            | TypeKind::Char32
+            | TypeKind::Short
+            | TypeKind::UShort
+            | TypeKind::Int
+            | TypeKind::UInt
+            | TypeKind::Long
+            | TypeKind::ULong
+            | TypeKind::LongLong
+            | TypeKind::ULongLong
+            | TypeKind::Int128
+            | TypeKind::UInt128 => {
+                // The names of C's integers don't say how big they are, but clang knows
+                let Ok(size) = ty.get_sizeof() else {
+                    return unsupported("which has no size");
+                };
+                let sign = if ty.is_signed_integer() { "i" } else { "u" };
+                format!("{sign}{}", size * 8)
+            }
+            TypeKind::Half | TypeKind::Float16 => "f16".to_owned(),
+            TypeKind::Float => "f32".to_owned(),
+            TypeKind::Double => "f64".to_owned(),
+            TypeKind::Float128 => "f128".to_owned(),
+            TypeKind::Pointer => {
+                let Some(pointee) = ty.get_pointee_type() else {
+                    return unsupported("which points to nothing");
+                };
+                let pointee = pointee.get_canonical_type();
+                // A pointer to the struct we're in the middle of defining (like the `next`
+                // of a linked list) would be infinitely big as a reference, but it can be null
+                if pointee.get_kind() == TypeKind::Record {
+                    if let Some(name) = self
+                        .type_name(&pointee)
+                        .filter(|name| self.defining.contains(name))
+                    {
+                        return Ok(format!("Option<&{name}>"));
+                    }
+                }
+                let is_opaque = match pointee.get_kind() {
+                    TypeKind::Void
+                    | TypeKind::FunctionPrototype
+                    | TypeKind::FunctionNoPrototype => true,
+                    // Pointers to incomplete types
+                    TypeKind::Record | TypeKind::Enum => pointee.get_sizeof().is_err(),
+                    _ => false,
+                };
+                if is_opaque {
+                    "ptr".to_owned()
+                } else {
+                    format!(
+                        "&{}",
+                        self.ty(pointee)
+                            .map_err(|reason| format!("{display}, pointing to {reason}"))?
+                    )
+                }
+            }
+            TypeKind::ConstantArray => {
+                let (Some(elem), Some(len)) = (ty.get_element_type(), ty.get_size()) else {
+                    return unsupported("which has no length");
+                };
+                let elem = self
+                    .ty(elem)
+                    .map_err(|reason| format!("{display}, an array of {reason}"))?;
+                format!("[{elem}; {len}]")
+            }
+            TypeKind::Record | TypeKind::Enum => {
+                let Some(name) = self.type_name(&ty) else {
+                    return unsupported("which is anonymous");
+                };
+                if let Some(reason) = self.failed.get(&name) {
+                    return unsupported(&reason.clone());
+                }
+                if !self.defined.contains(&name) {
+                    let decl = ty.get_declaration().ok_or("it has no declaration")?;
+                    self.defined.insert(name.clone());
+                    self.defining.insert(name.clone());
+                    let def = if ty.get_kind() == TypeKind::Enum {
+                        self.define_enum(&name, &decl)
+                    } else {
+                        self.define_record(&name, &ty, &decl)
+                    };
+                    self.defining.remove(&name);
+                    match def {
+                        Ok(def) => self.types.push(def),
+                        Err(reason) => {
+                            self.failed.insert(name, reason.clone());
+                            return unsupported(&reason);
+                        }
+                    }
+                }
+                name
+            }
+            TypeKind::LongDouble => return unsupported("which kdl-script has no equivalent of"),
+            TypeKind::IncompleteArray => return unsupported("which has no length"),
+            _ => return unsupported("which isn't supported"),
+        };
+        Ok(name)
+    }
+
+    /// The name of a struct/union/enum (anonymous ones are named after their typedef)
+    fn type_name(&self, ty: &Type<'tu>) -> Option<String> {
+        let decl = ty.get_declaration()?;
+        if decl.is_anonymous() {
+            return self.typedef_names.get(&decl).map(|name| ident(name));
+        }
+        decl.get_name().map(|name| ident(&name))
+    }
+
+    fn define_record(
+        &mut self,
+        name: &str,
+        ty: &Type<'tu>,
+        decl: &Entity<'tu>,
+    ) -> Result<String, String> {
+        let decl = decl.get_definition().ok_or("which is opaque")?;
+        let keyword = match decl.get_kind() {
+            EntityKind::StructDecl => "struct",
+            EntityKind::UnionDecl => "union",
+            _ => return Err("which isn't a struct or union".to_owned()),
+        };
+        let mut output = String::new();
+        let mut fields = vec![];
+        for child in decl.get_children() {
+            match child.get_kind() {
+                EntityKind::PackedAttr => output.push_str("@packed\n"),
+                EntityKind::AlignedAttr => {
+                    let align = ty.get_alignof().map_err(|e| e.to_string())?;
+                    output.push_str(&format!("@align {align}\n"));
+                }
+                EntityKind::FieldDecl => {
+                    if child.is_bit_field() {
+                        return Err("which has bitfields".to_owned());
+                    }
+                    if child
+                        .get_children()
+                        .iter()
+                        .any(|attr| attr.get_kind() == EntityKind::AlignedAttr)
+                    {
+                        return Err("which has a field with its own alignment".to_owned());
+                    }
+                    let field_name = child.get_name().map(|name| ident(&name));
+                    let field_ty = child.get_type().ok_or("which has a field with no type")?;
+                    let field_ty = self.ty(field_ty).map_err(|reason| {
+                        format!(
+                            "whose field {} is {reason}",
+                            field_name.as_deref().unwrap_or("(anonymous)")
+                        )
+                    })?;
+                    fields.push(format!(
+                        "    {} \"{field_ty}\"\n",
+                        field_name.as_deref().unwrap_or("_")
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if fields.is_empty() {
+            return Err("which has no fields".to_owned());
+        }
+        output.push_str(&format!("{keyword} \"{name}\" {{\n"));
+        output.extend(fields);
+        output.push_str("}\n");
+        Ok(output)
+    }
+
+    fn define_enum(&mut self, name: &str, decl: &Entity<'tu>) -> Result<String, String> {
+        let decl = decl.get_definition().ok_or("which is opaque")?;
+        let mut variants = vec![];
+        let mut values = HashSet::new();
+        for child in decl.get_children() {
+            if child.get_kind() != EntityKind::EnumConstantDecl {
+                continue;
+            }
+            let (Some(variant), Some((value, _))) =
+                (child.get_name(), child.get_enum_constant_value())
+            else {
+                return Err("which has a variant with no value".to_owned());
+            };
+            if i32::try_from(value).is_err() {
+                return Err(format!("whose variant {variant} doesn't fit in an int"));
+            }
+            // Rust doesn't allow aliases
+            if !values.insert(value) {
+                return Err(format!(
+                    "whose variant {variant} has the same value as another"
+                ));
+            }
+            variants.push(format!("    {} {value}\n", ident(&variant)));
+        }
+        if variants.is_empty() {
+            return Err("which has no variants".to_owned());
+        }
+        let mut output = format!("enum \"{name}\" {{\n");
+        output.extend(variants);
+        output.push_str("}\n");
+        Ok(output)
+    }
+}
+
+/// A C identifier that may need renaming to be usable in every language we generate
+fn ident(name: &str) -> String {
+    let is_primitive = kdl_script::types::PrimitiveTy::from_name(name).is_some();
+    if RESERVED.contains(&name) || is_primitive {
+        format!("{name}_")
+    } else {
+        name.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/harness/read/fixtures");
+
+    fn golden() -> String {
+        std::fs::read_to_string(format!("{FIXTURES}/c_header.kdl")).unwrap()
+    }
+
+    #[test]
+    fn import_fixture() {
+        let path = camino::Utf8PathBuf::from(format!("{FIXTURES}/c_header.h"));
+        let output = match import_header(&path, &[]) {
+            Ok(output) => output,
+            // Nothing to check without libclang
+            Err(GenerateError::ImportHeader { details, .. })
+                if details.starts_with("couldn't load libclang") =>
+            {
+                return;
+            }
+            Err(e) => panic!("{e}"),
+        };
+        let output = output.replacen(path.as_str(), "c_header.h", 1);
+        assert_eq!(output, golden());
+    }
+
+    #[test]
+    fn golden_compiles() {
+        let types = kdl_script::Compiler::new()
+            .compile_string("c_header.kdl", golden())
+            .unwrap();
+        assert_eq!(types.all_funcs().count(), 7);
+    }
+}
@@ -0,0 +1,210 @@
+//! Only running the tests affected by some changed files (`--since`, `--changed-files`)
+//!
+//! A test is affected if its own file changed, or any `.types.kdl` file it (transitively)
+//! imports. Other changed files inside a test directory (a README, say) are ignored.
+//! An imported C header only affects its own test.
+//! Anything else could be a rules file, a toolchain manifest, or the compiler itself,
+//! so we don't try to be clever about those and just run every test.
+
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::info;
+
+use super::*;
+
+/// Only keep the tests affected by the changed files (or all of them, if anything else changed)
+pub fn retain_affected_tests(
+    cfg: &Config,
+    changed: &ChangedFiles,
+    tests: &mut SortedMap<TestId, TestFile>,
+) -> Result<(), GenerateError> {
+    let changed = match changed {
+        ChangedFiles::Since(rev) => git_changed_files(rev)?,
+        ChangedFiles::List(files) => files.iter().map(|file| absolute(file)).collect(),
+    };
+    let mut test_dirs = cfg
+        .paths
+        .runtime_test_input_dirs
+        .iter()
+        .map(|dir| absolute(dir))
+        .collect::<Vec<_>>();
+    if !cfg.disable_builtin_tests {
+        test_dirs.push(absolute(&builtin_include_dir().join("tests")));
+    }
+    // Imported headers only affect their own test
+    test_dirs.extend(cfg.import_headers.iter().map(|path| absolute(path)));
+    if let Some(file) = changed
+        .iter()
+        .find(|file| !test_dirs.iter().any(|dir| file.starts_with(dir)))
+    {
+        info!("{file} changed, which could affect any test, so running all of them");
+        return Ok(());
+    }
+
+    let num_tests = tests.len();
+    tests.retain(|_, test_file| match test_file {
+        TestFile::Kdl(pathish) | TestFile::KdlProcgen(pathish) => test_inputs(pathish)
+            .iter()
+            .any(|input| changed.contains(input)),
+        // These are generated by abi-cafe itself
+        TestFile::Interleaved { .. } | TestFile::MixedRegs | TestFile::StackArgs { .. } => false,
+        TestFile::CHeader { path, .. } => changed.contains(&absolute(path)),
+    });
+    info!(
+        "{} of {num_tests} tests are affected by the {} changed files",
+        tests.len(),
+        changed.len()
+    );
+    Ok(())
+}
+
+/// Ask git which files changed since this revision (including uncommitted and new files)
+fn git_changed_files(rev: &str) -> Result<Vec<Utf8PathBuf>, GenerateError> {
+    let git = |args: &[&str]| -> Result<String, GenerateError> {
+        let output = Command::new("git").args(args).output()?;
+        if !output.status.success() {
+            return Err(GenerateError::GitDiff {
+                rev: rev.to_owned(),
+                details: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    // Both of these list paths relative to the root of the repo, not the working dir
+    let root = Utf8PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let diffed = git(&["diff", "--name-only", rev, "--"])?;
+    let untracked = git(&["ls-files", "--others", "--exclude-standard", "--full-name"])?;
+    Ok(diffed
+        .lines()
+        .chain(untracked.lines())
+        .filter(|file| !file.is_empty())
+        .map(|file| absolute(&root.join(file)))
+        .collect())
+}
+
+/// Every file a test is read from: the test itself, and everything it imports
+fn test_inputs(pathish: &Pathish) -> Vec<Utf8PathBuf> {
+    let imported = Arc::new(Mutex::new(vec![]));
+    let compiler = {
+        let imported = imported.clone();
+        let is_runtime = matches!(pathish, Pathish::Runtime(_));
+        kdl_script::Compiler::new().with_import_loader(move |path| {
+            let path = Utf8PathBuf::from(path);
+            imported.lock().unwrap().push(path.clone());
+            if is_runtime {
+                read_file_to_string(&Pathish::Runtime(path))
+            } else {
+                crate::files::try_get_file(path).ok_or_else(|| std::io::ErrorKind::NotFound.into())
+            }
+        })
+    };
+    // If this fails the test will fail to load for real later, but we still know
+    // every import up to (and including) the one that broke it
+    if let Ok(input) = read_file_to_string(pathish) {
+        let _ = compiler.expand_imports(pathish.as_str(), input);
+    }
+
+    let imported = std::mem::take(&mut *imported.lock().unwrap());
+    std::iter::once(pathish.as_str().into())
+        .chain(imported)
+        .map(|path| match pathish {
+            Pathish::Runtime(_) => absolute(&path),
+            Pathish::Static(_) => absolute(&builtin_include_dir().join(path)),
+        })
+        .collect()
+}
+
+/// Where the builtin tests were embedded from (only meaningful in a checkout of abi-cafe)
+fn builtin_include_dir() -> Utf8PathBuf {
+    Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("include")
+}
+
+/// Make a path absolute (and canonical, if it still exists), so paths can be compared
+fn absolute(path: &Utf8Path) -> Utf8PathBuf {
+    if let Ok(path) = path.canonicalize_utf8() {
+        return path;
+    }
+    // Deleted files can't be canonicalized, but their directory might still be around
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+        let parent = if parent.as_str().is_empty() {
+            Utf8Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(parent) = parent.canonicalize_utf8() {
+            return parent.join(name);
+        }
+    }
+    path.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn affected_tests() {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let dir = dir.join(format!("abi-cafe-{}-changes", std::process::id()));
+        let tests_dir = dir.join("tests");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(tests_dir.join("shared")).unwrap();
+        let files = [
+            (
+                "tests/points.kdl",
+                "import \"shared/points.types.kdl\"\nfn \"f\" {\n    inputs { _ \"Point\"; }\n}\n",
+            ),
+            (
+                "tests/simple.kdl",
+                "fn \"g\" {\n    inputs { _ \"u32\"; }\n}\n",
+            ),
+            (
+                "tests/shared/points.types.kdl",
+                "struct \"Point\" {\n    x \"u32\"\n}\n",
+            ),
+            ("tests/README.md", "the tests\n"),
+            ("abi-cafe-rules.toml", ""),
+        ];
+        for (file, contents) in files {
+            std::fs::write(dir.join(file), contents).unwrap();
+        }
+
+        let mut cfg = crate::cli::config_from_args(["abi-cafe"]).unwrap();
+        cfg.paths.runtime_test_input_dirs = vec![tests_dir.clone()];
+        cfg.disable_builtin_tests = true;
+        let affected = |changed: &[&str]| {
+            let mut tests = SortedMap::new();
+            for test in ["points", "simple"] {
+                let path = Pathish::Runtime(tests_dir.join(format!("{test}.kdl")));
+                tests.insert(test.to_owned(), TestFile::Kdl(path));
+            }
+            tests.insert("mixed_regs".to_owned(), TestFile::MixedRegs);
+            let changed = ChangedFiles::List(changed.iter().map(|file| dir.join(file)).collect());
+            retain_affected_tests(&cfg, &changed, &mut tests).unwrap();
+            tests.into_keys().collect::<Vec<_>>()
+        };
+
+        // A changed test only runs itself
+        assert_eq!(affected(&["tests/simple.kdl"]), ["simple"]);
+        // A changed import runs everything that imports it
+        assert_eq!(affected(&["tests/shared/points.types.kdl"]), ["points"]);
+        assert_eq!(
+            affected(&["tests/simple.kdl", "tests/shared/points.types.kdl"]),
+            ["points", "simple"]
+        );
+        // Anything outside the test dirs could affect every test (generated ones too)
+        assert_eq!(
+            affected(&["tests/simple.kdl", "abi-cafe-rules.toml"]),
+            ["mixed_regs", "points", "simple"]
+        );
+        // But nothing a test reads doesn't affect any of them
+        assert!(affected(&["tests/README.md"]).is_empty());
+        assert!(affected(&[]).is_empty());
+        // Even if it's been deleted
+        assert!(affected(&["tests/deleted.kdl"]).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
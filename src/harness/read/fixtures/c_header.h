@@ -0,0 +1,53 @@
+// The input of the c_header tests, with golden output in c_header.kdl
+//
+// This only uses types whose size is the same on every platform we test (no `long`),
+// and doesn't include anything, so it doesn't depend on which headers libclang finds.
+
+typedef struct Point {
+    int x;
+    int y;
+} Point;
+
+// Named after its typedef
+typedef struct {
+    double re;
+    double im;
+} Complex;
+
+// Pointers to the struct being defined become nullable
+struct Node {
+    long long value;
+    struct Node *next;
+};
+
+enum Color { RED, GREEN = 5, BLUE };
+
+union Bits {
+    unsigned int u;
+    float f;
+};
+
+// Pointers to opaque types become `ptr`
+typedef struct Handle Handle;
+
+struct Flags {
+    unsigned int a : 3;
+    unsigned int b : 5;
+};
+
+Point point_add(Point a, Point b);
+Complex complex_scale(Complex c, double k);
+long long list_sum(struct Node *head);
+enum Color next_color(enum Color c);
+void handle_poke(Handle *h, void *data, const char *name);
+float bits_float(union Bits bits);
+// Declaring it again doesn't make another function
+float bits_float(union Bits bits);
+// Names that are keywords somewhere get renamed
+int type(int match);
+
+// None of these can be imported
+int set_flags(struct Flags flags);
+long double long_double(long double x);
+int printf_like(const char *fmt, ...);
+int no_prototype();
@@ -4,6 +4,224 @@ pub fn procgen_test_for_ty_string(ty_name: &str, ty_def: Option<&str>) -> String
     test_body
 }
 
+/// Generate a test that interleaves ints and floats in the argument list
+///
+/// For each count we generate three functions:
+///
+/// * `mixed_N`: N ints and N floats, alternating
+/// * `ints_N`: N ints with 2 floats spread between them
+/// * `floats_N`: N floats with 2 ints spread between them
+///
+/// The lopsided ones are the interesting ones: they exhaust one class of register
+/// while the other still has room, which is where ABIs have to decide whether the
+/// remaining values of the exhausted class go on the stack "out of order". Sweeping
+/// the counts takes you from "fits in registers" to "heavily spilled".
+pub fn procgen_interleaved_test_string(int_ty: &str, float_ty: &str, counts: &[usize]) -> String {
+    let mut test_body = String::new();
+    procgen_interleaved_test_impl(&mut test_body, int_ty, float_ty, counts)
+        .expect("failed to format procgen!?");
+    test_body
+}
+
+fn procgen_interleaved_test_impl(
+    out: &mut dyn std::fmt::Write,
+    int_ty: &str,
+    float_ty: &str,
+    counts: &[usize],
+) -> std::fmt::Result {
+    let minor_count = 2;
+    for &count in counts {
+        add_func(
+            out,
+            &format!("mixed_{count}"),
+            &interleave_list(int_ty, count, float_ty, count),
+            &[],
+        )?;
+        add_func(
+            out,
+            &format!("ints_{count}"),
+            &interleave_list(int_ty, count, float_ty, minor_count),
+            &[],
+        )?;
+        add_func(
+            out,
+            &format!("floats_{count}"),
+            &interleave_list(float_ty, count, int_ty, minor_count),
+            &[],
+        )?;
+    }
+    Ok(())
+}
+
+/// Make a list of `major_count` majors with `minor_count` minors spread evenly between them
+fn interleave_list<'a>(
+    major: &'a str,
+    major_count: usize,
+    minor: &'a str,
+    minor_count: usize,
+) -> Vec<&'a str> {
+    let mut inputs = Vec::with_capacity(major_count + minor_count);
+    let mut minors_placed = 0;
+    for idx in 0..major_count {
+        inputs.push(major);
+        let minors_wanted = (idx + 1) * minor_count / major_count;
+        while minors_placed < minors_wanted {
+            inputs.push(minor);
+            minors_placed += 1;
+        }
+    }
+    inputs
+}
+
+/// Generate a test that passes lots of trailing arguments on the stack
+///
+/// Every function starts with 8 ints and 8 floats, which is enough to use up the
+/// argument registers of every ABI we know of, so each one of the `count` values after
+/// them has to go on the stack. For each count we generate:
+///
+/// * `ints_N`: N `i64`s
+/// * `bytes_N`: N `u8`s, which each still get a whole stack slot (and the rest of it is garbage)
+/// * `mixed_N`: N values cycling through every size (which is where slot alignment goes wrong)
+/// * `structs_N`: N small structs, which are copied onto the stack whole
+///
+/// Big counts push the callee deep into its caller's stack, well past the 128 byte red
+/// zone below the stack pointer on x64, so getting the stack pointer, its alignment, or
+/// the size of the argument area wrong tends to corrupt something or crash.
+pub fn procgen_stack_args_test_string(counts: &[usize]) -> String {
+    let mut test_body = String::new();
+    procgen_stack_args_test_impl(&mut test_body, counts).expect("failed to format procgen!?");
+    test_body
+}
+
+fn procgen_stack_args_test_impl(
+    out: &mut dyn std::fmt::Write,
+    counts: &[usize],
+) -> std::fmt::Result {
+    const REG_FILLERS: &[&str] = &["i64", "f64"];
+    const REG_FILLER_COUNT: usize = 8;
+    const MIXED: &[&str] = &["u8", "f64", "u16", "f32", "u32", "i64"];
+    let struct_ty = "StackPair";
+
+    writeln!(out, r#"struct "{struct_ty}" {{"#)?;
+    writeln!(out, r#"    a "u32""#)?;
+    writeln!(out, r#"    b "f32""#)?;
+    writeln!(out, r#"}}"#)?;
+
+    let reg_fillers = interleave_list(
+        REG_FILLERS[0],
+        REG_FILLER_COUNT,
+        REG_FILLERS[1],
+        REG_FILLER_COUNT,
+    );
+    let with_fillers = |stack_args: Vec<&'static str>| {
+        let mut inputs = reg_fillers.clone();
+        inputs.extend(stack_args);
+        inputs
+    };
+    for &count in counts {
+        add_func(
+            out,
+            &format!("ints_{count}"),
+            &with_fillers(vec!["i64"; count]),
+            &[],
+        )?;
+        add_func(
+            out,
+            &format!("bytes_{count}"),
+            &with_fillers(vec!["u8"; count]),
+            &[],
+        )?;
+        add_func(
+            out,
+            &format!("mixed_{count}"),
+            &with_fillers(MIXED.iter().copied().cycle().take(count).collect()),
+            &[],
+        )?;
+        add_func(
+            out,
+            &format!("structs_{count}"),
+            &with_fillers(vec![struct_ty; count]),
+            &[],
+        )?;
+    }
+    Ok(())
+}
+
+/// Generate a test of small structs that mix integer and float fields
+///
+/// Every struct is at most 16 bytes and has both kinds of field, so on x64 SysV
+/// it's split across a general purpose register and an SSE register (or two of
+/// each, or shares one register if the fields share an eightbyte). Getting which
+/// half goes where backwards is an easy mistake, especially for returns, where
+/// `{ int; float }` comes back in RAX and XMM0. For each struct we generate:
+///
+/// * `val_in_NAME`: the struct by value
+/// * `val_out_NAME`: the struct returned
+/// * `val_in_out_NAME`: the struct by value and returned
+pub fn procgen_mixed_regs_test_string() -> String {
+    let mut test_body = String::new();
+    procgen_mixed_regs_test_impl(&mut test_body).expect("failed to format procgen!?");
+    test_body
+}
+
+fn procgen_mixed_regs_test_impl(out: &mut dyn std::fmt::Write) -> std::fmt::Result {
+    for fields in mixed_regs_structs() {
+        let name = fields
+            .iter()
+            .map(|field| field.to_uppercase())
+            .collect::<String>();
+        let struct_ty = format!("Mixed{name}");
+        writeln!(out, r#"struct "{struct_ty}" {{"#)?;
+        for (field_idx, field) in fields.iter().enumerate() {
+            writeln!(out, r#"    f{field_idx} "{field}""#)?;
+        }
+        writeln!(out, r#"}}"#)?;
+
+        let name = name.to_lowercase();
+        add_func(out, &format!("val_in_{name}"), &[&struct_ty], &[])?;
+        add_func(out, &format!("val_out_{name}"), &[], &[&struct_ty])?;
+        add_func(
+            out,
+            &format!("val_in_out_{name}"),
+            &[&struct_ty],
+            &[&struct_ty],
+        )?;
+    }
+    Ok(())
+}
+
+/// Every list of fields that has both ints and floats and fits in 16 bytes
+fn mixed_regs_structs() -> Vec<Vec<&'static str>> {
+    const FIELDS: &[(&str, u64)] = &[("i32", 4), ("f32", 4), ("i64", 8), ("f64", 8)];
+    let mut structs = vec![];
+    let mut todo = vec![vec![]];
+    while let Some(fields) = todo.pop() {
+        for &(field, _) in FIELDS {
+            let mut fields: Vec<&str> = fields.clone();
+            fields.push(field);
+            // repr(C) layout: every field here is aligned to its own size
+            let mut size = 0u64;
+            let mut align = 1u64;
+            for field in &fields {
+                let (_, field_size) = FIELDS.iter().find(|(name, _)| name == field).unwrap();
+                size = size.next_multiple_of(*field_size) + field_size;
+                align = align.max(*field_size);
+            }
+            if size.next_multiple_of(align) > 16 {
+                continue;
+            }
+            let has_int = fields.iter().any(|field| field.starts_with('i'));
+            let has_float = fields.iter().any(|field| field.starts_with('f'));
+            if has_int && has_float {
+                structs.push(fields.clone());
+            }
+            todo.push(fields);
+        }
+    }
+    structs.sort();
+    structs
+}
+
 fn procgen_test_for_ty_impl(
     out: &mut dyn std::fmt::Write,
     ty_name: &str,
@@ -26,6 +244,9 @@ fn procgen_test_for_ty_impl(
     // Start gentle with basic one value in/out tests
     add_func(out, "val_in", &[ty], &[])?;
     add_func(out, "ref_in", &[&ty_ref], &[])?;
+    // Both at once, so a toolchain that mixes up (or aliases) the two gets caught
+    add_func(out, "val_and_ref_in", &[ty, &ty_ref], &[])?;
+    add_func(out, "ref_and_val_in", &[&ty_ref, ty], &[])?;
     if !has_refs {
         add_func(out, "val_out", &[], &[ty])?;
         add_func(out, "val_in_out", &[ty], &[ty])?;
@@ -78,6 +299,14 @@ fn add_structs(out: &mut dyn std::fmt::Write, ty: &str) -> std::fmt::Result {
         add_func(out, &format!("struct_in_{len}"), &[&struct_ty], &[])?;
         // Check that by-ref works, for good measure
         add_func(out, &format!("ref_struct_in_{len}"), &[&struct_ty_ref], &[])?;
+        // And both at once (big ones are often passed by a pointer to a copy, which
+        // looks a lot like the reference next to it)
+        add_func(
+            out,
+            &format!("val_and_ref_struct_in_{len}"),
+            &[&struct_ty, &struct_ty_ref],
+            &[],
+        )?;
     }
     Ok(())
 }
@@ -6,11 +6,28 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::error::*;
+use crate::fivemat::Fivemat;
+use crate::harness::hex_dump;
 use crate::harness::test::*;
 use crate::*;
 
 /// These are the builtin test-expectations, edit these if there are new rules!
 impl TestHarness {
+    /// Whether any function in this test passes (or returns) a 256-bit vector by value
+    fn passes_avx_vectors(&self, test: &str) -> bool {
+        let Some(test) = self.tests.get(test) else {
+            return false;
+        };
+        let types = &test.types;
+        types.all_funcs().any(|func| {
+            let func = types.realize_func(func);
+            func.inputs
+                .iter()
+                .chain(&func.outputs)
+                .any(|arg| types.ty_max_vector_size(arg.ty) >= Some(32))
+        })
+    }
+
     #[allow(unused_variables)]
     pub fn get_test_rules(&self, key: &TestKey) -> TestRules {
         use TestCheckMode::*;
@@ -20,8 +37,46 @@ impl TestHarness {
         let mut result = TestRules {
             run: Check,
             check: Pass(Check),
+            crash: None,
+            float_ulps: None,
         };
 
+        // Calling a function with the wrong convention should be noticed
+        // (unless the two are the same convention on this target)
+        if self.resolved_convention(key.options.convention)
+            != self.resolved_convention(key.options.callee_convention)
+        {
+            result.check = Fail(Check);
+        }
+
+        // A caller with AVX passes 256-bit vectors in ymm registers, and a callee without
+        // it expects them on the stack (--mixed-target-features), which should be noticed
+        let options = &key.options;
+        if options.target_features.enables_avx() != options.callee_target_features.enables_avx()
+            && self.passes_avx_vectors(&key.test)
+        {
+            result.check = Fail(Check);
+        }
+
+        // Unwinding out of a callee (--unwind) is only allowed by some conventions,
+        // otherwise the process must be aborted before the unwind escapes
+        if key.options.unwind {
+            let abort = if self.cfg_enabled("cfg(unix)") {
+                ExpectedCrash::Signal("SIGABRT".to_owned())
+            } else {
+                ExpectedCrash::Any
+            };
+            // (Only Rust callees unwind, see CcToolchain::generate_callee_impl)
+            let can_unwind = matches!(
+                key.options.callee_convention,
+                CallingConvention::CUnwind | CallingConvention::Rust
+            );
+            // Panicking out of `extern "C"` aborts, otherwise the caller catches it
+            if !can_unwind {
+                result.crash = Some(abort);
+            }
+        }
+
         for expect_file in &self.test_rules {
             for (target_cfg, rules) in &expect_file.target {
                 if !self.cfg_enabled(target_cfg) {
@@ -35,11 +90,22 @@ impl TestHarness {
                         if let Some(check) = rules.check {
                             result.check = check;
                         }
+                        if let Some(crash) = &rules.crash {
+                            result.crash = Some(crash.clone());
+                        }
+                        if let Some(float_ulps) = rules.float_ulps {
+                            result.float_ulps = Some(float_ulps);
+                        }
                     }
                 }
             }
         }
 
+        // Injected faults are a self-test, abi-cafe must catch them
+        if key.options.fault.is_some() {
+            result.check = Fail(Check);
+        }
+
         //
         //
         // THIS AREA RESERVED FOR VENDORS TO APPLY PATCHES
@@ -51,7 +117,7 @@ impl TestHarness {
         result
     }
 
-    fn cfg_enabled(&self, target_cfg: &str) -> bool {
+    pub(crate) fn cfg_enabled(&self, target_cfg: &str) -> bool {
         if target_cfg == "*" {
             true
         } else {
@@ -71,6 +137,88 @@ pub struct ExpectFile {
     pub target: IndexMap<String, IndexMap<TestKeyPattern, TestRulesPattern>>,
 }
 
+/// Which toolchain pairs may be run (see `--pair-rules`)
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PairRulesFile {
+    /// If not empty, only pairs matching one of these are run
+    #[serde(default)]
+    pub allow: Vec<PairRule>,
+    /// Pairs matching any of these are never run
+    #[serde(default)]
+    pub deny: Vec<PairRule>,
+}
+
+/// A pattern for toolchain pairs, where either side can be "*" to match any toolchain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PairRule {
+    pub caller: String,
+    pub callee: String,
+    /// Why this rule exists (recorded in the report for every pair it excludes)
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl PairRule {
+    pub fn matches(&self, caller: &str, callee: &str) -> bool {
+        (self.caller == "*" || self.caller == caller)
+            && (self.callee == "*" || self.callee == callee)
+    }
+}
+
+impl PairRulesFile {
+    /// Split these pairs into the ones we may run, and the ones we mustn't
+    pub fn apply(
+        &self,
+        pairs: Vec<(String, String)>,
+    ) -> (Vec<(String, String)>, Vec<ExcludedPair>) {
+        let mut allowed = vec![];
+        let mut excluded = vec![];
+        for (caller, callee) in pairs {
+            let reason = if !self.allow.is_empty()
+                && !self.allow.iter().any(|rule| rule.matches(&caller, &callee))
+            {
+                Some("not allowed by any pair rule".to_owned())
+            } else {
+                self.deny
+                    .iter()
+                    .find(|rule| rule.matches(&caller, &callee))
+                    .map(|rule| {
+                        rule.reason
+                            .clone()
+                            .unwrap_or_else(|| "denied by a pair rule".to_owned())
+                    })
+            };
+            match reason {
+                Some(reason) => excluded.push(ExcludedPair {
+                    caller,
+                    callee,
+                    reason,
+                }),
+                None => allowed.push((caller, callee)),
+            }
+        }
+        (allowed, excluded)
+    }
+}
+
+/// A pair we were asked to run, but the pair rules excluded
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludedPair {
+    pub caller: String,
+    pub callee: String,
+    pub reason: String,
+}
+
+/// A toolchain we were asked to test, that didn't end up running any tests
+#[derive(Debug, Clone, Serialize)]
+pub struct IdleToolchain {
+    pub toolchain: String,
+    /// Why it probably didn't (filtered out, unavailable, everything skipped...)
+    pub reason: String,
+}
+
 impl Serialize for BuildError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -123,9 +271,12 @@ pub struct RunOutput {
     pub caller_funcs: TestBuffer,
     #[serde(skip)]
     pub callee_funcs: TestBuffer,
+    /// The raw bytes of the inputs with padding, as the callee saw them (with `--pad-byte`)
+    #[serde(skip)]
+    pub callee_padding: TestBuffer,
 }
 
-pub fn report_test(results: TestRunResults) -> TestReport {
+pub fn report_test(id: String, results: TestRunResults) -> TestReport {
     use TestConclusion::*;
     use TestRunMode::*;
     // Ok now check if it matched our expectation
@@ -137,6 +288,12 @@ pub fn report_test(results: TestRunResults) -> TestReport {
         // to avoid different configs having to explicitly disable
         // a million unsupported combinations
         Skipped
+    } else if let Some(expected) = &results.rules.crash {
+        // The test must have died, and died in exactly the way we said it would
+        match &results.run {
+            Some(Err(RunError::BadExit { exit, .. })) if expected.matches(exit) => Passed,
+            _ => Failed,
+        }
     } else {
         let passed = match &results.rules.check {
             TestCheckMode::Pass(must_pass) => success_at_step(&results, must_pass, true),
@@ -168,12 +325,38 @@ pub fn report_test(results: TestRunResults) -> TestReport {
         } else {
             Some(TestCheckMode::Busted(results.rules.run))
         },
+        // If we were expecting a crash, suggest the crash we actually got
+        crash: match (&results.rules.crash, &results.run) {
+            (Some(_), Some(Err(RunError::BadExit { exit, .. }))) => {
+                Some(ExpectedCrash::from_exit(exit))
+            }
+            _ => None,
+        },
+        float_ulps: results.rules.float_ulps,
+    };
+    let skip_reason = if conclusion == Skipped {
+        Some(match &results.source {
+            Some(Err(GenerateError::Unsupported(e))) => match e {
+                UnsupportedError::Convention(_) => SkipReason::Convention,
+                UnsupportedError::Repr(_) => SkipReason::Repr,
+                UnsupportedError::Other(_) => SkipReason::Unsupported,
+            },
+            _ => SkipReason::Rules,
+        })
+    } else {
+        None
     };
     TestReport {
+        id,
         key: results.key.clone(),
-        rules: results.rules,
+        rules: results.rules.clone(),
         conclusion,
+        skip_reason,
         could_be,
+        interop_only: false,
+        lto_only: false,
+        source: None,
+        resumed: false,
         results,
     }
 }
@@ -191,29 +374,130 @@ fn success_at_step(results: &TestRunResults, step: &TestRunMode, wants_pass: boo
     res.map(|res| res == wants_pass)
 }
 
+/// The version of the json report's format (see docs/src/harness/report.schema.json)
+///
+/// This must be bumped whenever the shape of [`FullReport`][] changes
+/// in a way that could break consumers (removing/renaming/retyping fields).
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
 #[derive(Debug, Serialize)]
 pub struct FullReport {
+    /// See [`REPORT_SCHEMA_VERSION`][]
+    pub schema_version: u32,
+    /// The target the tests were run on
+    pub target: String,
+    /// The float ABI of the target, if it's RISC-V (see [`RiscvFloatAbi`][])
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub riscv_float_abi: Option<RiscvFloatAbi>,
+    /// The float ABI of the target, if it's 32-bit ARM (see [`ArmFloatAbi`][])
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arm_float_abi: Option<ArmFloatAbi>,
+    /// The slice of the tests this run was limited to (see `--shard`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard: Option<Shard>,
     pub summary: TestSummary,
+    /// The version of each toolchain that was paired up in this run
+    pub toolchain_versions: SortedMap<String, Option<String>>,
+    /// Extra env vars that were set when running the test binaries
+    pub run_env: SortedMap<String, String>,
+    /// The command test binaries were run with (if they weren't run directly)
+    pub runner: Option<String>,
+    /// The [`Config`][] that produced this report (with `--embed-config`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<serde_json::Value>,
     pub possible_rules: Option<ExpectFile>,
+    /// The pairs that `--pair-rules` stopped us from running
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excluded_pairs: Vec<ExcludedPair>,
+    /// The toolchains we were asked to test that didn't run a single test
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub idle_toolchains: Vec<IdleToolchain>,
+    /// Sorted by [`TestReport::id`][], so the same run always produces the same report
     pub tests: Vec<TestReport>,
+    /// How long each test took, by [`TestReport::id`][]
+    ///
+    /// This is the only part of the report that changes between identical runs,
+    /// so it's kept apart from everything else.
+    pub timings: SortedMap<String, TestTimings>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct TestReport {
+    /// A stable unique name for this test (the same as its name in test rules)
+    pub id: String,
     pub key: TestKey,
     pub rules: TestRules,
+    #[serde(serialize_with = "TestRunResults::serialize_or_resumed")]
     pub results: TestRunResults,
     pub conclusion: TestConclusion,
+    pub skip_reason: Option<SkipReason>,
     pub could_be: TestRulesPattern,
+    /// Whether this failed, but both toolchains pass when paired with themselves
+    pub interop_only: bool,
+    /// Whether this passed with `--lto`, but the same test failed without it
+    pub lto_only: bool,
+    /// The kdl-script source of the test, if it failed and `--embed-source` was passed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Whether this test wasn't run, because an earlier run already finished it (`--resume`)
+    ///
+    /// Its `results` are the ones that run recorded, which are only kept as json.
+    pub resumed: bool,
 }
 
-#[derive(Debug, Serialize)]
+impl TestReport {
+    /// Whether the test actually worked (regardless of whether that was expected)
+    pub fn works(&self) -> bool {
+        matches!(self.could_be.check, Some(TestCheckMode::Pass(_)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TestSummary {
     pub num_tests: u64,
     pub num_passed: u64,
     pub num_busted: u64,
     pub num_failed: u64,
     pub num_skipped: u64,
+    /// How many tests were skipped for each reason
+    #[serde(default)]
+    pub num_skipped_by_reason: SortedMap<SkipReason, u64>,
+    /// How many skipped tests should be treated as failures (see --error-on-skip)
+    #[serde(default)]
+    pub num_skip_errors: u64,
+    /// How many tests (failed or busted) only fail between two different toolchains
+    #[serde(default)]
+    pub num_interop_only: u64,
+    /// How many busted interop-only tests should be treated as failures
+    /// (see --error-on-interop-only)
+    #[serde(default)]
+    pub num_interop_errors: u64,
+    /// How many tests pass with `--lto`, but fail without it
+    #[serde(default)]
+    pub num_lto_only: u64,
+    /// How many idle toolchains should be treated as failures
+    /// (see --error-on-idle-toolchains)
+    #[serde(default)]
+    pub num_idle_toolchain_errors: u64,
+}
+
+impl TestSummary {
+    /// Add up the results of two runs (of different tests)
+    pub fn add(&mut self, other: &TestSummary) {
+        self.num_tests += other.num_tests;
+        self.num_passed += other.num_passed;
+        self.num_busted += other.num_busted;
+        self.num_failed += other.num_failed;
+        self.num_skipped += other.num_skipped;
+        for (&reason, &count) in &other.num_skipped_by_reason {
+            *self.num_skipped_by_reason.entry(reason).or_default() += count;
+        }
+        self.num_skip_errors += other.num_skip_errors;
+        self.num_interop_only += other.num_interop_only;
+        self.num_interop_errors += other.num_interop_errors;
+        self.num_lto_only += other.num_lto_only;
+        self.num_idle_toolchain_errors += other.num_idle_toolchain_errors;
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -235,8 +519,10 @@ pub struct TestKeyPattern {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TestOptionsPattern {
     pub convention: Option<CallingConvention>,
+    pub callee_convention: Option<CallingConvention>,
     pub val_generator: Option<ValueGeneratorKind>,
     pub repr: Option<LangRepr>,
+    pub callee_repr: Option<LangRepr>,
 }
 impl TestKey {
     pub(crate) fn toolchain_id(&self, call_side: CallSide) -> &str {
@@ -257,8 +543,10 @@ impl TestKeyPattern {
             options:
                 TestOptionsPattern {
                     convention,
+                    callee_convention,
                     val_generator,
                     repr,
+                    callee_repr,
                 },
         } = self;
 
@@ -289,6 +577,11 @@ impl TestKeyPattern {
                 return false;
             }
         }
+        if let Some(callee_convention) = callee_convention {
+            if callee_convention != &key.options.callee_convention {
+                return false;
+            }
+        }
         if let Some(val_generator) = val_generator {
             if val_generator != &key.options.val_generator {
                 return false;
@@ -299,6 +592,11 @@ impl TestKeyPattern {
                 return false;
             }
         }
+        if let Some(callee_repr) = callee_repr {
+            if callee_repr != &key.options.callee_repr {
+                return false;
+            }
+        }
 
         true
     }
@@ -318,7 +616,9 @@ impl std::str::FromStr for TestKeyPattern {
             toolchain: None,
             options: TestOptionsPattern {
                 convention: None,
+                callee_convention: None,
                 repr: None,
+                callee_repr: None,
                 val_generator: None,
             },
         };
@@ -349,12 +649,20 @@ impl std::str::FromStr for TestKeyPattern {
             }
 
             // repr
+            if let Some(callee_repr) = part.strip_prefix("callee_repr_") {
+                key.options.callee_repr = Some(callee_repr.parse()?);
+                continue;
+            }
             if let Some(repr) = part.strip_prefix("repr_") {
                 key.options.repr = Some(repr.parse()?);
                 continue;
             }
 
             // conv
+            if let Some(callee_conv) = part.strip_prefix("callee_conv_") {
+                key.options.callee_convention = Some(callee_conv.parse()?);
+                continue;
+            }
             if let Some(conv) = part.strip_prefix("conv_") {
                 key.options.convention = Some(conv.parse()?);
                 continue;
@@ -380,8 +688,10 @@ impl std::fmt::Display for TestKeyPattern {
             options:
                 TestOptionsPattern {
                     convention,
+                    callee_convention,
                     val_generator,
                     repr,
+                    callee_repr,
                 },
         } = self;
         let separator = "::";
@@ -393,10 +703,18 @@ impl std::fmt::Display for TestKeyPattern {
             output.push_str(separator);
             output.push_str(&format!("conv_{convention}"));
         }
+        if let Some(callee_convention) = callee_convention {
+            output.push_str(separator);
+            output.push_str(&format!("callee_conv_{callee_convention}"));
+        }
         if let Some(repr) = repr {
             output.push_str(separator);
             output.push_str(&format!("repr_{repr}"));
         }
+        if let Some(callee_repr) = callee_repr {
+            output.push_str(separator);
+            output.push_str(&format!("callee_repr_{callee_repr}"));
+        }
         if let Some(toolchain) = toolchain {
             output.push_str(separator);
             output.push_str(&format!("{toolchain}_toolchain"));
@@ -448,11 +766,17 @@ impl Serialize for TestKeyPattern {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TestRules {
     pub run: TestRunMode,
     #[serde(flatten)]
     pub check: TestCheckMode,
+    /// The test must crash in this way when run (overrides `check`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crash: Option<ExpectedCrash>,
+    /// Floats may differ by this many ULPs between caller and callee (exact if None)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub float_ulps: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -460,6 +784,10 @@ pub struct TestRulesPattern {
     pub run: Option<TestRunMode>,
     #[serde(flatten)]
     pub check: Option<TestCheckMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash: Option<ExpectedCrash>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub float_ulps: Option<u32>,
 }
 /// How far the test should be executed
 ///
@@ -500,6 +828,144 @@ pub enum TestCheckMode {
     Random(bool),
 }
 
+/// How a test binary that didn't exit successfully terminated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestExit {
+    /// It exited with a non-zero exit code
+    Code(i32),
+    /// It was killed by a signal (unix only)
+    Signal(i32),
+    /// The platform didn't tell us
+    Unknown,
+}
+
+impl TestExit {
+    /// The conventional name of the signal this died to, if we know it
+    pub fn signal_name(&self) -> Option<&'static str> {
+        let TestExit::Signal(signal) = self else {
+            return None;
+        };
+        let name = match signal {
+            1 => "SIGHUP",
+            2 => "SIGINT",
+            3 => "SIGQUIT",
+            4 => "SIGILL",
+            5 => "SIGTRAP",
+            6 => "SIGABRT",
+            #[cfg(target_os = "linux")]
+            7 => "SIGBUS",
+            #[cfg(not(target_os = "linux"))]
+            10 => "SIGBUS",
+            8 => "SIGFPE",
+            9 => "SIGKILL",
+            11 => "SIGSEGV",
+            13 => "SIGPIPE",
+            14 => "SIGALRM",
+            15 => "SIGTERM",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl std::fmt::Display for TestExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestExit::Code(code) => write!(f, "exit code {code}"),
+            TestExit::Signal(signal) => {
+                if let Some(name) = self.signal_name() {
+                    write!(f, "signal {signal} ({name})")
+                } else {
+                    write!(f, "signal {signal}")
+                }
+            }
+            TestExit::Unknown => write!(f, "unknown exit status"),
+        }
+    }
+}
+
+/// A crash that a test rule says must happen when the test is run
+///
+/// In a rules file this is written as `crash = "SIGSEGV"` (a signal name),
+/// `crash = "exit:3"` (an exit code), or `crash = "any"` (any bad exit).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectedCrash {
+    Any,
+    Signal(String),
+    Code(i32),
+}
+
+impl ExpectedCrash {
+    pub fn matches(&self, exit: &TestExit) -> bool {
+        match self {
+            ExpectedCrash::Any => true,
+            ExpectedCrash::Signal(name) => exit.signal_name() == Some(name.as_str()),
+            ExpectedCrash::Code(code) => *exit == TestExit::Code(*code),
+        }
+    }
+
+    /// The expectation that exactly matches this exit
+    pub fn from_exit(exit: &TestExit) -> Self {
+        match exit {
+            TestExit::Code(code) => ExpectedCrash::Code(*code),
+            TestExit::Signal(_) => exit
+                .signal_name()
+                .map(|name| ExpectedCrash::Signal(name.to_owned()))
+                .unwrap_or(ExpectedCrash::Any),
+            TestExit::Unknown => ExpectedCrash::Any,
+        }
+    }
+}
+
+impl std::str::FromStr for ExpectedCrash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "any" {
+            Ok(ExpectedCrash::Any)
+        } else if let Some(code) = s.strip_prefix("exit:") {
+            let code = code
+                .parse()
+                .map_err(|_| format!("invalid exit code in crash expectation: {s}"))?;
+            Ok(ExpectedCrash::Code(code))
+        } else if s.starts_with("SIG") {
+            Ok(ExpectedCrash::Signal(s.to_owned()))
+        } else {
+            Err(format!(
+                "unknown crash expectation: {s} (expected \"any\", \"exit:<code>\", or a signal like \"SIGSEGV\")"
+            ))
+        }
+    }
+}
+impl std::fmt::Display for ExpectedCrash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpectedCrash::Any => write!(f, "any"),
+            ExpectedCrash::Signal(name) => write!(f, "{name}"),
+            ExpectedCrash::Code(code) => write!(f, "exit:{code}"),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for ExpectedCrash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        let input = String::deserialize(deserializer)?;
+        input.parse().map_err(D::Error::custom)
+    }
+}
+impl Serialize for ExpectedCrash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct TestRunResults {
     pub key: TestKey,
@@ -510,6 +976,19 @@ pub struct TestRunResults {
     pub link: Option<Result<LinkOutput, LinkError>>,
     pub run: Option<Result<RunOutput, RunError>>,
     pub check: Option<CheckOutput>,
+    /// The size of the compiled callee (with `--codegen-size`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codegen_size: Option<CodegenSize>,
+    /// Where the LLVM IR of each side was written (with `--emit-llvm-ir`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub llvm_ir: Option<LlvmIr>,
+    /// Reported separately in [`FullReport::timings`][]
+    #[serde(skip)]
+    pub timings: TestTimings,
+    /// The results an earlier run recorded, if this test was resumed instead of run
+    /// (see [`TestReport::resumed`][]), which get reported instead of these
+    #[serde(skip)]
+    pub resumed: Option<serde_json::Value>,
 }
 
 impl TestRunResults {
@@ -523,6 +1002,20 @@ impl TestRunResults {
             link: None,
             run: None,
             check: None,
+            codegen_size: None,
+            llvm_ir: None,
+            timings: TestTimings::default(),
+            resumed: None,
+        }
+    }
+
+    fn serialize_or_resumed<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match &self.resumed {
+            Some(resumed) => resumed.serialize(serializer),
+            None => self.serialize(serializer),
         }
     }
 }
@@ -537,6 +1030,41 @@ pub struct GenerateOutput {
 pub struct BuildOutput {
     pub caller_lib: String,
     pub callee_lib: String,
+    /// The lib of C helpers that Rust sides round with, for `--rounding-mode`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounding_lib: Option<String>,
+}
+
+/// How long a test took to get through each step
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TestTimings {
+    pub total_ms: u64,
+    /// The milliseconds spent in each step that was run
+    pub steps_ms: SortedMap<TestRunMode, u64>,
+}
+
+impl TestTimings {
+    /// Record that a step that started at `start` just finished
+    pub fn record(&mut self, step: TestRunMode, start: std::time::Instant) {
+        let ms = start.elapsed().as_millis() as u64;
+        self.steps_ms.insert(step, ms);
+        self.total_ms += ms;
+    }
+}
+
+/// How much machine code the callee's functions compiled to
+#[derive(Debug, Serialize)]
+pub struct CodegenSize {
+    /// The size of each function (including the links of its tail call chain)
+    pub funcs: SortedMap<String, u64>,
+    pub total: u64,
+}
+
+/// The LLVM IR of each side of a test (None for sides whose toolchain can't emit it)
+#[derive(Debug, Clone, Serialize)]
+pub struct LlvmIr {
+    pub caller: Option<Utf8PathBuf>,
+    pub callee: Option<Utf8PathBuf>,
 }
 
 #[derive(Debug, Serialize)]
@@ -549,15 +1077,214 @@ pub struct CheckOutput {
     pub all_passed: bool,
     pub subtest_names: Vec<String>,
     pub subtest_checks: Vec<SubtestDetails>,
+    /// How many float values only matched thanks to the `float_ulps` tolerance
+    #[serde(skip_serializing_if = "is_zero")]
+    pub inexact_floats: usize,
+    /// What happened to the padding of every input the caller filled with `--pad-byte`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub padding: Vec<PaddingCheck>,
+}
+
+/// What the callee found in the padding of an input the caller filled with `--pad-byte`
+#[derive(Debug, Serialize)]
+pub struct PaddingCheck {
+    pub func_name: String,
+    pub arg_name: String,
+    /// How many bytes of padding the input has
+    pub padding_bytes: usize,
+    pub verdict: PaddingVerdict,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaddingVerdict {
+    /// Every byte of padding still had the pad byte
+    Preserved,
+    /// Every byte of padding was zero
+    Zeroed,
+    /// Anything else (whatever happened to be in the registers or stack slots it went through)
+    Garbage,
+}
+
+fn is_zero(val: &usize) -> bool {
+    *val == 0
 }
 
 #[derive(Debug, Serialize)]
 pub struct SubtestDetails {
     pub result: Result<(), CheckFailure>,
-    pub minimized: Option<GenerateOutput>,
+    pub minimized: Option<MinimizedFailure>,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+/// A failing subtest, minimized down to the one value that was wrong
+#[derive(Debug, Serialize)]
+pub struct MinimizedFailure {
+    #[serde(flatten)]
+    pub sources: GenerateOutput,
+    pub func_idx: usize,
+    pub func_name: String,
+    pub arg_idx: usize,
+    pub arg_name: String,
+    pub val_idx: usize,
+    pub val_path: String,
+    /// Every value of the arg (just the wrong one, if the arg is nothing but that value)
+    pub vals: Vec<MinimizedVal>,
+    /// A Cargo project that reproduces this (with `--cargo-reproducers`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cargo_reproducer: Option<Utf8PathBuf>,
+}
+
+/// One value of the arg of a [`MinimizedFailure`][]
+#[derive(Debug, Serialize)]
+pub struct MinimizedVal {
+    pub path: String,
+    pub kind: MinimizedValKind,
+    pub expected: String,
+    pub caller: String,
+    pub callee: String,
+    pub mismatched: bool,
+}
+
+/// How the values of a [`MinimizedVal`][] are written
+#[derive(Debug, Copy, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MinimizedValKind {
+    /// Native-endian hex bytes (`01 00 00 00`)
+    Bytes,
+    /// The names of variants (we only know the bytes of the wrong one for a tag mismatch)
+    Variant,
+}
+
+impl MinimizedFailure {
+    pub fn new(sources: GenerateOutput, failure: &CheckFailure) -> Self {
+        match failure {
+            CheckFailure::ValMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                func_name,
+                arg_name,
+                val_path,
+                expected,
+                caller,
+                callee,
+                arg_fields,
+                ..
+            } => {
+                let hex = |bytes: &[u8]| {
+                    bytes
+                        .iter()
+                        .map(|b| format!("{b:02X}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                let vals = if arg_fields.is_empty() {
+                    vec![MinimizedVal {
+                        path: val_path.clone(),
+                        kind: MinimizedValKind::Bytes,
+                        expected: hex(expected),
+                        caller: hex(caller),
+                        callee: hex(callee),
+                        mismatched: true,
+                    }]
+                } else {
+                    arg_fields
+                        .iter()
+                        .map(|field| MinimizedVal {
+                            path: format!("{arg_name}{}", field.field),
+                            kind: MinimizedValKind::Bytes,
+                            expected: hex(&field.expected),
+                            caller: hex(&field.caller),
+                            callee: hex(&field.callee),
+                            mismatched: field.mismatched(),
+                        })
+                        .collect()
+                };
+                Self {
+                    sources,
+                    func_idx: *func_idx,
+                    func_name: func_name.clone(),
+                    arg_idx: *arg_idx,
+                    arg_name: arg_name.clone(),
+                    val_idx: *val_idx,
+                    val_path: val_path.clone(),
+                    vals,
+                    cargo_reproducer: None,
+                }
+            }
+            CheckFailure::TagMismatch {
+                func_idx,
+                arg_idx,
+                val_idx,
+                func_name,
+                arg_name,
+                val_path,
+                expected,
+                caller,
+                callee,
+                ..
+            } => Self {
+                sources,
+                func_idx: *func_idx,
+                func_name: func_name.clone(),
+                arg_idx: *arg_idx,
+                arg_name: arg_name.clone(),
+                val_idx: *val_idx,
+                val_path: val_path.clone(),
+                vals: vec![MinimizedVal {
+                    path: val_path.clone(),
+                    kind: MinimizedValKind::Variant,
+                    expected: expected.clone(),
+                    caller: caller.clone(),
+                    callee: callee.clone(),
+                    mismatched: true,
+                }],
+                cargo_reproducer: None,
+            },
+        }
+    }
+}
+
+/// Why a test was skipped
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// A test rule said to skip it
+    Rules,
+    /// A toolchain doesn't support the calling convention
+    Convention,
+    /// A toolchain doesn't support the type repr
+    Repr,
+    /// A toolchain doesn't support something else the test needs
+    Unsupported,
+}
+impl std::str::FromStr for SkipReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "rules" => SkipReason::Rules,
+            "convention" => SkipReason::Convention,
+            "repr" => SkipReason::Repr,
+            "unsupported" => SkipReason::Unsupported,
+            _ => return Err(format!("unknown skip reason: {s}")),
+        };
+        Ok(val)
+    }
+}
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            SkipReason::Rules => "rules",
+            SkipReason::Convention => "convention",
+            SkipReason::Repr => "repr",
+            SkipReason::Unsupported => "unsupported",
+        };
+        string.fmt(f)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "kebab-case")]
 pub enum TestConclusion {
     Skipped,
@@ -566,10 +1293,23 @@ pub enum TestConclusion {
     Failed,
 }
 
+impl std::fmt::Display for TestConclusion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            TestConclusion::Skipped => "skipped",
+            TestConclusion::Passed => "passed",
+            TestConclusion::Busted => "busted",
+            TestConclusion::Failed => "failed",
+        };
+        string.fmt(f)
+    }
+}
+
 impl FullReport {
     pub fn print_human(
         &self,
         harness: &TestHarness,
+        group_by: Option<GroupBy>,
         mut f: impl std::io::Write,
     ) -> Result<(), std::io::Error> {
         use TestCheckMode::*;
@@ -579,14 +1319,42 @@ impl FullReport {
         let red = Style::new().red();
         let green = Style::new().green();
         let blue = Style::new().blue();
-        let mut sorted_tests = self.tests.iter().collect::<Vec<_>>();
-        sorted_tests.sort_by_key(|t| t.conclusion);
-        for test in sorted_tests {
+        let bold = Style::new().bold();
+        let mut sorted_tests = self
+            .tests
+            .iter()
+            .map(|t| (group_by.map(|g| g.group_name(&t.key)), t))
+            .collect::<Vec<_>>();
+        sorted_tests.sort_by(|(group_a, a), (group_b, b)| {
+            group_a.cmp(group_b).then(a.conclusion.cmp(&b.conclusion))
+        });
+        let mut cur_group = None;
+        for (group, test) in sorted_tests {
             if let Skipped = test.conclusion {
                 continue;
             }
+            if group.is_some() && group != cur_group {
+                if cur_group.is_some() {
+                    writeln!(f)?;
+                }
+                writeln!(f, "{}", bold.apply_to(group.as_deref().unwrap_or_default()))?;
+                cur_group = group;
+            }
             let pretty_test_name = harness.full_test_name(&test.key);
             write!(f, "{pretty_test_name:<64} ")?;
+            if let (Some(expected), true) = (&test.rules.crash, test.conclusion != Skipped) {
+                if test.conclusion == Passed {
+                    writeln!(f, "passed (crashed with {expected} as expected)")?;
+                } else {
+                    let got = crash_outcome(&test.results);
+                    writeln!(
+                        f,
+                        "{}",
+                        red.apply_to(format!("failed (expected crash {expected}, got {got})"))
+                    )?;
+                }
+                continue;
+            }
             match (&test.conclusion, &test.rules.check) {
                 (Skipped, _) => {
                     // Don't mention these, too many
@@ -599,7 +1367,8 @@ impl FullReport {
 
                 (Failed, Pass(_)) => {
                     write!(f, "{}", red.apply_to("failed"))?;
-                    if test.results.ran_to < TestRunMode::Check {
+                    // Resumed tests only have the details in the json report
+                    if test.results.ran_to < TestRunMode::Check && !test.resumed {
                         let (msg, err) = match &test.results.ran_to {
                             TestRunMode::Generate => {
                                 ("generate source code", format_err(&test.results.source))
@@ -616,6 +1385,7 @@ impl FullReport {
                         write!(f, "{}", red.apply_to(" to "))?;
                         writeln!(f, "{}", red.apply_to(msg))?;
                         writeln!(f, "  {}", red.apply_to(err))?;
+                        write_llvm_ir(&mut f, &test.results)?;
                     }
                 }
                 (Failed, Random(_)) => {
@@ -635,6 +1405,16 @@ impl FullReport {
                 }
             }
 
+            if test.interop_only {
+                write!(f, "{}", red.apply_to(" (interop-only)"))?;
+            }
+            if test.lto_only {
+                write!(f, "{}", red.apply_to(" (lto-only)"))?;
+            }
+            if test.resumed {
+                write!(f, " (resumed)")?;
+            }
+
             let be_detailed = test.results.ran_to >= TestRunMode::Check
                 && test.conclusion != TestConclusion::Busted;
             if !be_detailed {
@@ -647,11 +1427,41 @@ impl FullReport {
             let sub_results = &check_result.subtest_checks;
             let num_passed = sub_results.iter().filter(|t| t.result.is_ok()).count();
 
-            writeln!(f, " ({num_passed:>3}/{:<3} passed)", sub_results.len())?;
+            write!(f, " ({num_passed:>3}/{:<3} passed)", sub_results.len())?;
+            // Don't let a tolerance hide in the rules file
+            if check_result.inexact_floats > 0 {
+                write!(
+                    f,
+                    " ({} floats within {} ULPs)",
+                    check_result.inexact_floats,
+                    test.rules.float_ulps.unwrap_or_default()
+                )?;
+            }
+            if !check_result.padding.is_empty() {
+                let count = |verdict| {
+                    check_result
+                        .padding
+                        .iter()
+                        .filter(|padding| padding.verdict == verdict)
+                        .count()
+                };
+                write!(
+                    f,
+                    " (padding {} preserved, {} zeroed, {} garbage)",
+                    count(PaddingVerdict::Preserved),
+                    count(PaddingVerdict::Zeroed),
+                    count(PaddingVerdict::Garbage)
+                )?;
+            }
+            if let Some(size) = &test.results.codegen_size {
+                write!(f, " [callee {} bytes]", size.total)?;
+            }
+            writeln!(f)?;
             // If all the subtests pass, don't bother with a breakdown.
             if check_result.all_passed {
                 continue;
             }
+            write_llvm_ir(&mut f, &test.results)?;
 
             let max_name_len = check_result
                 .subtest_names
@@ -664,19 +1474,56 @@ impl FullReport {
                     writeln!(f, "{}", red.apply_to("failed!"))?;
                     if let Some(minimized) = &subtest.minimized {
                         writeln!(f, "    {}", blue.apply_to("minimized to:"))?;
-                        writeln!(f, "      caller: {}", blue.apply_to(&minimized.caller_src))?;
-                        writeln!(f, "      callee: {}", blue.apply_to(&minimized.callee_src))?;
+                        let sources = &minimized.sources;
+                        writeln!(f, "      caller: {}", blue.apply_to(&sources.caller_src))?;
+                        writeln!(f, "      callee: {}", blue.apply_to(&sources.callee_src))?;
+                        if let Some(dir) = &minimized.cargo_reproducer {
+                            writeln!(f, "      cargo:  {}", blue.apply_to(dir))?;
+                        }
                     }
                     writeln!(f, "{}", red.apply_to(e))?;
+                    write_arg_dump(&mut f, e)?;
                 } else {
                     writeln!(f)?;
                 }
             }
             writeln!(f)?;
         }
+
+        // Many failures are usually the same bug, so summarize them by what went wrong
+        let signatures = self.failure_signatures();
+        if !signatures.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{}", bold.apply_to("Failure Signatures:"))?;
+            let max_count_len = signatures
+                .iter()
+                .fold(0, |max, (_, ids)| max.max(ids.len().to_string().len()));
+            // Past a point these are all one-offs, so don't drown out the summary
+            const MAX_SIGNATURES: usize = 10;
+            for (signature, ids) in signatures.iter().take(MAX_SIGNATURES) {
+                let num_tests = ids.len();
+                let tests = if num_tests == 1 { "test " } else { "tests" };
+                writeln!(
+                    f,
+                    "  {num_tests:>max_count_len$} {tests}  {}",
+                    red.apply_to(signature)
+                )?;
+                writeln!(f, "  {:max_count_len$}        e.g. {}", "", ids[0])?;
+            }
+            if signatures.len() > MAX_SIGNATURES {
+                writeln!(f, "  ...and {} more", signatures.len() - MAX_SIGNATURES)?;
+            }
+        }
+        if !self.idle_toolchains.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{}", bold.apply_to("Idle Toolchains:"))?;
+            for idle in &self.idle_toolchains {
+                writeln!(f, "  {}: {}", blue.apply_to(&idle.toolchain), idle.reason)?;
+            }
+        }
         writeln!(f)?;
-        let summary_style = if self.summary.num_failed > 0 {
-            red
+        let summary_style = if self.failed() {
+            red.clone()
         } else if self.summary.num_busted > 0 {
             blue.clone()
         } else {
@@ -691,6 +1538,60 @@ impl FullReport {
             self.summary.num_skipped
         );
         writeln!(f, "{}", summary_style.apply_to(summary),)?;
+        if !self.summary.num_skipped_by_reason.is_empty() {
+            let reasons = self
+                .summary
+                .num_skipped_by_reason
+                .iter()
+                .map(|(reason, count)| format!("{count} {reason}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "  skipped because of: {reasons}")?;
+        }
+        if self.summary.num_skip_errors > 0 {
+            writeln!(
+                f,
+                "{}",
+                red.apply_to(format!(
+                    "{} skipped tests are errors because of --error-on-skip",
+                    self.summary.num_skip_errors
+                ))
+            )?;
+        }
+        if self.summary.num_interop_only > 0 {
+            writeln!(
+                f,
+                "  {} of the failures are interop-only (both toolchains pass when paired with themselves)",
+                self.summary.num_interop_only
+            )?;
+        }
+        if self.summary.num_lto_only > 0 {
+            writeln!(
+                f,
+                "  {} tests only pass with lto (the call was probably optimized away)",
+                self.summary.num_lto_only
+            )?;
+        }
+        if self.summary.num_interop_errors > 0 {
+            writeln!(
+                f,
+                "{}",
+                red.apply_to(format!(
+                    "{} busted tests are errors because they're interop-only (--error-on-interop-only)",
+                    self.summary.num_interop_errors
+                ))
+            )?;
+        }
+        if self.summary.num_idle_toolchain_errors > 0 {
+            writeln!(
+                f,
+                "{}",
+                red.apply_to(format!(
+                    "{} toolchains didn't run any tests, which is an error because of --error-on-idle-toolchains",
+                    self.summary.num_idle_toolchain_errors
+                ))
+            )?;
+        }
         if let Some(rules) = &self.possible_rules {
             writeln!(f)?;
             writeln!(
@@ -778,11 +1679,211 @@ impl FullReport {
         Ok(())
     }
 
+    /// One line per failed (or busted) test: `id<TAB>conclusion<TAB>reason`
+    ///
+    /// Passing and skipped tests aren't mentioned at all, this is for grep and diff.
+    pub fn print_compact(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        for test in &self.tests {
+            let conclusion = match test.conclusion {
+                TestConclusion::Failed => "failed",
+                TestConclusion::Busted => "busted",
+                TestConclusion::Passed | TestConclusion::Skipped => continue,
+            };
+            let reason = first_failure_reason(test).replace('\t', " ");
+            writeln!(f, "{}\t{conclusion}\t{reason}", test.id)?;
+        }
+        Ok(())
+    }
+
     pub fn failed(&self) -> bool {
         self.summary.num_failed > 0
+            || self.summary.num_skip_errors > 0
+            || self.summary.num_interop_errors > 0
+            || self.summary.num_idle_toolchain_errors > 0
+    }
+
+    /// Cluster the failed tests by the [`FailureSignature`][]s of their subtests
+    ///
+    /// Returns the ids of the tests with each signature, most common signatures first.
+    pub fn failure_signatures(&self) -> Vec<(FailureSignature, Vec<&str>)> {
+        let mut clusters = SortedMap::<FailureSignature, Vec<&str>>::new();
+        for test in &self.tests {
+            if test.conclusion != TestConclusion::Failed {
+                continue;
+            }
+            let Some(check) = &test.results.check else {
+                continue;
+            };
+            let mut signatures = check
+                .subtest_checks
+                .iter()
+                .filter_map(|subtest| subtest.result.as_ref().err())
+                .map(FailureSignature::new)
+                .collect::<Vec<_>>();
+            signatures.sort();
+            signatures.dedup();
+            for signature in signatures {
+                clusters.entry(signature).or_default().push(&test.id);
+            }
+        }
+        let mut clusters = clusters.into_iter().collect::<Vec<_>>();
+        // Stable, so ties are still sorted by signature
+        clusters.sort_by_key(|(_, ids)| std::cmp::Reverse(ids.len()));
+        clusters
     }
 }
 
+/// What a check failure looks like with the details of the specific test stripped away
+///
+/// Failures with the same signature are very likely to have the same root cause.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FailureSignature {
+    /// The type of the argument the bad value was in
+    pub arg_ty_name: String,
+    /// The path to the bad value inside the argument (i.e. `.y` or `[3].x`)
+    pub field_path: String,
+    pub val_ty_name: String,
+    /// The first byte that the caller and callee disagreed on (None for tags)
+    pub first_bad_byte: Option<usize>,
+}
+
+impl FailureSignature {
+    pub fn new(failure: &CheckFailure) -> Self {
+        let (arg_ty_name, val_path, val_ty_name, first_bad_byte) = match failure {
+            CheckFailure::ValMismatch {
+                arg_ty_name,
+                val_path,
+                val_ty_name,
+                caller,
+                callee,
+                ..
+            } => {
+                let first_bad_byte = (0..caller.len().max(callee.len()))
+                    .find(|&idx| caller.get(idx) != callee.get(idx));
+                (arg_ty_name, val_path, val_ty_name, first_bad_byte)
+            }
+            CheckFailure::TagMismatch {
+                arg_ty_name,
+                val_path,
+                val_ty_name,
+                ..
+            } => (arg_ty_name, val_path, val_ty_name, None),
+        };
+        // The path starts with the name of the arg, which is different for every function
+        let field_path = val_path
+            .find(['.', '['])
+            .map(|idx| val_path[idx..].to_owned())
+            .unwrap_or_default();
+        Self {
+            arg_ty_name: arg_ty_name.clone(),
+            field_path,
+            val_ty_name: val_ty_name.clone(),
+            first_bad_byte,
+        }
+    }
+}
+
+impl std::fmt::Display for FailureSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}: {}",
+            self.arg_ty_name, self.field_path, self.val_ty_name
+        )?;
+        match self.first_bad_byte {
+            Some(byte) => write!(f, " differed from byte {byte}"),
+            None => write!(f, " had the wrong variant"),
+        }
+    }
+}
+
+impl GroupBy {
+    /// Get the name of the group this test belongs to
+    pub fn group_name(&self, key: &TestKey) -> String {
+        match self {
+            GroupBy::Test => key.test.clone(),
+            GroupBy::Convention if key.options.convention != key.options.callee_convention => {
+                format!(
+                    "conv_{}::callee_conv_{}",
+                    key.options.convention, key.options.callee_convention
+                )
+            }
+            GroupBy::Convention => format!("conv_{}", key.options.convention),
+            GroupBy::Pair => format!("{}_calls_{}", key.caller, key.callee),
+            GroupBy::Repr if key.options.repr != key.options.callee_repr => format!(
+                "repr_{}::callee_repr_{}",
+                key.options.repr, key.options.callee_repr
+            ),
+            GroupBy::Repr => format!("repr_{}", key.options.repr),
+        }
+    }
+}
+
+/// How a test that was expected to crash actually ended
+fn crash_outcome(results: &TestRunResults) -> String {
+    match &results.run {
+        Some(Err(RunError::BadExit { exit, .. })) => exit.to_string(),
+        Some(Ok(_)) => "exited cleanly".to_owned(),
+        Some(Err(e)) => e.to_string(),
+        None => "didn't run".to_owned(),
+    }
+}
+
+/// The first thing that went wrong with a test, squashed onto one line
+fn first_failure_reason(test: &TestReport) -> String {
+    let results = &test.results;
+    if test.resumed {
+        // The details of the earlier run are only in the json report
+        return "failed in an earlier run (see --resume)".to_owned();
+    }
+    if let Some(expected) = &test.rules.crash {
+        return format!("expected crash {expected}, got {}", crash_outcome(results));
+    }
+    let stage_errors = [
+        ("generate", format_err(&results.source)),
+        ("build", format_err(&results.build)),
+        ("link", format_err(&results.link)),
+        ("run", format_err(&results.run)),
+    ];
+    if let Some((stage, err)) = stage_errors.iter().find(|(_, err)| !err.is_empty()) {
+        return format!("failed to {stage}: {}", summary_line(err));
+    }
+    if let Some(check) = &results.check {
+        let first_failure = check
+            .subtest_checks
+            .iter()
+            .find_map(|subtest| subtest.result.as_ref().err());
+        if let Some(failure) = first_failure {
+            return format!(
+                "{} ({})",
+                summary_line(&failure.to_string()),
+                FailureSignature::new(failure)
+            );
+        }
+    }
+    match test.rules.check {
+        TestCheckMode::Fail(_) => "passed, but was expected to fail".to_owned(),
+        TestCheckMode::Busted(_) if test.conclusion == TestConclusion::Failed => {
+            "passed, but the rules say it's busted".to_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+/// The most useful line of a (possibly huge) error message
+///
+/// For compiler output that's the first actual error, otherwise it's just the first line.
+fn summary_line(message: &str) -> &str {
+    let mut lines = message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    let first = lines.clone().next().unwrap_or_default();
+    lines
+        .find(|line| line.starts_with("error"))
+        .unwrap_or(first)
+}
+
 fn format_err<T, E: std::fmt::Display>(maybe_res: &Option<Result<T, E>>) -> String {
     let Some(res) = maybe_res else {
         return String::new();
@@ -792,3 +1893,316 @@ fn format_err<T, E: std::fmt::Display>(maybe_res: &Option<Result<T, E>>) -> Stri
     };
     format!("{res}")
 }
+
+/// Hex dump the whole arg a bad value was in, so shifted or swapped values stand out
+pub(crate) fn write_arg_dump(
+    f: &mut impl std::io::Write,
+    failure: &CheckFailure,
+) -> std::io::Result<()> {
+    use std::fmt::Write as _;
+    let CheckFailure::ValMismatch {
+        arg_name,
+        arg_ty_name,
+        arg_fields,
+        arg_size,
+        ..
+    } = failure
+    else {
+        return Ok(());
+    };
+    if arg_fields.is_empty() {
+        return Ok(());
+    }
+    let mut dump = String::new();
+    let mut dump_f = Fivemat::new(&mut dump, "  ");
+    dump_f.add_indent(3);
+    let how = if arg_size.is_some() {
+        "offsets into the arg, .. is padding"
+    } else {
+        "values laid end to end, padding not shown"
+    };
+    writeln!(&mut dump_f, "{arg_name}: {arg_ty_name} ({how}):")
+        .and_then(|()| hex_dump::write_hex_dump(&mut dump_f, arg_fields, *arg_size))
+        .map_err(std::io::Error::other)?;
+    write!(f, "{dump}")
+}
+
+/// Point at the LLVM IR of a failed test, if we emitted any (`--emit-llvm-ir`)
+fn write_llvm_ir(f: &mut impl std::io::Write, results: &TestRunResults) -> std::io::Result<()> {
+    let Some(ir) = &results.llvm_ir else {
+        return Ok(());
+    };
+    if ir.caller.is_none() && ir.callee.is_none() {
+        return Ok(());
+    }
+    let blue = Style::new().blue();
+    writeln!(f, "  {}", blue.apply_to("llvm ir:"))?;
+    for (side, path) in [("caller", &ir.caller), ("callee", &ir.callee)] {
+        if let Some(path) = path {
+            writeln!(f, "    {side}: {}", blue.apply_to(path))?;
+        }
+    }
+    Ok(())
+}
+
+/// How many tests a config selects, broken down by axis (`--count`)
+#[derive(Debug, Serialize)]
+pub struct TestCount {
+    /// How many options each axis of the test matrix has (in the order they're multiplied)
+    pub axes: IndexMap<&'static str, usize>,
+    /// How many pairs `--pair-rules` excluded (already left out of "pairs")
+    pub num_excluded_pairs: usize,
+    /// How many tests would actually be generated
+    pub total: usize,
+    /// How many of those the test rules would skip without running
+    pub num_skipped_by_rules: usize,
+}
+
+impl TestCount {
+    pub fn print_human(&self, mut f: impl std::io::Write) -> Result<(), std::io::Error> {
+        let max_name_len = self
+            .axes
+            .iter()
+            .fold(0, |max, (name, _)| max.max(name.len()));
+        for (name, count) in &self.axes {
+            writeln!(f, "{name:>max_name_len$}: {count}")?;
+        }
+        if self.num_excluded_pairs > 0 {
+            writeln!(
+                f,
+                "({} pairs excluded by --pair-rules)",
+                self.num_excluded_pairs
+            )?;
+        }
+        let product = self.axes.values().product::<usize>();
+        let multipliers = self
+            .axes
+            .values()
+            .map(|count| count.to_string())
+            .collect::<Vec<_>>()
+            .join(" x ");
+        writeln!(f)?;
+        writeln!(f, "{multipliers} = {product}")?;
+        if product != self.total {
+            writeln!(
+                f,
+                "{} tests after dropping the combinations that can't exist",
+                self.total
+            )?;
+        }
+        writeln!(
+            f,
+            "{} of them would be run ({} are skipped by the test rules)",
+            self.total - self.num_skipped_by_rules,
+            self.num_skipped_by_rules
+        )?;
+        Ok(())
+    }
+
+    pub fn print_json(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
+    }
+}
+
+/// Everything this build of abi-cafe supports, for tools that drive it (`--capabilities`)
+///
+/// Every value here can be passed back to the flag it's named after.
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    /// The version of abi-cafe
+    pub version: &'static str,
+    /// The `schema_version` of the json reports this version writes
+    pub report_schema_version: u32,
+    /// The target the tests would be built for
+    pub target: String,
+    pub conventions: Vec<String>,
+    pub reprs: Vec<String>,
+    /// `random<seed>` takes any u64 as the seed
+    pub value_generators: Vec<String>,
+    pub writers: Vec<String>,
+    pub linkers: Vec<String>,
+    pub lto: Vec<String>,
+    pub calls: Vec<String>,
+    pub faults: Vec<String>,
+    pub rounding_modes: Vec<String>,
+    pub instruments: Vec<String>,
+    /// Every toolchain that can be paired up (including custom ones and manifests)
+    pub toolchains: Vec<ToolchainCapabilities>,
+    /// Every test that can be run (builtin, `--add-tests`, and `--import-header`)
+    pub tests: Vec<TestId>,
+}
+
+/// What one toolchain supports
+#[derive(Debug, Serialize)]
+pub struct ToolchainCapabilities {
+    pub id: ToolchainId,
+    /// The language it generates ("c" or "rust")
+    pub lang: &'static str,
+    /// Whether the compiler it needs is installed
+    pub available: bool,
+    /// The version of that compiler (if it could be found)
+    pub version: Option<String>,
+    /// The kind of objects it can emit for `--lto` (null if it can't)
+    pub lto_unit: Option<LtoUnit>,
+    /// Whether it works with `--instrument=msan`
+    pub msan: bool,
+    /// Whether it works with `--target-features`
+    pub target_features: bool,
+    /// Whether it works with `--calls=cf_protection`
+    pub cf_protection: bool,
+}
+
+impl Capabilities {
+    pub fn print_json(&self, f: impl std::io::Write) -> Result<(), std::io::Error> {
+        serde_json::to_writer_pretty(f, self).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::harness::testing::*;
+
+    const SCHEMA: &str = include_str!("../../docs/src/harness/report.schema.json");
+
+    /// Check `value` against the parts of JSON Schema that report.schema.json uses,
+    /// with every object key having to be listed in its `properties`
+    fn check_schema(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+        if let Some(def) = schema["$ref"].as_str() {
+            let def = def.strip_prefix("#/$defs/").unwrap();
+            return check_schema(value, &root["$defs"][def], root, path);
+        }
+        if let Some(options) = schema["oneOf"].as_array() {
+            let errors = options
+                .iter()
+                .filter_map(|option| check_schema(value, option, root, path).err())
+                .collect::<Vec<_>>();
+            if errors.len() == options.len() {
+                return Err(errors.join("\n"));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if value != expected {
+                return Err(format!("{path} is {value}, not {expected}"));
+            }
+        }
+        if let Some(variants) = schema["enum"].as_array() {
+            if !variants.contains(value) {
+                return Err(format!("{path} is {value}, which isn't in its enum"));
+            }
+        }
+        let types = match &schema["type"] {
+            Value::String(ty) => vec![ty.as_str()],
+            Value::Array(tys) => tys.iter().filter_map(|ty| ty.as_str()).collect(),
+            _ => vec![],
+        };
+        let is_type = |ty: &str| match ty {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => panic!("schema has unknown type {ty}"),
+        };
+        if !types.is_empty() && !types.iter().any(|ty| is_type(ty)) {
+            return Err(format!("{path} is {value}, not {types:?}"));
+        }
+        if let Value::Object(fields) = value {
+            for required in schema["required"].as_array().into_iter().flatten() {
+                let required = required.as_str().unwrap();
+                if !fields.contains_key(required) {
+                    return Err(format!("{path} is missing required key {required}"));
+                }
+            }
+            let properties = schema["properties"].as_object();
+            for (key, field) in fields {
+                let path = format!("{path}.{key}");
+                if let Some(property) = properties.and_then(|props| props.get(key)) {
+                    check_schema(field, property, root, &path)?;
+                } else if let Some(extra) = schema.get("additionalProperties") {
+                    check_schema(field, extra, root, &path)?;
+                } else if properties.is_some() {
+                    return Err(format!("{path} isn't in the schema"));
+                }
+            }
+        }
+        if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+            for (idx, item) in items.iter().enumerate() {
+                check_schema(item, item_schema, root, &format!("{path}[{idx}]"))?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn matches_schema() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+
+        // Fill in as many of the optional parts of the report as we can
+        let mut passed = test_report("simple", "cc", "rustc", TestConclusion::Passed);
+        let results = &mut passed.results;
+        results.ran_to = TestRunMode::Check;
+        results.source = Some(Ok(GenerateOutput {
+            caller_src: "simple_caller.c".into(),
+            callee_src: "simple_callee.rs".into(),
+        }));
+        results.build = Some(Ok(BuildOutput {
+            caller_lib: "simple_caller".to_owned(),
+            callee_lib: "simple_callee".to_owned(),
+            rounding_lib: None,
+        }));
+        results.link = Some(Ok(LinkOutput {
+            test_bin: "simple.bin".into(),
+        }));
+        results.check = Some(CheckOutput {
+            all_passed: true,
+            subtest_names: vec!["simple::i32".to_owned()],
+            subtest_checks: vec![SubtestDetails {
+                result: Ok(()),
+                minimized: None,
+            }],
+            inexact_floats: 1,
+            padding: vec![],
+        });
+        results.codegen_size = Some(CodegenSize {
+            funcs: [("i32".to_owned(), 4)].into_iter().collect(),
+            total: 4,
+        });
+        passed.source = Some("fn \"i32\" { inputs { _ \"i32\"; } }".to_owned());
+        let mut skipped = test_report("simple", "cc", "cc", TestConclusion::Skipped);
+        skipped.skip_reason = Some(SkipReason::Unsupported);
+
+        let mut report = full_report(vec![passed, skipped]);
+        report.shard = Some(Shard { index: 1, count: 2 });
+        report.toolchain_versions = [("cc".to_owned(), None)].into_iter().collect();
+        report.run_env = [("RUST_LOG".to_owned(), "info".to_owned())]
+            .into_iter()
+            .collect();
+        report.excluded_pairs = vec![ExcludedPair {
+            caller: "rustc".to_owned(),
+            callee: "cc".to_owned(),
+            reason: "pair rules".to_owned(),
+        }];
+        report.idle_toolchains = vec![IdleToolchain {
+            toolchain: "clang".to_owned(),
+            reason: "no tests selected it".to_owned(),
+        }];
+        report.config = Some(json!({ "tests": ["simple"] }));
+
+        let value = serde_json::to_value(&report).unwrap();
+        check_schema(&value, &schema, &schema, "report").unwrap();
+        let tests = value["tests"].as_array().unwrap();
+        let passed = tests.iter().find(|t| t["conclusion"] == "passed").unwrap();
+        assert!(passed["results"]["check"].is_object());
+        assert!(passed["results"]["codegen_size"].is_object());
+
+        // The report doesn't just match because the checker lets anything through
+        let mut extra = value.clone();
+        extra["surprise"] = json!(true);
+        let error = check_schema(&extra, &schema, &schema, "report").unwrap_err();
+        assert_eq!(error, "report.surprise isn't in the schema");
+    }
+}
@@ -0,0 +1,492 @@
+//! Packaging minimized failures as Cargo projects (`--cargo-reproducers`)
+//!
+//! When one side of a failure is Rust, the people who can fix it want something they
+//! can `cargo test`, without abi-cafe in the way. So we regenerate the minimized test
+//! (with the harness callbacks, which are the only writer that tells us what both sides
+//! saw), and wrap it in a little crate: the Rust sides become modules, the C sides get
+//! built by `build.rs`, and a `#[test]` stands in for the harness, failing if the two
+//! sides (or abi-cafe's expectation) disagree about the value.
+//!
+//! Both get built the way abi-cafe built them: `build.rs` picks the same compiler and
+//! flags, and `.cargo/config.toml` gives rustc the same flags (target features, LTO,
+//! the linker...), since a failure that only shows up with those won't reproduce without.
+
+use std::fmt::Write;
+
+use camino::Utf8PathBuf;
+use tracing::info;
+
+use crate::error::*;
+use crate::harness::build::{linker_args, lto_args};
+use crate::harness::test::*;
+use crate::toolchains::CcCrateBuild;
+use crate::*;
+
+/// The value a Cargo reproducer checks (the one a failure was minimized to)
+#[derive(Debug, Clone)]
+pub struct ReproducedVal {
+    pub func_idx: usize,
+    pub val_idx: usize,
+    pub func_name: String,
+    pub val_path: String,
+    /// The bytes abi-cafe expected (None for tags, which we only know by name)
+    pub expected: Option<Vec<u8>>,
+}
+
+impl ReproducedVal {
+    pub fn new(failure: &CheckFailure) -> Self {
+        match failure {
+            CheckFailure::ValMismatch {
+                func_idx,
+                val_idx,
+                func_name,
+                val_path,
+                expected,
+                ..
+            } => Self {
+                func_idx: *func_idx,
+                val_idx: *val_idx,
+                func_name: func_name.clone(),
+                val_path: val_path.clone(),
+                expected: Some(expected.clone()),
+            },
+            CheckFailure::TagMismatch {
+                func_idx,
+                val_idx,
+                func_name,
+                val_path,
+                ..
+            } => Self {
+                func_idx: *func_idx,
+                val_idx: *val_idx,
+                func_name: func_name.clone(),
+                val_path: val_path.clone(),
+                expected: None,
+            },
+        }
+    }
+}
+
+impl TestHarness {
+    /// Whether we can make a Cargo reproducer for this test
+    ///
+    /// One side has to be Rust (otherwise there's nothing for rustc's triagers),
+    /// `build.rs` only knows how to build C (with compilers the `cc` crate can drive),
+    /// and Cargo builds the whole crate with one set of rustc flags. The C helpers of
+    /// `--rounding-mode` aren't part of the reproducer either.
+    pub fn can_cargo_reproduce(&self, key: &TestKey) -> bool {
+        if key.options.rounding_mode.is_some() {
+            return false;
+        }
+        let mut rustc_flags = vec![];
+        for call_side in [CallSide::Caller, CallSide::Callee] {
+            let toolchain = self.toolchain_by_test_key(key, call_side);
+            let codegen = key.options.codegen_for(call_side);
+            match toolchain.lang() {
+                "rust" => rustc_flags.push(toolchain.rustc_flags(&codegen)),
+                "c" if toolchain.cc_crate_build(&codegen).is_some() => {}
+                _ => return false,
+            }
+        }
+        !rustc_flags.is_empty() && rustc_flags.iter().all(|flags| *flags == rustc_flags[0])
+    }
+
+    /// Write a Cargo project that reproduces this (minimized) test, and return where it is
+    pub async fn write_cargo_reproducer(
+        &self,
+        key: &TestKey,
+        val: &ReproducedVal,
+    ) -> Result<Utf8PathBuf, GenerateError> {
+        let sources = self.generate_test(key).await?;
+        let dir = self
+            .paths
+            .reproducers_dir()
+            .join(self.base_id(key, None, "_"));
+        let src_dir = dir.join("src");
+        std::fs::create_dir_all(&src_dir)?;
+        info!("writing     {dir}");
+
+        let mut crate_attrs = vec![];
+        let mut modules = vec![];
+        let mut c_files = vec![];
+        let mut rustflags = vec![];
+        for (call_side, src_path) in [
+            (CallSide::Caller, &sources.caller_src),
+            (CallSide::Callee, &sources.callee_src),
+        ] {
+            let toolchain = self.toolchain_by_test_key(key, call_side);
+            let name = call_side.to_string();
+            let src = std::fs::read_to_string(src_path)?;
+            let src_ext = toolchain.src_ext(
+                &self.test(&key.test).types,
+                &key.options.for_call_side(call_side),
+            );
+            let file_name = format!("{name}.{src_ext}");
+            let codegen = key.options.codegen_for(call_side);
+            if toolchain.lang() == "rust" {
+                // can_cargo_reproduce checked that both Rust sides agree on these
+                rustflags = toolchain.rustc_flags(&codegen);
+                // Crate attributes (like #![feature]) only work in the crate root
+                let (attrs, body): (Vec<&str>, Vec<&str>) =
+                    src.lines().partition(|line| line.starts_with("#!["));
+                for attr in attrs {
+                    if !crate_attrs.contains(&attr.to_owned()) {
+                        crate_attrs.push(attr.to_owned());
+                    }
+                }
+                std::fs::write(src_dir.join(&file_name), body.join("\n"))?;
+                modules.push(name);
+            } else {
+                std::fs::write(src_dir.join(&file_name), src)?;
+                let build = toolchain
+                    .cc_crate_build(&codegen)
+                    .expect("can_cargo_reproduce should check the cc crate can build this");
+                c_files.push(CFile {
+                    file_name,
+                    toolchain_id: key.toolchain_id(call_side).to_owned(),
+                    lib_name: name,
+                    build,
+                });
+            }
+        }
+        let linker = key.options.linker;
+        let target = &self.toolchains.platform_info.target;
+        for arg in linker_args(target, linker).into_iter().chain(lto_args(
+            self.lto_unit(key).map(|unit| (key.options.lto, unit)),
+            linker,
+        )) {
+            if !rustflags.contains(&arg) {
+                rustflags.push(arg);
+            }
+        }
+
+        let id = self.base_id(key, None, "::");
+        std::fs::write(dir.join("Cargo.toml"), cargo_toml(!c_files.is_empty()))?;
+        if !c_files.is_empty() {
+            std::fs::write(dir.join("build.rs"), build_rs(&c_files))?;
+        }
+        let cargo_dir = dir.join(".cargo");
+        std::fs::create_dir_all(&cargo_dir)?;
+        std::fs::write(
+            cargo_dir.join("config.toml"),
+            cargo_config(target, &rustflags),
+        )?;
+        std::fs::write(
+            src_dir.join("lib.rs"),
+            lib_rs(&id, &crate_attrs, &modules, val),
+        )?;
+        Ok(dir)
+    }
+}
+
+fn cargo_toml(has_c: bool) -> String {
+    let mut out = String::new();
+    writeln!(out, "[package]").unwrap();
+    writeln!(out, r#"name = "abi-cafe-reproducer""#).unwrap();
+    writeln!(out, r#"version = "0.0.0""#).unwrap();
+    writeln!(out, r#"edition = "2021""#).unwrap();
+    writeln!(out, "publish = false").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "# Keep this out of any workspace it happens to be inside of"
+    )
+    .unwrap();
+    writeln!(out, "[workspace]").unwrap();
+    if has_c {
+        writeln!(out).unwrap();
+        writeln!(out, "[build-dependencies]").unwrap();
+        writeln!(out, r#"cc = "1.1.0""#).unwrap();
+    }
+    out
+}
+
+/// A C side of a reproducer, for `build.rs` to build
+#[derive(Debug, Clone)]
+struct CFile {
+    file_name: String,
+    toolchain_id: String,
+    lib_name: String,
+    build: CcCrateBuild,
+}
+
+fn cargo_config(target: &str, rustflags: &[String]) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# The target and rustc flags abi-cafe built and linked this with"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "# (with an explicit target, these don't apply to build.rs itself)"
+    )
+    .unwrap();
+    writeln!(out, "[build]").unwrap();
+    writeln!(out, "target = {target:?}").unwrap();
+    let rustflags = rustflags
+        .iter()
+        .map(|flag| format!("{flag:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(out, "rustflags = [{rustflags}]").unwrap();
+    out
+}
+
+fn build_rs(c_files: &[CFile]) -> String {
+    let mut out = String::new();
+    writeln!(out, "fn main() {{").unwrap();
+    for CFile {
+        file_name,
+        toolchain_id,
+        lib_name,
+        build,
+    } in c_files
+    {
+        if build.compiler.is_some() {
+            writeln!(out, "    // abi-cafe built this with {toolchain_id}").unwrap();
+        } else {
+            writeln!(
+                out,
+                "    // abi-cafe built this with {toolchain_id} (set CC to use the same compiler)"
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            r#"    println!("cargo:rerun-if-changed=src/{file_name}");"#
+        )
+        .unwrap();
+        writeln!(out, "    cc::Build::new()").unwrap();
+        if file_name.ends_with(".cpp") {
+            // Tests with @nontrivial types are C++20 (without exceptions, like abi-cafe builds them)
+            writeln!(out, "        .cpp(true)").unwrap();
+            writeln!(out, r#"        .std("c++20")"#).unwrap();
+            writeln!(out, r#"        .flag_if_supported("-fno-exceptions")"#).unwrap();
+        }
+        if let Some(compiler) = &build.compiler {
+            writeln!(out, "        .compiler({compiler:?})").unwrap();
+        }
+        for flag in &build.flags {
+            writeln!(out, "        .flag({flag:?})").unwrap();
+        }
+        writeln!(out, r#"        .file("src/{file_name}")"#).unwrap();
+        writeln!(out, r#"        .compile("{lib_name}");"#).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn lib_rs(id: &str, crate_attrs: &[String], modules: &[String], val: &ReproducedVal) -> String {
+    let ReproducedVal {
+        func_idx,
+        val_idx,
+        func_name,
+        val_path,
+        expected,
+    } = val;
+    let mut out = String::new();
+    writeln!(out, "//! Reproduces the abi-cafe failure {id}").unwrap();
+    writeln!(out, "//!").unwrap();
+    writeln!(
+        out,
+        "//! `do_test` (in the caller) calls {func_name}, and both sides report the bytes"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "//! of {val_path} through the callbacks below, which should be the same."
+    )
+    .unwrap();
+    for attr in crate_attrs {
+        writeln!(out, "{attr}").unwrap();
+    }
+    writeln!(out, "#![allow(warnings)]").unwrap();
+    writeln!(out).unwrap();
+    for module in modules {
+        writeln!(out, "mod {module};").unwrap();
+    }
+    out.push_str(LIB_RS_HARNESS);
+    writeln!(out).unwrap();
+    writeln!(out, "#[test]").unwrap();
+    writeln!(out, "fn reproduce() {{").unwrap();
+    writeln!(
+        out,
+        "    let (caller, callee) = run_test({func_idx}, {val_idx});"
+    )
+    .unwrap();
+    if let Some(expected) = expected {
+        let bytes = expected
+            .iter()
+            .map(|byte| format!("0x{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(out, "    // native-endian").unwrap();
+        writeln!(out, "    let expected: &[u8] = &[{bytes}];").unwrap();
+        writeln!(
+            out,
+            r#"    assert_eq!(caller.as_deref(), Some(expected), "the caller had the wrong {val_path}");"#
+        )
+        .unwrap();
+        writeln!(
+            out,
+            r#"    assert_eq!(callee.as_deref(), Some(expected), "the callee had the wrong {val_path}");"#
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            out,
+            r#"    assert_eq!(caller, callee, "the caller and callee disagreed about the variant of {val_path}");"#
+        )
+        .unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// The part of a reproducer's lib.rs that stands in for abi-cafe's harness
+const LIB_RS_HARNESS: &str = r#"
+/// Where one side of the call reports its values
+pub struct WriteBuffer {
+    func: u32,
+    vals: Vec<(u32, u32, Vec<u8>)>,
+}
+
+pub type SetFuncCallback = unsafe extern "C" fn(&mut WriteBuffer, u32);
+pub type WriteValCallback = unsafe extern "C" fn(&mut WriteBuffer, u32, *const u8, u32);
+
+unsafe extern "C" fn set_func(buf: &mut WriteBuffer, func: u32) {
+    buf.func = func;
+}
+
+unsafe extern "C" fn write_val(buf: &mut WriteBuffer, val: u32, input: *const u8, size: u32) {
+    let bytes = std::slice::from_raw_parts(input, size as usize).to_vec();
+    buf.vals.push((buf.func, val, bytes));
+}
+
+#[no_mangle]
+pub static mut CALLER_VALS: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut CALLEE_VALS: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut CALLEE_PADDING: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut SET_FUNC: Option<SetFuncCallback> = None;
+#[no_mangle]
+pub static mut WRITE_VAL: Option<WriteValCallback> = None;
+
+extern "C" {
+    fn do_test();
+}
+
+/// Run the test, and get the bytes of this value that the caller and callee saw
+fn run_test(func: u32, val: u32) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+    let mut caller = WriteBuffer { func: 0, vals: vec![] };
+    let mut callee = WriteBuffer { func: 0, vals: vec![] };
+    let mut callee_padding = WriteBuffer { func: 0, vals: vec![] };
+    unsafe {
+        CALLER_VALS = &mut caller as *mut _ as *mut ();
+        CALLEE_VALS = &mut callee as *mut _ as *mut ();
+        CALLEE_PADDING = &mut callee_padding as *mut _ as *mut ();
+        SET_FUNC = Some(set_func);
+        WRITE_VAL = Some(write_val);
+        do_test();
+    }
+    let find = |buf: &WriteBuffer| {
+        buf.vals
+            .iter()
+            .find(|(func_idx, val_idx, _)| (*func_idx, *val_idx) == (func, val))
+            .map(|(_, _, bytes)| bytes.clone())
+    };
+    (find(&caller), find(&callee))
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn clang_calls_rustc() {
+        let (harness, dir) = test_harness("reproducer", &["simple"]);
+        let options = TestOptions {
+            lto: Lto::Thin,
+            target_features: "+avx2".parse().unwrap(),
+            ..TestOptions::default()
+        };
+        let key = TestKey {
+            test: "simple".to_owned(),
+            caller: "clang".to_owned(),
+            callee: "rustc".to_owned(),
+            options,
+        };
+        assert!(harness.can_cargo_reproduce(&key));
+        let val = ReproducedVal {
+            func_idx: 1,
+            val_idx: 0,
+            func_name: "scale".to_owned(),
+            val_path: "arg0".to_owned(),
+            expected: Some(vec![0x01, 0x02]),
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let out = rt
+            .block_on(harness.write_cargo_reproducer(&key, &val))
+            .unwrap();
+        assert!(out.starts_with(dir.join("reproducers")));
+        let read = |path: &str| std::fs::read_to_string(out.join(path)).unwrap();
+
+        let cargo_toml = read("Cargo.toml");
+        assert!(cargo_toml.contains("[workspace]"));
+        assert!(cargo_toml.contains("[build-dependencies]\ncc = "));
+
+        // The caller gets built with the same compiler and flags as in the test
+        let build_rs = read("build.rs");
+        assert!(build_rs.contains(r#"println!("cargo:rerun-if-changed=src/caller.c");"#));
+        let build = r#"    cc::Build::new()
+        .compiler("clang")
+        .flag("-mavx2")
+        .flag("-O2")
+        .flag("-flto=thin")
+        .file("src/caller.c")
+        .compile("caller");
+"#;
+        assert!(build_rs.contains(build), "{build_rs}");
+        assert!(!build_rs.contains("set CC"));
+
+        // And the callee gets the LTO flags, while the caller's target features stay C-only
+        let config = read(".cargo/config.toml");
+        assert!(config.contains(r#"target = "x86_64-"#));
+        assert!(
+            config.contains(r#"rustflags = ["-Clinker-plugin-lto", "-Copt-level=2"]"#),
+            "{config}"
+        );
+
+        let lib_rs = read("src/lib.rs");
+        assert!(lib_rs.contains("//! Reproduces the abi-cafe failure simple::"));
+        assert!(lib_rs.contains("\nmod callee;\n"));
+        assert!(!lib_rs.contains("mod caller;"));
+        assert!(lib_rs.contains("run_test(1, 0)"));
+        assert!(lib_rs.contains("let expected: &[u8] = &[0x01, 0x02];"));
+        assert!(read("src/callee.rs").contains("fn scale"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cc_without_compiler() {
+        let c_files = [CFile {
+            file_name: "callee.cpp".to_owned(),
+            toolchain_id: "cc".to_owned(),
+            lib_name: "callee".to_owned(),
+            build: CcCrateBuild {
+                compiler: None,
+                flags: vec![],
+            },
+        }];
+        let build_rs = build_rs(&c_files);
+        assert!(build_rs.contains("with cc (set CC to use the same compiler)"));
+        assert!(build_rs.contains(".cpp(true)\n"));
+        assert!(!build_rs.contains(".compiler("));
+    }
+}
@@ -0,0 +1,481 @@
+//! Resumable runs (`--resume`)
+//!
+//! Running every test can take hours, so with `--resume <file>` the run keeps a work queue:
+//! a file listing the id of every test it's going to run, and the result of every test as
+//! soon as it's done. If the run gets interrupted, running again with the same file skips
+//! everything that already finished, and the final report still covers every test, with the
+//! results of the skipped ones read back from the file.
+//!
+//! The file is json lines: a [`QueueHeader`][] and then [`QueueEntry`][]s, which are only
+//! ever appended (the last entry for an id wins), so killing abi-cafe at any point costs at
+//! most the tests that were still running.
+//!
+//! Test ids cover the options that distinguish tests, but not everything that changes
+//! their results (like the version of rustc), so the header also has a [`RunFingerprint`][]
+//! of the rest, and a queue file is only resumed by a run with the same one.
+
+use std::io::Write;
+use std::sync::Mutex;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::HarnessError;
+use crate::harness::report::*;
+use crate::*;
+
+/// The settings that change the results of tests without changing their ids
+/// (everything else is either part of the ids, or only changes how results are reported)
+const FINGERPRINT_CONFIG: &[&str] = &[
+    "instrument",
+    "riscv_float_abi",
+    "arm_float_abi",
+    "rustc_codegen_backends",
+    "custom_c_toolchains",
+    "run_env",
+    "runner",
+    "disable_builtin_rules",
+    "check_dwarf",
+];
+
+/// The first line of a queue file
+#[derive(Debug, Serialize, Deserialize)]
+struct QueueHeader {
+    /// See [`REPORT_SCHEMA_VERSION`][]
+    schema_version: u32,
+    /// The target the tests are run on (results for another target can't be reused)
+    target: String,
+    fingerprint: RunFingerprint,
+}
+
+/// Everything about a run that has to match for its results to be reused
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunFingerprint {
+    /// The version of each toolchain that's paired up in the run
+    pub toolchain_versions: SortedMap<String, Option<String>>,
+    /// The [`FINGERPRINT_CONFIG`][] settings of the run, as they appear in `--embed-config`
+    pub config: SortedMap<String, serde_json::Value>,
+}
+
+impl RunFingerprint {
+    pub fn new(cfg: &Config, toolchain_versions: SortedMap<String, Option<String>>) -> Self {
+        let embedded = embedded_config(cfg);
+        let config = FINGERPRINT_CONFIG
+            .iter()
+            .map(|&name| (name.to_owned(), embedded[name].clone()))
+            .collect();
+        Self {
+            toolchain_versions,
+            config,
+        }
+    }
+
+    /// What this run does differently from the `old` one, if anything
+    ///
+    /// Toolchains that only one of the runs has don't count: none of the tests they're
+    /// in are in the other run anyway.
+    fn mismatch(&self, old: &RunFingerprint) -> Option<String> {
+        let differs = |what: &str, old: String, new: String| {
+            (old != new).then(|| format!("{what} was {old} in the earlier run, but it's {new} now"))
+        };
+        let show_version =
+            |version: &Option<String>| version.clone().unwrap_or_else(|| "unknown".to_owned());
+        let show_setting = |setting: Option<&serde_json::Value>| {
+            setting.map_or_else(|| "missing".to_owned(), |val| val.to_string())
+        };
+        let versions = self.toolchain_versions.iter().find_map(|(id, version)| {
+            let old_version = old.toolchain_versions.get(id)?;
+            differs(
+                &format!("the version of {id}"),
+                show_version(old_version),
+                show_version(version),
+            )
+        });
+        versions.or_else(|| {
+            FINGERPRINT_CONFIG.iter().find_map(|&name| {
+                differs(
+                    name,
+                    show_setting(old.config.get(name)),
+                    show_setting(self.config.get(name)),
+                )
+            })
+        })
+    }
+}
+
+/// Every other line of a queue file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+enum QueueEntry {
+    /// This test is part of the run, but hasn't finished yet
+    Pending { id: String },
+    /// This test finished, and this is its whole [`TestReport`][]
+    Done {
+        id: String,
+        report: serde_json::Value,
+    },
+}
+
+/// The parts of a [`TestReport`][] we read back for finished tests
+/// (the rest of it, like the results, is just reported as it was)
+#[derive(Debug, Clone, Deserialize)]
+struct QueuedReport {
+    conclusion: TestConclusion,
+    skip_reason: Option<SkipReason>,
+    could_be: TestRulesPattern,
+    #[serde(default)]
+    source: Option<String>,
+    results: serde_json::Value,
+}
+
+/// What an existing queue file says
+#[derive(Debug)]
+struct ParsedQueue {
+    header: QueueHeader,
+    /// Every test in the file, and its report if it's done
+    queued: SortedMap<String, Option<QueuedReport>>,
+    /// Whether the last line was cut short (and should be dropped)
+    cut_short: bool,
+}
+
+impl ParsedQueue {
+    fn parse(path: &Utf8Path, contents: &str) -> Result<Self, String> {
+        let mut lines = contents.lines().peekable();
+        let header = lines.next().ok_or_else(|| "it's empty".to_owned())?;
+        let header: QueueHeader = serde_json::from_str(header).map_err(|e| e.to_string())?;
+        let mut queued = SortedMap::new();
+        let mut cut_short = false;
+        while let Some(line) = lines.next() {
+            let entry = serde_json::from_str(line).and_then(|entry| match entry {
+                QueueEntry::Pending { id } => Ok((id, None)),
+                QueueEntry::Done { id, report } => Ok((id, Some(serde_json::from_value(report)?))),
+            });
+            match entry {
+                Ok((id, None)) => {
+                    queued.entry(id).or_insert(None);
+                }
+                Ok((id, Some(report))) => {
+                    queued.insert(id, Some(report));
+                }
+                // The last line can be cut short if we were killed while writing it
+                Err(_) if lines.peek().is_none() => {
+                    warn!("ignoring the unfinished last line of {path}");
+                    cut_short = true;
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(Self {
+            header,
+            queued,
+            cut_short,
+        })
+    }
+}
+
+/// The queue file of a run, and the tests it already finished
+pub struct ResumeQueue {
+    path: Utf8PathBuf,
+    file: Mutex<std::fs::File>,
+    done: SortedMap<String, QueuedReport>,
+}
+
+impl ResumeQueue {
+    /// Open the queue file at `path` for a run of these tests, creating it if it doesn't exist
+    pub fn open(
+        path: &Utf8Path,
+        target: &str,
+        fingerprint: RunFingerprint,
+        ids: &[String],
+    ) -> Result<Self, HarnessError> {
+        let queue_error = |details: String| HarnessError::ResumeQueue {
+            path: path.to_string(),
+            details,
+        };
+
+        let run_ids = ids.iter().collect::<std::collections::BTreeSet<_>>();
+        let mut queued = SortedMap::<String, Option<QueuedReport>>::new();
+        let mut cut_short = false;
+        let existing = match std::fs::read_to_string(path) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(queue_error(e.to_string())),
+        };
+        if let Some(contents) = &existing {
+            let parsed = ParsedQueue::parse(path, contents).map_err(queue_error)?;
+            let header = parsed.header;
+            if header.schema_version != REPORT_SCHEMA_VERSION {
+                return Err(queue_error(format!(
+                    "it has schema_version {}, but this abi-cafe only understands {REPORT_SCHEMA_VERSION}",
+                    header.schema_version
+                )));
+            }
+            if header.target != target {
+                return Err(queue_error(format!(
+                    "it's for {}, but this run is for {target}",
+                    header.target
+                )));
+            }
+            if let Some(mismatch) = fingerprint.mismatch(&header.fingerprint) {
+                return Err(queue_error(format!(
+                    "{mismatch}, so its results don't apply (delete it to start over)"
+                )));
+            }
+            queued = parsed.queued;
+            cut_short = parsed.cut_short;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| queue_error(e.to_string()))?;
+        let mut new_lines = String::new();
+        match &existing {
+            None => {
+                let header = QueueHeader {
+                    schema_version: REPORT_SCHEMA_VERSION,
+                    target: target.to_owned(),
+                    fingerprint,
+                };
+                new_lines.push_str(&serde_json::to_string(&header).unwrap());
+                new_lines.push('\n');
+            }
+            Some(contents) if cut_short => {
+                // Drop the line that was cut short, so it doesn't end up in the middle
+                let complete = contents.trim_end_matches(|c| c != '\n');
+                file.set_len(complete.len() as u64)
+                    .map_err(|e| queue_error(e.to_string()))?;
+            }
+            Some(contents) if !contents.ends_with('\n') => new_lines.push('\n'),
+            Some(_) => {}
+        }
+        for id in ids {
+            if !queued.contains_key(id) {
+                let entry = QueueEntry::Pending { id: id.clone() };
+                new_lines.push_str(&serde_json::to_string(&entry).unwrap());
+                new_lines.push('\n');
+            }
+        }
+        file.write_all(new_lines.as_bytes())
+            .map_err(|e| queue_error(e.to_string()))?;
+
+        let stale = queued.keys().filter(|id| !run_ids.contains(id)).count();
+        if stale > 0 {
+            warn!("{stale} tests in {path} aren't part of this run (did the flags change?)");
+        }
+        let done = queued
+            .into_iter()
+            .filter_map(|(id, result)| Some((id, result?)))
+            .filter(|(id, _)| run_ids.contains(id))
+            .collect::<SortedMap<_, _>>();
+        if existing.is_some() {
+            info!(
+                "resuming from {path}: {} tests already done, {} to go",
+                done.len(),
+                ids.len() - done.len()
+            );
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            file: Mutex::new(file),
+            done,
+        })
+    }
+
+    /// The report of this test, if an earlier run already finished it
+    pub fn resumed_report(&self, id: &str, key: TestKey, rules: TestRules) -> Option<TestReport> {
+        let report = self.done.get(id)?;
+        let mut results = TestRunResults::new(key.clone(), rules.clone());
+        results.resumed = Some(report.results.clone());
+        Some(TestReport {
+            id: id.to_owned(),
+            results,
+            key,
+            rules,
+            conclusion: report.conclusion,
+            skip_reason: report.skip_reason,
+            could_be: report.could_be.clone(),
+            interop_only: false,
+            lto_only: false,
+            source: report.source.clone(),
+            resumed: true,
+        })
+    }
+
+    /// Record that this test is done
+    pub fn record(&self, report: &TestReport) {
+        let entry = QueueEntry::Done {
+            id: report.id.clone(),
+            report: serde_json::to_value(report).expect("failed to serialize report!?"),
+        };
+        let mut line = serde_json::to_string(&entry).unwrap();
+        line.push('\n');
+        // Losing an entry only means running that test again, so don't stop the run over it
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("couldn't record {} in {}: {e}", report.id, self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    fn fingerprint(rustc_version: &str) -> RunFingerprint {
+        RunFingerprint {
+            toolchain_versions: SortedMap::from_iter([
+                ("cc".to_owned(), None),
+                ("rustc".to_owned(), Some(rustc_version.to_owned())),
+            ]),
+            config: SortedMap::from_iter([("runner".to_owned(), serde_json::Value::Null)]),
+        }
+    }
+
+    fn header_line(fingerprint: RunFingerprint) -> String {
+        serde_json::to_string(&QueueHeader {
+            schema_version: REPORT_SCHEMA_VERSION,
+            target: TARGET.to_owned(),
+            fingerprint,
+        })
+        .unwrap()
+    }
+
+    fn done_line(report: &TestReport) -> String {
+        serde_json::to_string(&QueueEntry::Done {
+            id: report.id.clone(),
+            report: serde_json::to_value(report).unwrap(),
+        })
+        .unwrap()
+    }
+
+    fn pending_line(id: &str) -> String {
+        serde_json::to_string(&QueueEntry::Pending { id: id.to_owned() }).unwrap()
+    }
+
+    /// A fresh path for a queue file
+    fn queue_path(name: &str) -> Utf8PathBuf {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let path = dir.join(format!("abi-cafe-{}-{name}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn last_entry_wins() {
+        let failed = test_report("simple", "cc", "rustc", TestConclusion::Failed);
+        let passed = test_report("simple", "cc", "rustc", TestConclusion::Passed);
+        let contents = [
+            header_line(fingerprint("1.80.0")),
+            pending_line(&failed.id),
+            done_line(&failed),
+            done_line(&passed),
+            // Pending again (a later run that was killed right away) doesn't undo it
+            pending_line(&failed.id),
+        ]
+        .join("\n");
+
+        let parsed = ParsedQueue::parse(Utf8Path::new("queue.jsonl"), &contents).unwrap();
+        assert!(!parsed.cut_short);
+        assert_eq!(parsed.queued.len(), 1);
+        let report = parsed.queued[&failed.id].as_ref().unwrap();
+        assert_eq!(report.conclusion, TestConclusion::Passed);
+    }
+
+    #[test]
+    fn truncated_last_line() {
+        let first = test_report("simple", "cc", "rustc", TestConclusion::Passed);
+        let second = test_report("simple", "rustc", "cc", TestConclusion::Failed);
+        let cut_short = done_line(&second);
+        let cut_short = &cut_short[..cut_short.len() / 2];
+        let contents = format!(
+            "{}\n{}\n{}\n{}\n{cut_short}",
+            header_line(fingerprint("1.80.0")),
+            pending_line(&first.id),
+            pending_line(&second.id),
+            done_line(&first),
+        );
+
+        let parsed = ParsedQueue::parse(Utf8Path::new("queue.jsonl"), &contents).unwrap();
+        assert!(parsed.cut_short);
+        assert!(parsed.queued[&first.id].is_some());
+        assert!(parsed.queued[&second.id].is_none());
+        // Only the last line gets to be broken
+        let broken = format!("{contents}\n{}", pending_line(&first.id));
+        assert!(ParsedQueue::parse(Utf8Path::new("queue.jsonl"), &broken).is_err());
+
+        // Resuming drops the broken line and carries on after it
+        let path = queue_path("truncated");
+        std::fs::write(&path, &contents).unwrap();
+        let ids = [first.id.clone(), second.id.clone()];
+        let queue = ResumeQueue::open(&path, TARGET, fingerprint("1.80.0"), &ids).unwrap();
+        let resumed = queue
+            .resumed_report(&first.id, first.key.clone(), first.rules.clone())
+            .unwrap();
+        assert!(resumed.resumed);
+        assert_eq!(resumed.conclusion, TestConclusion::Passed);
+        assert!(queue
+            .resumed_report(&second.id, second.key.clone(), second.rules.clone())
+            .is_none());
+        queue.record(&second);
+        drop(queue);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let parsed = ParsedQueue::parse(&path, &contents).unwrap();
+        assert!(!parsed.cut_short);
+        let report = parsed.queued[&second.id].as_ref().unwrap();
+        assert_eq!(report.conclusion, TestConclusion::Failed);
+
+        // The whole report was kept, and gets reported as it was
+        let resumed = serde_json::to_value(&resumed).unwrap();
+        let original = serde_json::to_value(&first).unwrap();
+        assert_eq!(resumed["results"], original["results"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fingerprint_mismatch() {
+        let path = queue_path("mismatch");
+        let ids = ["simple::conv_c::repr_c::cc_calls_rustc".to_owned()];
+        drop(ResumeQueue::open(&path, TARGET, fingerprint("1.80.0"), &ids).unwrap());
+
+        // Same everything is fine, and so is a toolchain the earlier run didn't have
+        drop(ResumeQueue::open(&path, TARGET, fingerprint("1.80.0"), &ids).unwrap());
+        let mut more_toolchains = fingerprint("1.80.0");
+        more_toolchains
+            .toolchain_versions
+            .insert("gcc".to_owned(), Some("14".to_owned()));
+        drop(ResumeQueue::open(&path, TARGET, more_toolchains, &ids).unwrap());
+
+        let Err(HarnessError::ResumeQueue { details, .. }) =
+            ResumeQueue::open(&path, TARGET, fingerprint("1.81.0"), &ids)
+        else {
+            panic!("resumed with a different rustc");
+        };
+        assert!(
+            details.contains("the version of rustc was 1.80.0"),
+            "{details}"
+        );
+
+        let mut runner = fingerprint("1.80.0");
+        runner
+            .config
+            .insert("runner".to_owned(), "qemu-x86_64".into());
+        let Err(HarnessError::ResumeQueue { details, .. }) =
+            ResumeQueue::open(&path, TARGET, runner, &ids)
+        else {
+            panic!("resumed with a different runner");
+        };
+        assert!(details.contains("runner was null"), "{details}");
+
+        assert!(
+            ResumeQueue::open(&path, "aarch64-apple-darwin", fingerprint("1.80.0"), &ids).is_err()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,9 +1,10 @@
 //! The runtime actual types and functions that are injected into
 //! compiled tests.
 
+use camino::Utf8Path;
 use serde::Deserialize;
 use serde::Serialize;
-use tracing::info;
+use tracing::{debug, info};
 
 use crate::error::*;
 use crate::harness::report::*;
@@ -26,7 +27,14 @@ impl TestHarness {
         linked_test: &LinkOutput,
     ) -> Result<RunOutput, RunError> {
         let test = self.test(&key.test);
-        let output = run_bin_test(test, linked_test)?;
+        let output = run_bin_test(
+            test,
+            linked_test,
+            &self.run_env,
+            self.runner.as_deref(),
+            self.toolchains.instrument,
+            &self.commands,
+        )?;
         Ok(output)
     }
 }
@@ -43,11 +51,15 @@ pub struct TestBuffer {
 #[derive(Debug, Serialize, Default)]
 pub struct FuncBuffer {
     pub vals: Vec<ValBuffer>,
+    /// How many values have been written to this func so far
+    pub num_writes: usize,
 }
 
 #[derive(Debug, Serialize, Default)]
 pub struct ValBuffer {
     pub bytes: Vec<u8>,
+    /// The order this value was written in, relative to the other writes in its func
+    pub write_idx: Option<usize>,
 }
 
 impl TestBuffer {
@@ -76,15 +88,19 @@ impl TestBuffer {
 // From the test's perspective the WriteBuffers are totally opaque.
 pub type SetFuncCallback = unsafe extern "C" fn(&mut TestBuffer, u32) -> ();
 pub type WriteValCallback = unsafe extern "C" fn(&mut TestBuffer, u32, *const u8, u32) -> ();
-pub type TestInit =
-    unsafe extern "C" fn(SetFuncCallback, WriteValCallback, &mut TestBuffer, &mut TestBuffer) -> ();
+pub type TestInit = unsafe extern "C" fn(
+    SetFuncCallback,
+    WriteValCallback,
+    &mut TestBuffer,
+    &mut TestBuffer,
+    &mut TestBuffer,
+) -> ();
 
 pub unsafe extern "C" fn set_func(test: &mut TestBuffer, func: u32) {
     let idx = func as usize;
     // If things aren't in-order, add empty entries to make the index exist
     let new_len = test.funcs.len().max(idx + 1);
-    test.funcs
-        .resize_with(new_len, || FuncBuffer { vals: vec![] });
+    test.funcs.resize_with(new_len, FuncBuffer::default);
     test.cur_func = Some(idx);
 }
 
@@ -115,8 +131,7 @@ fn write_val_inner(test: &mut TestBuffer, val_idx: u32, data: &[u8]) {
     // Get the value of the function (making room for it if need be)
     let val_idx = val_idx as usize;
     let new_len = func.vals.len().max(val_idx + 1);
-    func.vals
-        .resize_with(new_len, || ValBuffer { bytes: vec![] });
+    func.vals.resize_with(new_len, ValBuffer::default);
     let val = &mut func.vals[val_idx];
 
     // Push all the bytes of the value
@@ -125,6 +140,8 @@ fn write_val_inner(test: &mut TestBuffer, val_idx: u32, data: &[u8]) {
         return;
     }
     val.bytes = data.to_vec();
+    val.write_idx = Some(func.num_writes);
+    func.num_writes += 1;
 }
 
 /// Run the test!
@@ -134,6 +151,7 @@ fn run_dylib_test(test_dylib: &LinkOutput) -> Result<RunOutput, RunError> {
     // Initialize all the buffers the tests will write to
     let mut caller_vals = TestBuffer::new();
     let mut callee_vals = TestBuffer::new();
+    let mut callee_padding = TestBuffer::new();
 
     unsafe {
         info!(
@@ -146,23 +164,38 @@ fn run_dylib_test(test_dylib: &LinkOutput) -> Result<RunOutput, RunError> {
         let do_test: libloading::Symbol<TestInit> = lib.get(b"test_start")?;
         debug!("calling harness dynamic function");
         // Actually run the test!
-        do_test(set_func, write_val, &mut caller_vals, &mut callee_vals);
+        do_test(
+            set_func,
+            write_val,
+            &mut caller_vals,
+            &mut callee_vals,
+            &mut callee_padding,
+        );
 
         // Finalize the buffers (clear all the pending values).
         caller_vals.finish_tests()?;
         callee_vals.finish_tests()?;
+        callee_padding.finish_tests()?;
     }
 
     Ok(RunOutput {
         caller_funcs: caller_vals,
         callee_funcs: callee_vals,
+        callee_padding,
     })
 }
 
 /// Run the test!
 ///
 /// See the README for a high-level description of this design.
-fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, RunError> {
+fn run_bin_test(
+    test: Arc<Test>,
+    test_bin: &LinkOutput,
+    run_env: &SortedMap<String, String>,
+    runner: Option<&str>,
+    instrument: Option<Instrument>,
+    commands: &CommandLog,
+) -> Result<RunOutput, RunError> {
     #[derive(Deserialize, Serialize)]
     #[serde(rename_all = "kebab-case")]
     #[serde(tag = "info")]
@@ -184,11 +217,13 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
     enum HarnessSide {
         Caller,
         Callee,
+        CalleePadding,
     }
 
     // Initialize all the buffers the tests will write to
     let mut caller_vals = TestBuffer::new();
     let mut callee_vals = TestBuffer::new();
+    let mut callee_padding = TestBuffer::new();
     let mut finished_clean = false;
 
     unsafe {
@@ -199,7 +234,22 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
         // Load the dylib of the test, and get its test_start symbol
 
         debug!("loading     {}", &test_bin.test_bin);
-        let mut cmd = Command::new(&test_bin.test_bin);
+        // Run the binary with `<runner> <args..> <instrument> <args..> <test_bin>`
+        let wrapper: &[&str] = match instrument {
+            Some(Instrument::Valgrind) => &[
+                "valgrind",
+                "--quiet",
+                "--track-origins=yes",
+                INSTRUMENT_EXIT_ARG,
+            ],
+            Some(Instrument::Msan) | None => &[],
+        };
+        let mut cmd = runner_command(runner, wrapper, &test_bin.test_bin);
+        if instrument == Some(Instrument::Msan) {
+            cmd.env("MSAN_OPTIONS", INSTRUMENT_EXIT_OPTION);
+        }
+        cmd.envs(run_env);
+        commands.record(&cmd);
         let output = cmd.output().map_err(|e| RunError::ExecError {
             bin: test_bin.test_bin.clone(),
             e,
@@ -215,6 +265,7 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
                     let buf = match id {
                         HarnessSide::Caller => &mut caller_vals,
                         HarnessSide::Callee => &mut callee_vals,
+                        HarnessSide::CalleePadding => &mut callee_padding,
                     };
                     set_func(buf, func)
                 }
@@ -222,6 +273,7 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
                     let buf = match id {
                         HarnessSide::Caller => &mut caller_vals,
                         HarnessSide::Callee => &mut callee_vals,
+                        HarnessSide::CalleePadding => &mut callee_padding,
                     };
                     write_val_inner(buf, val, &bytes)
                 }
@@ -230,17 +282,26 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
                 }
             }
         }
+        if let Some(tool) = instrument.filter(|_| output.status.code() == Some(INSTRUMENT_EXIT)) {
+            return Err(RunError::Instrumented {
+                tool,
+                report: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
         if !output.status.success() {
             let (caller_func_idx, caller_val_idx, caller_func) = best_vals(&test, &caller_vals);
             let (callee_func_idx, callee_val_idx, callee_func) = best_vals(&test, &callee_vals);
+            let in_call =
+                caller_vals.cur_func.is_some() && caller_vals.cur_func != callee_vals.cur_func;
             return Err(RunError::BadExit {
-                status: output.status,
+                exit: TestExit::from_status(output.status),
                 caller_func_idx,
                 caller_val_idx,
                 caller_func,
                 callee_func_idx,
                 callee_val_idx,
                 callee_func,
+                in_call,
             });
         }
     }
@@ -254,13 +315,101 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
 
     caller_vals.finish_tests()?;
     callee_vals.finish_tests()?;
+    callee_padding.finish_tests()?;
 
     Ok(RunOutput {
         caller_funcs: caller_vals,
         callee_funcs: callee_vals,
+        callee_padding,
     })
 }
 
+/// The command to run a binary with `--runner` (if any), and then `wrapper`
+pub(crate) fn runner_command(runner: Option<&str>, wrapper: &[&str], bin: &Utf8Path) -> Command {
+    // The CLI already checked the runner's quotes
+    let mut parts = split_command_line(runner.unwrap_or_default()).unwrap_or_default();
+    parts.extend(wrapper.iter().map(|arg| arg.to_string()));
+    if let Some((program, args)) = parts.split_first() {
+        let mut cmd = Command::new(program);
+        cmd.args(args).arg(bin);
+        cmd
+    } else {
+        Command::new(bin)
+    }
+}
+
+/// Split a command line like `--runner`'s into its args, like a shell would
+///
+/// Args are separated by whitespace, unless it's quoted (`'...'` is taken literally,
+/// and `\` escapes `"` and `\` inside `"..."`) or escaped with `\`. This doesn't do any
+/// other shell things, like expanding variables.
+pub fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut arg = None::<String>;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("unterminated ' in {line}")),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => arg.extend(['\\', c]),
+                            None => return Err(format!("unterminated \" in {line}")),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("unterminated \" in {line}")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => arg.get_or_insert_with(String::new).push(c),
+                None => return Err(format!("trailing \\ in {line}")),
+            },
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// The exit code `--instrument` tools are told to use when they find something
+///
+/// Anything distinctive works, it just can't be a code a test might exit with by itself.
+const INSTRUMENT_EXIT: i32 = 86;
+const INSTRUMENT_EXIT_ARG: &str = "--error-exitcode=86";
+const INSTRUMENT_EXIT_OPTION: &str = "exitcode=86";
+
+impl TestExit {
+    /// Classify how a test binary died
+    pub fn from_status(status: std::process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return TestExit::Signal(signal);
+            }
+        }
+        match status.code() {
+            Some(code) => TestExit::Code(code),
+            None => TestExit::Unknown,
+        }
+    }
+}
+
 fn best_vals(test: &Test, vals: &TestBuffer) -> (usize, usize, String) {
     let default_funcs = FuncBuffer::default();
     let func_idx = vals.cur_func.unwrap_or(0);
@@ -270,3 +419,28 @@ fn best_vals(test: &Test, vals: &TestBuffer) -> (usize, usize, String) {
 
     (func_idx, val_idx, func_name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_runners() {
+        let split = |line| split_command_line(line).unwrap();
+        assert_eq!(split(""), Vec::<String>::new());
+        assert_eq!(
+            split("  qemu-aarch64 -L /usr/aarch64-linux-gnu "),
+            ["qemu-aarch64", "-L", "/usr/aarch64-linux-gnu"]
+        );
+        assert_eq!(
+            split(r#"ssh dev "cd '/tmp/my tests' && \"$0\"" 'a b'c"#),
+            ["ssh", "dev", r#"cd '/tmp/my tests' && "$0""#, "a bc"]
+        );
+        assert_eq!(split(r"run\ me '' x\\y"), ["run me", "", r"x\y"]);
+        assert_eq!(split(r#""a\b""#), [r"a\b"]);
+
+        assert!(split_command_line("sh -c 'oops").is_err());
+        assert!(split_command_line(r#"sh -c "oops"#).is_err());
+        assert!(split_command_line(r"oops\").is_err());
+    }
+}
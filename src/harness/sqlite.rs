@@ -0,0 +1,169 @@
+//! Recording results in an SQLite database (`--sqlite`)
+//!
+//! A json report is a snapshot of one run. To answer questions about history ("which
+//! tests flipped from passing to failing last month?") every run appends a row per test
+//! to a `results` table, which you can then query however you like.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use camino::Utf8Path;
+use rusqlite::{params, Connection};
+use tracing::info;
+
+use crate::error::*;
+use crate::harness::report::*;
+
+/// The table every run appends to
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS results (
+    timestamp INTEGER NOT NULL,
+    revision TEXT,
+    target TEXT NOT NULL,
+    id TEXT NOT NULL,
+    test TEXT NOT NULL,
+    caller TEXT NOT NULL,
+    callee TEXT NOT NULL,
+    convention TEXT NOT NULL,
+    repr TEXT NOT NULL,
+    conclusion TEXT NOT NULL,
+    interop_only INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS results_by_id ON results (id, timestamp);
+";
+
+impl FullReport {
+    /// Append a row for every test in this report to the database at `path`
+    ///
+    /// `revision` is whatever the user wants the run to be filed under (usually the
+    /// commit of the compiler being tested).
+    pub fn record_sqlite(
+        &self,
+        path: &Utf8Path,
+        revision: Option<&str>,
+    ) -> Result<(), HarnessError> {
+        let sqlite_error = |e: rusqlite::Error| HarnessError::Sqlite {
+            path: path.to_string(),
+            details: e.to_string(),
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        let mut db = Connection::open(path).map_err(sqlite_error)?;
+        self.insert_sqlite(&mut db, timestamp, revision)
+            .map_err(sqlite_error)?;
+        info!("recorded {} results in {path}", self.tests.len());
+        Ok(())
+    }
+
+    /// Append a row for every test in this report (all in one transaction)
+    fn insert_sqlite(
+        &self,
+        db: &mut Connection,
+        timestamp: u64,
+        revision: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        db.execute_batch(SCHEMA)?;
+        let transaction = db.transaction()?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO results VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for test in &self.tests {
+                let key = &test.key;
+                insert.execute(params![
+                    timestamp,
+                    revision,
+                    self.target,
+                    test.id,
+                    key.test,
+                    key.caller,
+                    key.callee,
+                    key.options.convention.to_string(),
+                    key.options.repr.to_string(),
+                    test.conclusion.to_string(),
+                    test.interop_only,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+
+    #[test]
+    fn round_trip() {
+        let mut quoted = test_report("it's quoted", "cc", "rustc", TestConclusion::Failed);
+        quoted.interop_only = true;
+        let report = full_report(vec![
+            test_report("simple", "rustc", "cc", TestConclusion::Passed),
+            quoted,
+        ]);
+
+        let mut db = Connection::open_in_memory().unwrap();
+        report
+            .insert_sqlite(&mut db, 1234, Some("abc'def"))
+            .unwrap();
+        // Appending again keeps the old rows
+        report.insert_sqlite(&mut db, 5678, None).unwrap();
+
+        let mut select = db
+            .prepare(
+                "SELECT timestamp, revision, target, id, test, caller, callee, convention, repr, conclusion, interop_only
+                FROM results ORDER BY timestamp, id",
+            )
+            .unwrap();
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, String>(7)?,
+                    row.get::<_, String>(8)?,
+                    row.get::<_, String>(9)?,
+                    row.get::<_, bool>(10)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(rows.len(), 4);
+        let (
+            timestamp,
+            revision,
+            target,
+            id,
+            test,
+            caller,
+            callee,
+            convention,
+            repr,
+            conclusion,
+            interop_only,
+        ) = &rows[0];
+        assert_eq!(*timestamp, 1234);
+        assert_eq!(revision.as_deref(), Some("abc'def"));
+        assert_eq!(target, &report.target);
+        assert_eq!(id, "it's quoted::conv_c::repr_c::cc_calls_rustc");
+        assert_eq!(test, "it's quoted");
+        assert_eq!((caller.as_str(), callee.as_str()), ("cc", "rustc"));
+        assert_eq!((convention.as_str(), repr.as_str()), ("c", "c"));
+        assert_eq!(conclusion, "failed");
+        assert!(*interop_only);
+        assert_eq!(rows[1].4, "simple");
+        assert_eq!(rows[1].9, "passed");
+        assert!(!rows[1].10);
+        assert_eq!(rows[2].0, 5678);
+        assert_eq!(rows[2].1, None);
+    }
+}
@@ -2,10 +2,13 @@ use std::sync::Arc;
 
 use crate::harness::vals::{ValueGeneratorKind, ValueTree};
 use crate::toolchains::*;
-use kdl_script::{parse::LangRepr, types::FuncIdx, DefinitionGraph, PunEnv, TypedProgram};
+use kdl_script::types::{Arg, Func, FuncIdx, PrimitiveTy, Ty, TyIdx};
+use kdl_script::{
+    parse::LangRepr, Definition, DefinitionGraph, LayoutTarget, PunEnv, TypedProgram,
+};
 use serde::Serialize;
 
-use crate::{error::GenerateError, CliParseError};
+use crate::{error::GenerateError, error::UnsupportedError, CliParseError};
 
 pub type ToolchainId = String;
 pub type TestId = String;
@@ -38,6 +41,8 @@ pub type TestId = String;
 pub struct Test {
     /// Name of the test (file stem)
     pub name: String,
+    /// The kdl-script source of the test (after procgen)
+    pub source: String,
     /// Parsed and Typechecked kdl-script program
     pub types: Arc<TypedProgram>,
 }
@@ -58,12 +63,184 @@ impl std::ops::Deref for TestWithVals {
 /// Options for a test
 #[derive(Clone, Debug, Serialize)]
 pub struct TestOptions {
-    /// The calling convention
+    /// The calling convention on the caller side
     pub convention: CallingConvention,
+    /// The calling convention on the callee side (usually the same as `convention`)
+    pub callee_convention: CallingConvention,
     pub functions: FunctionSelector,
     pub val_writer: WriteImpl,
     pub val_generator: ValueGeneratorKind,
+    /// The repr of types on the caller side
     pub repr: LangRepr,
+    /// The repr of types on the callee side (usually the same as `repr`)
+    pub callee_repr: LangRepr,
+    /// Whether the callee should write new values through reference inputs,
+    /// which the caller then checks after the call
+    pub inout_refs: bool,
+    /// Whether the caller should pass a pointer to a by-value input as the reference
+    /// input of the same type next to it, which the callee writes through before it
+    /// reports the by-value copy (which must be unaffected)
+    pub alias_refs: bool,
+    /// How many links of tail calls the callee should go through before doing its job
+    pub tail_call_chain: usize,
+    /// How deep a chain of calls the callee should make before reporting its outputs
+    pub nested_calls: usize,
+    /// Whether the callee should unwind out of every function (after reporting its inputs)
+    /// instead of returning, which the caller must catch
+    pub unwind: bool,
+    /// Deliberately break the test to check that we notice
+    pub fault: Option<FaultInjection>,
+    /// Whether Rust should declare the fields of repr(Rust) structs in a shuffled order
+    /// (both sides shuffle the same way, and must still agree on the layout)
+    pub shuffle_fields: bool,
+    /// Whether the caller and callee are shared libraries the harness `dlopen`s
+    /// (so every call goes through the PLT/GOT), instead of being statically linked
+    pub dlopen: bool,
+    /// The linker the test is linked with
+    pub linker: Linker,
+    /// How the caller calls the callee's functions
+    pub call_path: CallPath,
+    /// The byte the caller fills the padding of its inputs with, which the callee reports back
+    pub pad_byte: Option<u8>,
+    /// Whether both sides should put 128-bit integers through an operation that
+    /// calls a runtime library helper (a division) before reporting them
+    pub through_helpers: bool,
+    /// The rounding mode the caller runs the test in, if both sides should put every
+    /// f32/f64 through a rounding-sensitive operation before reporting it
+    pub rounding_mode: Option<RoundingMode>,
+    /// Whether the caller and callee are optimized together at link time
+    pub lto: Lto,
+    /// The extra target features the caller is compiled with
+    pub target_features: TargetFeatures,
+    /// The extra target features the callee is compiled with (usually the same as `target_features`)
+    pub callee_target_features: TargetFeatures,
+}
+/// A plain `conv_c::repr_c` test of every function, with everything else off
+impl Default for TestOptions {
+    fn default() -> Self {
+        TestOptions {
+            convention: CallingConvention::C,
+            callee_convention: CallingConvention::C,
+            functions: FunctionSelector::All,
+            val_writer: WriteImpl::HarnessCallback,
+            val_generator: ValueGeneratorKind::Graffiti,
+            repr: LangRepr::C,
+            callee_repr: LangRepr::C,
+            inout_refs: false,
+            alias_refs: false,
+            tail_call_chain: 0,
+            nested_calls: 0,
+            unwind: false,
+            fault: None,
+            shuffle_fields: false,
+            dlopen: false,
+            linker: Linker::Default,
+            call_path: CallPath::Direct,
+            pad_byte: None,
+            through_helpers: false,
+            rounding_mode: None,
+            lto: Lto::Off,
+            target_features: TargetFeatures::default(),
+            callee_target_features: TargetFeatures::default(),
+        }
+    }
+}
+impl TestOptions {
+    /// The repr a given side of the call should use
+    pub fn repr_for(&self, call_side: CallSide) -> LangRepr {
+        match call_side {
+            CallSide::Caller => self.repr,
+            CallSide::Callee => self.callee_repr,
+        }
+    }
+
+    /// The target features a given side of the call should be compiled with
+    pub fn target_features_for(&self, call_side: CallSide) -> &TargetFeatures {
+        match call_side {
+            CallSide::Caller => &self.target_features,
+            CallSide::Callee => &self.callee_target_features,
+        }
+    }
+
+    /// The codegen options a given side of the call should be compiled with
+    pub fn codegen_for(&self, call_side: CallSide) -> CodegenOptions {
+        CodegenOptions {
+            lto: self.lto,
+            target_features: self.target_features_for(call_side).clone(),
+            cf_protection: self.call_path == CallPath::CfProtection,
+        }
+    }
+
+    /// The calling convention a given side of the call should use
+    pub fn convention_for(&self, call_side: CallSide) -> CallingConvention {
+        match call_side {
+            CallSide::Caller => self.convention,
+            CallSide::Callee => self.callee_convention,
+        }
+    }
+
+    /// Whether a value of this type should be put through a runtime library helper
+    /// before it's reported (see `--through-helpers`)
+    pub fn through_helper(&self, types: &TypedProgram, ty: TyIdx) -> bool {
+        self.through_helpers
+            && matches!(
+                types.realize_ty(ty),
+                Ty::Primitive(PrimitiveTy::I128 | PrimitiveTy::U128)
+            )
+    }
+
+    /// Whether a value of this type should be put through a rounding-sensitive operation
+    /// before it's reported (see `--rounding-mode`)
+    pub fn rounds_float(&self, types: &TypedProgram, ty: TyIdx) -> bool {
+        self.rounding_mode.is_some()
+            && matches!(
+                types.realize_ty(ty),
+                Ty::Primitive(PrimitiveTy::F32 | PrimitiveTy::F64)
+            )
+    }
+
+    /// Get the options one side of the call should actually be generated with
+    ///
+    /// Codegen backends only look at `repr` and `convention`, so this is where we
+    /// hand the callee its own repr/convention when the two sides disagree.
+    /// [`FaultInjection::Align`][] is only for the caller, so the callee doesn't get it.
+    pub fn for_call_side(&self, call_side: CallSide) -> TestOptions {
+        let repr = self.repr_for(call_side);
+        let convention = self.convention_for(call_side);
+        let fault = match (self.fault, call_side) {
+            (Some(FaultInjection::Align), CallSide::Callee) => None,
+            (fault, _) => fault,
+        };
+        TestOptions {
+            repr,
+            callee_repr: repr,
+            convention,
+            callee_convention: convention,
+            fault,
+            ..self.clone()
+        }
+    }
+
+    /// The inputs a given side of the call should declare for a function
+    ///
+    /// This is where [`FaultInjection::ArgCount`][] makes the two sides disagree.
+    pub fn func_inputs<'a>(&self, func: &'a Func, call_side: CallSide) -> &'a [Arg] {
+        match (self.fault, call_side) {
+            (Some(FaultInjection::ArgCount), CallSide::Caller) => {
+                let len = func.inputs.len().saturating_sub(1);
+                &func.inputs[..len]
+            }
+            _ => &func.inputs,
+        }
+    }
+
+    /// The alignment structs should be declared with, if it isn't their own
+    ///
+    /// This is where [`FaultInjection::Align`][] makes the two sides disagree
+    /// (only the caller has the fault, see [`TestOptions::for_call_side`][]).
+    pub fn struct_align(&self) -> Option<u64> {
+        (self.fault == Some(FaultInjection::Align)).then_some(FAULT_STRUCT_ALIGN)
+    }
 }
 impl FunctionSelector {
     pub fn should_write_arg(&self, func_idx: usize, arg_idx: usize) -> bool {
@@ -74,7 +251,7 @@ impl FunctionSelector {
                     false
                 } else {
                     match args {
-                        ArgSelector::All => true,
+                        ArgSelector::All | ArgSelector::Vary { .. } => true,
                         ArgSelector::One { idx, vals: _ } => arg_idx == *idx,
                     }
                 }
@@ -89,7 +266,7 @@ impl FunctionSelector {
                     false
                 } else {
                     match args {
-                        ArgSelector::All => true,
+                        ArgSelector::All | ArgSelector::Vary { .. } => true,
                         ArgSelector::One { idx, vals } => {
                             if arg_idx != *idx {
                                 false
@@ -112,6 +289,20 @@ impl FunctionSelector {
             FunctionSelector::One { idx, args: _ } => vec![*idx],
         }
     }
+
+    /// The args that get real values, if this selection fixes the rest to zero
+    pub fn varied_args(&self) -> Option<VariedArgs> {
+        match self {
+            FunctionSelector::One {
+                idx,
+                args: ArgSelector::Vary { idxs },
+            } => Some(VariedArgs {
+                func: *idx,
+                args: idxs.clone(),
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -123,7 +314,95 @@ pub enum FunctionSelector {
 #[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ArgSelector {
     All,
-    One { idx: usize, vals: ValSelector },
+    One {
+        idx: usize,
+        vals: ValSelector,
+    },
+    /// Write every arg, but only give these ones real values (the rest are all zeros)
+    ///
+    /// Holding every other arg fixed makes it easy to tell which slot is miscompiled.
+    Vary {
+        idxs: Vec<usize>,
+    },
+}
+
+/// The args of a function that [`ArgSelector::Vary`][] gives real values
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VariedArgs {
+    pub func: FuncIdx,
+    pub args: Vec<usize>,
+}
+impl VariedArgs {
+    /// Whether this arg of this function is fixed to zero
+    pub fn is_fixed(&self, func_idx: FuncIdx, arg_idx: usize) -> bool {
+        self.func == func_idx && !self.args.contains(&arg_idx)
+    }
+}
+
+/// A selection from `--select-vals`, which refers to a function by name
+/// so that it can apply to any test that has a function with that name
+#[derive(Clone, Debug, Serialize)]
+pub struct FuncSelection {
+    pub func: String,
+    pub args: ArgSelector,
+}
+impl FuncSelection {
+    /// Get the selection for this test (None if it has no such function)
+    pub fn resolve(&self, types: &TypedProgram) -> Option<FunctionSelector> {
+        let idx = types
+            .all_funcs()
+            .find(|&func| types.realize_func(func).name == *self.func)?;
+        Some(FunctionSelector::One {
+            idx,
+            args: self.args.clone(),
+        })
+    }
+}
+impl std::str::FromStr for FuncSelection {
+    type Err = CliParseError;
+
+    /// Parses `func`, `func:argN`, `func:argN:valM`, or `func:vary:N+M+...`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || {
+            CliParseError::Other(format!(
+                "{s} is not a value selection (expected func, func:argN, func:argN:valM, or func:vary:N+M)"
+            ))
+        };
+        let parse_idx = |prefix: &str, part: &str| -> Result<usize, Self::Err> {
+            part.strip_prefix(prefix)
+                .and_then(|idx| idx.parse().ok())
+                .ok_or_else(bad)
+        };
+        let mut parts = s.split(':');
+        let func = parts
+            .next()
+            .filter(|func| !func.is_empty())
+            .ok_or_else(bad)?;
+        let args = match parts.collect::<Vec<_>>()[..] {
+            [] => ArgSelector::All,
+            ["vary", idxs] => ArgSelector::Vary {
+                idxs: idxs
+                    .split('+')
+                    .map(|idx| parse_idx("", idx))
+                    .collect::<Result<_, _>>()?,
+            },
+            [arg] => ArgSelector::One {
+                idx: parse_idx("arg", arg)?,
+                vals: ValSelector::All,
+            },
+            [arg, val] => ArgSelector::One {
+                idx: parse_idx("arg", arg)?,
+                vals: ValSelector::One {
+                    idx: parse_idx("val", val)?,
+                },
+            },
+            _ => return Err(bad()),
+        };
+        Ok(FuncSelection {
+            func: func.to_owned(),
+            args,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -132,6 +411,482 @@ pub enum ValSelector {
     One { idx: usize },
 }
 
+/// A way to deliberately break a test, so we can check that abi-cafe notices
+///
+/// Tests with a fault injected are expected to fail, so they will only
+/// be reported as failures if abi-cafe *doesn't* catch the problem.
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FaultInjection {
+    /// The caller declares and calls every function without its last input,
+    /// so the callee reads whatever garbage happens to be in that slot.
+    ArgCount,
+    /// The caller declares every struct with [`FAULT_STRUCT_ALIGN`][] alignment,
+    /// so its structs are bigger, more aligned, and passed differently than the callee's.
+    Align,
+}
+impl FaultInjection {
+    /// Every fault (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[Self::ArgCount, Self::Align];
+}
+
+/// The alignment the caller gives its structs with [`FaultInjection::Align`][]
+///
+/// This is more than any primitive needs (so every struct changes), and more than
+/// structs can be to be passed in registers (so how they're passed changes too).
+pub const FAULT_STRUCT_ALIGN: u64 = 64;
+impl std::str::FromStr for FaultInjection {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "argcount" => Ok(Self::ArgCount),
+            "align" => Ok(Self::Align),
+            _ => Err(CliParseError::Other(format!(
+                "{s} is not a fault injection"
+            ))),
+        }
+    }
+}
+impl std::fmt::Display for FaultInjection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::ArgCount => "argcount",
+            Self::Align => "align",
+        };
+        s.fmt(f)
+    }
+}
+
+/// How the caller calls the functions of the callee
+///
+/// Direct and indirect calls can be lowered differently (CFI like `-fcf-protection`
+/// or arm64e pointer authentication checks indirect calls), so a bug can be on just one.
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CallPath {
+    /// By name
+    Direct,
+    /// Through a function pointer the compiler can't see through (a volatile in C,
+    /// and `black_box` in Rust), so it can't turn it back into a direct call
+    Indirect,
+    /// Like `Indirect`, with both sides built with x86's control-flow protection
+    /// (`-fcf-protection=full` and `-Zcf-protection=full`), so every function starts
+    /// with the `endbr64` landing pad an indirect call has to land on
+    CfProtection,
+}
+impl CallPath {
+    /// Every call path (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[Self::Direct, Self::Indirect, Self::CfProtection];
+
+    /// Whether the caller calls through a function pointer
+    pub fn is_indirect(&self) -> bool {
+        *self != Self::Direct
+    }
+}
+impl std::str::FromStr for CallPath {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "direct" => Ok(Self::Direct),
+            "indirect" => Ok(Self::Indirect),
+            "cf_protection" => Ok(Self::CfProtection),
+            _ => Err(CliParseError::Other(format!("{s} is not a call path"))),
+        }
+    }
+}
+impl std::fmt::Display for CallPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Direct => "direct",
+            Self::Indirect => "indirect",
+            Self::CfProtection => "cf_protection",
+        };
+        s.fmt(f)
+    }
+}
+
+/// The rounding mode the caller switches the FPU to for the whole test (`--rounding-mode`)
+///
+/// The callee never sets it, it inherits it from the caller (and has to leave it alone),
+/// so both sides only agree about rounding-sensitive arithmetic if the mode survives the call.
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    /// `FE_TONEAREST`, the default
+    Nearest,
+    /// `FE_UPWARD`
+    Upward,
+    /// `FE_DOWNWARD`
+    Downward,
+    /// `FE_TOWARDZERO`
+    TowardZero,
+}
+impl RoundingMode {
+    /// Every rounding mode (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[
+        Self::Nearest,
+        Self::Upward,
+        Self::Downward,
+        Self::TowardZero,
+    ];
+
+    /// The name of the `<fenv.h>` macro for this mode
+    pub fn fenv_name(&self) -> &'static str {
+        match self {
+            Self::Nearest => "FE_TONEAREST",
+            Self::Upward => "FE_UPWARD",
+            Self::Downward => "FE_DOWNWARD",
+            Self::TowardZero => "FE_TOWARDZERO",
+        }
+    }
+
+    /// What Rust sides pass the C helper that switches to this mode (see rounding_mode.c)
+    pub fn helper_id(&self) -> u32 {
+        match self {
+            Self::Nearest => 0,
+            Self::Upward => 1,
+            Self::Downward => 2,
+            Self::TowardZero => 3,
+        }
+    }
+}
+impl std::str::FromStr for RoundingMode {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "upward" => Ok(Self::Upward),
+            "downward" => Ok(Self::Downward),
+            "towardzero" => Ok(Self::TowardZero),
+            _ => Err(CliParseError::Other(format!("{s} is not a rounding mode"))),
+        }
+    }
+}
+impl std::fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Nearest => "nearest",
+            Self::Upward => "upward",
+            Self::Downward => "downward",
+            Self::TowardZero => "towardzero",
+        };
+        s.fmt(f)
+    }
+}
+
+/// The linker to link the test binary (and any shared libraries) with
+///
+/// Tests are linked with `-fuse-ld=<linker>` (or `-Clinker=lld-link` on msvc).
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Linker {
+    /// Whatever the compilers use by default
+    Default,
+    /// GNU ld
+    Bfd,
+    /// GNU gold
+    Gold,
+    /// LLVM's lld
+    Lld,
+    /// mold
+    Mold,
+}
+impl Linker {
+    /// Every linker (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[Self::Default, Self::Bfd, Self::Gold, Self::Lld, Self::Mold];
+
+    /// The program the compiler driver will go looking for (None for the default)
+    pub fn program(&self, is_msvc: bool) -> Option<&'static str> {
+        match self {
+            Self::Default => None,
+            Self::Lld if is_msvc => Some("lld-link"),
+            Self::Bfd => Some("ld.bfd"),
+            Self::Gold => Some("ld.gold"),
+            Self::Lld => Some("ld.lld"),
+            Self::Mold => Some("ld.mold"),
+        }
+    }
+
+    /// Whether [`Linker::program`][] can be found in PATH
+    pub fn is_installed(&self, is_msvc: bool) -> bool {
+        let Some(program) = self.program(is_msvc) else {
+            return true;
+        };
+        let program = format!("{program}{}", std::env::consts::EXE_SUFFIX);
+        std::env::var_os("PATH").is_some_and(|path| {
+            std::env::split_paths(&path).any(|dir| dir.join(&program).is_file())
+        })
+    }
+}
+impl std::str::FromStr for Linker {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "bfd" => Ok(Self::Bfd),
+            "gold" => Ok(Self::Gold),
+            "lld" => Ok(Self::Lld),
+            "mold" => Ok(Self::Mold),
+            _ => Err(CliParseError::Other(format!("{s} is not a linker"))),
+        }
+    }
+}
+impl std::fmt::Display for Linker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Default => "default",
+            Self::Bfd => "bfd",
+            Self::Gold => "gold",
+            Self::Lld => "lld",
+            Self::Mold => "mold",
+        };
+        s.fmt(f)
+    }
+}
+
+/// Whether (and how) the caller and callee are compiled into one unit of link-time optimization
+///
+/// The sides get optimized (LTO does nothing without optimizations), and only pairs
+/// of toolchains with the same [`LtoUnit`][] are supported. LLVM sides are compiled
+/// with `-flto=thin`/`-flto=full` (rustc's `-Clinker-plugin-lto` only emits ThinLTO
+/// bitcode, so fat is unsupported for rustc), and gcc sides with `-flto`, where fat
+/// puts everything in one partition (`-flto-partition=one`).
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Lto {
+    /// No LTO, every side is compiled (without optimizations) on its own
+    Off,
+    /// Optimized in parallel pieces, with summaries of the whole program
+    Thin,
+    /// Optimized as one big module
+    Fat,
+}
+impl Lto {
+    /// Every LTO mode (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[Self::Off, Self::Thin, Self::Fat];
+}
+impl std::str::FromStr for Lto {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "thin" => Ok(Self::Thin),
+            "fat" => Ok(Self::Fat),
+            _ => Err(CliParseError::Other(format!("{s} is not an lto mode"))),
+        }
+    }
+}
+impl std::fmt::Display for Lto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Off => "off",
+            Self::Thin => "thin",
+            Self::Fat => "fat",
+        };
+        s.fmt(f)
+    }
+}
+
+/// The target features we know how to pass to both rustc and C compilers
+///
+/// (rustc's name, the name in gcc/clang's `-m<feature>`)
+pub static TARGET_FEATURES: &[(&str, &str)] = &[
+    ("sse", "sse"),
+    ("sse2", "sse2"),
+    ("sse3", "sse3"),
+    ("ssse3", "ssse3"),
+    ("sse4.1", "sse4.1"),
+    ("sse4.2", "sse4.2"),
+    ("avx", "avx"),
+    ("avx2", "avx2"),
+    ("fma", "fma"),
+    ("f16c", "f16c"),
+    ("avx512f", "avx512f"),
+    ("avx512bw", "avx512bw"),
+    ("avx512dq", "avx512dq"),
+    ("avx512vl", "avx512vl"),
+    ("bmi1", "bmi"),
+    ("bmi2", "bmi2"),
+    ("lzcnt", "lzcnt"),
+    ("popcnt", "popcnt"),
+    ("aes", "aes"),
+    ("pclmulqdq", "pclmul"),
+    ("sha", "sha"),
+];
+
+/// The features that imply AVX (and so 256-bit vectors in `ymm` registers)
+static AVX_FEATURES: &[&str] = &[
+    "avx", "avx2", "fma", "f16c", "avx512f", "avx512bw", "avx512dq", "avx512vl",
+];
+
+/// The parts of [`TestOptions`][] that change how one side is compiled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodegenOptions {
+    pub lto: Lto,
+    pub target_features: TargetFeatures,
+    /// Whether to build with x86's control-flow protection (see [`CallPath::CfProtection`][])
+    pub cf_protection: bool,
+}
+impl CodegenOptions {
+    /// The flags gcc/clang need for these (except LTO's, which depend on the compiler)
+    pub fn cc_flags(&self) -> Vec<String> {
+        let mut flags = self.target_features.cc_flags();
+        if self.cf_protection {
+            flags.push("-fcf-protection=full".to_owned());
+        }
+        flags
+    }
+}
+
+/// Extra target features (`+avx2`, `-sse3`...) one side of the call is compiled with
+///
+/// Features can change how vectors (and sometimes floats) are passed, so a caller
+/// and callee built with different features may not agree on the ABI, which is a
+/// real hazard when code built for a newer CPU calls into older code. On the command
+/// line (and in test ids) a set of features is written as one string, like `+avx2+fma`,
+/// or `default` for none. Only the features in [`TARGET_FEATURES`][] are allowed, since
+/// rustc and C compilers don't spell them all the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TargetFeatures {
+    /// (enabled, feature name)
+    pub features: Vec<(bool, String)>,
+}
+impl TargetFeatures {
+    /// Whether this is just the target's default features
+    pub fn is_default(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// The `-Ctarget-feature` flag rustc needs for these features
+    pub fn rustc_flag(&self) -> Option<String> {
+        if self.is_default() {
+            return None;
+        }
+        let features = self
+            .features
+            .iter()
+            .map(|(enabled, name)| format!("{}{name}", if *enabled { '+' } else { '-' }))
+            .collect::<Vec<_>>();
+        Some(format!("-Ctarget-feature={}", features.join(",")))
+    }
+
+    /// The `-m` flags gcc/clang need for these features
+    pub fn cc_flags(&self) -> Vec<String> {
+        self.features
+            .iter()
+            .map(|(enabled, name)| {
+                let (_, name) = TARGET_FEATURES
+                    .iter()
+                    .find(|(rustc_name, _)| rustc_name == name)
+                    .expect("target features should be validated when parsed");
+                if *enabled {
+                    format!("-m{name}")
+                } else {
+                    format!("-mno-{name}")
+                }
+            })
+            .collect()
+    }
+
+    /// Whether these features enable AVX, which changes how 256-bit vectors are passed
+    ///
+    /// Later features win, so `+avx2-avx` doesn't.
+    pub fn enables_avx(&self) -> bool {
+        let mut avx = false;
+        for (enabled, name) in &self.features {
+            if *enabled && AVX_FEATURES.contains(&&**name) {
+                avx = true;
+            } else if !*enabled && name == "avx" {
+                avx = false;
+            }
+        }
+        avx
+    }
+
+    /// A version of these features that can go in a file name (or a Rust crate name)
+    pub fn id(&self) -> String {
+        let features = self
+            .features
+            .iter()
+            .map(|(enabled, name)| {
+                let name = name.replace(['.', '-'], "_");
+                if *enabled {
+                    name
+                } else {
+                    format!("no_{name}")
+                }
+            })
+            .collect::<Vec<_>>();
+        features.join("_")
+    }
+}
+impl std::str::FromStr for TargetFeatures {
+    type Err = CliParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(Self::default());
+        }
+        if s.is_empty() {
+            return Err(CliParseError::Other(
+                "an empty set of target features should be written as default".to_owned(),
+            ));
+        }
+        let mut features = vec![];
+        let mut rest = s;
+        while !rest.is_empty() {
+            let enabled = match rest.as_bytes()[0] {
+                b'+' => true,
+                b'-' => false,
+                _ => {
+                    return Err(CliParseError::Other(format!(
+                        "{s} is not a set of target features (like +avx2+fma, or default)"
+                    )))
+                }
+            };
+            rest = &rest[1..];
+            let len = rest.find(['+', '-']).unwrap_or(rest.len());
+            let name = &rest[..len];
+            if !TARGET_FEATURES
+                .iter()
+                .any(|(rustc_name, _)| *rustc_name == name)
+            {
+                let known = TARGET_FEATURES
+                    .iter()
+                    .map(|(rustc_name, _)| *rustc_name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(CliParseError::Other(format!(
+                    "{s} has an unknown target feature ({name:?}) in it, the ones we know how to pass to every compiler are: {known}"
+                )));
+            }
+            features.push((enabled, name.to_owned()));
+            rest = &rest[len..];
+        }
+        Ok(Self { features })
+    }
+}
+impl std::fmt::Display for TargetFeatures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_default() {
+            return "default".fmt(f);
+        }
+        for (enabled, name) in &self.features {
+            write!(f, "{}{name}", if *enabled { '+' } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+impl Serialize for TargetFeatures {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum CallSide {
     Caller,
@@ -194,6 +949,8 @@ impl std::ops::Deref for TestWithToolchain {
 pub struct TestImpl {
     pub inner: Arc<TestWithToolchain>,
     pub options: TestOptions,
+    /// The target the layouts of types are computed for
+    pub layout_target: LayoutTarget,
 }
 impl std::ops::Deref for TestImpl {
     type Target = TestWithToolchain;
@@ -209,6 +966,11 @@ pub enum WriteImpl {
     Print,
     Noop,
 }
+impl WriteImpl {
+    /// Every writer (for `--capabilities`)
+    pub const ALL: &'static [Self] =
+        &[Self::HarnessCallback, Self::Assert, Self::Print, Self::Noop];
+}
 impl std::str::FromStr for WriteImpl {
     type Err = CliParseError;
 
@@ -242,8 +1004,10 @@ impl Test {
     pub async fn with_vals(
         self: &Arc<Self>,
         vals: ValueGeneratorKind,
+        varied_args: Option<VariedArgs>,
+        big_endian: bool,
     ) -> Result<Arc<TestWithVals>, GenerateError> {
-        let vals = Arc::new(ValueTree::new(&self.types, vals)?);
+        let vals = Arc::new(ValueTree::new(&self.types, vals, varied_args, big_endian)?);
         Ok(Arc::new(TestWithVals {
             inner: self.clone(),
             vals,
@@ -267,14 +1031,52 @@ impl TestWithVals {
 }
 
 impl TestWithToolchain {
-    pub fn with_options(self: &Arc<Self>, options: TestOptions) -> Result<TestImpl, GenerateError> {
+    pub fn with_options(
+        self: &Arc<Self>,
+        options: TestOptions,
+        layout_target: LayoutTarget,
+    ) -> Result<TestImpl, GenerateError> {
+        if let Some(FaultInjection::ArgCount) = options.fault {
+            // If there's no inputs to drop, there's no fault to inject
+            let funcs = options.functions.active_funcs(&self.types);
+            if funcs
+                .iter()
+                .all(|&func| self.types.realize_func(func).inputs.is_empty())
+            {
+                return Err(UnsupportedError::Other(
+                    "argcount fault injection needs functions with inputs".to_owned(),
+                ))?;
+            }
+        }
+        if let Some(FaultInjection::Align) = options.fault {
+            // If there's no structs to over-align, there's no fault to inject
+            let funcs = options.functions.active_funcs(&self.types);
+            let has_structs = self.defs.definitions(funcs).into_iter().any(|def| {
+                matches!(def, Definition::DefineTy(ty) if matches!(self.types.realize_ty(ty), Ty::Struct(_)))
+            });
+            if !has_structs {
+                return Err(UnsupportedError::Other(
+                    "align fault injection needs functions that use structs".to_owned(),
+                ))?;
+            }
+        }
         Ok(TestImpl {
             inner: self.clone(),
             options,
+            layout_target,
         })
     }
 }
 
+impl TestImpl {
+    /// The offsets of the padding bytes of a type (empty if its layout isn't specified)
+    pub fn padding_of(&self, ty: TyIdx) -> Vec<u64> {
+        self.types
+            .padding_of(ty, &self.env, self.options.repr, &self.layout_target)
+            .unwrap_or_default()
+    }
+}
+
 #[derive(
     Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
 )]
@@ -282,6 +1084,8 @@ impl TestWithToolchain {
 pub enum CallingConvention {
     /// The platform's default C convention (cdecl?)
     C,
+    /// The C convention, but unwinding out of the callee is allowed (Rust's `extern "C-unwind"`)
+    CUnwind,
     /// Rust's default calling convention
     Rust,
     /// ???
@@ -294,8 +1098,10 @@ pub enum CallingConvention {
     Win64,
     /// x64 non-windows C convention
     Sysv64,
-    /// ARM C convention
+    /// ARM C convention, with floats in integer registers (the base AAPCS)
     Aapcs,
+    /// ARM C convention, with floats in VFP registers (the hard-float AAPCS)
+    AapcsVfp,
     /// Win32 x86 system APIs
     Stdcall,
     /// Microsoft fastcall
@@ -306,21 +1112,71 @@ pub enum CallingConvention {
     /// MSCV `__vectorcall`
     /// GCC/Clang `__attribute__((vectorcall))`
     Vectorcall,
+    /// Microsoft thiscall (the first argument is a hidden `this` pointer)
+    /// MSVC `__thiscall` (only on C++ member functions)
+    /// GCC/Clang `__attribute__((thiscall))`
+    Thiscall,
 }
 
 impl CallingConvention {
+    /// Every convention (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[
+        Self::C,
+        Self::CUnwind,
+        Self::Rust,
+        Self::Cdecl,
+        Self::System,
+        Self::Win64,
+        Self::Sysv64,
+        Self::Aapcs,
+        Self::AapcsVfp,
+        Self::Stdcall,
+        Self::Fastcall,
+        Self::Vectorcall,
+        Self::Thiscall,
+    ];
+
     pub fn name(&self) -> &'static str {
         match self {
             CallingConvention::C => "c",
+            CallingConvention::CUnwind => "c_unwind",
             CallingConvention::Rust => "rust",
             CallingConvention::Cdecl => "cdecl",
             CallingConvention::System => "system",
             CallingConvention::Win64 => "win64",
             CallingConvention::Sysv64 => "sysv64",
             CallingConvention::Aapcs => "aapcs",
+            CallingConvention::AapcsVfp => "aapcs_vfp",
             CallingConvention::Stdcall => "stdcall",
             CallingConvention::Fastcall => "fastcall",
             CallingConvention::Vectorcall => "vectorcall",
+            CallingConvention::Thiscall => "thiscall",
+        }
+    }
+
+    /// The convention this actually is on the target with these cfgs
+    ///
+    /// Lots of conventions are just another name for the default one somewhere (cdecl
+    /// everywhere, stdcall and friends on x64 windows), so a caller and callee using
+    /// two of those aren't actually disagreeing.
+    pub fn resolved(self, has_cfg: impl Fn(&str) -> bool) -> Self {
+        use CallingConvention::*;
+        let is_x86 = has_cfg("target_arch=\"x86\"");
+        let is_x64 = has_cfg("target_arch=\"x86_64\"");
+        let is_arm = has_cfg("target_arch=\"arm\"");
+        let is_windows = has_cfg("target_os=\"windows\"");
+        match self {
+            // Unwinding aside, this is just C
+            C | CUnwind | Cdecl => C,
+            System if is_x86 && is_windows => Stdcall,
+            System => C,
+            Win64 if is_x64 && is_windows => C,
+            Sysv64 if is_x64 && !is_windows => C,
+            // x64 windows has only one convention (and vectorcall)
+            Stdcall | Fastcall | Thiscall if is_x64 && is_windows => C,
+            AapcsVfp if is_arm && has_cfg("target_abi=\"eabihf\"") => C,
+            Aapcs if is_arm && !has_cfg("target_abi=\"eabihf\"") => C,
+            other => other,
         }
     }
 }
@@ -337,17 +1193,183 @@ impl std::str::FromStr for CallingConvention {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let val = match s {
             "c" => CallingConvention::C,
+            "c_unwind" | "c-unwind" => CallingConvention::CUnwind,
             "rust" => CallingConvention::Rust,
             "cdecl" => CallingConvention::Cdecl,
             "system" => CallingConvention::System,
             "win64" => CallingConvention::Win64,
             "sysv64" => CallingConvention::Sysv64,
             "aapcs" => CallingConvention::Aapcs,
+            "aapcs_vfp" | "aapcs-vfp" => CallingConvention::AapcsVfp,
             "stdcall" => CallingConvention::Stdcall,
             "fastcall" => CallingConvention::Fastcall,
             "vectorcall" => CallingConvention::Vectorcall,
+            "thiscall" => CallingConvention::Thiscall,
             _ => return Err(format!("unknown CallingConvention: {s}")),
         };
         Ok(val)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_features_from_str() {
+        let features = "+avx2+fma-sse4.1".parse::<TargetFeatures>().unwrap();
+        assert_eq!(
+            features.features,
+            vec![
+                (true, "avx2".to_owned()),
+                (true, "fma".to_owned()),
+                (false, "sse4.1".to_owned()),
+            ]
+        );
+        assert_eq!(features.to_string(), "+avx2+fma-sse4.1");
+        assert_eq!(features.id(), "avx2_fma_no_sse4_1");
+        assert_eq!(
+            features.rustc_flag().as_deref(),
+            Some("-Ctarget-feature=+avx2,+fma,-sse4.1")
+        );
+        assert_eq!(features.cc_flags(), ["-mavx2", "-mfma", "-mno-sse4.1"]);
+
+        let default = "default".parse::<TargetFeatures>().unwrap();
+        assert!(default.is_default());
+        assert_eq!(default.to_string(), "default");
+        assert_eq!(default.rustc_flag(), None);
+        assert!(default.cc_flags().is_empty());
+    }
+
+    #[test]
+    fn target_features_cc_names() {
+        // rustc and gcc don't agree on the names of these
+        let features = "+bmi1+pclmulqdq".parse::<TargetFeatures>().unwrap();
+        assert_eq!(features.cc_flags(), ["-mbmi", "-mpclmul"]);
+        assert_eq!(
+            features.rustc_flag().as_deref(),
+            Some("-Ctarget-feature=+bmi1,+pclmulqdq")
+        );
+    }
+
+    #[test]
+    fn target_features_from_str_errors() {
+        for bad in [
+            "",
+            "avx2",
+            "+",
+            "+avx2+",
+            "+avx2-",
+            "+nonsense",
+            "+neon",
+            "+AVX2",
+        ] {
+            assert!(
+                bad.parse::<TargetFeatures>().is_err(),
+                "{bad:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn target_features_enable_avx() {
+        let enables_avx = |s: &str| s.parse::<TargetFeatures>().unwrap().enables_avx();
+        assert!(!enables_avx("default"));
+        assert!(!enables_avx("+sse4.2"));
+        assert!(enables_avx("+avx"));
+        assert!(enables_avx("+avx512f"));
+        assert!(enables_avx("-avx+avx2"));
+        assert!(!enables_avx("+avx2-avx"));
+    }
+
+    #[test]
+    fn lto_from_str() {
+        for &lto in Lto::ALL {
+            assert_eq!(lto.to_string().parse::<Lto>().unwrap(), lto);
+        }
+        assert_eq!("thin".parse::<Lto>().unwrap(), Lto::Thin);
+        assert_eq!(Lto::Fat.to_string(), "fat");
+        for bad in ["", "full", "Thin", "on"] {
+            assert!(bad.parse::<Lto>().is_err(), "{bad:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn rounding_mode_from_str() {
+        for &mode in RoundingMode::ALL {
+            assert_eq!(mode.to_string().parse::<RoundingMode>().unwrap(), mode);
+        }
+        assert_eq!(
+            "towardzero".parse::<RoundingMode>().unwrap(),
+            RoundingMode::TowardZero
+        );
+        assert_eq!(RoundingMode::Upward.to_string(), "upward");
+        assert_eq!(RoundingMode::Downward.fenv_name(), "FE_DOWNWARD");
+        for bad in ["", "up", "Nearest", "toward_zero", "FE_UPWARD"] {
+            assert!(
+                bad.parse::<RoundingMode>().is_err(),
+                "{bad:?} should be rejected"
+            );
+        }
+        // rounding_mode.c's table is in this order
+        let ids = RoundingMode::ALL.iter().map(RoundingMode::helper_id);
+        assert!(ids.eq(0..4));
+    }
+
+    #[test]
+    fn cf_protection_codegen() {
+        for &call_path in CallPath::ALL {
+            assert_eq!(
+                call_path.to_string().parse::<CallPath>().unwrap(),
+                call_path
+            );
+        }
+        let mut options = TestOptions {
+            target_features: "+avx2".parse().unwrap(),
+            call_path: CallPath::Indirect,
+            ..TestOptions::default()
+        };
+        assert_eq!(options.codegen_for(CallSide::Caller).cc_flags(), ["-mavx2"]);
+        options.call_path = CallPath::CfProtection;
+        assert!(options.call_path.is_indirect());
+        let codegen = options.codegen_for(CallSide::Caller);
+        assert_eq!(codegen.cc_flags(), ["-mavx2", "-fcf-protection=full"]);
+        // Both sides are protected, even with different target features
+        assert!(options.codegen_for(CallSide::Callee).cf_protection);
+    }
+
+    #[test]
+    fn resolved_conventions() {
+        use CallingConvention::*;
+        let resolved = |cfgs: &[&str], convention: CallingConvention| {
+            convention.resolved(|cfg| cfgs.contains(&cfg))
+        };
+        let x64_linux = &["target_arch=\"x86_64\"", "target_os=\"linux\""];
+        let x64_windows = &["target_arch=\"x86_64\"", "target_os=\"windows\""];
+        let x86_windows = &["target_arch=\"x86\"", "target_os=\"windows\""];
+        let arm_hard = &["target_arch=\"arm\"", "target_abi=\"eabihf\""];
+
+        for convention in [C, CUnwind, Cdecl, System, Sysv64] {
+            assert_eq!(resolved(x64_linux, convention), C, "{convention}");
+        }
+        for convention in [Win64, Stdcall, Fastcall, Thiscall] {
+            assert_eq!(resolved(x64_linux, convention), convention, "{convention}");
+            assert_eq!(resolved(x64_windows, convention), C, "{convention}");
+        }
+        assert_eq!(resolved(x64_windows, Vectorcall), Vectorcall);
+        assert_eq!(resolved(x64_windows, Sysv64), Sysv64);
+
+        assert_eq!(resolved(x86_windows, Cdecl), C);
+        assert_eq!(resolved(x86_windows, System), Stdcall);
+        assert_eq!(resolved(x86_windows, Stdcall), Stdcall);
+        assert_ne!(
+            resolved(x86_windows, Fastcall),
+            resolved(x86_windows, Stdcall)
+        );
+
+        assert_eq!(resolved(arm_hard, AapcsVfp), C);
+        assert_eq!(resolved(arm_hard, Aapcs), Aapcs);
+        assert_eq!(resolved(&["target_arch=\"arm\""], Aapcs), C);
+        assert_eq!(resolved(x64_linux, Rust), Rust);
+    }
+}
@@ -0,0 +1,147 @@
+//! Helpers for unit tests that need reports without running anything
+
+use std::sync::Arc;
+
+use camino::Utf8PathBuf;
+
+use crate::harness::report::*;
+use crate::harness::spawn_read_test;
+use crate::harness::test::*;
+use crate::*;
+
+/// A report for a test that concluded this way (without any actual results)
+pub fn test_report(
+    test: &str,
+    caller: &str,
+    callee: &str,
+    conclusion: TestConclusion,
+) -> TestReport {
+    test_report_with(test, caller, callee, TestOptions::default(), conclusion)
+}
+
+/// Like [`test_report`][] but with these options
+pub fn test_report_with(
+    test: &str,
+    caller: &str,
+    callee: &str,
+    options: TestOptions,
+    conclusion: TestConclusion,
+) -> TestReport {
+    let convention = if options.convention == options.callee_convention {
+        format!("conv_{}", options.convention)
+    } else {
+        format!(
+            "conv_{}::callee_conv_{}",
+            options.convention, options.callee_convention
+        )
+    };
+    let id = format!(
+        "{test}::{convention}::repr_{}::{caller}_calls_{callee}",
+        options.repr
+    );
+    let key = TestKey {
+        test: test.to_owned(),
+        caller: caller.to_owned(),
+        callee: callee.to_owned(),
+        options,
+    };
+    let rules = TestRules {
+        run: TestRunMode::Check,
+        check: TestCheckMode::Pass(TestRunMode::Check),
+        crash: None,
+        float_ulps: None,
+    };
+    TestReport {
+        id,
+        results: TestRunResults::new(key.clone(), rules.clone()),
+        key,
+        rules,
+        conclusion,
+        skip_reason: None,
+        could_be: TestRulesPattern {
+            run: None,
+            check: None,
+            crash: None,
+            float_ulps: None,
+        },
+        interop_only: false,
+        lto_only: false,
+        source: None,
+        resumed: false,
+    }
+}
+
+/// A report of a run of these tests on x86_64 linux
+pub fn full_report(mut tests: Vec<TestReport>) -> FullReport {
+    tests.sort_by(|a, b| a.id.cmp(&b.id));
+    let count = |conclusion| tests.iter().filter(|t| t.conclusion == conclusion).count() as u64;
+    FullReport {
+        schema_version: REPORT_SCHEMA_VERSION,
+        target: "x86_64-unknown-linux-gnu".to_owned(),
+        riscv_float_abi: None,
+        arm_float_abi: None,
+        shard: None,
+        summary: TestSummary {
+            num_tests: tests.len() as u64,
+            num_passed: count(TestConclusion::Passed),
+            num_busted: count(TestConclusion::Busted),
+            num_failed: count(TestConclusion::Failed),
+            num_skipped: count(TestConclusion::Skipped),
+            num_skipped_by_reason: SortedMap::new(),
+            num_skip_errors: 0,
+            num_interop_only: 0,
+            num_interop_errors: 0,
+            num_lto_only: 0,
+            num_idle_toolchain_errors: 0,
+        },
+        toolchain_versions: SortedMap::new(),
+        run_env: SortedMap::new(),
+        runner: None,
+        config: None,
+        possible_rules: None,
+        excluded_pairs: vec![],
+        idle_toolchains: vec![],
+        tests,
+        timings: SortedMap::new(),
+    }
+}
+
+/// Write these reports to a fresh dir, as `--output-format=json` would
+pub fn write_reports<const N: usize>(
+    name: &str,
+    reports: [(&str, FullReport); N],
+) -> [Utf8PathBuf; N] {
+    let dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+    let dir = dir.join(format!("abi-cafe-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    reports.map(|(file_name, report)| {
+        let path = dir.join(file_name);
+        let file = std::fs::File::create(&path).unwrap();
+        serde_json::to_writer(file, &report).unwrap();
+        path
+    })
+}
+
+/// Set up a harness with just these (builtin) tests, that generates them in a fresh dir
+pub fn test_harness(name: &str, test_names: &[&str]) -> (Arc<TestHarness>, Utf8PathBuf) {
+    let dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+    let dir = dir.join(format!("abi-cafe-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut cfg = crate::cli::config_from_args(["abi-cafe"]).unwrap();
+    cfg.paths.out_dir = dir.join("temp");
+    cfg.paths.generated_src_dir = dir.join("generated_impls");
+    cfg.paths.target_dir = dir.clone();
+    std::fs::create_dir_all(&cfg.paths.out_dir).unwrap();
+    // Only load the tests we need, the rest take a while to read
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut tests = SortedMap::new();
+    for (name, file) in crate::harness::find_tests(&cfg).unwrap() {
+        if test_names.contains(&name.as_str()) {
+            let test = rt.block_on(spawn_read_test(&rt, name.clone(), file));
+            tests.insert(name, test.unwrap().unwrap());
+        }
+    }
+    let harness = TestHarness::new(vec![], tests, vec![], &cfg);
+    (Arc::new(harness), dir)
+}
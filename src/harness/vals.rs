@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use kdl_script::types::*;
 use rand::Rng;
 use rand_core::{RngCore, SeedableRng};
@@ -8,9 +10,22 @@ use crate::harness::test::*;
 
 type RngImpl = rand_pcg::Pcg64;
 
+/// The longest string a `cstr` can get (they're anywhere from empty to this long)
+const MAX_CSTR_LEN: usize = 16;
+
+/// How many references deep a nullable reference can be before it's always null
+const MAX_REF_DEPTH: usize = 8;
+
 #[derive(Debug, Clone)]
 pub struct ValueTree {
     pub generator_kind: ValueGeneratorKind,
+    /// If set, every other arg of this function is fixed to zero
+    pub varied_args: Option<VariedArgs>,
+    /// Whether the target is big-endian
+    ///
+    /// The generators pick the bytes of each value as they should be laid out in
+    /// memory, so this decides which integers have those bytes.
+    pub big_endian: bool,
     pub funcs: Vec<FuncValues>,
 }
 
@@ -26,6 +41,47 @@ pub struct ArgValues {
     pub ty: TyIdx,
     pub vals: Vec<Value>,
     pub absolute_val_idx_start: usize,
+    /// If this is the value an in/out reference input should have after the call,
+    /// this is the index of that input (see [`TestOptions::inout_refs`][]).
+    ///
+    /// These come after all the normal inputs and outputs.
+    pub inout_of: Option<usize>,
+    /// If this is a reference input that the callee can write through, and the function
+    /// also takes its pointee type by value, this is the index of that by-value input
+    /// (see [`TestOptions::alias_refs`][]).
+    pub aliases: Option<usize>,
+    /// Whether this is one of the function's outputs (and not an input)
+    pub is_output: bool,
+}
+
+impl ArgValues {
+    /// Whether the callee unwinds before this value exists (see [`TestOptions::unwind`][])
+    pub fn unwound_past(&self, options: &TestOptions) -> bool {
+        options.unwind && (self.is_output || self.inout_of.is_some())
+    }
+}
+
+impl FuncValues {
+    /// The by-value input the caller passes a pointer to as this reference input
+    /// (see [`TestOptions::alias_refs`][])
+    pub fn aliased_input(&self, input_idx: usize, options: &TestOptions) -> Option<usize> {
+        self.args[input_idx].aliases.filter(|_| options.alias_refs)
+    }
+
+    /// Whether the callee writes new values through this reference input
+    pub fn writes_through(&self, input_idx: usize, options: &TestOptions) -> bool {
+        options.inout_refs || self.aliased_input(input_idx, options).is_some()
+    }
+
+    /// Whether both sides report the values of this arg at all
+    fn is_reported(&self, arg_idx: usize, options: &TestOptions) -> bool {
+        if let Some(input_idx) = self.args[arg_idx].inout_of {
+            return self.writes_through(input_idx, options);
+        }
+        // The callee writes through an aliased reference before it reports its inputs,
+        // so only its pointee's value after the call gets checked
+        self.aliased_input(arg_idx, options).is_none()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -52,6 +108,15 @@ pub struct ValueRef<'a> {
     pub absolute_val_idx: usize,
 }
 
+/// A value recorded from a failing test, to give its minimized test
+#[derive(Debug, Clone)]
+pub struct ReplayedVal {
+    pub func_idx: usize,
+    pub arg_idx: usize,
+    pub val_idx: usize,
+    pub val: ValueGenerator,
+}
+
 #[derive(Debug, Clone)]
 pub struct Value {
     pub val: ValueGenerator,
@@ -61,14 +126,26 @@ pub struct Value {
 
 #[derive(Debug, Clone)]
 pub enum ValueGenerator {
-    Graffiti { idx: u64 },
-    Random { seed: u64 },
+    Graffiti {
+        idx: u64,
+    },
+    Random {
+        seed: u64,
+    },
+    /// All zeros (and the first variant), for args fixed by [`ArgSelector::Vary`][]
+    Zero,
+    /// The exact bytes a value had when its test failed, so a minimized test gets
+    /// those and not whatever regenerating it would give (see [`ValueTree::with_replay`][])
+    Replay {
+        bytes: Arc<[u8]>,
+    },
 }
 
 #[derive(Debug, Clone)]
 enum ValueGeneratorBuilder {
     Graffiti { idx: u64 },
     Random { rng: RngImpl },
+    Zero,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
@@ -110,8 +187,15 @@ impl ValueTree {
     pub fn new(
         types: &TypedProgram,
         generator_kind: ValueGeneratorKind,
+        varied_args: Option<VariedArgs>,
+        big_endian: bool,
     ) -> Result<Self, GenerateError> {
         let mut generators = generator_kind.builder();
+        let is_fixed = |func_idx: FuncIdx, arg_idx: usize| {
+            varied_args
+                .as_ref()
+                .is_some_and(|varied| varied.is_fixed(func_idx, arg_idx))
+        };
         // Construct value generators for every function
         let funcs = types
             .all_funcs()
@@ -123,15 +207,22 @@ impl ValueTree {
                     .inputs
                     .iter()
                     .chain(&func.outputs)
-                    .map(|arg| {
+                    .enumerate()
+                    .map(|(arg_idx, arg)| {
                         let mut vals = vec![];
                         let arg_name = arg.name.to_string();
-                        generators.build_values(types, arg.ty, &mut vals, arg_name.clone())?;
+                        generators.build_values(types, arg.ty, &mut vals, arg_name.clone(), 0)?;
+                        if is_fixed(func_idx, arg_idx) {
+                            vals = fixed_values(types, arg.ty, arg_name.clone())?;
+                        }
                         let res = ArgValues {
                             ty: arg.ty,
                             arg_name,
                             vals,
                             absolute_val_idx_start,
+                            inout_of: None,
+                            aliases: None,
+                            is_output: arg_idx >= func.inputs.len(),
                         };
                         absolute_val_idx_start += res.vals.len();
                         Ok(res)
@@ -141,12 +232,81 @@ impl ValueTree {
             })
             .collect::<Result<Vec<_>, GenerateError>>()?;
 
+        // Now generate the values that in/out references should be given by the callee.
+        // This is done in a second pass so that enabling it doesn't perturb the other values.
+        let mut funcs = funcs;
+        for (func_idx, func_vals) in types.all_funcs().zip(&mut funcs) {
+            let func = types.realize_func(func_idx);
+            let mut absolute_val_idx_start = func_vals
+                .args
+                .last()
+                .map(|arg| arg.absolute_val_idx_start + arg.vals.len())
+                .unwrap_or(0);
+            let mut aliased = vec![];
+            for (input_idx, arg) in func.inputs.iter().enumerate() {
+                let Ty::Ref(ref_ty) = types.realize_ty(arg.ty) else {
+                    continue;
+                };
+                // The callee can't write through a null pointer
+                if ref_ty.nullable {
+                    continue;
+                }
+                // If the pointee has references of its own, the callee would need to
+                // leak pointers to its own temporaries back to the caller, so don't.
+                if ty_has_refs(types, ref_ty.pointee_ty) {
+                    continue;
+                }
+                let mut vals = vec![];
+                let arg_name = format!("{}_after", arg.name);
+                generators.build_values(types, arg.ty, &mut vals, arg_name.clone(), 0)?;
+                if is_fixed(func_idx, input_idx) {
+                    vals = fixed_values(types, arg.ty, arg_name.clone())?;
+                }
+                let res = ArgValues {
+                    ty: arg.ty,
+                    arg_name,
+                    vals,
+                    absolute_val_idx_start,
+                    inout_of: Some(input_idx),
+                    aliases: None,
+                    is_output: false,
+                };
+                absolute_val_idx_start += res.vals.len();
+                func_vals.args.push(res);
+
+                // Pair it up with a by-value input of the same type (each only gets one)
+                let by_val = func.inputs.iter().enumerate().position(|(val_idx, val)| {
+                    val.ty == ref_ty.pointee_ty && !aliased.contains(&val_idx)
+                });
+                if let Some(val_idx) = by_val {
+                    aliased.push(val_idx);
+                    func_vals.args[input_idx].aliases = Some(val_idx);
+                }
+            }
+        }
+
         Ok(ValueTree {
             generator_kind,
+            varied_args,
+            big_endian,
             funcs,
         })
     }
 
+    /// A copy of this tree with one value pinned to what it was in an earlier run
+    pub fn with_replay(&self, replay: &ReplayedVal) -> Self {
+        let mut tree = self.clone();
+        let val = tree
+            .funcs
+            .get_mut(replay.func_idx)
+            .and_then(|func| func.args.get_mut(replay.arg_idx))
+            .and_then(|arg| arg.vals.get_mut(replay.val_idx));
+        if let Some(val) = val {
+            val.val = replay.val.clone();
+        }
+        tree
+    }
+
     #[track_caller]
     pub fn at_func(&self, func_idx: usize) -> FuncValuesIter<'_> {
         assert!(
@@ -218,6 +378,12 @@ impl<'a> ArgValuesIter<'a> {
     }
 
     pub fn should_write_arg(&self, options: &TestOptions) -> bool {
+        if !self.func().is_reported(self.arg_idx, options) {
+            return false;
+        }
+        if self.arg().unwound_past(options) {
+            return false;
+        }
         options
             .functions
             .should_write_arg(self.func_idx, self.arg_idx)
@@ -226,6 +392,9 @@ impl<'a> ArgValuesIter<'a> {
     pub fn arg(&self) -> &'a ArgValues {
         &self.tree.funcs[self.func_idx].args[self.arg_idx]
     }
+    pub fn func(&self) -> &'a FuncValues {
+        &self.tree.funcs[self.func_idx]
+    }
 }
 
 impl<'a> Iterator for ArgValuesIter<'a> {
@@ -242,6 +411,12 @@ impl<'a> Iterator for ArgValuesIter<'a> {
 
 impl<'a> ValueRef<'a> {
     pub fn should_write_val(&self, options: &TestOptions) -> bool {
+        if !self.func().is_reported(self.arg_idx, options) {
+            return false;
+        }
+        if self.arg().unwound_past(options) {
+            return false;
+        }
         options
             .functions
             .should_write_val(self.func_idx, self.arg_idx, self.val_idx)
@@ -252,6 +427,83 @@ impl<'a> ValueRef<'a> {
     pub fn func(&self) -> &'a FuncValues {
         &self.tree.funcs[self.func_idx]
     }
+
+    /// The unsigned integer of `N` bytes that has [`ValueGenerator::fill_bytes`][]'s
+    /// bytes on the target (so graffiti still reads in memory order on big-endian)
+    fn generate_uint<const N: usize>(&self) -> u128 {
+        let mut buf = [0; N];
+        self.fill_bytes(&mut buf);
+        let mut wide = [0; 16];
+        if self.tree.big_endian {
+            wide[16 - N..].copy_from_slice(&buf);
+            u128::from_be_bytes(wide)
+        } else {
+            wide[..N].copy_from_slice(&buf);
+            u128::from_le_bytes(wide)
+        }
+    }
+    pub fn generate_u8(&self) -> u8 {
+        self.generate_uint::<1>() as u8
+    }
+    pub fn generate_u16(&self) -> u16 {
+        self.generate_uint::<2>() as u16
+    }
+    pub fn generate_u32(&self) -> u32 {
+        self.generate_uint::<4>() as u32
+    }
+    pub fn generate_u64(&self) -> u64 {
+        self.generate_uint::<8>() as u64
+    }
+    pub fn generate_u128(&self) -> u128 {
+        self.generate_uint::<16>()
+    }
+    pub fn generate_i8(&self) -> i8 {
+        self.generate_u8() as i8
+    }
+    pub fn generate_i16(&self) -> i16 {
+        self.generate_u16() as i16
+    }
+    pub fn generate_i32(&self) -> i32 {
+        self.generate_u32() as i32
+    }
+    pub fn generate_i64(&self) -> i64 {
+        self.generate_u64() as i64
+    }
+    pub fn generate_i128(&self) -> i128 {
+        self.generate_u128() as i128
+    }
+    /// The bytes of a `_BitInt(bits)` that's `size` bytes big, in the target's byte order
+    ///
+    /// Only the low `bits` bits come from the generator, the rest are the sign (or zero)
+    /// extension of those. Compilers don't all store that, so nothing checks those bits.
+    pub fn generate_bitint(&self, bits: u16, signed: bool, size: u64) -> Vec<u8> {
+        let mut bytes = vec![0; size as usize];
+        self.fill_bytes(&mut bytes);
+        if self.tree.big_endian {
+            bytes.reverse();
+        }
+        extend_bitint(&mut bytes, bits, signed);
+        if self.tree.big_endian {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+/// Overwrite the bits of a little-endian `_BitInt(bits)` past the first `bits`
+/// with the sign (or zero) extension of the value
+pub fn extend_bitint(le_bytes: &mut [u8], bits: u16, signed: bool) {
+    let bits = usize::from(bits);
+    let sign_bit = bits - 1;
+    let negative = signed && le_bytes[sign_bit / 8] >> (sign_bit % 8) & 1 == 1;
+    for bit in bits..le_bytes.len() * 8 {
+        let mask = 1 << (bit % 8);
+        if negative {
+            le_bytes[bit / 8] |= mask;
+        } else {
+            le_bytes[bit / 8] &= !mask;
+        }
+    }
 }
 impl std::ops::Deref for ValueRef<'_> {
     type Target = Value;
@@ -266,6 +518,40 @@ impl std::ops::Deref for Value {
     }
 }
 
+/// The values of an arg that [`ArgSelector::Vary`][] holds fixed
+///
+/// These are built separately, so that the real values the other args get
+/// are exactly the ones they would get if nothing was fixed.
+fn fixed_values(
+    types: &TypedProgram,
+    ty_idx: TyIdx,
+    path: String,
+) -> Result<Vec<Value>, GenerateError> {
+    let mut vals = vec![];
+    ValueGeneratorBuilder::Zero.build_values(types, ty_idx, &mut vals, path, 0)?;
+    Ok(vals)
+}
+
+/// Whether a type contains any references
+fn ty_has_refs(types: &TypedProgram, ty_idx: TyIdx) -> bool {
+    match types.realize_ty(ty_idx) {
+        Ty::Ref(_) => true,
+        Ty::Primitive(_) | Ty::Enum(_) | Ty::Empty => false,
+        Ty::Alias(ty) => ty_has_refs(types, ty.real),
+        Ty::Array(ty) => ty_has_refs(types, ty.elem_ty),
+        Ty::Struct(ty) => ty.fields.iter().any(|field| ty_has_refs(types, field.ty)),
+        Ty::Union(ty) => ty.fields.iter().any(|field| ty_has_refs(types, field.ty)),
+        Ty::Tagged(ty) => ty.variants.iter().any(|variant| {
+            variant
+                .fields
+                .iter()
+                .flatten()
+                .any(|field| ty_has_refs(types, field.ty))
+        }),
+        Ty::Pun(ty) => ty.blocks.iter().any(|block| ty_has_refs(types, block.real)),
+    }
+}
+
 impl ValueGeneratorKind {
     fn builder(&self) -> ValueGeneratorBuilder {
         match self {
@@ -292,16 +578,19 @@ impl ValueGeneratorBuilder {
             ValueGeneratorBuilder::Random { rng } => ValueGenerator::Random {
                 seed: rng.next_u64(),
             },
+            ValueGeneratorBuilder::Zero => ValueGenerator::Zero,
         };
         Value { val, ty, path }
     }
 
+    /// Build the values of a type (at this path), `ref_depth` references deep
     fn build_values(
         &mut self,
         types: &TypedProgram,
         ty_idx: TyIdx,
         vals: &mut Vec<Value>,
         path: String,
+        ref_depth: usize,
     ) -> Result<(), GenerateError> {
         let ty = types.realize_ty(ty_idx);
         match ty {
@@ -313,10 +602,26 @@ impl ValueGeneratorBuilder {
             Ty::Empty => {}
 
             // Alias and ref are just wrappers
-            Ty::Alias(ty) => self.build_values(types, ty.real, vals, path)?,
+            Ty::Alias(ty) => self.build_values(types, ty.real, vals, path, ref_depth)?,
+            Ty::Ref(ty) if ty.nullable => {
+                // generate the "tag" (whether it's null)
+                let mut tag_generator = self.next(ty_idx, path.clone());
+                if ref_depth >= MAX_REF_DEPTH {
+                    // A tree can branch out faster than coin flips end it, so cut it off
+                    tag_generator.val = ValueGenerator::Zero;
+                }
+                let is_some = tag_generator.generate_bool();
+                vals.push(tag_generator);
+
+                // and if it isn't, what it points to
+                if is_some {
+                    let new_path = format!("{path}.*");
+                    self.build_values(types, ty.pointee_ty, vals, new_path, ref_depth + 1)?;
+                }
+            }
             Ty::Ref(ty) => {
                 let new_path = format!("{path}.*");
-                self.build_values(types, ty.pointee_ty, vals, new_path)?;
+                self.build_values(types, ty.pointee_ty, vals, new_path, ref_depth + 1)?;
             }
 
             // Struct and array are just all of their fields combined
@@ -324,13 +629,13 @@ impl ValueGeneratorBuilder {
                 for field in &ty.fields {
                     let field_name = &field.ident;
                     let new_path = format!("{path}.{field_name}");
-                    self.build_values(types, field.ty, vals, new_path)?;
+                    self.build_values(types, field.ty, vals, new_path, ref_depth)?;
                 }
             }
             Ty::Array(ty) => {
                 for idx in 0..ty.len {
                     let new_path = format!("{path}[{idx}]");
-                    self.build_values(types, ty.elem_ty, vals, new_path)?;
+                    self.build_values(types, ty.elem_ty, vals, new_path, ref_depth)?;
                 }
             }
 
@@ -345,7 +650,7 @@ impl ValueGeneratorBuilder {
                 if let Some(field) = ty.fields.get(active_variant_idx) {
                     let field_name = &field.ident;
                     let new_path = format!("{path}.{field_name}");
-                    self.build_values(types, field.ty, vals, new_path)?;
+                    self.build_values(types, field.ty, vals, new_path, ref_depth)?;
                 }
             }
             Ty::Tagged(ty) => {
@@ -362,7 +667,7 @@ impl ValueGeneratorBuilder {
                             let variant_name = &variant.name;
                             let field_name = &field.ident;
                             let new_path = format!("{path}.{variant_name}.{field_name}");
-                            self.build_values(types, field.ty, vals, new_path)?;
+                            self.build_values(types, field.ty, vals, new_path, ref_depth)?;
                         }
                     }
                 }
@@ -382,7 +687,7 @@ impl ValueGeneratorBuilder {
 
                     // Shove values into a temp buffer instead of the main one
                     let mut new_vals = vec![];
-                    self.build_values(types, block.real, &mut new_vals, path.clone())?;
+                    self.build_values(types, block.real, &mut new_vals, path.clone(), ref_depth)?;
 
                     // If there are multiple blocks, check that this new one matches
                     // all the other ones in length (making the pun semantically comprehensible)
@@ -429,6 +734,13 @@ impl ValueGenerator {
                 let mut rng = RngImpl::seed_from_u64(*seed);
                 rng.fill_bytes(output);
             }
+            ValueGenerator::Zero => output.fill(0),
+            ValueGenerator::Replay { bytes } => {
+                // The generators are all prefix-stable, so a shorter value is a prefix
+                let len = output.len().min(bytes.len());
+                output[..len].copy_from_slice(&bytes[..len]);
+                output[len..].fill(0);
+            }
         }
     }
 
@@ -451,6 +763,13 @@ impl ValueGenerator {
                 RngImpl::seed_from_u64(*idx)
             }
             ValueGenerator::Random { seed } => RngImpl::seed_from_u64(*seed),
+            ValueGenerator::Zero => return 0,
+            ValueGenerator::Replay { bytes } => {
+                let mut seed = [0; 8];
+                let len = bytes.len().min(8);
+                seed[..len].copy_from_slice(&bytes[..len]);
+                RngImpl::seed_from_u64(u64::from_le_bytes(seed))
+            }
         };
         rng.gen_range(0..len)
     }
@@ -458,44 +777,128 @@ impl ValueGenerator {
         let idx = self.generate_idx(2);
         idx == 1
     }
-    pub fn generate_u8(&self) -> u8 {
-        let mut buf = [0; 1];
-        self.fill_bytes(&mut buf);
-        u8::from_le_bytes(buf)
-    }
-    pub fn generate_u16(&self) -> u16 {
-        let mut buf = [0; 2];
-        self.fill_bytes(&mut buf);
-        u16::from_le_bytes(buf)
-    }
-    pub fn generate_u32(&self) -> u32 {
-        let mut buf = [0; 4];
-        self.fill_bytes(&mut buf);
-        u32::from_le_bytes(buf)
-    }
-    pub fn generate_u64(&self) -> u64 {
-        let mut buf = [0; 8];
-        self.fill_bytes(&mut buf);
-        u64::from_le_bytes(buf)
-    }
-    pub fn generate_u128(&self) -> u128 {
-        let mut buf = [0; 16];
-        self.fill_bytes(&mut buf);
-        u128::from_le_bytes(buf)
-    }
-    pub fn generate_i8(&self) -> i8 {
-        self.generate_u8() as i8
-    }
-    pub fn generate_i16(&self) -> i16 {
-        self.generate_u16() as i16
-    }
-    pub fn generate_i32(&self) -> i32 {
-        self.generate_u32() as i32
-    }
-    pub fn generate_i64(&self) -> i64 {
-        self.generate_u64() as i64
+    /// The bytes of a `cstr`, not including the null terminator
+    ///
+    /// The length comes from the generator too, so some of them are empty. A zero
+    /// would end the string early, so any the generator picks become 0xff instead.
+    pub fn generate_cstr(&self) -> Vec<u8> {
+        let mut bytes = if let ValueGenerator::Replay { bytes } = self {
+            bytes.to_vec()
+        } else {
+            let mut bytes = vec![0; self.generate_idx(MAX_CSTR_LEN + 1)];
+            self.fill_bytes(&mut bytes);
+            bytes
+        };
+        for byte in &mut bytes {
+            if *byte == 0 {
+                *byte = 0xff;
+            }
+        }
+        bytes
     }
-    pub fn generate_i128(&self) -> i128 {
-        self.generate_u128() as i128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliased_refs() {
+        let types = kdl_script::Compiler::new()
+            .compile_string(
+                "aliased.kdl",
+                r#"
+                struct "Foo" {
+                    a "u32"
+                }
+                fn "val_ref" {
+                    inputs { val "Foo"; ptr "&Foo"; }
+                }
+                fn "ref_val_val" {
+                    inputs { ptr "&Foo"; a "Foo"; b "Foo"; }
+                }
+                fn "two_refs" {
+                    inputs { p "&Foo"; val "Foo"; q "&Foo"; }
+                }
+                fn "no_val" {
+                    inputs { ptr "&Foo"; x "u32"; }
+                }
+                "#
+                .to_owned(),
+            )
+            .unwrap();
+        let tree = ValueTree::new(&types, ValueGeneratorKind::Graffiti, None, false).unwrap();
+        let aliases = |func_idx: usize| {
+            let func = &tree.funcs[func_idx];
+            (0..types.realize_func(func_idx).inputs.len())
+                .map(|input_idx| func.args[input_idx].aliases)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(aliases(0), [None, Some(0)]);
+        assert_eq!(aliases(1), [Some(1), None, None]);
+        // Each by-value input only gets one reference
+        assert_eq!(aliases(2), [Some(1), None, None]);
+        assert_eq!(aliases(3), [None, None]);
+
+        // Only with --alias-refs, and then the callee writes through them (but not the others)
+        let mut options = TestOptions::default();
+        assert_eq!(tree.funcs[2].aliased_input(0, &options), None);
+        assert!(!tree.funcs[2].writes_through(0, &options));
+        options.alias_refs = true;
+        assert_eq!(tree.funcs[2].aliased_input(0, &options), Some(1));
+        assert!(tree.funcs[2].writes_through(0, &options));
+        assert!(!tree.funcs[2].writes_through(2, &options));
+
+        // Neither side reports the aliased reference going in, just what it has after
+        let func = tree.at_func(0);
+        let reported = func
+            .map(|arg| arg.should_write_arg(&options))
+            .collect::<Vec<_>>();
+        assert_eq!(reported, [true, false, true]);
+    }
+
+    #[test]
+    fn deep_trees_end() {
+        let types = kdl_script::Compiler::new()
+            .compile_string(
+                "trees.kdl",
+                r#"
+                struct "TreeNode" {
+                    left "Option<&TreeNode>"
+                    key "i64"
+                    right "Option<&TreeNode>"
+                }
+                fn "walk_tree" {
+                    inputs { root "Option<&TreeNode>"; }
+                }
+                "#
+                .to_owned(),
+            )
+            .unwrap();
+        let root_ty = types.realize_func(0).inputs[0].ty;
+
+        // Once it's deep enough, a nullable ref is always null
+        let mut vals = vec![];
+        ValueGeneratorKind::Random { seed: 0 }
+            .builder()
+            .build_values(&types, root_ty, &mut vals, "root".to_owned(), MAX_REF_DEPTH)
+            .unwrap();
+        assert_eq!(vals.len(), 1);
+        assert!(!vals[0].generate_bool());
+
+        // So however the coins land, no branch goes any deeper than that
+        let generators = (0..64)
+            .map(|seed| ValueGeneratorKind::Random { seed })
+            .chain([ValueGeneratorKind::Graffiti]);
+        for generator in generators {
+            let tree = ValueTree::new(&types, generator, None, false).unwrap();
+            let depth = tree.funcs[0].args[0]
+                .vals
+                .iter()
+                .map(|val| val.path.matches(".*").count())
+                .max()
+                .unwrap();
+            assert!(depth <= MAX_REF_DEPTH, "{generator:?} went {depth} deep");
+        }
     }
 }
@@ -0,0 +1,1580 @@
+//! Pair your compilers up at The ABI café!
+//!
+//! This is the library half of the abi-cafe CLI, so that it can be embedded
+//! in other test harnesses. Build a [`Config`][] (most easily with
+//! [`cli::config_from_args`][]) and pass it to [`run`][] to get a [`FullReport`][].
+
+pub mod cli;
+pub mod error;
+pub mod files;
+mod fivemat;
+pub mod harness;
+pub mod log;
+pub mod toolchains;
+
+use error::*;
+use files::Paths;
+use harness::report::*;
+pub use harness::report::{FullReport, TestConclusion, TestReport, TestSummary};
+use harness::test::*;
+use harness::vals::*;
+pub use harness::TestHarness;
+use harness::*;
+use indexmap::IndexMap;
+use toolchains::*;
+
+use camino::Utf8PathBuf;
+use kdl_script::parse::LangRepr;
+use std::process::Command;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::{debug, error, info, warn};
+
+pub type SortedMap<K, V> = std::collections::BTreeMap<K, V>;
+
+/// The exit codes of the abi-cafe CLI
+///
+/// These are stable, so scripts and CI can tell a real ABI bug from a broken setup.
+/// Any other code (i.e. 101 for a panic) means abi-cafe itself fell over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Every test passed (or was skipped)
+    Success = 0,
+    /// Some tests failed
+    TestsFailed = 1,
+    /// The CLI args were invalid
+    Usage = 2,
+    /// The toolchains, rules, or output couldn't be set up
+    Setup = 3,
+    /// Some test files couldn't be read or parsed
+    TestsFailedToLoad = 4,
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(code as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Human,
+    Json,
+    RustcJson,
+    /// One line per failure, for grep and diff
+    Compact,
+    /// A grid of toolchain pairs by conventions (see [`harness::heatmap`][])
+    Heatmap,
+}
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            OutputFormat::Human => "human",
+            OutputFormat::Json => "json",
+            OutputFormat::RustcJson => "rustc-json",
+            OutputFormat::Compact => "compact",
+            OutputFormat::Heatmap => "heatmap",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "human" => OutputFormat::Human,
+            "json" => OutputFormat::Json,
+            "rustc-json" => OutputFormat::RustcJson,
+            "compact" => OutputFormat::Compact,
+            "heatmap" => OutputFormat::Heatmap,
+            _ => return Err(format!("unknown output format: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+/// What order to start running tests in (`--order`)
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOrder {
+    /// The order the combinations are declared in
+    Declared,
+    /// The combinations most likely to fail first (see [`TestHarness::test_priority`][])
+    Priority,
+}
+impl std::fmt::Display for TestOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            TestOrder::Declared => "declared",
+            TestOrder::Priority => "priority",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for TestOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "declared" => TestOrder::Declared,
+            "priority" => TestOrder::Priority,
+            _ => return Err(format!("unknown test order: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+/// The format of `--emit-layouts`
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutFormat {
+    Human,
+    Json,
+}
+impl std::fmt::Display for LayoutFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            LayoutFormat::Human => "human",
+            LayoutFormat::Json => "json",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for LayoutFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "human" => LayoutFormat::Human,
+            "json" => LayoutFormat::Json,
+            _ => return Err(format!("unknown layout format: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Test,
+    Convention,
+    Pair,
+    Repr,
+}
+impl std::fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            GroupBy::Test => "test",
+            GroupBy::Convention => "convention",
+            GroupBy::Pair => "pair",
+            GroupBy::Repr => "repr",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "test" => GroupBy::Test,
+            "convention" => GroupBy::Convention,
+            "pair" => GroupBy::Pair,
+            "repr" => GroupBy::Repr,
+            _ => return Err(format!("unknown group-by: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+/// A tool that watches the test binaries run, to catch bugs comparing values can't
+/// (like reading padding or out of bounds), see `--instrument`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Instrument {
+    /// Run the test binaries under valgrind's memcheck
+    Valgrind,
+    /// Compile every side (and the harness) with LLVM's MemorySanitizer
+    Msan,
+}
+impl Instrument {
+    /// Every tool (for `--capabilities`)
+    pub const ALL: &'static [Self] = &[Self::Valgrind, Self::Msan];
+}
+impl std::fmt::Display for Instrument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Instrument::Valgrind => "valgrind",
+            Instrument::Msan => "msan",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for Instrument {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "valgrind" => Instrument::Valgrind,
+            "msan" | "memory" => Instrument::Msan,
+            _ => return Err(format!("unknown instrument: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+/// Which float args a RISC-V target passes in float registers (its `-mabi`)
+///
+/// This changes how structs of floats are passed: ilp32/lp64 pass everything in
+/// integer registers, the `f` variants only use float registers for f32s, and the `d`
+/// variants for f32s and f64s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiscvFloatAbi {
+    Ilp32,
+    Ilp32f,
+    Ilp32d,
+    Lp64,
+    Lp64f,
+    Lp64d,
+}
+impl RiscvFloatAbi {
+    /// The float ABI rustc uses for a target (None if it's not RISC-V)
+    ///
+    /// rustc's RISC-V targets always use the widest float registers they have,
+    /// so this is just whether the target has the D or F extension.
+    pub fn of_target(cfgs: &[cargo_platform::Cfg]) -> Option<Self> {
+        use std::str::FromStr;
+        let has_cfg = |cfg: &str| {
+            cfgs.contains(&cargo_platform::Cfg::from_str(cfg).expect("failed to parse cfg"))
+        };
+        let is_64bit = if has_cfg("target_arch=\"riscv64\"") {
+            true
+        } else if has_cfg("target_arch=\"riscv32\"") {
+            false
+        } else {
+            return None;
+        };
+        let abi = match (
+            is_64bit,
+            has_cfg("target_feature=\"d\""),
+            has_cfg("target_feature=\"f\""),
+        ) {
+            (true, true, _) => RiscvFloatAbi::Lp64d,
+            (true, false, true) => RiscvFloatAbi::Lp64f,
+            (true, false, false) => RiscvFloatAbi::Lp64,
+            (false, true, _) => RiscvFloatAbi::Ilp32d,
+            (false, false, true) => RiscvFloatAbi::Ilp32f,
+            (false, false, false) => RiscvFloatAbi::Ilp32,
+        };
+        Some(abi)
+    }
+
+    /// The `-march` a C compiler needs to have the float registers this ABI uses
+    pub fn march(&self) -> &'static str {
+        match self {
+            RiscvFloatAbi::Ilp32 => "rv32imac",
+            RiscvFloatAbi::Ilp32f => "rv32imafc",
+            RiscvFloatAbi::Ilp32d => "rv32gc",
+            RiscvFloatAbi::Lp64 => "rv64imac",
+            RiscvFloatAbi::Lp64f => "rv64imafc",
+            RiscvFloatAbi::Lp64d => "rv64gc",
+        }
+    }
+
+    /// The rustc target features for the float registers this ABI uses (like [`Self::march`][])
+    pub fn rustc_target_features(&self) -> Option<&'static str> {
+        match self {
+            RiscvFloatAbi::Ilp32 | RiscvFloatAbi::Lp64 => None,
+            RiscvFloatAbi::Ilp32f | RiscvFloatAbi::Lp64f => Some("+f"),
+            RiscvFloatAbi::Ilp32d | RiscvFloatAbi::Lp64d => Some("+f,+d"),
+        }
+    }
+}
+impl std::fmt::Display for RiscvFloatAbi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            RiscvFloatAbi::Ilp32 => "ilp32",
+            RiscvFloatAbi::Ilp32f => "ilp32f",
+            RiscvFloatAbi::Ilp32d => "ilp32d",
+            RiscvFloatAbi::Lp64 => "lp64",
+            RiscvFloatAbi::Lp64f => "lp64f",
+            RiscvFloatAbi::Lp64d => "lp64d",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for RiscvFloatAbi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "ilp32" => RiscvFloatAbi::Ilp32,
+            "ilp32f" => RiscvFloatAbi::Ilp32f,
+            "ilp32d" => RiscvFloatAbi::Ilp32d,
+            "lp64" => RiscvFloatAbi::Lp64,
+            "lp64f" => RiscvFloatAbi::Lp64f,
+            "lp64d" => RiscvFloatAbi::Lp64d,
+            _ => return Err(format!("unknown RISC-V float ABI: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+/// How a 32-bit ARM target uses its float registers (its `-mfloat-abi`)
+///
+/// soft and softfp pass every float in integer registers (softfp just does the math
+/// with the FPU), so they can be linked together. hard passes floats, and structs of up to
+/// four floats of the same type, in VFP registers, which is the aapcs-vfp convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArmFloatAbi {
+    Soft,
+    Softfp,
+    Hard,
+}
+impl ArmFloatAbi {
+    /// The float ABI rustc uses for a target (None if it's not 32-bit ARM)
+    pub fn of_target(cfgs: &[cargo_platform::Cfg]) -> Option<Self> {
+        use std::str::FromStr;
+        let has_cfg = |cfg: &str| {
+            cfgs.contains(&cargo_platform::Cfg::from_str(cfg).expect("failed to parse cfg"))
+        };
+        if !has_cfg("target_arch=\"arm\"") {
+            return None;
+        }
+        let abi = if has_cfg("target_abi=\"eabihf\"") {
+            ArmFloatAbi::Hard
+        } else if has_cfg("target_feature=\"vfp2\"") {
+            ArmFloatAbi::Softfp
+        } else {
+            ArmFloatAbi::Soft
+        };
+        Some(abi)
+    }
+
+    /// Whether floats get passed in VFP registers (so `extern "C"` is aapcs-vfp)
+    pub fn is_vfp(&self) -> bool {
+        *self == ArmFloatAbi::Hard
+    }
+
+    /// Whether objects built for these two float ABIs can be linked together
+    ///
+    /// soft and softfp pass floats the same way, so a C side can use either on a
+    /// target with the other one.
+    pub fn links_with(&self, other: ArmFloatAbi) -> bool {
+        self.is_vfp() == other.is_vfp()
+    }
+}
+impl std::fmt::Display for ArmFloatAbi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            ArmFloatAbi::Soft => "soft",
+            ArmFloatAbi::Softfp => "softfp",
+            ArmFloatAbi::Hard => "hard",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for ArmFloatAbi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "soft" => ArmFloatAbi::Soft,
+            "softfp" => ArmFloatAbi::Softfp,
+            "hard" => ArmFloatAbi::Hard,
+            _ => return Err(format!("unknown ARM float ABI: {s}")),
+        };
+        Ok(val)
+    }
+}
+
+/// The files that changed, to only run the tests they affect (`--since`, `--changed-files`)
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangedFiles {
+    /// Whatever git says changed since this revision
+    Since(String),
+    /// Exactly these files
+    List(Vec<Utf8PathBuf>),
+}
+
+/// Where to compare (or write) the generated source of every test (`--goldens`)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Goldens {
+    pub dir: Utf8PathBuf,
+    /// Overwrite the golden files instead of comparing to them (`--update-goldens`)
+    pub update: bool,
+}
+
+/// Only run one of several disjoint slices of the selected tests (`--shard`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Shard {
+    /// Which slice to run, counting from 1
+    pub index: usize,
+    /// How many slices the tests are split into
+    pub count: usize,
+}
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+impl std::str::FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |val: &str| val.trim().parse::<usize>().ok();
+        let Some((Some(index), Some(count))) = s.split_once('/').map(|(i, n)| (parse(i), parse(n)))
+        else {
+            return Err(format!("shards look like 2/4, not {s}"));
+        };
+        if index == 0 || index > count {
+            return Err(format!(
+                "shard {s} doesn't exist (they go from 1/{count} to {count}/{count})"
+            ));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Config {
+    pub output_format: OutputFormat,
+    pub output_file: Option<Utf8PathBuf>,
+    /// Also record the results here (see [`harness::sqlite`][])
+    pub sqlite: Option<Utf8PathBuf>,
+    pub sqlite_revision: Option<String>,
+    /// The work queue to resume the run from, and record it in (see [`harness::resume`][])
+    pub resume: Option<Utf8PathBuf>,
+    pub group_by: Option<GroupBy>,
+    pub order: TestOrder,
+    /// How many tests may be building or running at once
+    pub jobs: usize,
+    pub run_conventions: Vec<CallingConvention>,
+    pub run_mixed_conventions: Vec<(CallingConvention, CallingConvention)>,
+    pub run_reprs: Vec<LangRepr>,
+    pub run_mixed_reprs: Vec<(LangRepr, LangRepr)>,
+    pub run_toolchains: Vec<String>,
+    /// The toolchains the user explicitly named (with `--toolchains`, `--pairs`, or by
+    /// adding them), which are the only ones we complain about if they're idle
+    pub named_toolchains: Vec<String>,
+    pub run_pairs: Vec<(String, String)>,
+    pub run_tests: Vec<String>,
+    pub run_values: Vec<ValueGeneratorKind>,
+    pub run_writers: Vec<WriteImpl>,
+    pub run_selections: Vec<FunctionSelector>,
+    /// Selections by function name from `--select-vals` (replaces `run_selections` if non-empty)
+    pub select_vals: Vec<FuncSelection>,
+    pub interleave_counts: Vec<usize>,
+    /// How many values the stack_args test passes on the stack (empty disables it)
+    pub stack_args: Vec<usize>,
+    /// C headers to generate tests from (see [`harness::read`][])
+    pub import_headers: Vec<Utf8PathBuf>,
+    /// Extra args for libclang when parsing `import_headers` (`-I`, `-D`...)
+    pub header_clang_args: Vec<String>,
+    pub minimizing_write_impl: WriteImpl,
+    pub inout_refs: bool,
+    pub alias_refs: bool,
+    pub unwind: bool,
+    pub shuffle_rust_fields: bool,
+    pub dlopen: bool,
+    pub run_linkers: Vec<Linker>,
+    pub run_call_paths: Vec<CallPath>,
+    pub run_ltos: Vec<Lto>,
+    pub run_target_features: Vec<TargetFeatures>,
+    pub run_mixed_target_features: Vec<(TargetFeatures, TargetFeatures)>,
+    pub instrument: Option<Instrument>,
+    pub riscv_float_abi: Option<RiscvFloatAbi>,
+    pub arm_float_abi: Option<ArmFloatAbi>,
+    pub embed_source: bool,
+    /// Record this whole config in the report (see [`embedded_config`][])
+    pub embed_config: bool,
+    /// Write a Cargo project for every minimized failure with a Rust side
+    pub cargo_reproducers: bool,
+    pub codegen_size: bool,
+    /// Compile something with each toolchain before the tests start (see [`TestHarness::warm_up`][])
+    pub warmup: bool,
+    pub emit_llvm_ir: bool,
+    pub tail_call_chain: usize,
+    pub nested_calls: usize,
+    pub pad_byte: Option<u8>,
+    pub through_helpers: bool,
+    pub rounding_mode: Option<RoundingMode>,
+    pub fault_injection: Option<FaultInjection>,
+    pub error_on_skip: Option<Vec<SkipReason>>,
+    pub error_on_interop_only: bool,
+    pub error_on_idle_toolchains: bool,
+    pub rustc_codegen_backends: Vec<(String, String)>,
+    pub custom_c_toolchains: Vec<(String, Utf8PathBuf)>,
+    pub run_env: Vec<(String, String)>,
+    pub runner: Option<String>,
+    pub disable_builtin_tests: bool,
+    pub disable_builtin_rules: bool,
+    pub paths: Paths,
+    pub watch: bool,
+    pub fuzz: Option<FuzzConfig>,
+    pub skip_missing_toolchains: bool,
+    pub emit_layouts: Option<LayoutFormat>,
+    pub sysv_classes: bool,
+    pub layout_iterations: Option<usize>,
+    pub check_layouts: bool,
+    pub check_dwarf: bool,
+    pub count: bool,
+    pub capabilities: bool,
+    pub merge_reports: Vec<Utf8PathBuf>,
+    /// The old and new reports to compare, instead of running anything
+    pub compare_reports: Option<(Utf8PathBuf, Utf8PathBuf)>,
+    pub changed_files: Option<ChangedFiles>,
+    pub goldens: Option<Goldens>,
+    pub shard: Option<Shard>,
+    /// Only run the test with this id, and explain everything about it
+    pub explain: Option<String>,
+    pub debug: bool,
+}
+
+/// Settings for `--fuzz`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FuzzConfig {
+    /// The seed of the first iteration, each one after it counts up from there
+    /// (if None, every iteration gets a random seed)
+    pub first_seed: Option<u64>,
+    /// Stop after this many iterations (if None, keep going until something fails)
+    pub max_iterations: Option<u64>,
+    /// Keep going after a failure
+    pub keep_going: bool,
+}
+
+/// Run all the tests selected by the config and compute the final report
+///
+/// This is everything the abi-cafe CLI does except printing the report.
+/// Note that this spins up its own tokio runtime, so it mustn't be called
+/// from inside an async context.
+pub fn run(cfg: &Config) -> Result<FullReport, HarnessError> {
+    run_with_harness(cfg).map(|(_harness, report)| report)
+}
+
+/// Same as [`run`][] but also returns the [`TestHarness`][] that ran the tests,
+/// which is needed to print the report with [`FullReport::print_human`][] and friends.
+pub fn run_with_harness(cfg: &Config) -> Result<(Arc<TestHarness>, FullReport), HarnessError> {
+    cfg.paths.init_dirs()?;
+
+    let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
+    let _handle = rt.enter();
+
+    let harness = load_harness(cfg, &rt)?;
+    let (selected_pairs, excluded_pairs) = select_pairs(cfg)?;
+    let run_pairs = preflight_toolchains(cfg, &harness, selected_pairs.clone())?;
+
+    // Run the tests
+    let test_keys = test_keys(cfg, &harness, &run_pairs);
+    if let Some(id) = &cfg.explain {
+        if test_keys.is_empty() {
+            return Err(HarnessError::ExplainNotFound { id: id.clone() });
+        }
+    }
+
+    // Start the tests in priority order, but keep the report in the order they were declared
+    let mut run_order = (0..test_keys.len()).collect::<Vec<_>>();
+    if let TestOrder::Priority = cfg.order {
+        let priorities = harness.test_priorities(&test_keys);
+        // Stable, so equally interesting tests still run in declared order
+        run_order.sort_by_key(|&idx| std::cmp::Reverse(priorities[idx]));
+    }
+    if cfg.warmup {
+        rt.block_on(harness.warm_up(&test_keys));
+    }
+    let resume_queue = match &cfg.resume {
+        Some(path) => {
+            let ids = test_keys
+                .iter()
+                .map(|key| harness.base_id(key, None, "::"))
+                .collect::<Vec<_>>();
+            let target = &harness.toolchains.platform_info.target;
+            let fingerprint =
+                harness::resume::RunFingerprint::new(cfg, toolchain_versions(&harness, &run_pairs));
+            Some(Arc::new(harness::resume::ResumeQueue::open(
+                path,
+                target,
+                fingerprint,
+                &ids,
+            )?))
+        }
+        None => None,
+    };
+    let mut tasks = test_keys.iter().map(|_| None).collect::<Vec<_>>();
+    for idx in run_order {
+        let test_key = test_keys[idx].clone();
+        let rules = harness.get_test_rules(&test_key);
+        let id = harness.base_id(&test_key, None, "::");
+        if let Some(queue) = &resume_queue {
+            if let Some(report) = queue.resumed_report(&id, test_key.clone(), rules.clone()) {
+                tasks[idx] = Some(rt.spawn(std::future::ready(report)));
+                continue;
+            }
+        }
+        let task = harness.clone().spawn_test(&rt, rules, test_key);
+        // Record each test in the queue as soon as it's done, not when we get around to it
+        let resume_queue = resume_queue.clone();
+        tasks[idx] = Some(rt.spawn(async move {
+            let results = task.await.expect("failed to join task");
+            let report = report_test(id, results);
+            if let Some(queue) = resume_queue {
+                queue.record(&report);
+            }
+            report
+        }));
+    }
+
+    // Join on all the tasks, and compute their results
+    let reports = tasks
+        .into_iter()
+        .flatten()
+        .map(|task| rt.block_on(task).expect("failed to join task"))
+        .collect::<Vec<_>>();
+
+    // Compute the final report
+    let idle_toolchains = if cfg.explain.is_some() {
+        // Only running one test is the point
+        vec![]
+    } else {
+        idle_toolchains(cfg, &selected_pairs, &excluded_pairs, &run_pairs, &reports)
+    };
+    let mut full_report =
+        compute_final_report(cfg, &harness, reports, excluded_pairs, idle_toolchains);
+
+    if cfg.embed_source {
+        // Make failures self-contained for anyone without the tests checked out
+        for report in &mut full_report.tests {
+            if report.conclusion == TestConclusion::Failed {
+                report.source = Some(harness.test(&report.key.test).source.clone());
+            }
+        }
+    }
+
+    if cfg.embed_config {
+        full_report.config = Some(embedded_config(cfg));
+    }
+
+    if full_report.failed() {
+        generate_minimized_failures(cfg, &harness, &rt, &mut full_report);
+    }
+
+    Ok((harness, full_report))
+}
+
+/// The config as it goes in the report (`--embed-config`)
+///
+/// Paths under the current dir are made relative to it, and ones under the home dir
+/// start with `~`, so reports made on different machines (or by different users) can
+/// be compared, and don't leak more about the machine than they have to.
+pub fn embedded_config(cfg: &Config) -> serde_json::Value {
+    fn normalize(val: &mut serde_json::Value, prefixes: &[(String, &str)]) {
+        match val {
+            serde_json::Value::String(string) => {
+                for (prefix, replacement) in prefixes {
+                    if let Some(rest) = string.strip_prefix(prefix.as_str()) {
+                        if rest.is_empty() || rest.starts_with(std::path::MAIN_SEPARATOR) {
+                            *string = format!("{replacement}{rest}");
+                            break;
+                        }
+                    }
+                }
+            }
+            serde_json::Value::Array(vals) => {
+                for val in vals {
+                    normalize(val, prefixes);
+                }
+            }
+            serde_json::Value::Object(fields) => {
+                for val in fields.values_mut() {
+                    normalize(val, prefixes);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut prefixes = vec![];
+    if let Ok(dir) = std::env::current_dir() {
+        prefixes.push((dir.display().to_string(), "."));
+    }
+    if let Some(dir) = std::env::var_os("HOME") {
+        prefixes.push((dir.to_string_lossy().into_owned(), "~"));
+    }
+    let mut val = serde_json::to_value(cfg).expect("failed to serialize config!?");
+    normalize(&mut val, &prefixes);
+    val
+}
+
+/// Compute the layouts of the types in all the tests selected by the config
+///
+/// This doesn't build or run anything, it just reports what abi-cafe
+/// thinks the layouts should be on the current target.
+pub fn emit_layouts(cfg: &Config) -> Result<harness::layout::LayoutReport, HarnessError> {
+    let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
+    let _handle = rt.enter();
+
+    let harness = load_harness(cfg, &rt)?;
+    let tests = harness
+        .all_tests()
+        .into_iter()
+        .filter(|test| cfg.run_tests.is_empty() || cfg.run_tests.contains(&test.name))
+        .collect::<Vec<_>>();
+    let mut report = harness.layout_report(&tests, cfg.sysv_classes);
+    if let Some(iterations) = cfg.layout_iterations {
+        report.nondeterministic =
+            harness.check_layout_determinism(&tests, &report, cfg.sysv_classes, iterations);
+    }
+    Ok(report)
+}
+
+/// Check that the toolchains actually lay out the types in the selected tests
+/// the way abi-cafe computes them (see [`harness::layout_check`][])
+///
+/// This only builds tiny programs that print layouts, no tests are run.
+pub fn check_layouts(
+    cfg: &Config,
+) -> Result<harness::layout_check::LayoutCheckReport, HarnessError> {
+    cfg.paths.init_dirs()?;
+
+    let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
+    let _handle = rt.enter();
+
+    let harness = load_harness(cfg, &rt)?;
+    let (run_pairs, _excluded_pairs) = select_pairs(cfg)?;
+    let run_pairs = preflight_toolchains(cfg, &harness, run_pairs)?;
+    let toolchains = run_pairs
+        .into_iter()
+        .flat_map(|(caller_id, callee_id)| [caller_id, callee_id])
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let tests = harness
+        .all_tests()
+        .into_iter()
+        .filter(|test| cfg.run_tests.is_empty() || cfg.run_tests.contains(&test.name))
+        .collect::<Vec<_>>();
+    Ok(harness.check_layouts(&rt, &tests, toolchains, cfg.check_dwarf))
+}
+
+/// Find out everything this build (and machine) supports, without running anything
+/// (`--capabilities`)
+pub fn capabilities(cfg: &Config) -> Result<Capabilities, HarnessError> {
+    let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
+    let _handle = rt.enter();
+
+    let harness = load_harness(cfg, &rt)?;
+    fn names<T: ToString>(vals: &[T]) -> Vec<String> {
+        vals.iter().map(ToString::to_string).collect()
+    }
+    let toolchains = harness
+        .toolchains
+        .toolchains
+        .iter()
+        .map(|(id, toolchain)| ToolchainCapabilities {
+            id: id.clone(),
+            lang: toolchain.lang(),
+            available: toolchain.preflight().is_ok(),
+            version: toolchain.version(),
+            lto_unit: toolchain.lto_unit(),
+            msan: toolchain.supports_msan(),
+            target_features: toolchain.supports_target_features(),
+            cf_protection: toolchain.supports_cf_protection(),
+        })
+        .collect();
+    Ok(Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        report_schema_version: REPORT_SCHEMA_VERSION,
+        target: harness.toolchains.platform_info.target.clone(),
+        conventions: names(CallingConvention::ALL),
+        reprs: names(&[LangRepr::Rust, LangRepr::C]),
+        value_generators: vec!["graffiti".to_owned(), "random<seed>".to_owned()],
+        writers: names(WriteImpl::ALL),
+        linkers: names(Linker::ALL),
+        lto: names(Lto::ALL),
+        calls: names(CallPath::ALL),
+        faults: names(FaultInjection::ALL),
+        rounding_modes: names(RoundingMode::ALL),
+        instruments: names(Instrument::ALL),
+        toolchains,
+        tests: harness
+            .all_tests()
+            .iter()
+            .map(|test| test.name.clone())
+            .collect(),
+    })
+}
+
+/// Count the tests the config selects, without running anything (`--count`)
+///
+/// This goes through all the same filtering as [`run`][], so the total is exact.
+pub fn count_tests(cfg: &Config) -> Result<TestCount, HarnessError> {
+    let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
+    let _handle = rt.enter();
+
+    let harness = load_harness(cfg, &rt)?;
+    let (run_pairs, excluded_pairs) = select_pairs(cfg)?;
+    let run_pairs = preflight_toolchains(cfg, &harness, run_pairs)?;
+    let test_keys = test_keys(cfg, &harness, &run_pairs);
+    let num_skipped_by_rules = test_keys
+        .iter()
+        .filter(|key| harness.get_test_rules(key).run <= TestRunMode::Skip)
+        .count();
+    let num_tests = harness
+        .all_tests()
+        .iter()
+        .filter(|test| cfg.run_tests.is_empty() || cfg.run_tests.contains(&test.name))
+        .count();
+    Ok(TestCount {
+        axes: IndexMap::from([
+            ("tests", num_tests),
+            (
+                "conventions",
+                cfg.run_conventions.len() + cfg.run_mixed_conventions.len(),
+            ),
+            ("pairs", run_pairs.len()),
+            ("reprs", cfg.run_reprs.len() + cfg.run_mixed_reprs.len()),
+            ("values", cfg.run_values.len()),
+            ("writers", cfg.run_writers.len()),
+            ("linkers", cfg.run_linkers.len()),
+            ("calls", cfg.run_call_paths.len()),
+            ("lto", cfg.run_ltos.len()),
+            (
+                "features",
+                cfg.run_target_features.len() + cfg.run_mixed_target_features.len(),
+            ),
+            (
+                "selections",
+                if cfg.select_vals.is_empty() {
+                    cfg.run_selections.len()
+                } else {
+                    cfg.select_vals.len()
+                },
+            ),
+        ]),
+        num_excluded_pairs: excluded_pairs.len(),
+        total: test_keys.len(),
+        num_skipped_by_rules,
+    })
+}
+
+/// Every test the config selects, for these toolchain pairs
+fn test_keys(cfg: &Config, harness: &TestHarness, run_pairs: &[ToolchainPair]) -> Vec<TestKey> {
+    let mut test_keys = vec![];
+
+    // The cruel bastard that is combinatorics... THE GOD LOOPS
+    for test in harness.all_tests() {
+        if !cfg.run_tests.is_empty() && !cfg.run_tests.contains(&test.name) {
+            continue;
+        }
+        // Selections by name only apply to the tests that have those functions
+        let selections = if cfg.select_vals.is_empty() {
+            cfg.run_selections.clone()
+        } else {
+            cfg.select_vals
+                .iter()
+                .filter_map(|selection| selection.resolve(&test.types))
+                .collect()
+        };
+        let convention_pairs = cfg
+            .run_conventions
+            .iter()
+            .map(|&convention| (convention, convention))
+            .chain(cfg.run_mixed_conventions.iter().copied());
+        let target_feature_pairs = cfg
+            .run_target_features
+            .iter()
+            .map(|features| (features, features))
+            .chain(
+                cfg.run_mixed_target_features
+                    .iter()
+                    .map(|(caller, callee)| (caller, callee)),
+            );
+        for (convention, callee_convention) in convention_pairs {
+            if !test.has_convention(convention) || !test.has_convention(callee_convention) {
+                continue;
+            }
+            for (caller_id, callee_id) in run_pairs {
+                let repr_pairs = cfg
+                    .run_reprs
+                    .iter()
+                    .map(|&repr| (repr, repr))
+                    .chain(cfg.run_mixed_reprs.iter().copied());
+                for (repr, callee_repr) in repr_pairs {
+                    for &val_generator in &cfg.run_values {
+                        for &val_writer in &cfg.run_writers {
+                            for functions in &selections {
+                                for &linker in &cfg.run_linkers {
+                                    for &call_path in &cfg.run_call_paths {
+                                        for &lto in &cfg.run_ltos {
+                                            for (target_features, callee_target_features) in
+                                                target_feature_pairs.clone()
+                                            {
+                                                // Run the test!
+                                                let test_key = TestKey {
+                                                    test: test.name.to_owned(),
+                                                    caller: caller_id.to_owned(),
+                                                    callee: callee_id.to_owned(),
+                                                    options: TestOptions {
+                                                        convention,
+                                                        callee_convention,
+                                                        repr,
+                                                        callee_repr,
+                                                        val_writer,
+                                                        val_generator,
+                                                        functions: functions.clone(),
+                                                        inout_refs: cfg.inout_refs,
+                                                        alias_refs: cfg.alias_refs,
+                                                        tail_call_chain: cfg.tail_call_chain,
+                                                        nested_calls: cfg.nested_calls,
+                                                        unwind: cfg.unwind,
+                                                        fault: cfg.fault_injection,
+                                                        // Only repr(Rust) structs get shuffled
+                                                        shuffle_fields: cfg.shuffle_rust_fields
+                                                            && (repr == LangRepr::Rust
+                                                                || callee_repr == LangRepr::Rust),
+                                                        dlopen: cfg.dlopen,
+                                                        linker,
+                                                        call_path,
+                                                        pad_byte: cfg.pad_byte,
+                                                        through_helpers: cfg.through_helpers,
+                                                        rounding_mode: cfg.rounding_mode,
+                                                        lto,
+                                                        target_features: target_features.clone(),
+                                                        callee_target_features:
+                                                            callee_target_features.clone(),
+                                                    },
+                                                };
+                                                test_keys.push(test_key);
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(shard) = cfg.shard {
+        // Stripe the tests across the shards, but keep every pair of a test (with the
+        // same options, and with or without lto) in the same shard, so interop-only
+        // failures and lto-only passes can still be found
+        let mut groups = SortedMap::new();
+        test_keys.retain(|key| {
+            let mut pairless = TestKey {
+                caller: ToolchainId::new(),
+                callee: ToolchainId::new(),
+                ..key.clone()
+            };
+            pairless.options.lto = Lto::Off;
+            let next_group = groups.len();
+            let group = *groups
+                .entry(harness.base_id(&pairless, None, "::"))
+                .or_insert(next_group);
+            group % shard.count == shard.index - 1
+        });
+    }
+    if let Some(id) = &cfg.explain {
+        // Either of the forms test ids show up in (the report, or generated file names)
+        test_keys.retain(|key| {
+            harness.base_id(key, None, "::") == *id || harness.base_id(key, None, "_") == *id
+        });
+    }
+    test_keys
+}
+
+/// A caller toolchain id and a callee toolchain id
+type ToolchainPair = (String, String);
+
+/// Get the toolchain pairs we're going to test (and the ones `--pair-rules` excluded)
+fn select_pairs(cfg: &Config) -> Result<(Vec<ToolchainPair>, Vec<ExcludedPair>), HarnessError> {
+    let pair_rules = harness::find_pair_rules(cfg)?;
+    let run_pairs = cfg
+        .run_pairs
+        .iter()
+        .filter(|(caller_id, callee_id)| {
+            cfg.run_toolchains.is_empty()
+                || cfg.run_toolchains.iter().any(|x| x == caller_id)
+                || cfg.run_toolchains.iter().any(|x| x == callee_id)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let (run_pairs, excluded_pairs) = pair_rules.apply(run_pairs);
+    for excluded in &excluded_pairs {
+        info!(
+            "not running {}_calls_{}: {}",
+            excluded.caller, excluded.callee, excluded.reason
+        );
+    }
+    Ok((run_pairs, excluded_pairs))
+}
+
+/// Check that the compilers of all the toolchains in these pairs exist
+///
+/// With `--skip-missing-toolchains` pairs using a missing toolchain are dropped (with a warning),
+/// otherwise any missing toolchain is an error (instead of every test using it failing).
+fn preflight_toolchains(
+    cfg: &Config,
+    harness: &TestHarness,
+    run_pairs: Vec<(String, String)>,
+) -> Result<Vec<(String, String)>, HarnessError> {
+    let ids = run_pairs
+        .iter()
+        .flat_map(|(caller_id, callee_id)| [caller_id, callee_id])
+        .collect::<std::collections::BTreeSet<_>>();
+    let missing = harness.toolchains.preflight(ids);
+    if missing.is_empty() {
+        return Ok(run_pairs);
+    }
+    if !cfg.skip_missing_toolchains {
+        return Err(HarnessError::MissingToolchains { missing });
+    }
+    for e in &missing {
+        warn!("skipping every pairing with {}", e.toolchain);
+        warn!("{:?}", miette::Report::new(e.clone()));
+    }
+    Ok(run_pairs
+        .into_iter()
+        .filter(|(caller_id, callee_id)| {
+            !missing
+                .iter()
+                .any(|e| &e.toolchain == caller_id || &e.toolchain == callee_id)
+        })
+        .collect())
+}
+
+/// Find the toolchains we were asked to test that didn't run a single test, and guess why
+///
+/// `selected_pairs` are the pairs [`select_pairs`][] picked, and `run_pairs` are the ones
+/// that survived [`preflight_toolchains`][].
+fn idle_toolchains(
+    cfg: &Config,
+    selected_pairs: &[ToolchainPair],
+    excluded_pairs: &[ExcludedPair],
+    run_pairs: &[ToolchainPair],
+    reports: &[TestReport],
+) -> Vec<IdleToolchain> {
+    find_idle_toolchains(
+        &cfg.named_toolchains,
+        &cfg.run_pairs,
+        selected_pairs,
+        excluded_pairs,
+        run_pairs,
+        reports,
+    )
+}
+
+/// The guts of [`idle_toolchains`][], with `named` being the toolchains the user asked
+/// for and `requested_pairs` the pairs from `--pairs` (or the defaults)
+fn find_idle_toolchains(
+    named: &[String],
+    requested_pairs: &[ToolchainPair],
+    selected_pairs: &[ToolchainPair],
+    excluded_pairs: &[ExcludedPair],
+    run_pairs: &[ToolchainPair],
+    reports: &[TestReport],
+) -> Vec<IdleToolchain> {
+    let uses = |caller: &str, callee: &str, id: &str| caller == id || callee == id;
+    let in_pairs = |pairs: &[ToolchainPair], id: &str| {
+        pairs
+            .iter()
+            .any(|(caller, callee)| uses(caller, callee, id))
+    };
+    let named = named.iter().collect::<std::collections::BTreeSet<_>>();
+
+    let mut idle = vec![];
+    for id in named {
+        let reports = reports
+            .iter()
+            .filter(|report| uses(&report.key.caller, &report.key.callee, id))
+            .collect::<Vec<_>>();
+        if reports
+            .iter()
+            .any(|report| report.conclusion != TestConclusion::Skipped)
+        {
+            continue;
+        }
+        let reason = if !in_pairs(requested_pairs, id) {
+            "none of the --pairs use it".to_owned()
+        } else if !in_pairs(selected_pairs, id) {
+            if excluded_pairs
+                .iter()
+                .any(|excluded| uses(&excluded.caller, &excluded.callee, id))
+            {
+                "--pair-rules excluded every pair that uses it".to_owned()
+            } else {
+                "--toolchains filtered out every pair that uses it".to_owned()
+            }
+        } else if !in_pairs(run_pairs, id) {
+            "it isn't available (and --skip-missing-toolchains skipped it)".to_owned()
+        } else if reports.is_empty() {
+            "none of the selected tests ran with it".to_owned()
+        } else {
+            let mut num_skipped_by_reason = SortedMap::<SkipReason, usize>::new();
+            for report in &reports {
+                if let Some(reason) = report.skip_reason {
+                    *num_skipped_by_reason.entry(reason).or_default() += 1;
+                }
+            }
+            let reasons = num_skipped_by_reason
+                .iter()
+                .map(|(reason, count)| format!("{count} {reason}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("every test with it was skipped ({reasons})")
+        };
+        idle.push(IdleToolchain {
+            toolchain: id.clone(),
+            reason,
+        });
+    }
+    idle
+}
+
+/// Read all the tests and rules and set up the harness
+fn load_harness(
+    cfg: &Config,
+    rt: &tokio::runtime::Runtime,
+) -> Result<Arc<TestHarness>, HarnessError> {
+    // Grab all the tests
+    let test_rules = harness::find_test_rules(cfg)?;
+    let toolchain_manifests = toolchains::find_toolchain_manifests(cfg)?;
+    let test_sources = harness::find_tests(cfg)?;
+    let read_tasks = test_sources
+        .into_iter()
+        .map(|(test, test_file)| harness::spawn_read_test(rt, test, test_file));
+
+    // We could async pipeline this harder but it's nice to know all the tests upfront
+    // Also we want it to be a hard error for any test to fail to load, as this indicates
+    // an abi-cafe developer error
+    let mut tests = SortedMap::new();
+    let mut test_read_fails = false;
+    for task in read_tasks {
+        let res = rt.block_on(task).expect("failed to join on task");
+        match res {
+            Ok(test) => {
+                tests.insert(test.name.clone(), test);
+            }
+            Err(e) => {
+                test_read_fails = true;
+                error!("{:?}", miette::Report::new(e));
+            }
+        }
+    }
+    if test_read_fails {
+        return Err(HarnessError::TestsFailedToLoad);
+    }
+    debug!("loaded tests!");
+
+    let harness = Arc::new(TestHarness::new(
+        test_rules,
+        tests,
+        toolchain_manifests,
+        cfg,
+    ));
+    if let Some(abi) = cfg.riscv_float_abi {
+        let platform_info = &harness.toolchains.platform_info;
+        if platform_info.riscv_float_abi != Some(abi) {
+            return Err(HarnessError::RiscvFloatAbi {
+                abi,
+                target: platform_info.target.clone(),
+                target_abi: platform_info
+                    .riscv_float_abi
+                    .map(|abi| abi.to_string())
+                    .unwrap_or_else(|| "no RISC-V float ABI at all".to_owned()),
+            });
+        }
+    }
+    if let Some(abi) = cfg.arm_float_abi {
+        let platform_info = &harness.toolchains.platform_info;
+        if !platform_info
+            .arm_float_abi
+            .is_some_and(|target_abi| abi.links_with(target_abi))
+        {
+            return Err(HarnessError::ArmFloatAbi {
+                abi,
+                target: platform_info.target.clone(),
+                target_abi: platform_info
+                    .arm_float_abi
+                    .map(|abi| abi.to_string())
+                    .unwrap_or_else(|| "no ARM float ABI at all".to_owned()),
+            });
+        }
+    }
+    debug!("initialized test harness!");
+    Ok(harness)
+}
+
+fn compute_final_report(
+    cfg: &Config,
+    harness: &Arc<TestHarness>,
+    mut reports: Vec<TestReport>,
+    excluded_pairs: Vec<ExcludedPair>,
+    idle_toolchains: Vec<IdleToolchain>,
+) -> FullReport {
+    use TestConclusion::*;
+
+    // Don't let the order we ran things in leak into the report
+    reports.sort_by(|a, b| a.id.cmp(&b.id));
+    let timings = reports
+        .iter()
+        .map(|report| (report.id.clone(), report.results.timings.clone()))
+        .collect();
+
+    let mut expects = IndexMap::<TestKeyPattern, TestRulesPattern>::new();
+
+    let mut num_tests = 0;
+    let mut num_passed = 0;
+    let mut num_busted = 0;
+    let mut num_failed = 0;
+    let mut num_skipped = 0;
+    let mut num_skipped_by_reason = SortedMap::new();
+    let mut num_skip_errors = 0;
+    let mut num_interop_only = 0;
+    let mut num_interop_errors = 0;
+    let mut num_lto_only = 0;
+    mark_interop_only(harness, &mut reports);
+    mark_lto_only(harness, &mut reports);
+    for report in &reports {
+        num_tests += 1;
+        if report.lto_only {
+            num_lto_only += 1;
+        }
+        if report.interop_only {
+            num_interop_only += 1;
+            if cfg.error_on_interop_only && report.conclusion == Busted {
+                num_interop_errors += 1;
+            }
+        }
+        match report.conclusion {
+            Busted => num_busted += 1,
+            Skipped => {
+                num_skipped += 1;
+                if let Some(reason) = report.skip_reason {
+                    *num_skipped_by_reason.entry(reason).or_insert(0) += 1;
+                    if let Some(error_reasons) = &cfg.error_on_skip {
+                        if error_reasons.is_empty() || error_reasons.contains(&reason) {
+                            num_skip_errors += 1;
+                        }
+                    }
+                }
+            }
+            Passed => num_passed += 1,
+            Failed => {
+                num_failed += 1;
+                let pattern = harness.base_id(&report.key, None, "::");
+                if let Ok(pattern) = pattern.parse() {
+                    expects.insert(pattern, report.could_be.clone());
+                }
+            }
+        }
+    }
+
+    let possible_rules = if expects.is_empty() {
+        None
+    } else {
+        Some(ExpectFile {
+            target: IndexMap::from_iter([(
+                harness.toolchains.platform_info.target.clone(),
+                expects,
+            )]),
+        })
+    };
+
+    let toolchain_versions = toolchain_versions(harness, &cfg.run_pairs);
+
+    FullReport {
+        schema_version: REPORT_SCHEMA_VERSION,
+        target: harness.toolchains.platform_info.target.clone(),
+        riscv_float_abi: harness.toolchains.platform_info.riscv_float_abi,
+        arm_float_abi: cfg
+            .arm_float_abi
+            .or(harness.toolchains.platform_info.arm_float_abi),
+        shard: cfg.shard,
+        summary: TestSummary {
+            num_tests,
+            num_passed,
+            num_busted,
+            num_failed,
+            num_skipped,
+            num_skipped_by_reason,
+            num_skip_errors,
+            num_interop_only,
+            num_interop_errors,
+            num_lto_only,
+            num_idle_toolchain_errors: if cfg.error_on_idle_toolchains {
+                idle_toolchains.len() as u64
+            } else {
+                0
+            },
+        },
+        toolchain_versions,
+        run_env: harness.run_env.clone(),
+        runner: harness.runner.clone(),
+        config: None,
+        possible_rules,
+        excluded_pairs,
+        idle_toolchains,
+        tests: reports,
+        timings,
+    }
+}
+
+/// The version of each toolchain in these pairs
+fn toolchain_versions(
+    harness: &TestHarness,
+    pairs: &[(String, String)],
+) -> SortedMap<String, Option<String>> {
+    let mut toolchain_versions = SortedMap::new();
+    for (caller_id, callee_id) in pairs {
+        for id in [caller_id, callee_id] {
+            if toolchain_versions.contains_key(id) {
+                continue;
+            }
+            if let Some(toolchain) = harness.toolchains.toolchains.get(id) {
+                toolchain_versions.insert(id.clone(), toolchain.version());
+            }
+        }
+    }
+    toolchain_versions
+}
+
+/// Find the failures where both toolchains pass when they're paired with themselves
+///
+/// Those are genuine disagreements between two toolchains, instead of one of them
+/// being broken on its own.
+fn mark_interop_only(harness: &TestHarness, reports: &mut [TestReport]) {
+    let works = reports
+        .iter()
+        .filter(|report| report.conclusion != TestConclusion::Skipped)
+        .map(|report| (harness.base_id(&report.key, None, "::"), report.works()))
+        .collect::<SortedMap<_, _>>();
+    let self_pair_works = |key: &TestKey, toolchain: &ToolchainId| {
+        let self_pair = TestKey {
+            caller: toolchain.clone(),
+            callee: toolchain.clone(),
+            ..key.clone()
+        };
+        works
+            .get(&harness.base_id(&self_pair, None, "::"))
+            .copied()
+            .unwrap_or(false)
+    };
+    for report in reports {
+        let key = &report.key;
+        report.interop_only = report.conclusion != TestConclusion::Skipped
+            && key.caller != key.callee
+            && !report.works()
+            && self_pair_works(key, &key.caller)
+            && self_pair_works(key, &key.callee);
+    }
+}
+
+/// Find the tests that pass with LTO, but fail without it
+///
+/// LTO can inline the callee into the caller, and then there's no real call left
+/// to get the ABI wrong, so the pass doesn't mean much.
+fn mark_lto_only(harness: &TestHarness, reports: &mut [TestReport]) {
+    let works_without_lto = reports
+        .iter()
+        .filter(|report| {
+            report.key.options.lto == Lto::Off && report.conclusion != TestConclusion::Skipped
+        })
+        .map(|report| (harness.base_id(&report.key, None, "::"), report.works()))
+        .collect::<SortedMap<_, _>>();
+    for report in reports {
+        let mut without_lto = report.key.clone();
+        without_lto.options.lto = Lto::Off;
+        report.lto_only = report.key.options.lto != Lto::Off
+            && report.conclusion != TestConclusion::Skipped
+            && report.works()
+            && works_without_lto.get(&harness.base_id(&without_lto, None, "::")) == Some(&false);
+    }
+}
+
+fn generate_minimized_failures(
+    cfg: &Config,
+    harness: &Arc<TestHarness>,
+    rt: &tokio::runtime::Runtime,
+    reports: &mut FullReport,
+) {
+    if cfg.cargo_reproducers {
+        // Don't leave the reproducers of old runs lying around with the new ones
+        files::clear_and_create_dir(cfg.paths.reproducers_dir());
+    }
+    // Every minimization goes through the same pool as the main run, so they all
+    // get started up front and are taken in whatever order they finish
+    let mut tasks = tokio::task::JoinSet::new();
+    for (test_idx, report) in reports.tests.iter().enumerate() {
+        let Some(check) = report.results.check.as_ref() else {
+            continue;
+        };
+        // FIXME: certainly classes of run failure could also be minimized,
+        // because we have information indicating there was an error in a specific func!
+        for (subtest_idx, subtest) in check.subtest_checks.iter().enumerate() {
+            let Err(failure) = &subtest.result else {
+                continue;
+            };
+
+            let functions = match *failure {
+                CheckFailure::ValMismatch {
+                    func_idx,
+                    arg_idx,
+                    val_idx,
+                    ..
+                }
+                | CheckFailure::TagMismatch {
+                    func_idx,
+                    arg_idx,
+                    val_idx,
+                    ..
+                } => FunctionSelector::One {
+                    idx: func_idx,
+                    args: ArgSelector::One {
+                        idx: arg_idx,
+                        vals: ValSelector::One { idx: val_idx },
+                    },
+                },
+            };
+
+            let mut test_key = report.key.clone();
+            test_key.options.functions = functions;
+            // Pin the minimized test to the bytes the failure actually saw
+            if let Err(e) = rt.block_on(harness.record_replay(&report.key, &test_key, failure)) {
+                warn!(
+                    "couldn't record the values of {}: {e}",
+                    harness.full_test_name(&report.key)
+                );
+            }
+            // Reproducers need both sides to report their values, to compare them
+            let reproducer = (cfg.cargo_reproducers && harness.can_cargo_reproduce(&test_key))
+                .then(|| {
+                    let mut key = test_key.clone();
+                    key.options.val_writer = WriteImpl::HarnessCallback;
+                    (key, harness::reproducer::ReproducedVal::new(failure))
+                });
+            test_key.options.val_writer = cfg.minimizing_write_impl;
+            let rules = report.rules.clone();
+
+            let harness = harness.clone();
+            tasks.spawn_on(
+                async move {
+                    let results = harness.generate_limited(test_key, rules).await;
+                    let reproducer = match reproducer {
+                        Some((key, val)) => {
+                            match harness.write_cargo_reproducer(&key, &val).await {
+                                Ok(dir) => Some(dir),
+                                Err(e) => {
+                                    warn!("couldn't write a cargo reproducer: {e}");
+                                    None
+                                }
+                            }
+                        }
+                        None => None,
+                    };
+                    (test_idx, subtest_idx, results, reproducer)
+                },
+                rt.handle(),
+            );
+        }
+    }
+
+    info!("minimizing {} failures...", tasks.len());
+    while let Some(task) = rt.block_on(tasks.join_next()) {
+        let (test_idx, subtest_idx, results, reproducer) = task.expect("failed to join task");
+        let subtest = &mut reports.tests[test_idx]
+            .results
+            .check
+            .as_mut()
+            .unwrap()
+            .subtest_checks[subtest_idx];
+        if let (Some(Ok(sources)), Err(failure)) = (results.source, &subtest.result) {
+            let mut minimized = MinimizedFailure::new(sources, failure);
+            minimized.cargo_reproducer = reproducer;
+            subtest.minimized = Some(minimized);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::testing::*;
+
+    fn pair(caller: &str, callee: &str) -> ToolchainPair {
+        (caller.to_owned(), callee.to_owned())
+    }
+
+    #[test]
+    fn idle_only_named_toolchains() {
+        // `--pairs rustc_calls_rustc` (so cc is only there by default)
+        let pairs = vec![pair("rustc", "rustc")];
+        let reports = vec![test_report(
+            "simple",
+            "rustc",
+            "rustc",
+            TestConclusion::Passed,
+        )];
+        let named = vec!["rustc".to_owned()];
+        let idle = find_idle_toolchains(&named, &pairs, &pairs, &[], &pairs, &reports);
+        assert!(idle.is_empty(), "{idle:?}");
+
+        // `--toolchains cc --pairs rustc_calls_rustc` does name cc
+        let named = vec!["cc".to_owned(), "rustc".to_owned()];
+        let idle = find_idle_toolchains(&named, &pairs, &pairs, &[], &pairs, &reports);
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].toolchain, "cc");
+        assert_eq!(idle[0].reason, "none of the --pairs use it");
+    }
+
+    #[test]
+    fn idle_skipped_toolchains() {
+        let pairs = vec![pair("cc", "rustc"), pair("rustc", "rustc")];
+        let mut skipped = test_report("simple", "cc", "rustc", TestConclusion::Skipped);
+        skipped.skip_reason = Some(SkipReason::Unsupported);
+        let reports = vec![
+            skipped,
+            test_report("simple", "rustc", "rustc", TestConclusion::Passed),
+        ];
+        let named = vec!["cc".to_owned(), "rustc".to_owned()];
+        let idle = find_idle_toolchains(&named, &pairs, &pairs, &[], &pairs, &reports);
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].toolchain, "cc");
+        assert!(idle[0].reason.starts_with("every test with it was skipped"));
+    }
+
+    #[test]
+    fn riscv_float_registers() {
+        // Both sides have to agree on which float registers exist
+        for abi in ["ilp32", "ilp32f", "ilp32d", "lp64", "lp64f", "lp64d"] {
+            let abi: RiscvFloatAbi = abi.parse().unwrap();
+            let extensions = abi.march()[4..].replace('g', "imafd");
+            let features = abi.rustc_target_features().unwrap_or_default();
+            assert_eq!(extensions.contains('f'), features.contains("+f"), "{abi}");
+            assert_eq!(extensions.contains('d'), features.contains("+d"), "{abi}");
+            assert!(abi
+                .march()
+                .starts_with(if abi.to_string().starts_with("lp64") {
+                    "rv64"
+                } else {
+                    "rv32"
+                }));
+        }
+    }
+
+    #[test]
+    fn arm_float_abis_link() {
+        use ArmFloatAbi::*;
+        assert!(Soft.links_with(Softfp));
+        assert!(Softfp.links_with(Soft));
+        assert!(Hard.links_with(Hard));
+        assert!(!Hard.links_with(Softfp));
+        assert!(!Soft.links_with(Hard));
+    }
+}
@@ -72,23 +72,24 @@ impl MapLogger {
     pub fn new() -> Self {
         Self::default()
     }
-    /*
+    /// Forget everything that's been logged so far (except for spans that are still live)
     pub fn clear(&self) {
-        let mut log = self.state.lock().ok()?;
+        let mut log = self.state.lock().unwrap();
         let ids = log.sub_spans.keys().cloned().collect::<Vec<_>>();
         for id in ids {
-            let span = log.sub_spans.get_mut(&id)?;
+            let Some(span) = log.sub_spans.get_mut(&id) else {
+                continue;
+            };
             if !span.destroyed {
                 span.events.clear();
                 continue;
             }
             log.sub_spans.remove(&id);
+            log.test_spans.remove(&id);
         }
         log.root_span.events.clear();
         log.cur_string = None;
-        Some(())
     }
-    */
     fn print_span_if_test(&self, span_id: &Id) -> Result<(), std::fmt::Error> {
         let span = {
             let log = self.state.lock().unwrap();
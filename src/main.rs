@@ -1,311 +1,328 @@
-mod cli;
-mod error;
-mod files;
-mod fivemat;
-mod harness;
-mod log;
-mod toolchains;
+use abi_cafe::error::HarnessError;
+use abi_cafe::harness::vals::ValueGeneratorKind;
+use abi_cafe::log::MapLogger;
+use abi_cafe::*;
 
-use error::*;
-use files::Paths;
-use harness::report::*;
-use harness::test::*;
-use harness::vals::*;
-use harness::*;
-use indexmap::IndexMap;
-use toolchains::*;
-
-use kdl_script::parse::LangRepr;
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
 use std::error::Error;
-use std::process::Command;
 use std::sync::Arc;
-use tokio::sync::OnceCell;
+use std::time::Duration;
 use tracing::{debug, error, info};
 
-pub type SortedMap<K, V> = std::collections::BTreeMap<K, V>;
-
-#[derive(Debug, Clone, Copy)]
-pub enum OutputFormat {
-    Human,
-    Json,
-    RustcJson,
-}
-impl std::fmt::Display for OutputFormat {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string = match self {
-            OutputFormat::Human => "human",
-            OutputFormat::Json => "json",
-            OutputFormat::RustcJson => "rustc-json",
-        };
-        string.fmt(f)
-    }
-}
-impl std::str::FromStr for OutputFormat {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let val = match s {
-            "human" => OutputFormat::Human,
-            "json" => OutputFormat::Json,
-            "rustc-json" => OutputFormat::RustcJson,
-            _ => return Err(format!("unknown output format: {s}")),
-        };
-        Ok(val)
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct Config {
-    pub output_format: OutputFormat,
-    pub run_conventions: Vec<CallingConvention>,
-    pub run_reprs: Vec<LangRepr>,
-    pub run_toolchains: Vec<String>,
-    pub run_pairs: Vec<(String, String)>,
-    pub run_tests: Vec<String>,
-    pub run_values: Vec<ValueGeneratorKind>,
-    pub run_writers: Vec<WriteImpl>,
-    pub run_selections: Vec<FunctionSelector>,
-    pub minimizing_write_impl: WriteImpl,
-    pub rustc_codegen_backends: Vec<(String, String)>,
-    pub disable_builtin_tests: bool,
-    pub disable_builtin_rules: bool,
-    pub paths: Paths,
-    pub debug: bool,
-}
-
 #[derive(Debug, thiserror::Error)]
 #[error("some tests failed")]
 pub struct TestsFailed {}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cfg = cli::make_app();
-    debug!("parsed cli!");
-    cfg.paths.init_dirs()?;
+#[derive(Debug, thiserror::Error)]
+#[error("couldn't run the tests")]
+pub struct HarnessFailed {
+    exit_code: ExitCode,
+}
 
-    let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
-    let _handle = rt.enter();
+/// Harness errors are diagnostics, so log them properly instead of just debug-printing them
+fn log_harness_error(e: HarnessError) -> HarnessFailed {
+    let exit_code = e.exit_code();
+    error!("{:?}", miette::Report::new(e));
+    HarnessFailed { exit_code }
+}
 
-    // Grab all the tests
-    let test_rules = harness::find_test_rules(&cfg)?;
-    let test_sources = harness::find_tests(&cfg)?;
-    let read_tasks = test_sources
-        .into_iter()
-        .map(|(test, test_file)| harness::spawn_read_test(&rt, test, test_file));
+fn main() -> std::process::ExitCode {
+    // clap exits with 2 by itself for the args it rejects
+    let (cfg, logger) = match cli::make_app() {
+        Ok(app) => app,
+        Err(e) => {
+            // There's no logger yet
+            eprintln!("{:?}", miette::Report::new(e));
+            return ExitCode::Usage.into();
+        }
+    };
+    debug!("parsed cli!");
 
-    // We could async pipeline this harder but it's nice to know all the tests upfront
-    // Also we want it to be a hard error for any test to fail to load, as this indicates
-    // an abi-cafe developer error
-    let mut tests = SortedMap::new();
-    let mut test_read_fails = false;
-    for task in read_tasks {
-        let res = rt.block_on(task).expect("failed to join on task");
-        match res {
-            Ok(test) => {
-                tests.insert(test.name.clone(), test);
-            }
-            Err(e) => {
-                test_read_fails = true;
-                error!("{:?}", miette::Report::new(e));
+    let exit_code = match real_main(cfg, &logger) {
+        Ok(()) => ExitCode::Success,
+        Err(e) => {
+            if let Some(e) = e.downcast_ref::<HarnessFailed>() {
+                e.exit_code
+            } else if e.is::<TestsFailed>() {
+                ExitCode::TestsFailed
+            } else {
+                // Some io error writing the output or watching files
+                error!("{e}");
+                ExitCode::Setup
             }
         }
+    };
+    exit_code.into()
+}
+
+fn real_main(cfg: Config, logger: &MapLogger) -> Result<(), Box<dyn Error>> {
+    if cfg.watch {
+        return watch(&cfg, logger);
     }
-    if test_read_fails {
-        Err(TestsFailed {})?;
+    if let Some(fuzz_cfg) = &cfg.fuzz {
+        return fuzz(&cfg, fuzz_cfg, logger);
     }
-    debug!("loaded tests!");
-
-    let harness = Arc::new(TestHarness::new(test_rules, tests, &cfg));
-    debug!("initialized test harness!");
-
-    // Run the tests
-    let mut tasks = vec![];
-
-    // The cruel bastard that is combinatorics... THE GOD LOOPS
-    for test in harness.all_tests() {
-        if !cfg.run_tests.is_empty() && !cfg.run_tests.contains(&test.name) {
-            continue;
+    if let Some(format) = cfg.emit_layouts {
+        let layouts = emit_layouts(&cfg).map_err(log_harness_error)?;
+        let mut output = output_file(&cfg)?;
+        match format {
+            LayoutFormat::Human => layouts.print_human(&mut output)?,
+            LayoutFormat::Json => layouts.print_json(&mut output)?,
         }
-        for &convention in &cfg.run_conventions {
-            if !test.has_convention(convention) {
-                continue;
-            }
-            for (caller_id, callee_id) in &cfg.run_pairs {
-                if !cfg.run_toolchains.is_empty()
-                    && !cfg.run_toolchains.iter().any(|x| x == caller_id)
-                    && !cfg.run_toolchains.iter().any(|x| &**x == callee_id)
-                {
-                    continue;
-                }
-                for &repr in &cfg.run_reprs {
-                    for &val_generator in &cfg.run_values {
-                        for &val_writer in &cfg.run_writers {
-                            for functions in &cfg.run_selections {
-                                // Run the test!
-                                let test_key = TestKey {
-                                    test: test.name.to_owned(),
-                                    caller: caller_id.to_owned(),
-                                    callee: callee_id.to_owned(),
-                                    options: TestOptions {
-                                        convention,
-                                        repr,
-                                        val_writer,
-                                        val_generator,
-                                        functions: functions.clone(),
-                                    },
-                                };
-                                let rules = harness.get_test_rules(&test_key);
-                                let task = harness.clone().spawn_test(&rt, rules, test_key.clone());
-
-                                tasks.push(task);
-                            }
-                        }
-                    }
-                }
-            }
+        if layouts.failed() {
+            Err(TestsFailed {})?;
+        }
+        return Ok(());
+    }
+    if cfg.capabilities {
+        let capabilities = capabilities(&cfg).map_err(log_harness_error)?;
+        capabilities.print_json(output_file(&cfg)?)?;
+        return Ok(());
+    }
+    if cfg.count {
+        let count = count_tests(&cfg).map_err(log_harness_error)?;
+        let mut output = output_file(&cfg)?;
+        match cfg.output_format {
+            OutputFormat::Json => count.print_json(&mut output)?,
+            OutputFormat::Human
+            | OutputFormat::RustcJson
+            | OutputFormat::Compact
+            | OutputFormat::Heatmap => count.print_human(&mut output)?,
+        }
+        return Ok(());
+    }
+    if !cfg.merge_reports.is_empty() {
+        let report =
+            harness::multi_target::merge_reports(&cfg.merge_reports).map_err(log_harness_error)?;
+        let mut output = output_file(&cfg)?;
+        match cfg.output_format {
+            OutputFormat::Json => report.print_json(&mut output)?,
+            OutputFormat::Human
+            | OutputFormat::RustcJson
+            | OutputFormat::Compact
+            | OutputFormat::Heatmap => report.print_human(&mut output)?,
+        }
+        if report.failed() {
+            Err(TestsFailed {})?;
+        }
+        return Ok(());
+    }
+    if let Some((old, new)) = &cfg.compare_reports {
+        let comparison = harness::compare::compare_reports(old, new).map_err(log_harness_error)?;
+        let mut output = output_file(&cfg)?;
+        match cfg.output_format {
+            OutputFormat::Json => comparison.print_json(&mut output)?,
+            OutputFormat::Human
+            | OutputFormat::RustcJson
+            | OutputFormat::Compact
+            | OutputFormat::Heatmap => comparison.print_human(&mut output)?,
+        }
+        if comparison.failed() {
+            Err(TestsFailed {})?;
         }
+        return Ok(());
+    }
+    if cfg.check_layouts {
+        let report = check_layouts(&cfg).map_err(log_harness_error)?;
+        let mut output = output_file(&cfg)?;
+        match cfg.output_format {
+            OutputFormat::Json => report.print_json(&mut output)?,
+            OutputFormat::Human
+            | OutputFormat::RustcJson
+            | OutputFormat::Compact
+            | OutputFormat::Heatmap => report.print_human(&mut output)?,
+        }
+        if report.failed() {
+            Err(TestsFailed {})?;
+        }
+        return Ok(());
     }
-    // Join on all the tasks, and compute their results
-    let reports = tasks
-        .into_iter()
-        .map(|task| {
-            let results = rt.block_on(task).expect("failed to join task");
-            report_test(results)
-        })
-        .collect::<Vec<_>>();
 
-    // Compute the final report
-    let mut full_report = compute_final_report(&cfg, &harness, reports);
+    let (harness, full_report) = run_with_harness(&cfg).map_err(log_harness_error)?;
+    if cfg.explain.is_some() {
+        full_report.print_explain(&harness, &harness.commands(), output_file(&cfg)?)?;
+    } else {
+        print_report(&cfg, &harness, &full_report)?;
+    }
+    if let Some(db) = &cfg.sqlite {
+        full_report
+            .record_sqlite(db, cfg.sqlite_revision.as_deref())
+            .map_err(log_harness_error)?;
+    }
 
     if full_report.failed() {
-        generate_minimized_failures(&cfg, &harness, &rt, &mut full_report);
+        Err(TestsFailed {})?;
     }
+    Ok(())
+}
 
-    let mut output = std::io::stdout();
+fn print_report(
+    cfg: &Config,
+    harness: &TestHarness,
+    full_report: &FullReport,
+) -> Result<(), Box<dyn Error>> {
+    let mut output = output_file(cfg)?;
     match cfg.output_format {
-        OutputFormat::Human => full_report.print_human(&harness, &mut output)?,
-        OutputFormat::Json => full_report.print_json(&harness, &mut output)?,
-        OutputFormat::RustcJson => full_report.print_rustc_json(&harness, &mut output)?,
+        OutputFormat::Human => full_report.print_human(harness, cfg.group_by, &mut output)?,
+        OutputFormat::Json => full_report.print_json(harness, &mut output)?,
+        OutputFormat::RustcJson => full_report.print_rustc_json(harness, &mut output)?,
+        OutputFormat::Compact => full_report.print_compact(&mut output)?,
+        OutputFormat::Heatmap => full_report.print_heatmap(&mut output)?,
     }
+    Ok(())
+}
 
-    if full_report.failed() {
-        Err(TestsFailed {})?;
+/// Where to print the final output (`--output` or stdout)
+fn output_file(cfg: &Config) -> Result<Box<dyn std::io::Write>, Box<dyn Error>> {
+    if let Some(path) = &cfg.output_file {
+        // Colors are for terminals, don't put escape codes in the file
+        console::set_colors_enabled(false);
+        Ok(Box::new(std::fs::File::create(path)?))
+    } else {
+        Ok(Box::new(std::io::stdout()))
     }
-    Ok(())
 }
 
-fn compute_final_report(
-    _cfg: &Config,
-    harness: &Arc<TestHarness>,
-    reports: Vec<TestReport>,
-) -> FullReport {
-    use TestConclusion::*;
+/// Run the tests, and then run them again every time the test files change
+fn watch(cfg: &Config, logger: &MapLogger) -> Result<(), Box<dyn Error>> {
+    let test_dirs = &cfg.paths.runtime_test_input_dirs;
+    assert!(!test_dirs.is_empty(), "--watch requires --add-tests");
 
-    let mut expects = IndexMap::<TestKeyPattern, TestRulesPattern>::new();
+    // Rapid saves (or editors that write several files) should only cause one re-run
+    let (tx, rx) = std::sync::mpsc::channel();
+    let debounce = Duration::from_millis(500);
+    let mut debouncer = new_debouncer(debounce, tx)?;
+    for test_dir in test_dirs {
+        debouncer
+            .watcher()
+            .watch(test_dir.as_std_path(), RecursiveMode::Recursive)?;
+    }
+    if let Some(rules_file) = &cfg.paths.runtime_rules_file {
+        debouncer
+            .watcher()
+            .watch(rules_file.as_std_path(), RecursiveMode::NonRecursive)?;
+    }
 
-    let mut num_tests = 0;
-    let mut num_passed = 0;
-    let mut num_busted = 0;
-    let mut num_failed = 0;
-    let mut num_skipped = 0;
-    for report in &reports {
-        num_tests += 1;
-        match report.conclusion {
-            Busted => num_busted += 1,
-            Skipped => num_skipped += 1,
-            Passed => num_passed += 1,
-            Failed => {
-                num_failed += 1;
-                let pattern = harness.base_id(&report.key, None, "::");
-                if let Ok(pattern) = pattern.parse() {
-                    expects.insert(pattern, report.could_be.clone());
+    let mut prev_results = None;
+    loop {
+        match run_with_harness(cfg) {
+            Ok((harness, full_report)) => {
+                print_report(cfg, &harness, &full_report)?;
+                let results = conclusions(&harness, &full_report);
+                if let Some(prev_results) = &prev_results {
+                    print_delta(prev_results, &results, std::io::stderr())?;
                 }
+                prev_results = Some(results);
+            }
+            Err(e) => {
+                // Probably a typo in the test file being edited, just wait for the next save
+                error!("{:?}", miette::Report::new(e));
             }
         }
-    }
-
-    let possible_rules = if expects.is_empty() {
-        None
-    } else {
-        Some(ExpectFile {
-            target: IndexMap::from_iter([(
-                harness.toolchains.platform_info.target.clone(),
-                expects,
-            )]),
-        })
-    };
 
-    FullReport {
-        summary: TestSummary {
-            num_tests,
-            num_passed,
-            num_busted,
-            num_failed,
-            num_skipped,
-        },
-        possible_rules,
-        tests: reports,
+        let test_dirs = test_dirs.iter().map(|dir| dir.as_str()).collect::<Vec<_>>();
+        info!("watching {} for changes...", test_dirs.join(", "));
+        rx.recv()??;
+        // Drain anything else that piled up while we were waiting, or is about to
+        while rx.recv_timeout(debounce).is_ok() {}
+        logger.clear();
     }
 }
 
-fn generate_minimized_failures(
-    cfg: &Config,
-    harness: &Arc<TestHarness>,
-    rt: &tokio::runtime::Runtime,
-    reports: &mut FullReport,
-) {
-    info!("minimizing failures...");
-    let mut tasks = vec![];
-    for (test_idx, report) in reports.tests.iter().enumerate() {
-        let Some(check) = report.results.check.as_ref() else {
-            continue;
+/// Rerun the tests with fresh random values until something fails
+fn fuzz(cfg: &Config, fuzz_cfg: &FuzzConfig, logger: &MapLogger) -> Result<(), Box<dyn Error>> {
+    let mut failing_seeds = vec![];
+    let mut num_iterations = 0;
+    while fuzz_cfg
+        .max_iterations
+        .is_none_or(|max| num_iterations < max)
+    {
+        let seed = match fuzz_cfg.first_seed {
+            Some(first_seed) => first_seed.wrapping_add(num_iterations),
+            None => rand::random(),
         };
-        // FIXME: certainly classes of run failure could also be minimized,
-        // because we have information indicating there was an error in a specific func!
-        for (subtest_idx, subtest) in check.subtest_checks.iter().enumerate() {
-            let Err(failure) = &subtest.result else {
-                continue;
-            };
+        num_iterations += 1;
+        let mut iteration_cfg = cfg.clone();
+        iteration_cfg.run_values = vec![ValueGeneratorKind::Random { seed }];
 
-            let functions = match *failure {
-                CheckFailure::ValMismatch {
-                    func_idx,
-                    arg_idx,
-                    val_idx,
-                    ..
-                }
-                | CheckFailure::TagMismatch {
-                    func_idx,
-                    arg_idx,
-                    val_idx,
-                    ..
-                } => FunctionSelector::One {
-                    idx: func_idx,
-                    args: ArgSelector::One {
-                        idx: arg_idx,
-                        vals: ValSelector::One { idx: val_idx },
-                    },
-                },
-            };
+        let (harness, full_report) = run_with_harness(&iteration_cfg).map_err(log_harness_error)?;
+        let summary = &full_report.summary;
+        info!(
+            "fuzz iteration {num_iterations} (seed {seed}): {} passed, {} failed",
+            summary.num_passed, summary.num_failed
+        );
+        if full_report.failed() {
+            print_report(&iteration_cfg, &harness, &full_report)?;
+            error!("fuzz iteration {num_iterations} failed, reproduce it with --seed {seed}");
+            failing_seeds.push(seed);
+            if !fuzz_cfg.keep_going {
+                break;
+            }
+        }
+        logger.clear();
+    }
+
+    if failing_seeds.is_empty() {
+        info!("no failures in {num_iterations} fuzz iterations");
+        return Ok(());
+    }
+    if fuzz_cfg.keep_going {
+        let seeds = failing_seeds
+            .iter()
+            .map(|seed| seed.to_string())
+            .collect::<Vec<_>>();
+        error!(
+            "{}/{num_iterations} fuzz iterations failed, with seeds: {}",
+            failing_seeds.len(),
+            seeds.join(", ")
+        );
+    }
+    Err(TestsFailed {})?
+}
 
-            let mut test_key = report.key.clone();
-            test_key.options.functions = functions;
-            test_key.options.val_writer = cfg.minimizing_write_impl;
-            let mut rules = report.rules;
-            rules.run = TestRunMode::Generate;
+fn conclusions(
+    harness: &Arc<TestHarness>,
+    full_report: &FullReport,
+) -> SortedMap<String, TestConclusion> {
+    full_report
+        .tests
+        .iter()
+        .map(|test| (harness.base_id(&test.key, None, "::"), test.conclusion))
+        .collect()
+}
 
-            let task = harness.clone().spawn_test(rt, rules, test_key);
-            tasks.push((test_idx, subtest_idx, task));
+/// Print which tests changed their result since the last run
+///
+/// This goes to stderr, so it doesn't end up in the middle of the json (or `--output`).
+fn print_delta(
+    prev_results: &SortedMap<String, TestConclusion>,
+    results: &SortedMap<String, TestConclusion>,
+    mut f: impl std::io::Write,
+) -> Result<(), std::io::Error> {
+    let mut num_changed = 0;
+    writeln!(f)?;
+    writeln!(f, "changes since the last run:")?;
+    for (test, conclusion) in results {
+        match prev_results.get(test) {
+            Some(prev) if prev == conclusion => {}
+            Some(prev) => {
+                num_changed += 1;
+                writeln!(f, "  {test}: {prev} -> {conclusion}")?;
+            }
+            None => {
+                num_changed += 1;
+                writeln!(f, "  {test}: (new) {conclusion}")?;
+            }
         }
     }
-
-    for (test_idx, subtest_idx, task) in tasks {
-        let results = rt.block_on(task).expect("failed to join task");
-        reports.tests[test_idx]
-            .results
-            .check
-            .as_mut()
-            .unwrap()
-            .subtest_checks[subtest_idx]
-            .minimized = results.source.and_then(|r| r.ok());
+    for (test, prev) in prev_results {
+        if !results.contains_key(test) {
+            num_changed += 1;
+            writeln!(f, "  {test}: {prev} -> (removed)")?;
+        }
     }
+    if num_changed == 0 {
+        writeln!(f, "  (none)")?;
+    }
+    Ok(())
 }
@@ -4,12 +4,12 @@ mod declare;
 mod init;
 mod write;
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use kdl_script::types::*;
 use kdl_script::PunEnv;
 use std::collections::HashMap;
 use std::fmt::Write;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use super::super::*;
 use super::*;
@@ -18,14 +18,37 @@ use crate::harness::vals::ArgValuesIter;
 
 const CALLER_VALS: &str = "CALLER_VALS";
 const CALLEE_VALS: &str = "CALLEE_VALS";
+const CALLEE_PADDING: &str = "CALLEE_PADDING";
 const INDENT: &str = "    ";
 
 pub struct CcToolchain {
     cc_flavor: CCFlavor,
     target: String,
     platform: Platform,
+    /// Whether we're targeting 32-bit x86 (where thiscall exists)
+    is_x86: bool,
     mode: &'static str,
+    /// A specific compiler binary to use instead of the one on PATH
+    command: Option<Utf8PathBuf>,
+    overrides: CcOverrides,
     debug: bool,
+    /// Compile with MemorySanitizer (only clang can)
+    msan: bool,
+    /// The float ABI to pass to the compiler (on RISC-V)
+    riscv_float_abi: Option<RiscvFloatAbi>,
+    /// The float ABI to pass to the compiler (on 32-bit ARM)
+    arm_float_abi: Option<ArmFloatAbi>,
+    /// See [`CcToolchain::bitint_maxwidth`][]
+    bitint_maxwidth: OnceLock<Option<u64>>,
+}
+
+/// Tweaks to the C we generate (used by [toolchain manifests][super::manifest])
+#[derive(Debug, Default)]
+pub struct CcOverrides {
+    /// How to declare each calling convention (if None, use the builtin declarations)
+    pub conventions: Option<SortedMap<CallingConvention, String>>,
+    /// How to spell these primitive types
+    pub types: HashMap<PrimitiveTy, String>,
 }
 
 #[derive(PartialEq)]
@@ -69,8 +92,12 @@ impl Toolchain for CcToolchain {
     fn lang(&self) -> &'static str {
         "c"
     }
-    fn src_ext(&self) -> &'static str {
-        "c"
+    fn src_ext(&self, types: &TypedProgram, options: &TestOptions) -> &'static str {
+        if Self::is_cpp(types, options) {
+            "cpp"
+        } else {
+            "c"
+        }
     }
 
     fn pun_env(&self) -> Arc<PunEnv> {
@@ -79,18 +106,74 @@ impl Toolchain for CcToolchain {
         })
     }
 
+    fn version(&self) -> Option<String> {
+        let output = self.gnu_command()?.arg("--version").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        stdout.lines().next().map(|line| line.trim().to_owned())
+    }
+
+    fn preflight(&self) -> Result<(), MissingCompiler> {
+        let (binary, install_hint) = match self.mode {
+            TOOLCHAIN_MSVC => {
+                if cc::windows_registry::find_tool(&self.target, "cl.exe").is_some() {
+                    return Ok(());
+                }
+                (
+                    "cl.exe",
+                    "install the Visual Studio Build Tools with the \"Desktop development with C++\" workload",
+                )
+            }
+            _ if self.version().is_some() => return Ok(()),
+            TOOLCHAIN_CC => {
+                if let Some(command) = &self.command {
+                    return Err(MissingCompiler {
+                        binary: command.to_string(),
+                        install_hint: "fix the path passed to --add-toolchain".to_owned(),
+                    });
+                }
+                if self.cc_build().get_compiler().is_like_msvc() {
+                    // version() can't ask msvc, so assume cc found something that works
+                    return Ok(());
+                }
+                (
+                    "cc",
+                    "install a C compiler (e.g. `apt install build-essential` or `xcode-select --install`), or point $CC at one",
+                )
+            }
+            TOOLCHAIN_GCC => (
+                "gcc",
+                "install gcc (e.g. `apt install gcc` or `brew install gcc`)",
+            ),
+            TOOLCHAIN_CLANG => (
+                "clang",
+                "install clang (e.g. `apt install clang` or `xcode-select --install`)",
+            ),
+            TOOLCHAIN_ZIGCC => ("zig", "install zig (see https://ziglang.org/download/)"),
+            _ => return Ok(()),
+        };
+        Err(MissingCompiler {
+            binary: binary.to_owned(),
+            install_hint: install_hint.to_owned(),
+        })
+    }
+
     fn compile_callee(
         &self,
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         match self.mode {
-            "cc" => self.compile_cc(src_path, out_dir, lib_name),
-            "gcc" => self.compile_gcc(src_path, out_dir, lib_name),
-            "clang" => self.compile_clang(src_path, out_dir, lib_name),
-            "msvc" => self.compile_msvc(src_path, out_dir, lib_name),
-            "zigcc" => self.compile_zigcc(src_path, out_dir, lib_name),
+            "cc" => self.compile_cc(src_path, out_dir, lib_name, codegen, commands),
+            "gcc" => self.compile_gcc(src_path, out_dir, lib_name, codegen, commands),
+            "clang" => self.compile_clang(src_path, out_dir, lib_name, codegen, commands),
+            "msvc" => self.compile_msvc(src_path, out_dir, lib_name, codegen, commands),
+            "zigcc" => self.compile_zigcc(src_path, out_dir, lib_name, codegen, commands),
             _ => unimplemented!("unknown c compiler"),
         }
     }
@@ -100,17 +183,124 @@ impl Toolchain for CcToolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         match self.mode {
-            "cc" => self.compile_cc(src_path, out_dir, lib_name),
-            "gcc" => self.compile_gcc(src_path, out_dir, lib_name),
-            "clang" => self.compile_clang(src_path, out_dir, lib_name),
-            "msvc" => self.compile_msvc(src_path, out_dir, lib_name),
-            "zigcc" => self.compile_zigcc(src_path, out_dir, lib_name),
+            "cc" => self.compile_cc(src_path, out_dir, lib_name, codegen, commands),
+            "gcc" => self.compile_gcc(src_path, out_dir, lib_name, codegen, commands),
+            "clang" => self.compile_clang(src_path, out_dir, lib_name, codegen, commands),
+            "msvc" => self.compile_msvc(src_path, out_dir, lib_name, codegen, commands),
+            "zigcc" => self.compile_zigcc(src_path, out_dir, lib_name, codegen, commands),
             _ => unimplemented!("unknown c compiler"),
         }
     }
 
+    fn supports_target_features(&self) -> bool {
+        // cl.exe only has /arch
+        self.cc_flavor != CCFlavor::Msvc
+    }
+
+    fn lto_unit(&self) -> Option<LtoUnit> {
+        match self.cc_flavor {
+            CCFlavor::Gcc => Some(LtoUnit::Gcc),
+            CCFlavor::Clang => Some(LtoUnit::Llvm),
+            CCFlavor::Msvc | CCFlavor::Zigcc => None,
+        }
+    }
+
+    fn supports_msan(&self) -> bool {
+        match self.cc_flavor {
+            CCFlavor::Clang => true,
+            // gcc only has ASan/UBSan, -fsanitize=memory is an LLVM thing (and zig cc
+            // doesn't ship its runtime)
+            CCFlavor::Gcc | CCFlavor::Msvc | CCFlavor::Zigcc => false,
+        }
+    }
+
+    fn supports_cf_protection(&self) -> bool {
+        // cl.exe has /guard:cf instead, which checks targets against a table
+        self.cc_flavor != CCFlavor::Msvc
+    }
+
+    fn cc_crate_build(&self, codegen: &CodegenOptions) -> Option<CcCrateBuild> {
+        let compiler = match self.cc_flavor {
+            _ if self.mode == TOOLCHAIN_CC => self.command.as_ref().map(|c| c.to_string()),
+            CCFlavor::Gcc => Some("gcc".to_owned()),
+            CCFlavor::Clang => Some("clang".to_owned()),
+            // cc can't drive `zig cc`, and we can't build with msvc ourselves yet
+            CCFlavor::Zigcc | CCFlavor::Msvc => return None,
+        };
+        let mut flags = self.extra_flags();
+        flags.extend(codegen.cc_flags());
+        if self.msan {
+            flags.push("-fsanitize=memory".to_owned());
+        }
+        if let Some(flag) = self.lto_flag(codegen.lto) {
+            flags.push("-O2".to_owned());
+            flags.push(flag.to_owned());
+        }
+        Some(CcCrateBuild { compiler, flags })
+    }
+
+    fn emit_llvm_ir(
+        &self,
+        src_path: &Utf8Path,
+        ir_path: &Utf8Path,
+        codegen: &CodegenOptions,
+    ) -> Result<(), BuildError> {
+        let mut cmd = match self.cc_flavor {
+            _ if self.mode == TOOLCHAIN_CC => {
+                let compiler = self.cc_build().try_get_compiler()?;
+                if !compiler.is_like_clang() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        "cc didn't find an LLVM-based compiler",
+                    ))?;
+                }
+                compiler.to_command()
+            }
+            CCFlavor::Clang => Command::new("clang"),
+            CCFlavor::Zigcc => {
+                let mut cmd = Command::new("zig");
+                cmd.arg("cc");
+                cmd
+            }
+            CCFlavor::Gcc | CCFlavor::Msvc => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("{} doesn't use LLVM", self.mode),
+                ))?
+            }
+        };
+        if self.debug {
+            cmd.arg("-g");
+        }
+        if self.msan {
+            cmd.arg("-fsanitize=memory");
+        }
+        if let Some(flag) = self.lto_flag(codegen.lto) {
+            cmd.arg(flag).arg("-O2");
+        }
+        cmd.args(self.extra_flags())
+            .args(self.src_flags(src_path))
+            .args(codegen.cc_flags())
+            .arg("-S")
+            .arg("-emit-llvm")
+            .arg("-o")
+            .arg(ir_path)
+            .arg(src_path);
+        debug!("running: {:?}", cmd);
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(BuildError::IrCompile {
+                toolchain: self.mode.to_owned(),
+                output,
+            });
+        }
+        Ok(())
+    }
+
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
         let mut f = Fivemat::new(f, INDENT);
         let mut state = TestState::new(test);
@@ -122,6 +312,55 @@ impl Toolchain for CcToolchain {
         let mut state = TestState::new(test);
         self.generate_caller_impl(&mut f, &mut state)
     }
+
+    fn generate_layout_probe(
+        &self,
+        f: &mut dyn Write,
+        test: TestImpl,
+    ) -> Result<(), GenerateError> {
+        let mut f = Fivemat::new(f, INDENT);
+        let mut state = TestState::new(test);
+        self.generate_layout_probe_impl(&mut f, &mut state)
+    }
+
+    fn compile_layout_probe(
+        &self,
+        src_path: &Utf8Path,
+        exe_path: &Utf8Path,
+    ) -> Result<(), BuildError> {
+        let mut cmd = match self.cc_flavor {
+            CCFlavor::Msvc => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "msvc can't build layout probes yet",
+                ))?
+            }
+            _ if self.mode == TOOLCHAIN_CC => self.cc_build().try_get_compiler()?.to_command(),
+            CCFlavor::Zigcc => {
+                let mut cmd = Command::new("zig");
+                cmd.arg("cc");
+                cmd
+            }
+            CCFlavor::Gcc => Command::new("gcc"),
+            CCFlavor::Clang => Command::new("clang"),
+        };
+        // Always with debug info, for --check-dwarf
+        cmd.args(self.extra_flags())
+            .args(self.src_flags(src_path))
+            .arg("-g")
+            .arg("-o")
+            .arg(exe_path)
+            .arg(src_path);
+        debug!("running: {:?}", cmd);
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(BuildError::ProbeCompile {
+                toolchain: self.mode.to_owned(),
+                output,
+            });
+        }
+        Ok(())
+    }
 }
 
 impl CcToolchain {
@@ -130,20 +369,39 @@ impl CcToolchain {
         f: &mut Fivemat,
         state: &mut TestState,
     ) -> Result<(), GenerateError> {
+        if state.options.unwind {
+            return Err(UnsupportedError::Other(
+                "c callers can't catch unwinding".to_owned(),
+            ))?;
+        }
         // Generate type decls and gather up functions
         self.generate_definitions(f, state)?;
         // Generate decls of the functions we want to call
         self.generate_caller_externs(f, state)?;
+        if state.options.rounding_mode.is_some() {
+            writeln!(f, "#include <fenv.h>\n#include <stdlib.h>\n")?;
+        }
 
         // Generate the test function the harness will call
         writeln!(f, "void do_test(void) {{")?;
         f.add_indent(1);
+        if let Some(rounding_mode) = state.options.rounding_mode {
+            writeln!(
+                f,
+                "if (fesetround({}) != 0) abort();",
+                rounding_mode.fenv_name()
+            )?;
+        }
         for &func in &state.desired_funcs {
             // Generate the individual function calls
             self.generate_caller_body(f, state, func)?;
         }
+        if state.options.rounding_mode.is_some() {
+            writeln!(f, "fesetround(FE_TONEAREST);")?;
+        }
         f.sub_indent(1);
         writeln!(f, "}}")?;
+        self.write_harness_suffix(f, state)?;
 
         Ok(())
     }
@@ -163,15 +421,26 @@ impl CcToolchain {
 
         // Create vars for all the inputs
         let mut func_vals = state.vals.at_func(func);
-        for arg in &function.inputs {
+        let mut aliased = vec![];
+        for (input_idx, arg) in function.inputs.iter().enumerate() {
             let arg_vals: ArgValuesIter = func_vals.next_arg();
+            // References to other inputs are made once those all exist
+            if let Some(val_idx) = arg_vals.func().aliased_input(input_idx, &state.options) {
+                aliased.push((arg, &function.inputs[val_idx]));
+                continue;
+            }
             // Create and report the input
             self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
+            self.fill_padding(f, state, &arg.name, arg.ty)?;
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
         }
+        for (arg, val) in aliased {
+            let (pre, post) = &state.tynames[&arg.ty];
+            writeln!(f, "{pre}{}{post} = &{};", arg.name, val.name)?;
+        }
 
         // Call the function
-        self.call_function(f, state, function)?;
+        self.call_function(f, state, func)?;
 
         // Report all the outputs
         for arg in &function.outputs {
@@ -180,6 +449,15 @@ impl CcToolchain {
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
         }
 
+        // Report what the callee wrote through any in/out references
+        for arg_vals in func_vals {
+            let Some(input_idx) = arg_vals.arg().inout_of else {
+                continue;
+            };
+            let arg = &function.inputs[input_idx];
+            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+        }
+
         f.sub_indent(1);
         writeln!(f, "}}")?;
         Ok(())
@@ -189,12 +467,38 @@ impl CcToolchain {
         &self,
         f: &mut Fivemat,
         state: &TestState,
-        function: &Func,
+        func: FuncIdx,
     ) -> Result<(), GenerateError> {
-        let func_name = &function.name;
+        let function = state.types.realize_func(func);
+        let mut func_name = function.name.to_string();
 
         // make sure the outputs aren't weird
         self.check_returns(state, function)?;
+        if state.options.call_path.is_indirect() {
+            // Reading it back out of a volatile means it can't be devirtualized
+            let ptr_name = format!("{func_name}_ptr");
+            self.generate_func_ptr(f, state, func, CallSide::Caller, &ptr_name)?;
+            writeln!(f, " = {func_name};")?;
+            func_name = ptr_name;
+        }
+        let mut inputs = state.options.func_inputs(function, CallSide::Caller).iter();
+        // A thiscall function is a member function of a class that derives from what
+        // `this` points to, so call it on a copy and then copy back whatever it wrote
+        let thiscall_this = if state.options.convention == CallingConvention::Thiscall {
+            let (this, _) = self.thiscall_this(state, function, CallSide::Caller)?;
+            let this_name = format!("{}_this", this.name);
+            writeln!(
+                f,
+                "{} {this_name}{{ *{} }};",
+                Self::thiscall_class(function),
+                this.name
+            )?;
+            func_name = format!("{this_name}.{func_name}");
+            inputs.next();
+            Some((this, this_name))
+        } else {
+            None
+        };
         if let Some(arg) = function.outputs.first() {
             let (pre, post) = &state.tynames[&arg.ty];
             write!(f, "{pre}{}{post} = ", arg.name)?;
@@ -202,8 +506,6 @@ impl CcToolchain {
 
         // Call the function
         write!(f, "{func_name}(")?;
-        let inputs = function.inputs.iter();
-
         for (arg_idx, arg) in inputs.enumerate() {
             if arg_idx > 0 {
                 write!(f, ", ")?;
@@ -211,6 +513,9 @@ impl CcToolchain {
             write!(f, "{}", arg.name)?;
         }
         writeln!(f, ");")?;
+        if let Some((this, this_name)) = thiscall_this {
+            writeln!(f, "*{} = {this_name};", this.name)?;
+        }
         writeln!(f)?;
         Ok(())
     }
@@ -224,11 +529,75 @@ impl CcToolchain {
     ) -> Result<(), GenerateError> {
         // Generate type decls and gather up functions
         self.generate_definitions(f, state)?;
+        // The only thing C could unwind with is a foreign exception, which the Rust
+        // caller's `catch_unwind` always aborts on, so there'd be nothing to check
+        if state.options.unwind {
+            return Err(UnsupportedError::Other(
+                "c callees can't unwind into rust callers".to_owned(),
+            ))?;
+        }
+        if state.options.nested_calls > 0 {
+            writeln!(f, "#define ABI_CAFE_NOINLINE {}", self.noinline_decl())?;
+            writeln!(
+                f,
+                "{}",
+                crate::files::get_file("harness/c/nested_calls_prefix.h")
+            )?;
+        }
 
         for &func in &state.desired_funcs {
             // Generate the individual function definitions
             self.generate_callee_body(f, state, func)?;
         }
+        self.write_harness_suffix(f, state)?;
+        Ok(())
+    }
+
+    fn generate_layout_probe_impl(
+        &self,
+        f: &mut Fivemat,
+        state: &mut TestState,
+    ) -> Result<(), GenerateError> {
+        // Generate type decls and gather up functions
+        self.generate_definitions(f, state)?;
+        writeln!(f, "#include <stddef.h>\n")?;
+        // C++ only has alignof
+        let alignof = if Self::is_cpp(&state.types, &state.options) {
+            "alignof"
+        } else {
+            "_Alignof"
+        };
+
+        writeln!(f, "int main(void) {{")?;
+        f.add_indent(1);
+        for def in state.defs.definitions(state.desired_funcs.iter().copied()) {
+            let kdl_script::Definition::DefineTy(ty) = def else {
+                continue;
+            };
+            let (name, fields) = match state.types.realize_ty(ty) {
+                Ty::Struct(struct_ty) => (&struct_ty.name, Some(&struct_ty.fields)),
+                Ty::Union(union_ty) => (&union_ty.name, None),
+                Ty::Enum(enum_ty) => (&enum_ty.name, None),
+                _ => continue,
+            };
+            // A pointer variable makes sure the type ends up in the debug info
+            writeln!(f, "{name} *probe_{name} = NULL; (void)probe_{name};")?;
+            writeln!(
+                f,
+                r#"printf("ty {name} %llu %llu\n", (unsigned long long)sizeof({name}), (unsigned long long){alignof}({name}));"#
+            )?;
+            for field in fields.into_iter().flatten() {
+                let field_name = &field.ident;
+                writeln!(
+                    f,
+                    r#"printf("field {name} {field_name} %llu\n", (unsigned long long)offsetof({name}, {field_name}));"#
+                )?;
+            }
+        }
+        writeln!(f, "return 0;")?;
+        f.sub_indent(1);
+        writeln!(f, "}}")?;
+        self.write_harness_suffix(f, state)?;
         Ok(())
     }
 
@@ -239,13 +608,64 @@ impl CcToolchain {
         func: FuncIdx,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
-        self.generate_signature(f, state, func)?;
-        writeln!(f, " {{")?;
-        f.add_indent(1);
+        let chain_len = state.options.tail_call_chain;
+        let link_name = |link: usize| {
+            if link == 0 {
+                function.name.to_string()
+            } else {
+                format!("{}_tail{link}", function.name)
+            }
+        };
+
+        // If we're making a tail call chain, the last link is the one that does the work
+        let musttail = if chain_len > 0 {
+            // musttail only applies to `return f(...)`, which C doesn't allow in a void
+            // function (C++ does)
+            if function.outputs.is_empty() && !Self::is_cpp(&state.types, &state.options) {
+                Err(UnsupportedError::Other(
+                    "tail call chains of functions that return void need C++".to_owned(),
+                ))?
+            }
+            let musttail = self.musttail_decl()?;
+            write!(f, "static {}", self.noinline_decl())?;
+            musttail
+        } else {
+            ""
+        };
+        if state.options.convention == CallingConvention::Thiscall {
+            // Define the member function, and give `this` the name the input has
+            self.generate_thiscall_class(f, state, func, CallSide::Callee)?;
+            let (this, this_ty) = self.thiscall_this(state, function, CallSide::Callee)?;
+            let class = format!("{}::", Self::thiscall_class(function));
+            self.generate_thiscall_signature(f, state, func, CallSide::Callee, &class)?;
+            writeln!(f, " {{")?;
+            f.add_indent(1);
+            let this_tyname = state.tynames[&this_ty].0.trim_end();
+            writeln!(f, "{this_tyname} *{} = this;", this.name)?;
+        } else {
+            self.generate_signature_named(f, state, func, CallSide::Callee, &link_name(chain_len))?;
+            writeln!(f, " {{")?;
+            f.add_indent(1);
+        }
 
         // Report we're starting a function
         self.write_set_function(f, state, CALLEE_VALS, func)?;
 
+        // Write through references that alias by-value inputs before reporting those,
+        // so if the "copy" is really the caller's variable it has the new value
+        for arg_vals in state.vals.at_func(func) {
+            let Some(input_idx) = arg_vals.arg().inout_of else {
+                continue;
+            };
+            if arg_vals
+                .func()
+                .aliased_input(input_idx, &state.options)
+                .is_some()
+            {
+                self.write_through(f, state, &function.inputs[input_idx], arg_vals)?;
+            }
+        }
+
         // Report the inputs
         let mut func_vals = state.vals.at_func(func);
         for arg in &function.inputs {
@@ -253,6 +673,13 @@ impl CcToolchain {
             let arg_name = &arg.name;
             self.write_var(f, state, arg_name, arg.ty, arg_vals, CALLEE_VALS)?;
         }
+        self.write_padding(f, state, func, function)?;
+
+        // Clobber everything a call is allowed to, so whatever the callee has to preserve
+        // for its caller actually has to be saved somewhere
+        if state.options.nested_calls > 0 {
+            writeln!(f, "abi_cafe_nested_call({});", state.options.nested_calls)?;
+        }
 
         // Create outputs and report them
         for arg in &function.outputs {
@@ -261,6 +688,22 @@ impl CcToolchain {
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
         }
 
+        // Write new values through any in/out references and report them
+        for arg_vals in func_vals {
+            let Some(input_idx) = arg_vals.arg().inout_of else {
+                continue;
+            };
+            let func_vals = arg_vals.func();
+            if !func_vals.writes_through(input_idx, &state.options) {
+                continue;
+            }
+            let arg = &function.inputs[input_idx];
+            if func_vals.aliased_input(input_idx, &state.options).is_none() {
+                self.write_through(f, state, arg, arg_vals.clone())?;
+            }
+            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
+        }
+
         // Return the outputs
         self.check_returns(state, function)?;
         if let Some(arg) = function.outputs.first() {
@@ -268,19 +711,69 @@ impl CcToolchain {
         }
         f.sub_indent(1);
         writeln!(f, "}}")?;
+
+        // All the other links (including the actual exported function) just
+        // tail call the next link with all their inputs
+        for link in (0..chain_len).rev() {
+            writeln!(f)?;
+            if link > 0 {
+                write!(f, "static {}", self.noinline_decl())?;
+            }
+            self.generate_signature_named(f, state, func, CallSide::Callee, &link_name(link))?;
+            writeln!(f, " {{")?;
+            f.add_indent(1);
+            write!(f, "{musttail}return {}(", link_name(link + 1))?;
+            for (arg_idx, arg) in function.inputs.iter().enumerate() {
+                if arg_idx > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", arg.name)?;
+            }
+            writeln!(f, ");")?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+        }
         Ok(())
     }
 }
 
 impl CcToolchain {
-    pub fn new(system_info: &Config, target: &str, mode: &'static str) -> Self {
+    /// Write the new value of an in/out reference through it
+    fn write_through(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        arg: &Arg,
+        arg_vals: ArgValuesIter,
+    ) -> Result<(), GenerateError> {
+        let arg_name = &arg.name;
+        let new_name = format!("{arg_name}_after");
+        self.init_var(f, state, &new_name, arg.ty, arg_vals)?;
+        writeln!(f, "memcpy({arg_name}, {new_name}, sizeof(*{arg_name}));")?;
+        Ok(())
+    }
+
+    pub fn new(
+        system_info: &Config,
+        target: &str,
+        mode: &'static str,
+        command: Option<Utf8PathBuf>,
+    ) -> Self {
+        assert!(
+            command.is_none() || mode == TOOLCHAIN_CC,
+            "custom compiler paths are only supported for the cc toolchain"
+        );
         let cc_flavor = match mode {
             TOOLCHAIN_GCC => CCFlavor::Gcc,
             TOOLCHAIN_CLANG => CCFlavor::Clang,
             TOOLCHAIN_MSVC => CCFlavor::Msvc,
             TOOLCHAIN_ZIGCC => CCFlavor::Zigcc,
             TOOLCHAIN_CC => {
-                let compiler = cc::Build::new()
+                let mut build = cc::Build::new();
+                if let Some(command) = &command {
+                    build.compiler(command);
+                }
+                let compiler = build
                     .cargo_metadata(false)
                     .cargo_debug(false)
                     .cargo_warnings(false)
@@ -304,23 +797,174 @@ impl CcToolchain {
         } else {
             Platform::Unixy
         };
+        let is_x86 = target
+            .split('-')
+            .next()
+            .is_some_and(|arch| matches!(arch, "i386" | "i486" | "i586" | "i686"));
 
         Self {
             cc_flavor,
             target: target.to_owned(),
             platform,
+            is_x86,
             mode,
+            command,
+            overrides: CcOverrides::default(),
             debug: system_info.debug,
+            msan: system_info.instrument == Some(Instrument::Msan),
+            riscv_float_abi: None,
+            arm_float_abi: None,
+            bitint_maxwidth: OnceLock::new(),
         }
     }
 
-    fn extra_flags(&self) -> &[&str] {
-        match self.cc_flavor {
-            CCFlavor::Gcc if cfg!(target_arch = "arm") => &["-mfp16-format=ieee"],
+    /// Make the compiler use this float ABI (see `--riscv-float-abi`)
+    pub fn with_riscv_float_abi(mut self, abi: Option<RiscvFloatAbi>) -> Self {
+        self.riscv_float_abi = abi;
+        self
+    }
+
+    /// Make the compiler use this float ABI (see `--arm-float-abi`)
+    pub fn with_arm_float_abi(mut self, abi: Option<ArmFloatAbi>) -> Self {
+        self.arm_float_abi = abi;
+        self
+    }
+
+    /// Generate slightly different C (see [`CcOverrides`][])
+    pub fn with_overrides(mut self, overrides: CcOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// A command that runs our compiler, if it takes gcc-style arguments
+    fn gnu_command(&self) -> Option<Command> {
+        let cmd = match self.mode {
+            TOOLCHAIN_CC => {
+                let compiler = self.cc_build().get_compiler();
+                if compiler.is_like_msvc() {
+                    return None;
+                }
+                Command::new(compiler.path())
+            }
+            TOOLCHAIN_GCC => Command::new("gcc"),
+            TOOLCHAIN_CLANG => Command::new("clang"),
+            TOOLCHAIN_ZIGCC => {
+                let mut cmd = Command::new("zig");
+                cmd.arg("cc");
+                cmd
+            }
+            _ => return None,
+        };
+        Some(cmd)
+    }
+
+    /// The widest `_BitInt` the compiler supports (`__BITINT_MAXWIDTH__`)
+    ///
+    /// None means it doesn't have `_BitInt` at all (gcc before 14, clang before 16, msvc).
+    fn bitint_maxwidth(&self) -> Option<u64> {
+        *self.bitint_maxwidth.get_or_init(|| {
+            let mut cmd = self.gnu_command()?;
+            cmd.args(self.extra_flags())
+                .args(["-dM", "-E", "-x", "c", "-"])
+                .stdin(std::process::Stdio::null());
+            debug!("running: {:?}", cmd);
+            let output = cmd.output().ok()?;
+            let defines = String::from_utf8(output.stdout).ok()?;
+            defines.lines().find_map(|line| {
+                line.strip_prefix("#define __BITINT_MAXWIDTH__ ")?
+                    .trim()
+                    .parse()
+                    .ok()
+            })
+        })
+    }
+
+    /// Get a cc::Build with our compiler selected
+    fn cc_build(&self) -> cc::Build {
+        let mut build = cc::Build::new();
+        if let Some(command) = &self.command {
+            build.compiler(command);
+        }
+        build
+            .cargo_metadata(false)
+            .cargo_debug(false)
+            .cargo_warnings(false)
+            .cargo_output(false)
+            .target(&self.target);
+        build
+    }
+
+    /// The flag that makes the compiler emit objects for LTO (see [`Lto`][])
+    fn lto_flag(&self, lto: Lto) -> Option<&'static str> {
+        match (lto, &self.cc_flavor) {
+            (Lto::Off, _) => None,
+            (_, CCFlavor::Gcc) => Some("-flto"),
+            (Lto::Thin, _) => Some("-flto=thin"),
+            (Lto::Fat, _) => Some("-flto=full"),
+        }
+    }
+
+    fn extra_flags(&self) -> Vec<String> {
+        let mut flags = match self.cc_flavor {
+            CCFlavor::Gcc if cfg!(target_arch = "arm") => vec!["-mfp16-format=ieee".to_owned()],
             CCFlavor::Clang if cfg!(all(target_arch = "powerpc64", target_endian = "little")) => {
-                &["-mfloat128"]
+                vec!["-mfloat128".to_owned()]
+            }
+            _ => vec![],
+        };
+        // Cross compilers don't always default to the float ABI of the target we're
+        // linking against, so always say which one we want. The -march is just so the
+        // float registers it needs exist, so one the user gave cc wins.
+        if let Some(abi) = self.riscv_float_abi {
+            if self.cc_flavor != CCFlavor::Msvc {
+                if !self.user_sets_march() {
+                    flags.push(format!("-march={}", abi.march()));
+                }
+                flags.push(format!("-mabi={abi}"));
+            }
+        }
+        if let Some(abi) = self.arm_float_abi {
+            if self.cc_flavor != CCFlavor::Msvc {
+                flags.push(format!("-mfloat-abi={abi}"));
             }
-            _ => &[],
+        }
+        flags
+    }
+
+    /// Whether the user passed cc a `-march` of their own (the `cc` crate adds `CFLAGS`
+    /// before our flags, so ours would override it)
+    fn user_sets_march(&self) -> bool {
+        if self.mode != TOOLCHAIN_CC {
+            return false;
+        }
+        let target = &self.target;
+        [
+            format!("CFLAGS_{target}"),
+            format!("CFLAGS_{}", target.replace('-', "_")),
+            "TARGET_CFLAGS".to_owned(),
+            "CFLAGS".to_owned(),
+        ]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .any(|cflags| {
+            cflags
+                .split_whitespace()
+                .any(|flag| flag.starts_with("-march="))
+        })
+    }
+
+    /// Flags for compiling this particular source file
+    ///
+    /// Tests with @nontrivial types are C++, which we build without exceptions
+    /// so linking them doesn't need the C++ runtime. They're C++20 because that's the
+    /// first version with the designated initializers we initialize structs with.
+    fn src_flags(&self, src_path: &Utf8Path) -> Vec<&'static str> {
+        if src_path.extension() != Some("cpp") {
+            vec![]
+        } else if self.cc_flavor == CCFlavor::Msvc {
+            vec!["/std:c++20"]
+        } else {
+            vec!["-std=c++20", "-fno-exceptions"]
         }
     }
 
@@ -329,23 +973,36 @@ impl CcToolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
-        let mut build = cc::Build::new();
+        let mut build = self.cc_build();
         for flag in self.extra_flags() {
             build.flag(flag);
         }
+        for flag in self.src_flags(src_path) {
+            build.flag(flag);
+        }
+        if self.msan {
+            build.flag("-fsanitize=memory");
+        }
+        for flag in codegen.cc_flags() {
+            build.flag(flag);
+        }
+        if let Some(flag) = self.lto_flag(codegen.lto) {
+            build.flag(flag);
+        }
         build
             .file(src_path)
-            .opt_level(0)
+            .opt_level(if codegen.lto == Lto::Off { 0 } else { 2 })
             .debug(self.debug)
-            .cargo_metadata(false)
-            .cargo_debug(false)
-            .cargo_warnings(false)
-            .cargo_output(false)
-            .target(&self.target)
-            .out_dir(out_dir)
-            // .warnings_into_errors(true)
-            .try_compile(lib_name)?;
+            .out_dir(out_dir);
+        if let Ok(compiler) = build.try_get_compiler() {
+            // cc adds the output and source itself
+            commands.record_line(format!("{:?} -c {src_path}", compiler.to_command()));
+        }
+        // .warnings_into_errors(true)
+        build.try_compile(lib_name)?;
         Ok(String::from(lib_name))
     }
 
@@ -354,6 +1011,8 @@ impl CcToolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         let obj_path = out_dir.join(format!("{lib_name}.o"));
         let lib_path = out_dir.join(format!("lib{lib_name}.a"));
@@ -361,23 +1020,34 @@ impl CcToolchain {
         for flag in self.extra_flags() {
             cmd.arg(flag);
         }
+        cmd.args(self.src_flags(src_path));
+        cmd.args(codegen.cc_flags());
         if self.debug {
             cmd.arg("-g");
         }
+        if self.msan {
+            cmd.arg("-fsanitize=memory");
+        }
+        if let Some(flag) = self.lto_flag(codegen.lto) {
+            cmd.arg("-O2").arg(flag);
+        }
         cmd.arg("-ffunction-sections")
             .arg("-fdata-sections")
             .arg("-fPIC")
             .arg("-o")
             .arg(&obj_path)
             .arg("-c")
-            .arg(src_path)
-            .status()?;
-        Command::new("ar")
-            .arg("cq")
-            .arg(&lib_path)
-            .arg(&obj_path)
-            .status()?;
-        Command::new("ar").arg("s").arg(&lib_path).status()?;
+            .arg(src_path);
+        commands.record(&cmd);
+        cmd.status()?;
+        let mut cmd = Command::new("ar");
+        cmd.arg("cq").arg(&lib_path).arg(&obj_path);
+        commands.record(&cmd);
+        cmd.status()?;
+        let mut cmd = Command::new("ar");
+        cmd.arg("s").arg(&lib_path);
+        commands.record(&cmd);
+        cmd.status()?;
         Ok(String::from(lib_name))
     }
 
@@ -386,6 +1056,8 @@ impl CcToolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         let obj_path = out_dir.join(format!("{lib_name}.o"));
         let lib_path = out_dir.join(format!("lib{lib_name}.a"));
@@ -394,6 +1066,8 @@ impl CcToolchain {
         for flag in self.extra_flags() {
             cmd.arg(flag);
         }
+        cmd.args(self.src_flags(src_path));
+        cmd.args(codegen.cc_flags());
         if self.debug {
             cmd.arg("-g");
         }
@@ -403,14 +1077,17 @@ impl CcToolchain {
             .arg("-o")
             .arg(&obj_path)
             .arg("-c")
-            .arg(src_path)
-            .status()?;
-        Command::new("ar")
-            .arg("cq")
-            .arg(&lib_path)
-            .arg(&obj_path)
-            .status()?;
-        Command::new("ar").arg("s").arg(&lib_path).status()?;
+            .arg(src_path);
+        commands.record(&cmd);
+        cmd.status()?;
+        let mut cmd = Command::new("ar");
+        cmd.arg("cq").arg(&lib_path).arg(&obj_path);
+        commands.record(&cmd);
+        cmd.status()?;
+        let mut cmd = Command::new("ar");
+        cmd.arg("s").arg(&lib_path);
+        commands.record(&cmd);
+        cmd.status()?;
         Ok(String::from(lib_name))
     }
 
@@ -419,6 +1096,8 @@ impl CcToolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         let obj_path = out_dir.join(format!("{lib_name}.o"));
         let lib_path = out_dir.join(format!("lib{lib_name}.a"));
@@ -426,23 +1105,31 @@ impl CcToolchain {
         for flag in self.extra_flags() {
             cmd.arg(flag);
         }
+        cmd.args(self.src_flags(src_path));
+        cmd.args(codegen.cc_flags());
         if self.debug {
             cmd.arg("-g");
         }
+        if let Some(flag) = self.lto_flag(codegen.lto) {
+            cmd.arg("-O2").arg(flag);
+        }
         cmd.arg("-ffunction-sections")
             .arg("-fdata-sections")
             .arg("-fPIC")
             .arg("-o")
             .arg(&obj_path)
             .arg("-c")
-            .arg(src_path)
-            .status()?;
-        Command::new("ar")
-            .arg("cq")
-            .arg(&lib_path)
-            .arg(&obj_path)
-            .status()?;
-        Command::new("ar").arg("s").arg(&lib_path).status()?;
+            .arg(src_path);
+        commands.record(&cmd);
+        cmd.status()?;
+        let mut cmd = Command::new("ar");
+        cmd.arg("cq").arg(&lib_path).arg(&obj_path);
+        commands.record(&cmd);
+        cmd.status()?;
+        let mut cmd = Command::new("ar");
+        cmd.arg("s").arg(&lib_path);
+        commands.record(&cmd);
+        cmd.status()?;
         Ok(String::from(lib_name))
     }
 
@@ -451,6 +1138,8 @@ impl CcToolchain {
         _src_path: &Utf8Path,
         _out_dir: &Utf8Path,
         _lib_name: &str,
+        _codegen: &CodegenOptions,
+        _commands: &CommandLog,
     ) -> Result<String, BuildError> {
         unimplemented!()
     }
@@ -1,6 +1,8 @@
 use super::*;
-use kdl_script::parse::Attr;
-use kdl_script::types::{AliasTy, ArrayTy, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use kdl_script::parse::{ArgAttr, Attr};
+use kdl_script::types::{
+    AliasTy, Arg, ArrayTy, Func, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx, TypedProgram,
+};
 use std::fmt::Write;
 
 impl CcToolchain {
@@ -10,13 +12,121 @@ impl CcToolchain {
         state: &TestState,
     ) -> Result<(), GenerateError> {
         for &func in &state.desired_funcs {
-            self.generate_signature(f, state, func)?;
+            if state.options.convention == CallingConvention::Thiscall {
+                self.generate_thiscall_class(f, state, func, CallSide::Caller)?;
+                continue;
+            }
+            self.generate_signature(f, state, func, CallSide::Caller)?;
             writeln!(f, ";")?;
         }
         writeln!(f)?;
         Ok(())
     }
 
+    /// Whether the source generated with these options is C++ instead of C
+    ///
+    /// Only C++ can declare @nontrivial types, and thiscall functions are C++ member
+    /// functions (see [`CcToolchain::generate_thiscall_class`][]).
+    pub fn is_cpp(types: &TypedProgram, options: &TestOptions) -> bool {
+        types.has_nontrivial_tys() || options.convention == CallingConvention::Thiscall
+    }
+
+    /// The input a thiscall function takes as `this`, and the struct it points to
+    pub fn thiscall_this<'a>(
+        &self,
+        state: &TestState,
+        function: &'a Func,
+        call_side: CallSide,
+    ) -> Result<(&'a Arg, TyIdx), GenerateError> {
+        if state.options.tail_call_chain > 0 || state.options.call_path.is_indirect() {
+            Err(UnsupportedError::Other(
+                "thiscall member functions can't be tail called or called through pointers"
+                    .to_owned(),
+            ))?
+        }
+        let inputs = state.options.func_inputs(function, call_side);
+        if let Some(arg) = inputs.first() {
+            if let Ty::Ref(RefTy {
+                pointee_ty,
+                nullable: false,
+            }) = state.types.realize_ty(arg.ty)
+            {
+                if let Ty::Struct(_) = state.types.realize_ty(*pointee_ty) {
+                    return Ok((arg, *pointee_ty));
+                }
+            }
+        }
+        Err(UnsupportedError::Other(format!(
+            "thiscall needs the first input of {} to be a reference to a struct (it's `this`)",
+            function.name
+        )))?
+    }
+
+    /// The class a thiscall function is a member function of
+    pub fn thiscall_class(function: &Func) -> String {
+        format!("abi_cafe_{}_this", function.name)
+    }
+
+    /// Declare the class a thiscall function is a member function of
+    ///
+    /// MSVC passes `this` in ecx, but GCC and Clang only do that for member functions
+    /// with their thiscall attribute. So the function belongs to a class that derives from
+    /// the struct `this` points to without adding anything (so they're the same object),
+    /// and `ABI_CAFE_SYMBOL` gives it the plain name the other side calls.
+    pub fn generate_thiscall_class(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+        call_side: CallSide,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        let (_, this_ty) = self.thiscall_this(state, function, call_side)?;
+        let convention_decl = self.convention_decl(state.options.convention)?;
+        let this_tyname = state.tynames[&this_ty].0.trim_end();
+        writeln!(
+            f,
+            "struct {} : {this_tyname} {{",
+            Self::thiscall_class(function)
+        )?;
+        f.add_indent(1);
+        self.generate_thiscall_signature(f, state, func, call_side, convention_decl)?;
+        writeln!(f, " ABI_CAFE_SYMBOL(\"{}\");", function.name)?;
+        f.sub_indent(1);
+        writeln!(f, "}};")?;
+        writeln!(f)?;
+        Ok(())
+    }
+
+    /// The signature of a thiscall member function (without `this`), with its name
+    /// prefixed like `abi_cafe_func_this::` (for the definition) or not (for the declaration)
+    pub fn generate_thiscall_signature(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+        call_side: CallSide,
+        prefix: &str,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        let (pre, post) = if let Some(output) = function.outputs.first() {
+            let (pre, post) = &state.tynames[&output.ty];
+            (&**pre, &**post)
+        } else {
+            ("void ", "")
+        };
+        let inputs = state.options.func_inputs(function, call_side);
+        write!(f, "{pre}{prefix}{}{post}(", function.name)?;
+        for (idx, arg) in inputs.iter().skip(1).enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            self.generate_input(f, state, arg)?;
+        }
+        write!(f, ")")?;
+        Ok(())
+    }
+
     pub fn generate_definitions(
         &self,
         f: &mut Fivemat,
@@ -56,6 +166,24 @@ impl CcToolchain {
         let (prefix, suffix) = match state.types.realize_ty(ty) {
             // Structural types that don't need definitions but we should
             // intern the name of
+            Ty::Primitive(prim) if self.overrides.types.contains_key(prim) => {
+                (self.overrides.types[prim].clone(), None)
+            }
+            Ty::Primitive(PrimitiveTy::BitInt { bits, signed }) => {
+                match self.bitint_maxwidth() {
+                    Some(maxwidth) if u64::from(*bits) <= maxwidth => {}
+                    Some(maxwidth) => Err(UnsupportedError::Other(format!(
+                        "{} only supports _BitInts up to {maxwidth} bits",
+                        self.mode
+                    )))?,
+                    None => Err(UnsupportedError::Other(format!(
+                        "{} doesn't support _BitInt",
+                        self.mode
+                    )))?,
+                }
+                let unsigned = if *signed { "" } else { "unsigned " };
+                (format!("{unsigned}_BitInt({bits}) "), None)
+            }
             Ty::Primitive(prim) => {
                 let name = match prim {
                     PrimitiveTy::I8 => "int8_t ",
@@ -72,6 +200,7 @@ impl CcToolchain {
                     PrimitiveTy::F64 => "double ",
                     PrimitiveTy::Bool => "bool ",
                     PrimitiveTy::Ptr => "void *",
+                    PrimitiveTy::CStr => "const char *",
                     PrimitiveTy::I256 => {
                         Err(UnsupportedError::Other("c doesn't have i256?".to_owned()))?
                     }
@@ -160,6 +289,21 @@ impl CcToolchain {
                             ))?,
                         }
                     }
+                    PrimitiveTy::F32x4
+                    | PrimitiveTy::I64x2
+                    | PrimitiveTy::F32x8
+                    | PrimitiveTy::I64x4
+                        if !cfg!(any(target_arch = "x86", target_arch = "x86_64")) =>
+                    {
+                        Err(UnsupportedError::Other(
+                            "vectors are only tested on x86".to_owned(),
+                        ))?
+                    }
+                    PrimitiveTy::F32x4 => "__m128 ",
+                    PrimitiveTy::I64x2 => "__m128i ",
+                    PrimitiveTy::F32x8 => "__m256 ",
+                    PrimitiveTy::I64x4 => "__m256i ",
+                    PrimitiveTy::BitInt { .. } => unreachable!("bitints are named above"),
                 };
                 (name.to_owned(), None)
             }
@@ -167,7 +311,8 @@ impl CcToolchain {
                 let (pre, post) = &state.tynames[elem_ty];
                 (pre.clone(), Some(format!("[{len}]{post}")))
             }
-            Ty::Ref(RefTy { pointee_ty }) => {
+            // Nullable or not, it's just a pointer
+            Ty::Ref(RefTy { pointee_ty, .. }) => {
                 let (pre, post) = &state.tynames[pointee_ty];
                 // If the last type modifier was postfix (an array dimension)
                 // Then we need to introduce a set of parens to make this pointer
@@ -263,7 +408,13 @@ impl CcToolchain {
                     | PrimitiveTy::F64
                     | PrimitiveTy::F128
                     | PrimitiveTy::Bool
-                    | PrimitiveTy::Ptr => {
+                    | PrimitiveTy::Ptr
+                    | PrimitiveTy::CStr
+                    | PrimitiveTy::BitInt { .. }
+                    | PrimitiveTy::F32x4
+                    | PrimitiveTy::I64x2
+                    | PrimitiveTy::F32x8
+                    | PrimitiveTy::I64x4 => {
                         // Builtin
                     }
                 };
@@ -297,7 +448,25 @@ impl CcToolchain {
             Ty::Struct(struct_ty) => {
                 // Emit an actual struct decl
                 self.generate_repr_attr(f, state, &struct_ty.attrs, "struct")?;
-                writeln!(f, "typedef struct {} {{", struct_ty.name)?;
+                let align = match state.options.struct_align() {
+                    Some(align) if self.cc_flavor == CCFlavor::Msvc => {
+                        format!("__declspec(align({align})) ")
+                    }
+                    Some(align) => format!("__attribute__((aligned({align}))) "),
+                    None => String::new(),
+                };
+                let ty_name = &struct_ty.name;
+                // See harness/c/cpp_prefix.h
+                let base = if struct_ty
+                    .attrs
+                    .iter()
+                    .any(|a| matches!(a, Attr::NonTrivial(_)))
+                {
+                    format!(" : abi_cafe_nontrivial<{ty_name}>")
+                } else {
+                    String::new()
+                };
+                writeln!(f, "typedef struct {align}{ty_name}{base} {{")?;
                 f.add_indent(1);
                 for field in &struct_ty.fields {
                     let field_name = &field.ident;
@@ -309,6 +478,12 @@ impl CcToolchain {
             }
             Ty::Union(union_ty) => {
                 // Emit an actual union decl
+                if state.types.ty_is_nontrivial(ty) {
+                    // C++ deletes the copy constructor of these
+                    return Err(UnsupportedError::Other(
+                        "unions with @nontrivial fields".to_owned(),
+                    ))?;
+                }
                 self.generate_repr_attr(f, state, &union_ty.attrs, "union")?;
                 writeln!(f, "typedef union {} {{", union_ty.name)?;
                 f.add_indent(1);
@@ -390,7 +565,13 @@ impl CcToolchain {
                     | PrimitiveTy::F64
                     | PrimitiveTy::F128
                     | PrimitiveTy::Bool
-                    | PrimitiveTy::Ptr => {
+                    | PrimitiveTy::Ptr
+                    | PrimitiveTy::CStr
+                    | PrimitiveTy::BitInt { .. }
+                    | PrimitiveTy::F32x4
+                    | PrimitiveTy::I64x2
+                    | PrimitiveTy::F32x8
+                    | PrimitiveTy::I64x4 => {
                         // Builtin
                     }
                 };
@@ -418,6 +599,11 @@ impl CcToolchain {
         _ty_style: &str,
     ) -> Result<(), GenerateError> {
         use kdl_script::parse::{AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, Repr};
+        // @nontrivial is handled by the struct decl itself (it's a C++ base class)
+        let attrs = attrs
+            .iter()
+            .filter(|attr| !matches!(attr, Attr::NonTrivial(_)))
+            .collect::<Vec<_>>();
         if !attrs.is_empty() {
             return Err(UnsupportedError::Other(
                 "c doesn't support attrs yet".to_owned(),
@@ -441,6 +627,7 @@ impl CcToolchain {
                 Attr::Passthrough(AttrPassthrough(attr)) => {
                     other_attrs.push(attr);
                 }
+                Attr::NonTrivial(_) => unreachable!(),
                 Attr::Repr(AttrRepr { reprs }) => {
                     default_lang_repr = false;
                     // Any explicit repr attributes disables default C
@@ -494,6 +681,21 @@ impl CcToolchain {
         f: &mut Fivemat,
         state: &TestState,
         func: FuncIdx,
+        call_side: CallSide,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        self.generate_signature_named(f, state, func, call_side, &function.name)
+    }
+
+    /// Same as [`CcToolchain::generate_signature`][] but with a different name
+    /// (used for the links of a tail call chain)
+    pub fn generate_signature_named(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+        call_side: CallSide,
+        func_name: &str,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
 
@@ -504,29 +706,135 @@ impl CcToolchain {
             ("void ", "")
         };
         let convention_decl = self.convention_decl(state.options.convention)?;
-        write!(f, "{pre}{}{}{post}(", convention_decl, function.name)?;
-        let mut multiarg = false;
-        // Add inputs
-        for arg in &function.inputs {
-            if multiarg {
+        let inputs = state.options.func_inputs(function, call_side);
+        let nonnull_args = inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, arg)| arg.attrs.contains(&ArgAttr::NonNull))
+            .map(|(idx, _)| (idx + 1).to_string())
+            .collect::<Vec<_>>();
+        // (msvc says this with SAL annotations on the inputs instead)
+        if !nonnull_args.is_empty() && self.cc_flavor != CCFlavor::Msvc {
+            write!(f, "__attribute__((nonnull({}))) ", nonnull_args.join(", "))?;
+        }
+        write!(f, "{pre}{}{}{post}", convention_decl, func_name)?;
+        self.generate_inputs(f, state, inputs)
+    }
+
+    /// Declare a pointer to a function that can't be seen through, like
+    /// `void (__stdcall *volatile name)(int32_t x)` (for [`CallPath::is_indirect`][])
+    ///
+    /// This spells out the type instead of using `__typeof__`, which msvc doesn't have,
+    /// and `@nonnull` is left out since it's an attribute of functions, not their pointers.
+    pub fn generate_func_ptr(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+        call_side: CallSide,
+        ptr_name: &str,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        let (pre, post) = if let Some(output) = function.outputs.first() {
+            let (pre, post) = &state.tynames[&output.ty];
+            (&**pre, &**post)
+        } else {
+            ("void ", "")
+        };
+        let convention_decl = self.convention_decl(state.options.convention)?;
+        let inputs = state.options.func_inputs(function, call_side);
+        write!(f, "{pre}({convention_decl}*volatile {ptr_name}){post}")?;
+        self.generate_inputs(f, state, inputs)
+    }
+
+    /// The parenthesized inputs of a signature
+    fn generate_inputs(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        inputs: &[Arg],
+    ) -> Result<(), GenerateError> {
+        write!(f, "(")?;
+        for (arg_idx, arg) in inputs.iter().enumerate() {
+            if arg_idx > 0 {
                 write!(f, ", ")?;
             }
-            multiarg = true;
-            let arg_name = &arg.name;
-            let (pre, post) = &state.tynames[&arg.ty];
-            write!(f, "{pre}{}{post}", arg_name)?;
+            self.generate_input(f, state, arg)?;
         }
         write!(f, ")")?;
         Ok(())
     }
 
-    pub fn convention_decl(
+    /// Declare one input of a signature, applying its [`ArgAttr`][]s
+    /// (except `@nonnull`, which goes on the whole function)
+    ///
+    /// msvc has neither of those, so it gets the SAL annotations that mean the same thing
+    /// instead, `_Notnull_` and `_Inout_` (a valid pointer to one readable and writable
+    /// value). Those only matter to `/analyze`, but they're the closest thing msvc has.
+    fn generate_input(
         &self,
-        convention: CallingConvention,
-    ) -> Result<&'static str, GenerateError> {
+        f: &mut Fivemat,
+        state: &TestState,
+        arg: &Arg,
+    ) -> Result<(), GenerateError> {
+        let arg_name = &arg.name;
+        if self.cc_flavor == CCFlavor::Msvc {
+            if arg.attrs.contains(&ArgAttr::Dereferenceable) {
+                write!(f, "_Inout_ ")?;
+            } else if arg.attrs.contains(&ArgAttr::NonNull) {
+                write!(f, "_Notnull_ ")?;
+            }
+        }
+        let restrict = if !arg.attrs.contains(&ArgAttr::NoAlias) {
+            ""
+        } else if self.cc_flavor == CCFlavor::Msvc {
+            "__restrict "
+        } else {
+            "restrict "
+        };
+        if arg.attrs.contains(&ArgAttr::Dereferenceable) && self.cc_flavor != CCFlavor::Msvc {
+            // `T x[static 1]` is a `T*` that points to at least one `T`
+            let pointee = self.ref_pointee(state, arg.ty);
+            let (pre, post) = &state.tynames[&pointee];
+            write!(f, "{pre}{arg_name}[{restrict}static 1]{post}")?;
+        } else {
+            let (pre, post) = &state.tynames[&arg.ty];
+            // Keep `T* restrict x` legible ("(*" or "*" get a space, a typedef already has one)
+            let space = if restrict.is_empty() || pre.ends_with(' ') {
+                ""
+            } else {
+                " "
+            };
+            write!(f, "{pre}{space}{restrict}{arg_name}{post}")?;
+        }
+        Ok(())
+    }
+
+    /// Get what a reference points to, seeing through aliases and puns
+    ///
+    /// The type checker already made sure every input with [`ArgAttr`][]s is a reference.
+    fn ref_pointee(&self, state: &TestState, ty: TyIdx) -> TyIdx {
+        match state.types.realize_ty(ty) {
+            Ty::Ref(RefTy { pointee_ty, .. }) => *pointee_ty,
+            Ty::Alias(alias_ty) => self.ref_pointee(state, alias_ty.real),
+            Ty::Pun(pun) => {
+                let real_ty = state.types.resolve_pun(pun, &state.env).unwrap();
+                self.ref_pointee(state, real_ty)
+            }
+            _ => unreachable!("only references can have input attributes"),
+        }
+    }
+
+    pub fn convention_decl(&self, convention: CallingConvention) -> Result<&str, GenerateError> {
         use CCFlavor::*;
         use CallingConvention::*;
         use Platform::*;
+        if let Some(conventions) = &self.overrides.conventions {
+            return match conventions.get(&convention) {
+                Some(decl) => Ok(decl),
+                None => Err(self.unsupported_convention(&convention))?,
+            };
+        }
         // GCC (as __attribute__'s)
         //
         //  * x86: cdecl, fastcall, thiscall, stdcall,
@@ -541,7 +849,7 @@ impl CcToolchain {
         //  * __cdecl, __clrcall, __stdcall, __fastcall, __thiscall, __vectorcall
 
         let val = match convention {
-            System | Win64 | Sysv64 | Aapcs => {
+            System | Win64 | Sysv64 => {
                 // Don't want to think about these yet, I think they're
                 // all properly convered by other ABIs
                 return Err(self.unsupported_convention(&convention))?;
@@ -550,7 +858,8 @@ impl CcToolchain {
             Rust => {
                 return Err(self.unsupported_convention(&convention))?;
             }
-            C => "",
+            // C has no idea what unwinding is, so this is just C
+            C | CUnwind => "",
             Cdecl => {
                 if self.platform == Windows {
                     match self.cc_flavor {
@@ -591,21 +900,77 @@ impl CcToolchain {
                     return Err(self.unsupported_convention(&convention))?;
                 }
             }
+            // These can be picked explicitly no matter what -mfloat-abi says
+            // (except that soft-float has no VFP registers to pass anything in)
+            Aapcs | AapcsVfp => {
+                let Some(abi) = self.arm_float_abi else {
+                    return Err(self.unsupported_convention(&convention))?;
+                };
+                match (&self.cc_flavor, convention) {
+                    (Msvc, _) => return Err(self.unsupported_convention(&convention))?,
+                    (_, Aapcs) => "__attribute__((pcs(\"aapcs\"))) ",
+                    (_, _) if abi == ArmFloatAbi::Soft => {
+                        return Err(UnsupportedError::Convention(
+                            "aapcs_vfp (soft-float has no VFP registers)".to_owned(),
+                        ))?
+                    }
+                    (_, _) => "__attribute__((pcs(\"aapcs-vfp\"))) ",
+                }
+            }
+            Thiscall => {
+                if self.is_x86 {
+                    match self.cc_flavor {
+                        // We'd have to spell out the mangled names of the member functions
+                        // to give them the names the other side calls
+                        Msvc => {
+                            return Err(UnsupportedError::Convention(
+                                "thiscall (msvc can't give member functions unmangled names)"
+                                    .to_owned(),
+                            ))?
+                        }
+                        // See generate_thiscall_class
+                        Gcc | Clang | Zigcc => "__attribute__((thiscall)) ",
+                    }
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
         };
 
         Ok(val)
     }
 
+    /// Attributes for a function that must not be inlined (the links of a tail call chain)
+    pub fn noinline_decl(&self) -> &'static str {
+        match self.cc_flavor {
+            CCFlavor::Msvc => "__declspec(noinline) ",
+            CCFlavor::Gcc | CCFlavor::Clang | CCFlavor::Zigcc => "__attribute__((noinline)) ",
+        }
+    }
+
+    /// Attributes for a return that must be a tail call
+    ///
+    /// Only clang-likes have `musttail`, and a call that's just allowed to be a tail call
+    /// wouldn't test anything.
+    pub fn musttail_decl(&self) -> Result<&'static str, GenerateError> {
+        match self.cc_flavor {
+            CCFlavor::Clang | CCFlavor::Zigcc => Ok("__attribute__((musttail)) "),
+            CCFlavor::Gcc | CCFlavor::Msvc => Err(UnsupportedError::Other(
+                "tail call chains need clang's musttail".to_owned(),
+            ))?,
+        }
+    }
+
     fn lang_repr_decl(&self, repr: LangRepr) -> Result<Option<&'static str>, GenerateError> {
         match repr {
-            LangRepr::Rust => Err(UnsupportedError::Other(
-                "c doesn't support repr rust".to_owned(),
+            LangRepr::Rust => Err(UnsupportedError::Repr(
+                "rust (c doesn't have it)".to_owned(),
             ))?,
             LangRepr::C => Ok(None),
         }
     }
 
     fn unsupported_convention(&self, convention: &CallingConvention) -> UnsupportedError {
-        UnsupportedError::Other(format!("unsupported convention {convention}"))
+        UnsupportedError::Convention(convention.to_string())
     }
 }
@@ -1,5 +1,5 @@
 use super::*;
-use crate::harness::vals::{ArgValuesIter, Value};
+use crate::harness::vals::{ArgValuesIter, ValueRef};
 use kdl_script::types::{AliasTy, ArrayTy, PrimitiveTy, RefTy, Ty, TyIdx};
 use std::fmt::Write;
 
@@ -9,7 +9,7 @@ impl CcToolchain {
         f: &mut Fivemat,
         state: &TestState,
         ty: TyIdx,
-        val: &Value,
+        val: &ValueRef,
         alias: Option<&str>,
     ) -> Result<(), GenerateError> {
         match state.types.realize_ty(ty) {
@@ -66,6 +66,14 @@ impl CcToolchain {
                         write!(f, "(void*){:#X}ul", val.generate_u32())?
                     }
                 }
+                PrimitiveTy::CStr => {
+                    // Octal escapes are at most 3 digits, so they can't eat the next byte
+                    write!(f, "\"")?;
+                    for byte in val.generate_cstr() {
+                        write!(f, "\\{byte:03o}")?;
+                    }
+                    write!(f, "\"")?
+                }
                 PrimitiveTy::I256 => {
                     Err(UnsupportedError::Other("c doesn't have i256?".to_owned()))?
                 }
@@ -86,6 +94,53 @@ impl CcToolchain {
                         "(((union {{ __uint128_t bits; __float128 value; }}){{ .bits = ((__uint128_t){lower:#X}ull) | (((__uint128_t){higher:#X}ull) << 64) }}).value)"
                     )?
                 }
+                PrimitiveTy::BitInt { bits, signed } => {
+                    // Build the value out of 64-bit chunks, since wider literals need C23
+                    let (size, _) = state.layout_target.bitint_layout(*bits);
+                    let mut bytes = val.generate_bitint(*bits, *signed, size);
+                    if val.tree.big_endian {
+                        bytes.reverse();
+                    }
+                    let unsigned = format!("unsigned _BitInt({bits})");
+                    let chunks = bytes
+                        .chunks(8)
+                        .take(usize::from(*bits).div_ceil(64))
+                        .enumerate()
+                        .rev()
+                        .map(|(idx, chunk)| {
+                            let mut chunk_bytes = [0; 8];
+                            chunk_bytes[..chunk.len()].copy_from_slice(chunk);
+                            let chunk = u64::from_le_bytes(chunk_bytes);
+                            if idx == 0 {
+                                format!("({unsigned}){chunk:#X}ull")
+                            } else {
+                                format!("(({unsigned}){chunk:#X}ull << {})", idx * 64)
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" | ");
+                    let sign = if *signed { "" } else { "unsigned " };
+                    write!(f, "(({sign}_BitInt({bits}))({chunks}))")?
+                }
+                PrimitiveTy::F32x4
+                | PrimitiveTy::I64x2
+                | PrimitiveTy::F32x8
+                | PrimitiveTy::I64x4 => {
+                    // Vectors are just their bytes (in memory order, like everything else)
+                    let size = prim.vector_size().unwrap() as usize;
+                    let mut bytes = vec![0; size];
+                    val.fill_bytes(&mut bytes);
+                    let bytes = bytes
+                        .iter()
+                        .map(|byte| format!("{byte:#04x}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let (tyname, _) = &state.tynames[&ty];
+                    write!(
+                        f,
+                        "(((union {{ uint8_t bytes[{size}]; {tyname}value; }}){{ .bytes = {{ {bytes} }} }}).value)"
+                    )?
+                }
             },
             Ty::Enum(enum_ty) => {
                 let name = alias.unwrap_or(&enum_ty.name);
@@ -116,7 +171,15 @@ impl CcToolchain {
                 let val = vals.next_val();
                 self.init_leaf_value(f, state, ty, &val, alias)?;
             }
-            Ty::Ref(RefTy { pointee_ty }) => {
+            Ty::Ref(RefTy {
+                pointee_ty,
+                nullable,
+            }) => {
+                // A nullable reference has an implicit "tag" for whether it's null
+                if *nullable && !vals.next_val().generate_bool() {
+                    write!(f, "NULL")?;
+                    return Ok(());
+                }
                 // The value is a mutable reference to a temporary
                 write!(f, "&{ref_temp_name}")?;
 
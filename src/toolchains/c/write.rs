@@ -1,5 +1,5 @@
 use super::*;
-use kdl_script::types::{Ty, TyIdx};
+use kdl_script::types::{Func, FuncIdx, Ty, TyIdx};
 use std::fmt::Write;
 
 impl CcToolchain {
@@ -12,6 +12,29 @@ impl CcToolchain {
     ) -> Result<(), GenerateError> {
         // Always need includes for things like int8_t
         writeln!(f, "{}", crate::files::get_file("harness/c/test_prefix.h"))?;
+        let has_vectors = state
+            .defs
+            .definitions(state.desired_funcs.iter().copied())
+            .into_iter()
+            .any(|def| match def {
+                kdl_script::Definition::DeclareTy(ty) | kdl_script::Definition::DefineTy(ty) => {
+                    matches!(state.types.realize_ty(ty), Ty::Primitive(prim) if prim.vector_size().is_some())
+                }
+                kdl_script::Definition::DefineFunc(_) | kdl_script::Definition::DeclareFunc(_) => {
+                    false
+                }
+            });
+        if has_vectors {
+            writeln!(f, "#include <immintrin.h>\n")?;
+        }
+        // For the SAL annotations msvc gets for input attributes
+        if self.cc_flavor == CCFlavor::Msvc {
+            writeln!(f, "#include <sal.h>\n")?;
+        }
+        // @nontrivial types and thiscall make this C++ (which closes in write_harness_suffix)
+        if Self::is_cpp(&state.types, &state.options) {
+            writeln!(f, "{}", crate::files::get_file("harness/c/cpp_prefix.h"))?;
+        }
         // No extra harness gunk if not needed
         if state.options.val_writer != WriteImpl::HarnessCallback {
             return Ok(());
@@ -28,6 +51,18 @@ impl CcToolchain {
         Ok(())
     }
 
+    /// Every test should end by closing anything [`CcToolchain::write_harness_prefix`][] opened
+    pub fn write_harness_suffix(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+    ) -> Result<(), GenerateError> {
+        if Self::is_cpp(&state.types, &state.options) {
+            writeln!(f, "}} // extern \"C\"")?;
+        }
+        Ok(())
+    }
+
     /// Emit the WRITE calls and FINISHED_VAL for this value.
     /// This will WRITE every leaf subfield of the type.
     /// `to` is the BUFFER to use, `from` is the variable name of the value.
@@ -219,6 +254,39 @@ impl CcToolchain {
                 }
                 */
             }
+            Ty::Ref(ref_ty) if ref_ty.nullable => {
+                // Process the implicit "is it null" tag (0 for null, 1 for not)
+                let tag_generator = vals.next_val();
+                let should_write = tag_generator.should_write_val(&state.options);
+                if tag_generator.generate_bool() {
+                    writeln!(f, "if ({from}) {{")?;
+                    f.add_indent(1);
+                    if should_write {
+                        self.write_tag_field(f, state, to, from, 1, &tag_generator)?;
+                    }
+                    // Add a deref, and recurse into the pointee
+                    let base = format!("(*{from})");
+                    self.write_fields(f, state, to, &base, ref_ty.pointee_ty, vals)?;
+                    f.sub_indent(1);
+                    writeln!(f, "}} else {{")?;
+                    f.add_indent(1);
+                    if should_write {
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    }
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                } else if should_write {
+                    writeln!(f, "if ({from}) {{")?;
+                    f.add_indent(1);
+                    self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    f.sub_indent(1);
+                    writeln!(f, "}} else {{")?;
+                    f.add_indent(1);
+                    self.write_tag_field(f, state, to, from, 0, &tag_generator)?;
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                }
+            }
             Ty::Ref(ref_ty) => {
                 // Add a deref, and recurse into the pointee
                 let base = format!("(*{from})");
@@ -253,21 +321,90 @@ impl CcToolchain {
         match state.options.val_writer {
             WriteImpl::HarnessCallback => {
                 let val_idx = val.absolute_val_idx;
+                let is_cstr = matches!(
+                    state.types.realize_ty(val.ty),
+                    Ty::Primitive(PrimitiveTy::CStr)
+                );
                 // Convenience for triggering test failures
                 let rvalue = if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
-                    "(uint32_t)0x12345678"
+                    if is_cstr {
+                        "\"\\x12\\x34\\x56\\x78\""
+                    } else {
+                        "(uint32_t)0x12345678"
+                    }
                 } else {
                     path
                 };
+                if is_cstr {
+                    // Report the bytes the pointer points to (up to the terminator)
+                    writeln!(f, "write_cstr({to}, {val_idx}, {rvalue});")?;
+                    return Ok(());
+                }
+                if state.options.through_helper(&state.types, val.ty) {
+                    // The volatile keeps this a real division, which is a libgcc/compiler-rt call
+                    writeln!(f, "{{")?;
+                    f.add_indent(1);
+                    let (pre, post) = &state.tynames[&val.ty];
+                    writeln!(f, "volatile {pre}_one{post} = 1;")?;
+                    writeln!(f, "{pre}_temp{post} = {rvalue} / _one;")?;
+                    writeln!(f, "write_val({to}, {val_idx}, _temp);")?;
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                    return Ok(());
+                }
+                if state.options.rounds_float(&state.types, val.ty) && rvalue == path {
+                    // 1/3 isn't exact, so this rounds differently in every rounding mode
+                    // (and the volatiles keep the compiler from assuming the default one)
+                    let float = match state.types.realize_ty(val.ty) {
+                        Ty::Primitive(PrimitiveTy::F32) => "float",
+                        _ => "double",
+                    };
+                    writeln!(f, "{{")?;
+                    f.add_indent(1);
+                    writeln!(f, "volatile {float} _one = 1, _three = 3;")?;
+                    writeln!(f, "{float} _temp = {rvalue} + _one / _three;")?;
+                    writeln!(f, "write_val({to}, {val_idx}, _temp);")?;
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                    return Ok(());
+                }
                 writeln!(f, "write_val({to}, {val_idx}, {rvalue});")?;
             }
             WriteImpl::Assert => {
+                if let Ty::Primitive(PrimitiveTy::CStr) = state.types.realize_ty(val.ty) {
+                    // Compare what the pointers point to, not the pointers
+                    write!(f, "assert_eq(strcmp({path}, ")?;
+                    self.init_leaf_value(f, state, val.ty, val, None)?;
+                    writeln!(f, "), 0);")?;
+                    return Ok(());
+                }
+                if let Ty::Primitive(prim) = state.types.realize_ty(val.ty) {
+                    if let Some(size) = prim.vector_size() {
+                        // Vectors don't have ==, so compare their bytes
+                        let (tyname, _) = &state.tynames[&val.ty];
+                        write!(f, "{{ {tyname}_expected = ")?;
+                        self.init_leaf_value(f, state, val.ty, val, None)?;
+                        writeln!(f, "; assert_eq(memcmp(&{path}, &_expected, {size}), 0); }}")?;
+                        return Ok(());
+                    }
+                }
                 write!(f, "assert_eq({path}, ")?;
                 self.init_leaf_value(f, state, val.ty, val, None)?;
                 writeln!(f, ");")?;
             }
             WriteImpl::Print => {
-                writeln!(f, "printf(\"%d\", {path});")?;
+                if let Ty::Primitive(prim) = state.types.realize_ty(val.ty) {
+                    if prim.vector_size().is_some() {
+                        Err(UnsupportedError::Other(
+                            "printing vectors isn't implemented".to_owned(),
+                        ))?
+                    }
+                }
+                if let Ty::Primitive(PrimitiveTy::CStr) = state.types.realize_ty(val.ty) {
+                    writeln!(f, "printf(\"%s\", {path});")?;
+                } else {
+                    writeln!(f, "printf(\"%d\", {path});")?;
+                }
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
@@ -340,6 +477,60 @@ impl CcToolchain {
         Ok(())
     }
 
+    /// Fill the padding of an input with the `--pad-byte`
+    pub fn fill_padding(
+        &self,
+        f: &mut dyn Write,
+        state: &TestState,
+        var_name: &str,
+        var_ty: TyIdx,
+    ) -> Result<(), GenerateError> {
+        let Some(pad_byte) = state.options.pad_byte else {
+            return Ok(());
+        };
+        let padding = state.padding_of(var_ty);
+        if padding.is_empty() {
+            return Ok(());
+        }
+        for offset in padding {
+            writeln!(
+                f,
+                "((unsigned char*)&{var_name})[{offset}] = 0x{pad_byte:02x};"
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Report the raw bytes of every input with padding, so the harness can see
+    /// what happened to the `--pad-byte` the caller filled it with
+    pub fn write_padding(
+        &self,
+        f: &mut dyn Write,
+        state: &TestState,
+        func: FuncIdx,
+        function: &Func,
+    ) -> Result<(), GenerateError> {
+        if state.options.pad_byte.is_none()
+            || state.options.val_writer != WriteImpl::HarnessCallback
+        {
+            return Ok(());
+        }
+        let padded = function
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, arg)| !state.padding_of(arg.ty).is_empty())
+            .collect::<Vec<_>>();
+        if padded.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "set_func({CALLEE_PADDING}, {func});")?;
+        for (arg_idx, arg) in padded {
+            writeln!(f, "write_val({CALLEE_PADDING}, {arg_idx}, {});", arg.name)?;
+        }
+        Ok(())
+    }
+
     pub fn write_set_function(
         &self,
         f: &mut dyn Write,
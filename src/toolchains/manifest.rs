@@ -0,0 +1,484 @@
+//! Toolchains declared by a manifest file, instead of being built into abi-cafe
+//!
+//! A manifest reuses one of the builtin codegen backends (currently only C),
+//! and describes how to compile its output, how to spell each calling convention,
+//! and how to spell primitive types. This is enough to test niche compilers for
+//! C-like languages we'll never ship builtin support for.
+//!
+//! ```toml
+//! # abi-cafe-toolchains/tcc.toml
+//! lang = "c"
+//! version = ["tcc", "-v"]
+//! compile = ["tcc", "-c", "{input}", "-o", "{output}"]
+//!
+//! [conventions]
+//! c = ""
+//!
+//! [types]
+//! f16 = "_Float16"
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::process::Command;
+use std::sync::Arc;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use kdl_script::types::PrimitiveTy;
+use kdl_script::PunEnv;
+use serde::Deserialize;
+
+use super::c::CcOverrides;
+use super::*;
+use crate::Config;
+
+/// The format of a toolchain manifest file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    /// The id of the toolchain (defaults to the name of the manifest file)
+    #[serde(default)]
+    name: Option<String>,
+    /// The builtin codegen backend whose output the compiler accepts
+    lang: ManifestLang,
+    /// Command that prints the compiler's version (the first line is used)
+    #[serde(default)]
+    version: Option<Vec<String>>,
+    /// Command that compiles `{input}` to an object file at `{output}`
+    compile: Vec<String>,
+    /// Extra args for `compile` when `--debug` is passed
+    #[serde(default)]
+    debug_args: Vec<String>,
+    /// How to declare each calling convention
+    ///
+    /// If this is present, any convention not listed is unsupported.
+    /// Otherwise the builtin backend's declarations are used.
+    #[serde(default)]
+    conventions: Option<SortedMap<String, String>>,
+    /// How to spell primitive types (overriding the builtin backend)
+    #[serde(default)]
+    types: SortedMap<String, String>,
+}
+
+/// The codegen backends a manifest can reuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestLang {
+    C,
+}
+
+/// A loaded (and validated) toolchain manifest
+pub struct ToolchainManifest {
+    pub id: ToolchainId,
+    pub lang: ManifestLang,
+    pub version: Option<Vec<String>>,
+    pub compile: Vec<String>,
+    pub debug_args: Vec<String>,
+    pub overrides: CcOverrides,
+}
+
+impl ToolchainManifest {
+    fn from_file(id: ToolchainId, file: ManifestFile) -> Result<Self, GenerateError> {
+        let invalid = |details: String| GenerateError::InvalidToolchainManifest {
+            toolchain: id.clone(),
+            details,
+        };
+        if file.compile.is_empty() {
+            return Err(invalid("compile command is empty".to_owned()));
+        }
+        if file.version.as_ref().is_some_and(|cmd| cmd.is_empty()) {
+            return Err(invalid("version command is empty".to_owned()));
+        }
+        let conventions = file
+            .conventions
+            .map(|conventions| {
+                conventions
+                    .into_iter()
+                    .map(|(convention, decl)| {
+                        let convention: CallingConvention = convention.parse().map_err(invalid)?;
+                        Ok((convention, spell_with_space(decl)))
+                    })
+                    .collect::<Result<SortedMap<_, _>, GenerateError>>()
+            })
+            .transpose()?;
+        let types = file
+            .types
+            .into_iter()
+            .map(|(prim, name)| {
+                let Some(prim) = PrimitiveTy::from_name(&prim) else {
+                    return Err(invalid(format!("{prim} isn't a primitive type")));
+                };
+                Ok((prim, spell_with_space(name)))
+            })
+            .collect::<Result<HashMap<PrimitiveTy, String>, GenerateError>>()?;
+        Ok(Self {
+            id,
+            lang: file.lang,
+            version: file.version,
+            compile: file.compile,
+            debug_args: file.debug_args,
+            overrides: CcOverrides { conventions, types },
+        })
+    }
+}
+
+/// A toolchain described by a [`ToolchainManifest`][]
+pub struct ManifestToolchain {
+    id: ToolchainId,
+    /// The builtin backend that actually generates the source
+    codegen: CcToolchain,
+    version: Option<Vec<String>>,
+    compile: Vec<String>,
+    debug_args: Vec<String>,
+    debug: bool,
+}
+
+impl ManifestToolchain {
+    pub fn new(cfg: &Config, target: &str, manifest: ToolchainManifest) -> Self {
+        let codegen = match manifest.lang {
+            // The gcc flavour of C is the most widely understood one (`__attribute__`s)
+            ManifestLang::C => CcToolchain::new(cfg, target, TOOLCHAIN_GCC, None)
+                .with_overrides(manifest.overrides),
+        };
+        Self {
+            id: manifest.id,
+            codegen,
+            version: manifest.version,
+            compile: manifest.compile,
+            debug_args: manifest.debug_args,
+            debug: cfg.debug,
+        }
+    }
+}
+
+/// Declarations get pasted right before the next token, so make sure they're spaced out
+fn spell_with_space(mut decl: String) -> String {
+    if !decl.is_empty() && !decl.ends_with([' ', '*']) {
+        decl.push(' ');
+    }
+    decl
+}
+
+impl Toolchain for ManifestToolchain {
+    fn lang(&self) -> &'static str {
+        self.codegen.lang()
+    }
+    fn src_ext(&self, types: &kdl_script::TypedProgram, options: &TestOptions) -> &'static str {
+        self.codegen.src_ext(types, options)
+    }
+    fn pun_env(&self) -> Arc<PunEnv> {
+        self.codegen.pun_env()
+    }
+
+    fn version(&self) -> Option<String> {
+        let (bin, args) = self.version.as_ref()?.split_first()?;
+        let output = Command::new(bin).args(args).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // Plenty of compilers print their version to stderr
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        let stderr = String::from_utf8(output.stderr).ok()?;
+        let first_line = stdout.lines().chain(stderr.lines()).next();
+        first_line.map(|line| line.trim().to_owned())
+    }
+
+    fn preflight(&self) -> Result<(), MissingCompiler> {
+        let found = if self.version.is_some() {
+            self.version().is_some()
+        } else {
+            find_binary(&self.compile[0])
+        };
+        if found {
+            Ok(())
+        } else {
+            let binary = self.version.as_ref().unwrap_or(&self.compile)[0].clone();
+            Err(MissingCompiler {
+                binary,
+                install_hint: format!(
+                    "install it, or fix the commands in the manifest for {}",
+                    self.id
+                ),
+            })
+        }
+    }
+
+    fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
+        self.codegen.generate_callee(f, test)
+    }
+    fn generate_caller(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
+        self.codegen.generate_caller(f, test)
+    }
+
+    fn compile_callee(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+        _codegen: &CodegenOptions,
+        commands: &CommandLog,
+    ) -> Result<String, BuildError> {
+        self.compile(src_path, out_dir, lib_name, commands)
+    }
+    fn compile_caller(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+        _codegen: &CodegenOptions,
+        commands: &CommandLog,
+    ) -> Result<String, BuildError> {
+        self.compile(src_path, out_dir, lib_name, commands)
+    }
+}
+
+impl ManifestToolchain {
+    fn compile(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+        commands: &CommandLog,
+    ) -> Result<String, BuildError> {
+        let obj_path = out_dir.join(format!("{lib_name}.o"));
+        let lib_path = out_dir.join(format!("lib{lib_name}.a"));
+        let (bin, args) = self
+            .compile
+            .split_first()
+            .expect("manifest compile command was empty");
+        let mut cmd = Command::new(bin);
+        for arg in args {
+            cmd.arg(
+                arg.replace("{input}", src_path.as_str())
+                    .replace("{output}", obj_path.as_str()),
+            );
+        }
+        if self.debug {
+            cmd.args(&self.debug_args);
+        }
+        commands.record(&cmd);
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(BuildError::ManifestCompile {
+                toolchain: self.id.clone(),
+                output,
+            });
+        }
+        // `ar` adds to an existing archive, which would still have the last build's object
+        match std::fs::remove_file(&lib_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e)?,
+            _ => {}
+        }
+        let mut cmd = Command::new("ar");
+        cmd.arg("crs").arg(&lib_path).arg(&obj_path);
+        commands.record(&cmd);
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(BuildError::Archive {
+                lib_path: lib_path.to_string(),
+                output,
+            });
+        }
+        Ok(String::from(lib_name))
+    }
+}
+
+/// Whether this binary can be found (either as a path, or on PATH)
+fn find_binary(bin: &str) -> bool {
+    let bin = Utf8Path::new(bin);
+    if bin.components().count() > 1 {
+        return bin.exists();
+    }
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&paths).any(|dir| {
+        let path = dir.join(bin);
+        path.exists()
+            || path
+                .with_extension(std::env::consts::EXE_EXTENSION)
+                .exists()
+    })
+}
+
+/// Read every `*.toml` toolchain manifest in the `--toolchain-manifests` dir
+///
+/// Manifest toolchains can't reuse the id of any other toolchain.
+pub fn find_toolchain_manifests(cfg: &Config) -> Result<Vec<ToolchainManifest>, GenerateError> {
+    let Some(dir) = cfg.paths.runtime_toolchain_manifest_dir.as_deref() else {
+        return Ok(vec![]);
+    };
+    let taken_ids = [TOOLCHAIN_RUSTC]
+        .iter()
+        .chain(C_TOOLCHAINS)
+        .map(|id| id.to_string())
+        .chain(cfg.rustc_codegen_backends.iter().map(|(id, _)| id.clone()))
+        .chain(cfg.custom_c_toolchains.iter().map(|(id, _)| id.clone()))
+        .collect();
+    read_toolchain_manifests(dir, taken_ids)
+}
+
+/// Read every `*.toml` toolchain manifest in this dir (none of them can use `taken_ids`)
+fn read_toolchain_manifests(
+    dir: &Utf8Path,
+    mut taken_ids: Vec<ToolchainId>,
+) -> Result<Vec<ToolchainManifest>, GenerateError> {
+    let mut manifest_paths = std::fs::read_dir(dir)?
+        .map(|entry| Ok(Utf8PathBuf::try_from(entry?.path()).expect("non-utf8 path")))
+        .collect::<Result<Vec<_>, std::io::Error>>()?;
+    manifest_paths.retain(|path| path.extension() == Some("toml"));
+    manifest_paths.sort();
+
+    let mut manifests = vec![];
+    for path in manifest_paths {
+        let data = std::fs::read_to_string(&path)?;
+        let file: ManifestFile =
+            toml::from_str(&data).map_err(|e| GenerateError::InvalidToolchainManifest {
+                toolchain: path.to_string(),
+                details: e.to_string(),
+            })?;
+        let id = file
+            .name
+            .clone()
+            .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_owned());
+        if taken_ids.contains(&id) {
+            return Err(GenerateError::InvalidToolchainManifest {
+                toolchain: id,
+                details: format!("{path} uses an id that's already taken by another toolchain"),
+            });
+        }
+        taken_ids.push(id.clone());
+        manifests.push(ToolchainManifest::from_file(id, file)?);
+    }
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(id: &str, toml: &str) -> Result<ToolchainManifest, GenerateError> {
+        let file: ManifestFile = toml::from_str(toml).unwrap();
+        ToolchainManifest::from_file(id.to_owned(), file)
+    }
+
+    fn invalid_details(result: Result<impl Sized, GenerateError>) -> String {
+        match result {
+            Err(GenerateError::InvalidToolchainManifest { details, .. }) => details,
+            Err(e) => panic!("wrong error: {e}"),
+            Ok(_) => panic!("manifest should be invalid"),
+        }
+    }
+
+    /// A fresh dir for manifest files
+    fn manifest_dir(name: &str) -> Utf8PathBuf {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let dir = dir.join(format!("abi-cafe-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn from_file() {
+        let manifest = parse(
+            "tcc",
+            r#"
+            lang = "c"
+            version = ["tcc", "-v"]
+            compile = ["tcc", "-c", "{input}", "-o", "{output}"]
+            debug_args = ["-g"]
+
+            [conventions]
+            c = ""
+            stdcall = "__attribute__((stdcall))"
+
+            [types]
+            f16 = "_Float16"
+            u8 = "unsigned char *"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.id, "tcc");
+        assert_eq!(manifest.lang, ManifestLang::C);
+        assert_eq!(manifest.compile[0], "tcc");
+        assert_eq!(manifest.debug_args, vec!["-g".to_owned()]);
+        let conventions = manifest.overrides.conventions.unwrap();
+        assert_eq!(conventions.len(), 2);
+        assert_eq!(conventions[&CallingConvention::C], "");
+        assert_eq!(
+            conventions[&CallingConvention::Stdcall],
+            "__attribute__((stdcall)) "
+        );
+        let types = manifest.overrides.types;
+        assert_eq!(types[&PrimitiveTy::F16], "_Float16 ");
+        // Pointers are already spaced out
+        assert_eq!(types[&PrimitiveTy::U8], "unsigned char *");
+
+        // Without a [conventions] table the builtin ones are used
+        let manifest = parse("tcc", "lang = \"c\"\ncompile = [\"tcc\"]").unwrap();
+        assert!(manifest.overrides.conventions.is_none());
+        assert!(manifest.version.is_none());
+    }
+
+    #[test]
+    fn from_file_errors() {
+        let details = invalid_details(parse("tcc", "lang = \"c\"\ncompile = []"));
+        assert_eq!(details, "compile command is empty");
+        let details = invalid_details(parse(
+            "tcc",
+            "lang = \"c\"\ncompile = [\"tcc\"]\nversion = []",
+        ));
+        assert_eq!(details, "version command is empty");
+        let details = invalid_details(parse(
+            "tcc",
+            "lang = \"c\"\ncompile = [\"tcc\"]\n[conventions]\nfancy = \"\"",
+        ));
+        assert!(details.contains("fancy"), "{details}");
+        let details = invalid_details(parse(
+            "tcc",
+            "lang = \"c\"\ncompile = [\"tcc\"]\n[types]\nu7 = \"int\"",
+        ));
+        assert_eq!(details, "u7 isn't a primitive type");
+    }
+
+    #[test]
+    fn read_manifests() {
+        let dir = manifest_dir("manifests");
+        std::fs::write(dir.join("tcc.toml"), "lang = \"c\"\ncompile = [\"tcc\"]").unwrap();
+        std::fs::write(
+            dir.join("other.toml"),
+            "name = \"chibicc\"\nlang = \"c\"\ncompile = [\"chibicc\"]",
+        )
+        .unwrap();
+        // Not a manifest
+        std::fs::write(dir.join("README.md"), "# hi").unwrap();
+
+        let manifests = read_toolchain_manifests(&dir, vec!["rustc".to_owned()]).unwrap();
+        let ids = manifests.iter().map(|m| m.id.as_str()).collect::<Vec<_>>();
+        // Sorted by file name, and `name` beats the file name
+        assert_eq!(ids, ["chibicc", "tcc"]);
+
+        // Ids can't be reused, neither from another toolchain nor another manifest
+        let err = read_toolchain_manifests(&dir, vec!["tcc".to_owned()]);
+        assert!(invalid_details(err).contains("already taken"));
+        std::fs::write(
+            dir.join("tcc2.toml"),
+            "name = \"tcc\"\nlang = \"c\"\ncompile = [\"tcc\"]",
+        )
+        .unwrap();
+        let err = read_toolchain_manifests(&dir, vec![]);
+        assert!(invalid_details(err).contains("tcc2.toml"));
+
+        // Bad toml is an error about that file
+        std::fs::remove_file(dir.join("tcc2.toml")).unwrap();
+        std::fs::write(dir.join("broken.toml"), "lang = \"c\"\ncompile = 1").unwrap();
+        match read_toolchain_manifests(&dir, vec![]) {
+            Err(GenerateError::InvalidToolchainManifest { toolchain, .. }) => {
+                assert!(toolchain.ends_with("broken.toml"), "{toolchain}")
+            }
+            _ => panic!("broken.toml should be rejected"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,16 +1,20 @@
 use std::fmt::Write;
-use std::sync::Arc;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
 
 use crate::harness::test::*;
-use crate::{error::*, SortedMap};
+use crate::{error::*, ArmFloatAbi, Instrument, RiscvFloatAbi, SortedMap};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use kdl_script::PunEnv;
+use tracing::debug;
 
 pub mod c;
+pub mod manifest;
 pub mod rust;
 
 pub use c::CcToolchain;
+pub use manifest::{find_toolchain_manifests, ManifestToolchain, ToolchainManifest};
 pub use rust::RustcToolchain;
 
 pub const TOOLCHAIN_RUSTC: &str = "rustc";
@@ -30,10 +34,15 @@ const C_TOOLCHAINS: &[&str] = &[
 
 /// A compiler/language toolchain!
 pub trait Toolchain {
-    #[allow(dead_code)]
     fn lang(&self) -> &'static str;
-    fn src_ext(&self) -> &'static str;
+    /// The extension of the source files this generates for this program
+    /// (with the options of the side of the call it's generating)
+    fn src_ext(&self, types: &kdl_script::TypedProgram, options: &TestOptions) -> &'static str;
     fn pun_env(&self) -> Arc<PunEnv>;
+    /// Get the version of the underlying compiler (if it can be found)
+    fn version(&self) -> Option<String>;
+    /// Check that the compiler this toolchain needs is actually installed
+    fn preflight(&self) -> Result<(), MissingCompiler>;
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError>;
     fn generate_caller(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError>;
 
@@ -42,13 +51,145 @@ pub trait Toolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError>;
     fn compile_caller(
         &self,
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError>;
+
+    /// The kind of LTO unit this toolchain's objects can be optimized in (see [`Lto`][])
+    fn lto_unit(&self) -> Option<LtoUnit> {
+        None
+    }
+
+    /// Whether this toolchain can compile with extra target features (see [`TargetFeatures`][])
+    fn supports_target_features(&self) -> bool {
+        false
+    }
+
+    /// Whether this toolchain can compile with MemorySanitizer (see [`Instrument::Msan`][])
+    fn supports_msan(&self) -> bool {
+        false
+    }
+
+    /// Whether this toolchain can compile with x86's control-flow protection
+    /// (see [`CallPath::CfProtection`][])
+    fn supports_cf_protection(&self) -> bool {
+        false
+    }
+
+    /// Emit the LLVM IR of a caller/callee to `ir_path` (only LLVM-based toolchains can)
+    ///
+    /// This should use the same flags as compiling it, so the IR is what actually got linked.
+    fn emit_llvm_ir(
+        &self,
+        _src_path: &Utf8Path,
+        _ir_path: &Utf8Path,
+        _codegen: &CodegenOptions,
+    ) -> Result<(), BuildError> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this toolchain doesn't use LLVM",
+        ))?
+    }
+
+    /// Generate a program that prints the real size/align/field offsets of
+    /// every nominal type in the test (see [`crate::harness::layout_check`][])
+    ///
+    /// Each line of output is either `ty <name> <size> <align>` or `field <ty> <field> <offset>`.
+    fn generate_layout_probe(
+        &self,
+        _f: &mut dyn Write,
+        _test: TestImpl,
+    ) -> Result<(), GenerateError> {
+        Err(UnsupportedError::Other(format!(
+            "{} can't generate layout probes",
+            self.lang()
+        )))?
+    }
+    /// Compile a program from [`Toolchain::generate_layout_probe`][] to an executable
+    fn compile_layout_probe(
+        &self,
+        _src_path: &Utf8Path,
+        _exe_path: &Utf8Path,
+    ) -> Result<(), BuildError> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this toolchain can't build layout probes",
+        ))?
+    }
+
+    /// How a Cargo reproducer's `build.rs` can build a side like this toolchain does
+    /// (None if the `cc` crate can't, see [`crate::harness::reproducer`][])
+    fn cc_crate_build(&self, _codegen: &CodegenOptions) -> Option<CcCrateBuild> {
+        None
+    }
+    /// The flags rustc compiles a side with on top of the defaults (for the `RUSTFLAGS`
+    /// of Cargo reproducers, see [`crate::harness::reproducer`][])
+    fn rustc_flags(&self, _codegen: &CodegenOptions) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// How to build a side with the `cc` crate, see [`Toolchain::cc_crate_build`][]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcCrateBuild {
+    /// The compiler to use (None for whatever `cc` picks)
+    pub compiler: Option<String>,
+    /// Flags on top of the ones `cc` always passes
+    pub flags: Vec<String>,
+}
+
+/// What the objects a toolchain emits for LTO contain
+///
+/// Only objects of the same kind can be optimized together by the linker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LtoUnit {
+    /// LLVM bitcode (clang and rustc), which the linker needs to be lld for
+    Llvm,
+    /// GIMPLE, which GNU ld, gold, and mold can hand back to gcc
+    Gcc,
+}
+
+/// The compiler a toolchain needs, which [`Toolchain::preflight`][] couldn't find
+#[derive(Debug, Clone)]
+pub struct MissingCompiler {
+    /// The binary (or file) we were looking for
+    pub binary: String,
+    /// How to get it
+    pub install_hint: String,
+}
+
+/// Every command run to build and run tests, in the order they ran (see `abi-cafe explain`)
+#[derive(Debug, Default)]
+pub struct CommandLog {
+    commands: Mutex<Vec<String>>,
+}
+
+impl CommandLog {
+    /// Remember that we're about to run this
+    pub fn record(&self, cmd: &Command) {
+        let cmd = format!("{cmd:?}");
+        debug!("running: {cmd}");
+        self.commands.lock().unwrap().push(cmd);
+    }
+
+    /// Remember a command we couldn't get a [`Command`][] for (like the ones cc runs)
+    pub fn record_line(&self, cmd: String) {
+        debug!("running: {cmd}");
+        self.commands.lock().unwrap().push(cmd);
+    }
+
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
 }
 
 /// All the toolchains
@@ -57,6 +198,7 @@ pub struct Toolchains {
     pub rustc_command: Utf8PathBuf,
     pub toolchains: ToolchainMap,
     pub debug: bool,
+    pub instrument: Option<Instrument>,
 }
 pub type ToolchainMap = SortedMap<String, Arc<dyn Toolchain + Send + Sync>>;
 
@@ -66,10 +208,17 @@ pub struct PlatformInfo {
     pub target: String,
     /// Enabled rustc cfgs, used for our own test harness cfgs
     pub cfgs: Vec<cargo_platform::Cfg>,
+    /// The float ABI of the target, if it's RISC-V (see `--riscv-float-abi`)
+    pub riscv_float_abi: Option<RiscvFloatAbi>,
+    /// The float ABI of the target, if it's 32-bit ARM (see `--arm-float-abi`)
+    pub arm_float_abi: Option<ArmFloatAbi>,
 }
 
 /// Create all the toolchains
-pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
+pub(crate) fn create_toolchains(
+    cfg: &crate::Config,
+    manifests: Vec<ToolchainManifest>,
+) -> Toolchains {
     let mut toolchains = ToolchainMap::default();
 
     let rustc_command: Utf8PathBuf = "rustc".into();
@@ -92,12 +241,39 @@ pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
         );
     }
 
-    // Add c toolchains
+    // Add c toolchains (--arm-float-abi can pick another float ABI that links with the target's)
+    let arm_float_abi = cfg.arm_float_abi.or(platform_info.arm_float_abi);
     for &name in C_TOOLCHAINS {
         add_toolchain(
             &mut toolchains,
             name,
-            CcToolchain::new(cfg, &platform_info.target, name),
+            CcToolchain::new(cfg, &platform_info.target, name, None)
+                .with_riscv_float_abi(platform_info.riscv_float_abi)
+                .with_arm_float_abi(arm_float_abi),
+        );
+    }
+    for (name, path) in &cfg.custom_c_toolchains {
+        add_toolchain(
+            &mut toolchains,
+            name,
+            CcToolchain::new(
+                cfg,
+                &platform_info.target,
+                TOOLCHAIN_CC,
+                Some(path.to_owned()),
+            )
+            .with_riscv_float_abi(platform_info.riscv_float_abi)
+            .with_arm_float_abi(arm_float_abi),
+        );
+    }
+
+    // Add toolchains from manifests
+    for manifest in manifests {
+        let id = manifest.id.clone();
+        add_toolchain(
+            &mut toolchains,
+            id,
+            ManifestToolchain::new(cfg, &platform_info.target, manifest),
         );
     }
 
@@ -106,6 +282,46 @@ pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
         rustc_command,
         toolchains,
         debug: cfg.debug,
+        instrument: cfg.instrument,
+    }
+}
+
+impl Toolchains {
+    /// Check that all these toolchains exist and have their compilers installed
+    pub fn preflight<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a ToolchainId>,
+    ) -> Vec<ToolchainMissingError> {
+        let mut missing = vec![];
+        for id in ids {
+            let Some(toolchain) = self.toolchains.get(id) else {
+                missing.push(ToolchainMissingError {
+                    toolchain: id.clone(),
+                    binary: id.clone(),
+                    install_hint: format!(
+                        "{id} isn't a known toolchain, the available ones are: {}",
+                        self.toolchains
+                            .keys()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                });
+                continue;
+            };
+            if let Err(MissingCompiler {
+                binary,
+                install_hint,
+            }) = toolchain.preflight()
+            {
+                missing.push(ToolchainMissingError {
+                    toolchain: id.clone(),
+                    binary,
+                    install_hint,
+                });
+            }
+        }
+        missing
     }
 }
 
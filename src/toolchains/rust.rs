@@ -59,10 +59,14 @@ pub struct RustcToolchain {
     pub platform_info: PlatformInfo,
     /// Windowsy or Unixy?
     platform: Platform,
+    /// Are we targeting 32-bit x86 (where thiscall exists)?
+    is_x86: bool,
     /// What codegen backend are we using?
     codegen_backend: Option<String>,
     /// Enable debuginfo
     debug: bool,
+    /// Compile with MemorySanitizer
+    msan: bool,
 }
 
 #[derive(PartialEq)]
@@ -75,7 +79,7 @@ impl Toolchain for RustcToolchain {
     fn lang(&self) -> &'static str {
         "rust"
     }
-    fn src_ext(&self) -> &'static str {
+    fn src_ext(&self, _types: &TypedProgram, _options: &TestOptions) -> &'static str {
         "rs"
     }
     fn pun_env(&self) -> Arc<PunEnv> {
@@ -83,11 +87,28 @@ impl Toolchain for RustcToolchain {
             lang: "rust".to_string(),
         })
     }
+    fn version(&self) -> Option<String> {
+        Some(self.version.clone())
+    }
+    fn preflight(&self) -> Result<(), MissingCompiler> {
+        // We've already run rustc to get its version, so only the backend can be missing
+        if let Some(codegen_backend) = &self.codegen_backend {
+            if !Utf8Path::new(codegen_backend).exists() {
+                return Err(MissingCompiler {
+                    binary: codegen_backend.clone(),
+                    install_hint: "build the codegen backend, or fix the path passed to --add-rustc-codegen-backend".to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
     fn compile_callee(
         &self,
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         let mut cmd = Command::new(&self.command);
         cmd.arg("--crate-type")
@@ -101,10 +122,8 @@ impl Toolchain for RustcToolchain {
         if self.debug {
             cmd.arg("-g");
         }
-        if let Some(codegen_backend) = &self.codegen_backend {
-            cmd.arg(format!("-Zcodegen-backend={codegen_backend}"));
-        }
-        debug!("running: {:?}", cmd);
+        cmd.args(self.rustc_flags(codegen));
+        commands.record(&cmd);
         let out = cmd.output()?;
 
         if !out.status.success() {
@@ -119,9 +138,95 @@ impl Toolchain for RustcToolchain {
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
+        codegen: &CodegenOptions,
+        commands: &CommandLog,
     ) -> Result<String, BuildError> {
         // Currently no need to be different
-        self.compile_callee(src_path, out_dir, lib_name)
+        self.compile_callee(src_path, out_dir, lib_name, codegen, commands)
+    }
+
+    fn supports_target_features(&self) -> bool {
+        true
+    }
+
+    fn lto_unit(&self) -> Option<LtoUnit> {
+        // Only the LLVM backend emits bitcode
+        self.codegen_backend.is_none().then_some(LtoUnit::Llvm)
+    }
+
+    fn supports_msan(&self) -> bool {
+        // Sanitizers are still unstable, and only the LLVM backend has them
+        self.is_nightly && self.codegen_backend.is_none()
+    }
+
+    fn supports_cf_protection(&self) -> bool {
+        // -Zcf-protection is unstable too (and just forwarded to LLVM)
+        self.is_nightly && self.codegen_backend.is_none()
+    }
+
+    fn emit_llvm_ir(
+        &self,
+        src_path: &Utf8Path,
+        ir_path: &Utf8Path,
+        codegen: &CodegenOptions,
+    ) -> Result<(), BuildError> {
+        if self.codegen_backend.is_some() {
+            // Whatever this backend is, it's not going to produce LLVM IR
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "custom codegen backends don't use LLVM",
+            ))?;
+        }
+        let mut cmd = Command::new(&self.command);
+        cmd.arg("--crate-type")
+            .arg("staticlib")
+            .arg("--emit=llvm-ir")
+            .arg("--target")
+            .arg(&self.platform_info.target)
+            .arg("-o")
+            .arg(ir_path)
+            .arg(src_path);
+        if self.debug {
+            cmd.arg("-g");
+        }
+        cmd.args(self.rustc_flags(codegen));
+        debug!("running: {:?}", cmd);
+        let out = cmd.output()?;
+
+        if !out.status.success() {
+            Err(BuildError::RustCompile(out))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn rustc_flags(&self, codegen: &CodegenOptions) -> Vec<String> {
+        let mut flags = vec![];
+        if let Some(codegen_backend) = &self.codegen_backend {
+            flags.push(format!("-Zcodegen-backend={codegen_backend}"));
+        }
+        if self.msan {
+            flags.push("-Zsanitizer=memory".to_owned());
+        }
+        flags.extend(codegen.target_features.rustc_flag());
+        if codegen.cf_protection {
+            flags.push("-Zcf-protection=full".to_owned());
+        }
+        // The float ABI itself is the target's (its llvm-abiname), but make sure the
+        // float registers it needs are there, just like the C side's -march
+        if let Some(features) = self
+            .platform_info
+            .riscv_float_abi
+            .and_then(|abi| abi.rustc_target_features())
+        {
+            flags.push(format!("-Ctarget-feature={features}"));
+        }
+        flags.extend(
+            self.lto_flags(codegen.lto)
+                .iter()
+                .map(|&flag| flag.to_owned()),
+        );
+        flags
     }
 
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
@@ -135,25 +240,132 @@ impl Toolchain for RustcToolchain {
         let mut state = TestState::new(test);
         self.generate_caller_impl(&mut f, &mut state)
     }
+
+    fn generate_layout_probe(
+        &self,
+        f: &mut dyn Write,
+        test: TestImpl,
+    ) -> Result<(), GenerateError> {
+        let mut f = Fivemat::new(f, INDENT);
+        let mut state = TestState::new(test);
+        self.generate_layout_probe_impl(&mut f, &mut state)
+    }
+
+    fn compile_layout_probe(
+        &self,
+        src_path: &Utf8Path,
+        exe_path: &Utf8Path,
+    ) -> Result<(), BuildError> {
+        let mut cmd = Command::new(&self.command);
+        cmd.arg("--crate-type")
+            .arg("bin")
+            .arg("--target")
+            .arg(&self.platform_info.target)
+            // Always with debug info, for --check-dwarf
+            .arg("-g")
+            .arg("-o")
+            .arg(exe_path)
+            .arg(src_path);
+        if let Some(codegen_backend) = &self.codegen_backend {
+            cmd.arg(format!("-Zcodegen-backend={codegen_backend}"));
+        }
+        debug!("running: {:?}", cmd);
+        let out = cmd.output()?;
+
+        if !out.status.success() {
+            Err(BuildError::RustCompile(out))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl RustcToolchain {
+    /// The flags that make rustc emit bitcode for the linker to optimize together with
+    /// the other side (see [`Lto`][])
+    ///
+    /// This is always ThinLTO bitcode. `-Clto=fat` would merge a whole copy of std into
+    /// every static lib, so the two sides couldn't be linked together anymore, which is
+    /// why fat LTO is unsupported with rustc (see `TestHarness::generate_test`).
+    fn lto_flags(&self, lto: Lto) -> &'static [&'static str] {
+        match lto {
+            Lto::Off => &[],
+            Lto::Thin | Lto::Fat => &["-Clinker-plugin-lto", "-Copt-level=2"],
+        }
+    }
+
+    /// Check that rustc is willing to pass the vectors in this test's signatures
+    fn check_vector_args(
+        &self,
+        state: &TestState,
+        call_side: CallSide,
+    ) -> Result<(), GenerateError> {
+        let max_vector = state
+            .desired_funcs
+            .iter()
+            .flat_map(|&func| {
+                let func = state.types.realize_func(func);
+                func.inputs.iter().chain(&func.outputs)
+            })
+            .filter_map(|arg| state.types.ty_max_vector_size(arg.ty))
+            .max();
+        let Some(size) = max_vector else {
+            return Ok(());
+        };
+        if let CallSide::Caller = call_side {
+            if !self.is_nightly {
+                Err(UnsupportedError::Other(
+                    "calling functions that take vectors needs simd_ffi, requires nightly"
+                        .to_owned(),
+                ))?
+            }
+        }
+        if size >= 32 && !state.options.target_features_for(call_side).enables_avx() {
+            Err(UnsupportedError::Other(
+                "rustc only passes 256-bit vectors with AVX enabled".to_owned(),
+            ))?
+        }
+        Ok(())
+    }
+
     pub fn generate_caller_impl(
         &self,
         f: &mut Fivemat,
         state: &mut TestState,
     ) -> Result<(), GenerateError> {
+        self.check_vector_args(state, CallSide::Caller)?;
         // Generate type decls and gather up functions
         self.generate_definitions(f, state)?;
         // Generate decls of the functions we want to call
         self.generate_caller_externs(f, state)?;
+        if state.options.rounding_mode.is_some() {
+            writeln!(
+                f,
+                "{}",
+                crate::files::get_file("harness/rust/rounding_mode_prefix.rs")
+            )?;
+        }
 
         // Generate the test function the harness will call
         writeln!(f, "#[no_mangle]\npub extern \"C\" fn do_test() {{")?;
+        if let Some(rounding_mode) = state.options.rounding_mode {
+            writeln!(
+                f,
+                "unsafe {{ abi_cafe_set_rounding_mode({}) }};",
+                rounding_mode.helper_id()
+            )?;
+        }
         for &func in &state.desired_funcs {
             // Generate the individual function calls
             self.generate_caller_body(f, state, func)?;
         }
+        if state.options.rounding_mode.is_some() {
+            writeln!(
+                f,
+                "unsafe {{ abi_cafe_set_rounding_mode({}) }};",
+                RoundingMode::Nearest.helper_id()
+            )?;
+        }
         writeln!(f, "}}")?;
 
         Ok(())
@@ -174,15 +386,30 @@ impl RustcToolchain {
 
         // Create vars for all the inputs
         let mut func_vals = state.vals.at_func(func);
-        for arg in &function.inputs {
+        let mut aliased = vec![];
+        for (input_idx, arg) in function.inputs.iter().enumerate() {
             let arg_vals: ArgValuesIter = func_vals.next_arg();
+            // References to other inputs are made once those all exist
+            if let Some(val_idx) = arg_vals.func().aliased_input(input_idx, &state.options) {
+                aliased.push((arg, &function.inputs[val_idx]));
+                continue;
+            }
             // Create and report the input
             self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
+            self.fill_padding(f, state, &arg.name, arg.ty)?;
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
         }
+        // `f(val, &mut val)` copies `val` to a temporary before taking the borrow, we just
+        // spell that out so the borrow checker accepts the reference coming first too
+        for (arg, val) in aliased {
+            let val_name = &val.name;
+            writeln!(f, "let mut {val_name} = {val_name};")?;
+            writeln!(f, "let {val_name}_arg = {val_name};")?;
+            writeln!(f, "let {} = &mut {val_name};", arg.name)?;
+        }
 
         // Call the function
-        self.call_function(f, state, function)?;
+        self.call_function(f, state, func, function)?;
 
         // Report all the outputs
         for arg in &function.outputs {
@@ -191,6 +418,15 @@ impl RustcToolchain {
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
         }
 
+        // Report what the callee wrote through any in/out references
+        for arg_vals in func_vals {
+            let Some(input_idx) = arg_vals.arg().inout_of else {
+                continue;
+            };
+            let arg = &function.inputs[input_idx];
+            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+        }
+
         f.sub_indent(1);
         writeln!(f, "}}")?;
         Ok(())
@@ -200,27 +436,75 @@ impl RustcToolchain {
         &self,
         f: &mut Fivemat,
         state: &TestState,
+        func_idx: FuncIdx,
         function: &Func,
     ) -> Result<(), GenerateError> {
-        let func_name = &function.name;
+        let mut func_name = function.name.to_string();
 
         // make sure the outputs aren't weird
         self.check_returns(state, function)?;
-        if let Some(output) = function.outputs.first() {
-            write!(f, "let {} = ", output.name)?;
+        let inputs = state.options.func_inputs(function, CallSide::Caller);
+        // See generate_caller_body
+        let func_vals = &state.vals.funcs[func_idx];
+        let aliased = (0..function.inputs.len())
+            .filter_map(|input_idx| func_vals.aliased_input(input_idx, &state.options))
+            .collect::<Vec<_>>();
+        let args = inputs
+            .iter()
+            .enumerate()
+            .map(|(input_idx, arg)| {
+                // See RustcToolchain::generate_signature_named
+                if state.types.ty_is_nontrivial(arg.ty) {
+                    format!("&{}", arg.name)
+                } else if aliased.contains(&input_idx) {
+                    format!("{}_arg", arg.name)
+                } else {
+                    arg.name.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        if state.options.call_path.is_indirect() {
+            // black_box keeps rustc from turning this back into a direct call
+            let convention_decl = self.convention_decl(state.options.convention)?;
+            let ptr_name = format!("{func_name}_ptr");
+            // (the inputs can be inferred from the call, but not the output)
+            let arg_tys = vec!["_"; inputs.len()].join(", ");
+            let ret_ty = match function.outputs.first() {
+                Some(arg) => &state.tynames[&arg.ty],
+                None => "()",
+            };
+            writeln!(
+                f,
+                r#"let {ptr_name} = core::hint::black_box({func_name} as unsafe extern "{convention_decl}" fn({arg_tys}) -> {ret_ty});"#
+            )?;
+            func_name = ptr_name;
         }
 
-        // Call the function
-        write!(f, "{func_name}(")?;
-        let inputs = function.inputs.iter();
+        // The callee is supposed to unwind, so catch it (the outputs never exist)
+        if state.options.unwind {
+            writeln!(
+                f,
+                "let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {{ {func_name}({args}); }})).is_err();"
+            )?;
+            writeln!(f, "if !unwound {{")?;
+            f.add_indent(1);
+            writeln!(
+                f,
+                r#"eprintln!("{func_name} returned instead of unwinding");"#
+            )?;
+            writeln!(f, "std::process::exit(1);")?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            writeln!(f)?;
+            return Ok(());
+        }
 
-        for (arg_idx, arg) in inputs.enumerate() {
-            if arg_idx > 0 {
-                write!(f, ", ")?;
-            }
-            write!(f, "{}", arg.name)?;
+        // Call the function
+        if let Some(output) = function.outputs.first() {
+            write!(f, "let {} = ", output.name)?;
         }
-        writeln!(f, ");")?;
+        writeln!(f, "{func_name}({args});")?;
         writeln!(f)?;
         Ok(())
     }
@@ -232,8 +516,23 @@ impl RustcToolchain {
         f: &mut Fivemat,
         state: &mut TestState,
     ) -> Result<(), GenerateError> {
+        self.check_vector_args(state, CallSide::Callee)?;
         // Generate type decls and gather up functions
         self.generate_definitions(f, state)?;
+        if state.options.nested_calls > 0 {
+            writeln!(
+                f,
+                "{}",
+                crate::files::get_file("harness/rust/nested_calls_prefix.rs")
+            )?;
+        }
+        if state.options.rounding_mode.is_some() {
+            writeln!(
+                f,
+                "{}",
+                crate::files::get_file("harness/rust/rounding_mode_prefix.rs")
+            )?;
+        }
 
         for &func in &state.desired_funcs {
             // Generate the individual function definitions
@@ -242,6 +541,47 @@ impl RustcToolchain {
         Ok(())
     }
 
+    fn generate_layout_probe_impl(
+        &self,
+        f: &mut Fivemat,
+        state: &mut TestState,
+    ) -> Result<(), GenerateError> {
+        // Generate type decls and gather up functions
+        self.generate_definitions(f, state)?;
+
+        writeln!(f, "#[allow(non_snake_case)]")?;
+        writeln!(f, "fn main() {{")?;
+        f.add_indent(1);
+        for def in state.defs.definitions(state.desired_funcs.iter().copied()) {
+            let kdl_script::Definition::DefineTy(ty) = def else {
+                continue;
+            };
+            let (name, fields) = match state.types.realize_ty(ty) {
+                Ty::Struct(struct_ty) => (&struct_ty.name, Some(&struct_ty.fields)),
+                Ty::Union(union_ty) => (&union_ty.name, None),
+                Ty::Enum(enum_ty) => (&enum_ty.name, None),
+                Ty::Tagged(tagged_ty) => (&tagged_ty.name, None),
+                _ => continue,
+            };
+            // A variable makes sure the type ends up in the debug info
+            writeln!(f, "let _probe_{name}: Option<&{name}> = None;")?;
+            writeln!(
+                f,
+                r#"println!("ty {name} {{}} {{}}", core::mem::size_of::<{name}>(), core::mem::align_of::<{name}>());"#
+            )?;
+            for field in fields.into_iter().flatten() {
+                let field_name = &field.ident;
+                writeln!(
+                    f,
+                    r#"println!("field {name} {field_name} {{}}", core::mem::offset_of!({name}, {field_name}));"#
+                )?;
+            }
+        }
+        f.sub_indent(1);
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+
     fn generate_callee_body(
         &self,
         f: &mut Fivemat,
@@ -250,9 +590,56 @@ impl RustcToolchain {
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
         let convention_decl = self.convention_decl(state.options.convention)?;
-        writeln!(f, "#[no_mangle]")?;
-        write!(f, "pub unsafe extern \"{convention_decl}\" ")?;
-        self.generate_signature(f, state, func)?;
+        let chain_len = state.options.tail_call_chain;
+        let link_name = |link: usize| {
+            if link == 0 {
+                function.name.to_string()
+            } else {
+                format!("{}_tail{link}", function.name)
+            }
+        };
+
+        // All the links of a tail call chain (including the actual exported function)
+        // just tail call the next link with all their inputs (and `become` makes sure
+        // they really are tail calls)
+        if chain_len > 0 && !self.is_nightly {
+            Err(UnsupportedError::Other(
+                "tail call chains need explicit_tail_calls, requires nightly".to_owned(),
+            ))?
+        }
+        for link in 0..chain_len {
+            if link == 0 {
+                writeln!(f, "#[no_mangle]")?;
+                write!(f, "pub ")?;
+            } else {
+                writeln!(f, "#[inline(never)]")?;
+            }
+            write!(f, "unsafe extern \"{convention_decl}\" ")?;
+            self.generate_signature_named(f, state, func, CallSide::Callee, &link_name(link))?;
+            writeln!(f, " {{")?;
+            f.add_indent(1);
+            write!(f, "unsafe {{ become {}(", link_name(link + 1))?;
+            for (arg_idx, arg) in function.inputs.iter().enumerate() {
+                if arg_idx > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", arg.name)?;
+            }
+            writeln!(f, ") }}")?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            writeln!(f)?;
+        }
+
+        // The last link is the one that does the work
+        if chain_len > 0 {
+            writeln!(f, "#[inline(never)]")?;
+            write!(f, "unsafe extern \"{convention_decl}\" ")?;
+        } else {
+            writeln!(f, "#[no_mangle]")?;
+            write!(f, "pub unsafe extern \"{convention_decl}\" ")?;
+        }
+        self.generate_signature_named(f, state, func, CallSide::Callee, &link_name(chain_len))?;
         writeln!(f, " {{")?;
         f.add_indent(1);
         writeln!(f, "unsafe {{")?;
@@ -261,6 +648,21 @@ impl RustcToolchain {
         // Report we're starting a function
         self.write_set_function(f, state, CALLEE_VALS, func)?;
 
+        // Write through references that alias by-value inputs before reporting those,
+        // so if the "copy" is really the caller's variable it has the new value
+        for arg_vals in state.vals.at_func(func) {
+            let Some(input_idx) = arg_vals.arg().inout_of else {
+                continue;
+            };
+            if arg_vals
+                .func()
+                .aliased_input(input_idx, &state.options)
+                .is_some()
+            {
+                self.write_through(f, state, &function.inputs[input_idx], arg_vals)?;
+            }
+        }
+
         // Report the inputs
         let mut func_vals = state.vals.at_func(func);
         for arg in &function.inputs {
@@ -268,6 +670,20 @@ impl RustcToolchain {
             let arg_name = &arg.name;
             self.write_var(f, state, arg_name, arg.ty, arg_vals, CALLEE_VALS)?;
         }
+        // No reporting the padding of the inputs like C does: in Rust the padding of a
+        // value is uninitialized once it's been copied (which passing it is), so reading
+        // it would be UB
+
+        // Clobber everything a call is allowed to, so whatever the callee has to preserve
+        // for its caller actually has to be saved somewhere
+        if state.options.nested_calls > 0 {
+            writeln!(f, "abi_cafe_nested_call({});", state.options.nested_calls)?;
+        }
+
+        // Unwind instead of returning (everything after this is unreachable, but still typechecks)
+        if state.options.unwind {
+            writeln!(f, r#"panic!("unwinding out of {}");"#, function.name)?;
+        }
 
         // Create outputs and report them
         for arg in &function.outputs {
@@ -276,6 +692,22 @@ impl RustcToolchain {
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
         }
 
+        // Write new values through any in/out references and report them
+        for arg_vals in func_vals {
+            let Some(input_idx) = arg_vals.arg().inout_of else {
+                continue;
+            };
+            let func_vals = arg_vals.func();
+            if !func_vals.writes_through(input_idx, &state.options) {
+                continue;
+            }
+            let arg = &function.inputs[input_idx];
+            if func_vals.aliased_input(input_idx, &state.options).is_none() {
+                self.write_through(f, state, arg, arg_vals.clone())?;
+            }
+            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
+        }
+
         // Return the outputs
         self.check_returns(state, function)?;
         if let Some(arg) = function.outputs.first() {
@@ -290,6 +722,21 @@ impl RustcToolchain {
 }
 
 impl RustcToolchain {
+    /// Write the new value of an in/out reference through it
+    fn write_through(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        arg: &Arg,
+        arg_vals: ArgValuesIter,
+    ) -> Result<(), GenerateError> {
+        let arg_name = &arg.name;
+        let new_name = format!("{arg_name}_after");
+        self.init_var(f, state, &new_name, arg.ty, arg_vals)?;
+        writeln!(f, "core::mem::swap({arg_name}, {new_name});")?;
+        Ok(())
+    }
+
     pub fn new(system_info: &Config, command: &Utf8Path, codegen_backend: Option<String>) -> Self {
         // Get rustc's version and host
         let rustc_info = Command::new(command)
@@ -334,15 +781,25 @@ impl RustcToolchain {
         } else {
             Platform::Unixy
         };
+        let is_x86 = cfgs.contains(
+            &cargo_platform::Cfg::from_str("target_arch=\"x86\"").expect("failed to parse x86 cfg"),
+        );
 
         Self {
             command: command.to_owned(),
             version,
             is_nightly,
-            platform_info: PlatformInfo { target: host, cfgs },
+            platform_info: PlatformInfo {
+                target: host,
+                riscv_float_abi: RiscvFloatAbi::of_target(&cfgs),
+                arm_float_abi: ArmFloatAbi::of_target(&cfgs),
+                cfgs,
+            },
             platform,
+            is_x86,
             codegen_backend,
             debug: system_info.debug,
+            msan: system_info.instrument == Some(Instrument::Msan),
         }
     }
 
@@ -361,6 +818,16 @@ impl RustcToolchain {
                 "multiple returns (should this be a struct?)".to_owned(),
             ))?;
         }
+        let returns_nontrivial = function
+            .outputs
+            .iter()
+            .any(|arg| state.types.ty_is_nontrivial(arg.ty));
+        if returns_nontrivial {
+            return Err(UnsupportedError::Other(
+                "rust can't return @nontrivial types (they're returned through a hidden pointer)"
+                    .to_owned(),
+            ))?;
+        }
         Ok(())
     }
 }
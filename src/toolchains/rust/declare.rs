@@ -1,6 +1,8 @@
 use super::*;
 use kdl_script::parse::{Attr, AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, LangRepr, Repr};
-use kdl_script::types::{AliasTy, ArrayTy, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use kdl_script::types::{AliasTy, ArrayTy, FuncIdx, PrimitiveTy, RefTy, StructTy, Ty, TyIdx};
+use rand::seq::SliceRandom;
+use rand_core::SeedableRng;
 use std::fmt::Write;
 
 impl RustcToolchain {
@@ -13,7 +15,7 @@ impl RustcToolchain {
         writeln!(f, "extern \"{convention_decl}\" {{",)?;
         f.add_indent(1);
         for &func in &state.desired_funcs {
-            self.generate_signature(f, state, func)?;
+            self.generate_signature(f, state, func, CallSide::Caller)?;
             writeln!(f, ";")?;
         }
         f.sub_indent(1);
@@ -62,6 +64,28 @@ impl RustcToolchain {
         let (tyname, borrowed_tyname) = match state.types.realize_ty(ty) {
             // Structural types that don't need definitions but we should
             // intern the name of
+            Ty::Primitive(PrimitiveTy::BitInt { bits, signed }) => {
+                let sign = if *signed { "" } else { "U" };
+                (format!("{sign}BitInt{bits}"), None)
+            }
+            Ty::Primitive(prim) if prim.vector_size().is_some() => {
+                let arch = if cfg!(target_arch = "x86_64") {
+                    "x86_64"
+                } else if cfg!(target_arch = "x86") {
+                    "x86"
+                } else {
+                    return Err(UnsupportedError::Other(
+                        "vectors are only tested on x86".to_owned(),
+                    ))?;
+                };
+                let name = match prim {
+                    PrimitiveTy::F32x4 => "__m128",
+                    PrimitiveTy::I64x2 => "__m128i",
+                    PrimitiveTy::F32x8 => "__m256",
+                    _ => "__m256i",
+                };
+                (format!("core::arch::{arch}::{name}"), None)
+            }
             Ty::Primitive(prim) => {
                 let name = match prim {
                     PrimitiveTy::I8 => "i8",
@@ -78,6 +102,7 @@ impl RustcToolchain {
                     PrimitiveTy::F64 => "f64",
                     PrimitiveTy::Bool => "bool",
                     PrimitiveTy::Ptr => "*mut ()",
+                    PrimitiveTy::CStr => "*const core::ffi::c_char",
                     PrimitiveTy::I256 => {
                         Err(UnsupportedError::Other("rust doesn't have i256".to_owned()))?
                     }
@@ -102,6 +127,11 @@ impl RustcToolchain {
                             ))?;
                         }
                     }
+                    PrimitiveTy::F32x4
+                    | PrimitiveTy::I64x2
+                    | PrimitiveTy::F32x8
+                    | PrimitiveTy::I64x4 => unreachable!("vectors are named above"),
+                    PrimitiveTy::BitInt { .. } => unreachable!("bitints are named above"),
                 };
                 (name.to_owned(), None)
             }
@@ -113,16 +143,27 @@ impl RustcToolchain {
                     .map(|elem_tyname| format!("[{elem_tyname}; {len}]"));
                 (format!("[{elem_tyname}; {len}]"), borrowed_tyname)
             }
-            Ty::Ref(RefTy { pointee_ty }) => {
+            Ty::Ref(RefTy {
+                pointee_ty,
+                nullable,
+            }) => {
                 let pointee_tyname = &state.tynames[pointee_ty];
                 let borrowed_pointee_tyname = state
                     .borrowed_tynames
                     .get(pointee_ty)
                     .unwrap_or(pointee_tyname);
-                (
-                    format!("&mut {pointee_tyname}"),
-                    Some(format!("&'a mut {borrowed_pointee_tyname}")),
-                )
+                if *nullable {
+                    // The null pointer optimization makes this exactly a nullable pointer
+                    (
+                        format!("Option<&mut {pointee_tyname}>"),
+                        Some(format!("Option<&'a mut {borrowed_pointee_tyname}>")),
+                    )
+                } else {
+                    (
+                        format!("&mut {pointee_tyname}"),
+                        Some(format!("&'a mut {borrowed_pointee_tyname}")),
+                    )
+                }
             }
             Ty::Empty => ("()".to_owned(), None),
             // Nominal types we need to emit a decl for
@@ -176,6 +217,20 @@ impl RustcToolchain {
             Ty::Struct(struct_ty) => {
                 // Emit an actual struct decl
                 self.generate_repr_attr(f, state, &struct_ty.attrs, "struct")?;
+                if let Some(align) = state.options.struct_align() {
+                    let has_layout_attrs = struct_ty.attrs.iter().any(|attr| match attr {
+                        Attr::Align(_) | Attr::Packed(_) => true,
+                        Attr::Repr(AttrRepr { reprs }) => reprs.contains(&Repr::Transparent),
+                        _ => false,
+                    });
+                    if has_layout_attrs {
+                        // rustc won't let us add another repr(align) to these
+                        return Err(UnsupportedError::Other(
+                            "align fault injection on a struct with @align, @packed or @repr \"transparent\"".to_owned(),
+                        ))?;
+                    }
+                    writeln!(f, "#[repr(align({align}))]")?;
+                }
                 if has_borrows {
                     writeln!(f, "struct {}<'a> {{", struct_ty.name)?;
                 } else {
@@ -183,7 +238,8 @@ impl RustcToolchain {
                     writeln!(f, "struct {} {{", struct_ty.name)?;
                 }
                 f.add_indent(1);
-                for field in &struct_ty.fields {
+                for field_idx in self.field_order(state, struct_ty) {
+                    let field = &struct_ty.fields[field_idx];
                     let field_name = &field.ident;
                     let field_tyname = state
                         .borrowed_tynames
@@ -278,6 +334,13 @@ impl RustcToolchain {
             Ty::Pun(..) => {
                 // Puns should be evaporated by the type name interner
             }
+            Ty::Primitive(PrimitiveTy::BitInt { bits, .. }) => {
+                // Rust doesn't have these, so lay out the bytes the way C would
+                let (size, align) = state.layout_target.bitint_layout(*bits);
+                writeln!(f, "#[repr(C, align({align}))]")?;
+                writeln!(f, "#[derive(Copy, Clone, Debug, PartialEq)]")?;
+                writeln!(f, "struct {}([u8; {size}]);\n", state.tynames[&ty])?;
+            }
             Ty::Primitive(prim) => {
                 match prim {
                     PrimitiveTy::I8
@@ -297,9 +360,15 @@ impl RustcToolchain {
                     | PrimitiveTy::F64
                     | PrimitiveTy::F128
                     | PrimitiveTy::Bool
-                    | PrimitiveTy::Ptr => {
+                    | PrimitiveTy::Ptr
+                    | PrimitiveTy::CStr
+                    | PrimitiveTy::F32x4
+                    | PrimitiveTy::I64x2
+                    | PrimitiveTy::F32x8
+                    | PrimitiveTy::I64x4 => {
                         // Builtin
                     }
+                    PrimitiveTy::BitInt { .. } => unreachable!("bitints are defined above"),
                 };
             }
             Ty::Array(ArrayTy { .. }) => {
@@ -315,6 +384,32 @@ impl RustcToolchain {
         Ok(())
     }
 
+    /// The order to declare the fields of a struct in (see [`TestOptions::shuffle_fields`][])
+    fn field_order(&self, state: &TestState, struct_ty: &StructTy) -> Vec<usize> {
+        let mut order = (0..struct_ty.fields.len()).collect::<Vec<_>>();
+        // Only repr(Rust) is allowed to reorder fields, so only bother with those
+        let mut is_repr_rust = state.options.repr == LangRepr::Rust;
+        for attr in &struct_ty.attrs {
+            if let Attr::Repr(AttrRepr { reprs }) = attr {
+                is_repr_rust = reprs.iter().all(|repr| *repr == Repr::Lang(LangRepr::Rust));
+            }
+        }
+        if !state.options.shuffle_fields || !is_repr_rust {
+            return order;
+        }
+        // Both sides need to shuffle the same way, so seed it with the name of the type
+        // (and the values' seed, so fuzzing with random values also tries new orders)
+        let mut seed = match state.options.val_generator {
+            ValueGeneratorKind::Graffiti => 0,
+            ValueGeneratorKind::Random { seed } => seed,
+        };
+        for byte in struct_ty.name.bytes() {
+            seed = (seed ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        order.shuffle(&mut rand_pcg::Pcg64::seed_from_u64(seed));
+        order
+    }
+
     pub fn generate_repr_attr(
         &self,
         f: &mut Fivemat,
@@ -337,6 +432,8 @@ impl RustcToolchain {
                 Attr::Passthrough(AttrPassthrough(attr)) => {
                     other_attrs.push(attr.to_string());
                 }
+                // Rust has no copy constructors, we pass these by reference instead
+                Attr::NonTrivial(_) => {}
                 Attr::Repr(AttrRepr { reprs }) => {
                     default_lang_repr = false;
                     // Any explicit repr attributes disables default C
@@ -360,7 +457,13 @@ impl RustcToolchain {
                                 | PrimitiveTy::F64
                                 | PrimitiveTy::F128
                                 | PrimitiveTy::Bool
-                                | PrimitiveTy::Ptr => {
+                                | PrimitiveTy::Ptr
+                                | PrimitiveTy::CStr
+                                | PrimitiveTy::BitInt { .. }
+                                | PrimitiveTy::F32x4
+                                | PrimitiveTy::I64x2
+                                | PrimitiveTy::F32x8
+                                | PrimitiveTy::I64x4 => {
                                     return Err(UnsupportedError::Other(format!(
                                         "unsupport repr({prim:?})"
                                     )))?;
@@ -413,21 +516,48 @@ impl RustcToolchain {
         f: &mut Fivemat,
         state: &TestState,
         func: FuncIdx,
+        call_side: CallSide,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        self.generate_signature_named(f, state, func, call_side, &function.name)
+    }
+
+    /// Same as [`RustcToolchain::generate_signature`][] but with a different name
+    /// (used for the links of a tail call chain)
+    pub fn generate_signature_named(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+        call_side: CallSide,
+        func_name: &str,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
         self.check_returns(state, function)?;
 
-        write!(f, "fn {}(", function.name)?;
+        write!(f, "fn {}(", func_name)?;
         let mut multiarg = false;
         // Add inputs
-        for arg in &function.inputs {
+        for arg in state.options.func_inputs(function, call_side) {
             if multiarg {
                 write!(f, ", ")?;
             }
             multiarg = true;
             let arg_name = &arg.name;
             let arg_ty = &state.tynames[&arg.ty];
-            write!(f, "{}: {}", arg_name, arg_ty)?;
+            // C++ passes @nontrivial types by an invisible reference, so we make it visible
+            if state.types.ty_is_nontrivial(arg.ty) {
+                if cfg!(all(target_arch = "x86", target_env = "msvc")) {
+                    // ...except for msvc on x86, which constructs them in the caller's
+                    // argument area (inalloca), which rust has no way to spell
+                    Err(UnsupportedError::Other(
+                        "@nontrivial args on i686 msvc (they're passed with inalloca)".to_owned(),
+                    ))?;
+                }
+                write!(f, "{}: &{}", arg_name, arg_ty)?;
+            } else {
+                write!(f, "{}: {}", arg_name, arg_ty)?;
+            }
         }
         // Add normal returns
         if let Some(arg) = function.outputs.first() {
@@ -455,11 +585,30 @@ impl RustcToolchain {
 
         let conv = match convention {
             CallingConvention::C => "C",
+            CallingConvention::CUnwind => "C-unwind",
             CallingConvention::Rust => "Rust",
             CallingConvention::System => "system",
             CallingConvention::Win64 => "win64",
             CallingConvention::Sysv64 => "sysv64",
-            CallingConvention::Aapcs => "aapcs",
+            CallingConvention::Aapcs => {
+                if self.platform_info.arm_float_abi.is_some() {
+                    "aapcs"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
+            // rustc has no way to ask for aapcs-vfp, but it's what "C" is on hard-float targets
+            CallingConvention::AapcsVfp => {
+                if self
+                    .platform_info
+                    .arm_float_abi
+                    .is_some_and(|abi| abi.is_vfp())
+                {
+                    "C"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
             CallingConvention::Cdecl => {
                 if self.platform == Windows {
                     "cdecl"
@@ -486,19 +635,26 @@ impl RustcToolchain {
                     if self.is_nightly {
                         "vectorcall"
                     } else {
-                        return Err(UnsupportedError::Other(
-                            "vectorcall is an unstable rust feature, requires nightly".to_owned(),
+                        return Err(UnsupportedError::Convention(
+                            "vectorcall (an unstable rust feature, requires nightly)".to_owned(),
                         ))?;
                     }
                 } else {
                     return Err(self.unsupported_convention(&convention))?;
                 }
             }
+            CallingConvention::Thiscall => {
+                if self.is_x86 {
+                    "thiscall"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
         };
         Ok(conv)
     }
 
     fn unsupported_convention(&self, convention: &CallingConvention) -> UnsupportedError {
-        UnsupportedError::Other(format!("unsupported convention {convention}"))
+        UnsupportedError::Convention(convention.to_string())
     }
 }
@@ -9,7 +9,7 @@ impl RustcToolchain {
         f: &mut Fivemat,
         state: &TestState,
         ty: TyIdx,
-        val: &Value,
+        val: &ValueRef,
         alias: Option<&str>,
     ) -> Result<(), GenerateError> {
         match state.types.realize_ty(ty) {
@@ -36,6 +36,13 @@ impl RustcToolchain {
                         write!(f, "{:#X}u32 as *mut ()", val.generate_u32())?
                     }
                 }
+                PrimitiveTy::CStr => {
+                    write!(f, "b\"")?;
+                    for byte in val.generate_cstr() {
+                        write!(f, "\\x{byte:02x}")?;
+                    }
+                    write!(f, "\\0\".as_ptr() as *const core::ffi::c_char")?
+                }
                 PrimitiveTy::I256 => {
                     Err(UnsupportedError::Other("rust doesn't have i256".to_owned()))?
                 }
@@ -44,6 +51,35 @@ impl RustcToolchain {
                 }
                 PrimitiveTy::F16 => write!(f, "f16::from_bits({})", val.generate_u16())?,
                 PrimitiveTy::F128 => write!(f, "f128::from_bits({})", val.generate_u128())?,
+                PrimitiveTy::BitInt { bits, signed } => {
+                    let (size, _) = state.layout_target.bitint_layout(*bits);
+                    let bytes = val.generate_bitint(*bits, *signed, size);
+                    let bytes = bytes
+                        .iter()
+                        .map(|byte| format!("{byte:#04x}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    write!(f, "{}([{bytes}])", state.tynames[&ty])?
+                }
+                PrimitiveTy::F32x4
+                | PrimitiveTy::I64x2
+                | PrimitiveTy::F32x8
+                | PrimitiveTy::I64x4 => {
+                    // Vectors are just their bytes (in memory order, like everything else)
+                    let size = prim.vector_size().unwrap() as usize;
+                    let mut bytes = vec![0; size];
+                    val.fill_bytes(&mut bytes);
+                    let bytes = bytes
+                        .iter()
+                        .map(|byte| format!("{byte:#04x}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let tyname = &state.tynames[&ty];
+                    write!(
+                        f,
+                        "core::mem::transmute::<[u8; {size}], {tyname}>([{bytes}])"
+                    )?
+                }
             },
             Ty::Enum(enum_ty) => {
                 let name = alias.unwrap_or(&enum_ty.name);
@@ -77,9 +113,21 @@ impl RustcToolchain {
             Ty::Empty => {
                 write!(f, "()")?;
             }
-            Ty::Ref(RefTy { pointee_ty }) => {
-                // The value is a mutable reference to a temporary
-                write!(f, "&mut {ref_temp_name}")?;
+            Ty::Ref(RefTy {
+                pointee_ty,
+                nullable,
+            }) => {
+                // A nullable reference has an implicit "tag" for whether it's null
+                if *nullable {
+                    if !vals.next_val().generate_bool() {
+                        write!(f, "None")?;
+                        return Ok(());
+                    }
+                    write!(f, "Some(&mut {ref_temp_name})")?;
+                } else {
+                    // The value is a mutable reference to a temporary
+                    write!(f, "&mut {ref_temp_name}")?;
+                }
                 // Now do the rest of the recursion on constructing the temporary
                 let mut ref_temp = String::new();
                 let mut ref_temp_f = Fivemat::new(&mut ref_temp, INDENT);
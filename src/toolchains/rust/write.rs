@@ -19,12 +19,14 @@ impl RustcToolchain {
         }
         let mut has_f16 = false;
         let mut has_f128 = false;
+        let mut has_vectors = false;
         for def in state.defs.definitions(state.desired_funcs.iter().copied()) {
             match def {
                 kdl_script::Definition::DeclareTy(ty) | kdl_script::Definition::DefineTy(ty) => {
                     match state.types.realize_ty(ty) {
                         Ty::Primitive(PrimitiveTy::F16) => has_f16 = true,
                         Ty::Primitive(PrimitiveTy::F128) => has_f128 = true,
+                        Ty::Primitive(prim) if prim.vector_size().is_some() => has_vectors = true,
                         _ => {}
                     }
                 }
@@ -37,6 +39,13 @@ impl RustcToolchain {
         if has_f128 {
             writeln!(f, "#![feature(f128)]")?;
         }
+        if has_vectors && self.is_nightly {
+            writeln!(f, "#![feature(simd_ffi)]")?;
+        }
+        if state.options.tail_call_chain > 0 && self.is_nightly {
+            writeln!(f, "#![feature(explicit_tail_calls)]")?;
+            writeln!(f, "#![allow(incomplete_features)]")?;
+        }
         // Load test harness "headers"
         writeln!(
             f,
@@ -234,6 +243,38 @@ impl RustcToolchain {
                     }
                 }
             }
+            Ty::Ref(ref_ty) if ref_ty.nullable => {
+                // Process the implicit "is it null" tag (0 for None, 1 for Some)
+                let tag_generator = vals.next_val();
+                let should_write = tag_generator.should_write_val(&state.options);
+                if tag_generator.generate_bool() {
+                    writeln!(f, "if let Some(pointee) = &{from} {{")?;
+                    f.add_indent(1);
+                    if should_write {
+                        self.write_tag_field(f, state, to, from, 1, &tag_generator)?;
+                    }
+                    // Do the ugly deref thing to deal with pattern autoref, and recurse
+                    self.write_fields(f, state, to, "(**pointee)", ref_ty.pointee_ty, vals)?;
+                    f.sub_indent(1);
+                    writeln!(f, "}} else {{")?;
+                    f.add_indent(1);
+                    if should_write {
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    }
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                } else if should_write {
+                    writeln!(f, "if {from}.is_some() {{")?;
+                    f.add_indent(1);
+                    self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    f.sub_indent(1);
+                    writeln!(f, "}} else {{")?;
+                    f.add_indent(1);
+                    self.write_tag_field(f, state, to, from, 0, &tag_generator)?;
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                }
+            }
             Ty::Ref(ref_ty) => {
                 // Add a deref, and recurse into the pointee
                 let base = format!("(*{from})");
@@ -268,20 +309,81 @@ impl RustcToolchain {
         match state.options.val_writer {
             WriteImpl::HarnessCallback => {
                 let val_idx = val.absolute_val_idx;
+                let is_cstr = matches!(
+                    state.types.realize_ty(val.ty),
+                    Ty::Primitive(PrimitiveTy::CStr)
+                );
                 // Convenience for triggering test failures
                 let rvalue = if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
-                    "0x12345678u32"
+                    if is_cstr {
+                        "b\"\\x12\\x34\\x56\\x78\\0\".as_ptr() as *const core::ffi::c_char"
+                    } else {
+                        "0x12345678u32"
+                    }
                 } else {
                     path
                 };
+                if is_cstr {
+                    // Report the bytes the pointer points to (up to the terminator)
+                    writeln!(f, "write_cstr({to}, {val_idx}, {rvalue});")?;
+                    return Ok(());
+                }
+                if state.options.through_helper(&state.types, val.ty) {
+                    // black_box keeps this a real division, which is a compiler-builtins call
+                    writeln!(
+                        f,
+                        "write_val({to}, {val_idx}, &({rvalue} / core::hint::black_box(1)));"
+                    )?;
+                    return Ok(());
+                }
+                if state.options.rounds_float(&state.types, val.ty) && rvalue == path {
+                    // Float arithmetic in another rounding mode is UB in Rust, the C helper
+                    // does it instead (see rounding_mode_prefix.rs)
+                    let float = match state.types.realize_ty(val.ty) {
+                        Ty::Primitive(PrimitiveTy::F32) => "f32",
+                        _ => "f64",
+                    };
+                    writeln!(
+                        f,
+                        "write_val({to}, {val_idx}, &abi_cafe_round_{float}({rvalue}));"
+                    )?;
+                    return Ok(());
+                }
                 writeln!(f, "write_val({to}, {val_idx}, &{rvalue});")?;
             }
             WriteImpl::Assert => {
+                if let Ty::Primitive(PrimitiveTy::CStr) = state.types.realize_ty(val.ty) {
+                    // Compare what the pointers point to, not the pointers
+                    write!(
+                        f,
+                        "assert_eq!(core::ffi::CStr::from_ptr({path}), core::ffi::CStr::from_ptr("
+                    )?;
+                    self.init_leaf_value(f, state, val.ty, val, None)?;
+                    writeln!(f, "));")?;
+                    return Ok(());
+                }
+                if let Ty::Primitive(prim) = state.types.realize_ty(val.ty) {
+                    if let Some(size) = prim.vector_size() {
+                        // Vectors don't have PartialEq, so compare their bytes
+                        let bytes = format!("core::mem::transmute::<_, [u8; {size}]>");
+                        write!(f, "assert_eq!({bytes}({path}), {bytes}(")?;
+                        self.init_leaf_value(f, state, val.ty, val, None)?;
+                        writeln!(f, "));")?;
+                        return Ok(());
+                    }
+                }
                 write!(f, "assert_eq!({path}, ")?;
                 self.init_leaf_value(f, state, val.ty, val, None)?;
                 writeln!(f, ");")?;
             }
             WriteImpl::Print => {
+                if let Ty::Primitive(PrimitiveTy::CStr) = state.types.realize_ty(val.ty) {
+                    writeln!(
+                        f,
+                        "println!(\"{{:?}}\", core::ffi::CStr::from_ptr({path}));"
+                    )?;
+                    return Ok(());
+                }
                 writeln!(f, "println!(\"{{:?}}\", {path});")?;
             }
             WriteImpl::Noop => {
@@ -344,6 +446,32 @@ impl RustcToolchain {
         Ok(())
     }
 
+    /// Fill the padding of an input with the `--pad-byte`
+    pub fn fill_padding(
+        &self,
+        f: &mut dyn Write,
+        state: &TestState,
+        var_name: &str,
+        var_ty: TyIdx,
+    ) -> Result<(), GenerateError> {
+        let Some(pad_byte) = state.options.pad_byte else {
+            return Ok(());
+        };
+        let padding = state.padding_of(var_ty);
+        if padding.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "let mut {var_name} = {var_name};")?;
+        writeln!(
+            f,
+            "let {var_name}_bytes = core::ptr::addr_of_mut!({var_name}).cast::<u8>();"
+        )?;
+        for offset in padding {
+            writeln!(f, "{var_name}_bytes.add({offset}).write(0x{pad_byte:02x});")?;
+        }
+        Ok(())
+    }
+
     pub fn write_set_function(
         &self,
         f: &mut dyn Write,
@@ -0,0 +1,29 @@
+//! Checks that abi-cafe actually notices when the two sides of a call disagree
+//!
+//! This runs the real binary, so it needs a working C compiler and rustc.
+
+use std::process::Command;
+
+#[test]
+fn argcount_is_caught() {
+    let output = Command::new(env!("CARGO_BIN_EXE_abi-cafe"))
+        .args(["--tests", "simple", "--conventions", "c", "--reprs", "c"])
+        .args(["--pairs", "cc_calls_cc,rustc_calls_cc,cc_calls_rustc"])
+        .args(["--inject-fault", "argcount", "--output-format", "json"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "abi-cafe failed:\n{stderr}");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let tests = report["tests"].as_array().unwrap();
+    assert_eq!(tests.len(), 3, "{stderr}");
+    for test in tests {
+        let id = &test["id"];
+        assert!(id.as_str().unwrap().ends_with("::fault_argcount"), "{id}");
+        // The fault makes the test expect to fail its check, and it only gets reported
+        // as passing if that's how it went (abi-cafe's "failed as expected")
+        assert_eq!(test["rules"]["fail"], "check", "{id}");
+        assert_eq!(test["conclusion"], "passed", "{id} went unnoticed");
+    }
+}